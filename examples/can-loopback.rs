@@ -13,7 +13,7 @@ use panic_halt as _;
 use cortex_m_rt::entry;
 use nb::block;
 use stm32f7xx_hal::{
-    can::Can,
+    can::{Can, Mode},
     pac,
     prelude::*,
     rcc::{HSEClock, HSEClockMode},
@@ -46,8 +46,7 @@ fn main() -> ! {
         // APB1 (PCLK1): 130MHz, Bit rate: 512kBit/s, Sample Point 87.5%
         // Value was calculated with http://www.bittiming.can-wiki.info/
         .set_bit_timing(0x001e_000b)
-        .set_loopback(true)
-        .set_silent(true)
+        .set_mode(Mode::SilentLoopback)
         .enable();
 
     let mut filters = can.modify_filters();
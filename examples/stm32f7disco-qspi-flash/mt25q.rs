@@ -84,6 +84,7 @@ impl Mt25q {
             dwidth: QspiWidth::SING,
             instruction: CMD_READ_ID,
             address: None,
+            alternate_bytes: None,
             dummy: 0,
             data_len: Some(3),
         };
@@ -121,6 +122,7 @@ impl Mt25q {
             dwidth: QspiWidth::QUAD,
             instruction: CMD_MEM_READ,
             address: Some(src & MAX_ADDR),
+            alternate_bytes: None,
             dummy: 8,
             data_len: Some(len),
         };
@@ -166,6 +168,7 @@ impl Mt25q {
             dwidth: QspiWidth::QUAD,
             instruction: CMD_MEM_PROGRAM,
             address: Some(dst & MAX_ADDR),
+            alternate_bytes: None,
             dummy: 0,
             data_len: Some(len),
         };
@@ -195,6 +198,7 @@ impl Mt25q {
             dwidth: QspiWidth::QUAD,
             instruction: CMD_MEM_READ,
             address: Some(src & MAX_ADDR),
+            alternate_bytes: None,
             dummy: 8,
             data_len: Some(len),
         };
@@ -229,6 +233,7 @@ impl Mt25q {
                 dwidth: QspiWidth::QUAD,
                 instruction: CMD_MEM_PROGRAM,
                 address: Some(curr_addr & MAX_ADDR),
+                alternate_bytes: None,
                 dummy: 0,
                 data_len: Some(size),
             };
@@ -264,6 +269,7 @@ impl Mt25q {
                 dwidth: QspiWidth::NONE,
                 instruction: CMD_SUBSECT_ERASE,
                 address: Some(addr & MAX_ADDR),
+                alternate_bytes: None,
                 dummy: 0,
                 data_len: None,
             };
@@ -288,6 +294,7 @@ impl Mt25q {
             dwidth: QspiWidth::SING,
             instruction: CMD_READ_STATUS,
             address: None,
+            alternate_bytes: None,
             dummy: 0,
             data_len: Some(1),
         };
@@ -306,6 +313,7 @@ impl Mt25q {
             dwidth: QspiWidth::NONE,
             instruction: CMD_WRITE_ENABLE,
             address: None,
+            alternate_bytes: None,
             dummy: 0,
             data_len: None,
         };
@@ -1,5 +1,52 @@
 //! # Controller Area Network (CAN) Interface
 //!
+//! The peripherals are driven with the [`bxcan`] crate: `Can` implements its
+//! `Instance`, `FilterOwner` and `MasterInstance` traits and is passed to
+//! `bxcan::Can::builder`. The STM32F72x and STM32F73x only have CAN1, and
+//! `Can<CAN1>` is then not a `MasterInstance`.
+//!
+//! `bxcan::Can` and `bxcan::Frame` implement the `embedded_can` 0.3 `nb::Can`
+//! and `Frame` traits, as used by CANopen and UAVCAN stacks, when the
+//! `embedded-can-03` feature of `bxcan` is enabled in the application:
+//!
+//! ```toml
+//! bxcan = { version = "0.6", features = ["embedded-can-03"] }
+//! ```
+//!
+//! ## Test modes
+//!
+//! [`ModeExt::set_mode`] selects the loopback, silent or combined test modes
+//! on `bxcan::Can::builder` or `bxcan::Can::modify_config`, so that protocol
+//! stacks can be tested on a bench without a bus.
+//!
+//! ## Error handling
+//!
+//! [`ErrorStatusExt::error_status`] reads the error counters and the fault
+//! confinement state. The peripheral recovers automatically from bus-off,
+//! unless [`BusOffRecoveryExt::set_automatic_bus_off_recovery`] disables it.
+//!
+//! ## Transmit queue
+//!
+//! The three transmit mailboxes are sent by identifier priority. [`TxQueue`]
+//! queues more frames in software and feeds them in order or by priority.
+//!
+//! ## Interrupts
+//!
+//! The FIFO message pending, transmit mailbox empty and error interrupts are
+//! enabled with `bxcan::Can::enable_interrupts`. With the `async` feature,
+//! `receive` and `transmit` await the frames instead, provided the interrupt
+//! handlers call `on_rx_interrupt` and `on_tx_interrupt`.
+//!
+//! ## Acceptance filters
+//!
+//! CAN1 and CAN2 share 28 filter banks, owned by CAN1. They are configured
+//! through `bxcan::Can::modify_filters` on CAN1: each bank is either a pair of
+//! 32-bit or four 16-bit list entries (`ListEntry32`, `ListEntry16`), or one
+//! 32-bit or two 16-bit id/mask pairs (`Mask32`, `Mask16`). `set_split` gives
+//! the banks from the split index onward to CAN2, which are then configured
+//! through `slave_filters`. [`FilterFifo`] assigns the banks to the receive
+//! FIFO 0 or 1. Without CAN2, all the banks stay with CAN1.
+//!
 //! ## Alternate function remapping
 //!
 //! TX: Alternate Push-Pull Output
@@ -7,26 +54,36 @@
 //!
 //! ### CAN1
 //!
-//! | Function | NoRemap | Remap |
-//! |----------|---------|-------|
-//! | TX       | PA12    | PB9   |
-//! | RX       | PA11    | PB8   |
+//! | Function | NoRemap | Remap | Remap | Remap |
+//! |----------|---------|-------|-------|-------|
+//! | TX       | PA12    | PB9   | PD1   | PH13  |
+//! | RX       | PA11    | PB8   | PD0   | PI9   |
 //!
-//! ### CAN2
+//! ### CAN2 (not on the STM32F72x and STM32F73x)
 //!
 //! | Function | NoRemap | Remap |
 //! |----------|---------|-------|
 //! | TX       | PB6     | PB13  |
 //! | RX       | PB5     | PB12  |
 
-use crate::gpio::gpiob::{PB12, PB13, PB5, PB6, PB8, PB9};
+#[cfg(feature = "has-can2")]
+use crate::gpio::gpiob::{PB12, PB13, PB5, PB6};
+use crate::gpio::gpiob::{PB8, PB9};
 use crate::gpio::{
     gpioa::{PA11, PA12},
+    gpiod::{PD0, PD1},
+    gpioh::PH13,
+    gpioi::PI9,
     Alternate,
 };
+use crate::pac::can1;
 use crate::pac::CAN1;
+#[cfg(feature = "has-can2")]
 use crate::pac::CAN2;
-use crate::rcc::APB1;
+use crate::rcc::{Clocks, Enable, Reset, APB1};
+use crate::BitsPerSecond;
+
+pub use bxcan;
 
 mod sealed {
     pub trait Sealed {}
@@ -46,12 +103,26 @@ impl Pins for (PB9<Alternate<9>>, PB8<Alternate<9>>) {
     type Instance = CAN1;
 }
 
+impl sealed::Sealed for (PD1<Alternate<9>>, PD0<Alternate<9>>) {}
+impl Pins for (PD1<Alternate<9>>, PD0<Alternate<9>>) {
+    type Instance = CAN1;
+}
+
+impl sealed::Sealed for (PH13<Alternate<9>>, PI9<Alternate<9>>) {}
+impl Pins for (PH13<Alternate<9>>, PI9<Alternate<9>>) {
+    type Instance = CAN1;
+}
+
+#[cfg(feature = "has-can2")]
 impl sealed::Sealed for (PB6<Alternate<9>>, PB5<Alternate<9>>) {}
+#[cfg(feature = "has-can2")]
 impl Pins for (PB6<Alternate<9>>, PB5<Alternate<9>>) {
     type Instance = CAN2;
 }
 
+#[cfg(feature = "has-can2")]
 impl sealed::Sealed for (PB13<Alternate<9>>, PB12<Alternate<9>>) {}
+#[cfg(feature = "has-can2")]
 impl Pins for (PB13<Alternate<9>>, PB12<Alternate<9>>) {
     type Instance = CAN2;
 }
@@ -63,22 +134,67 @@ pub struct Can<Instance> {
 
 impl<Instance> Can<Instance>
 where
-    Instance: crate::rcc::Enable<Bus = APB1>,
+    Instance: CanInstance,
 {
     /// Creates a CAN interaface.
+    ///
+    /// The peripheral is reset. The bit timing, the operating mode and the
+    /// filters are then configured through `bxcan::Can::builder`.
+    ///
+    /// CAN1 isn't reset when CAN2 is already clocked, as the filters of CAN2
+    /// are in CAN1 and would be lost.
     pub fn new<P>(can: Instance, apb: &mut APB1, _pins: P) -> Can<Instance>
     where
         P: Pins<Instance = Instance>,
     {
-        Instance::enable(apb);
+        Instance::enable_clock(apb);
         Can { _peripheral: can }
     }
+
+    /// Releases the CAN peripheral
+    pub fn free(self) -> Instance {
+        self._peripheral
+    }
+}
+
+/// CAN peripherals
+pub trait CanInstance: sealed::Sealed {
+    #[doc(hidden)]
+    fn enable_clock(apb: &mut APB1);
+}
+
+impl sealed::Sealed for CAN1 {}
+impl CanInstance for CAN1 {
+    fn enable_clock(apb: &mut APB1) {
+        CAN1::enable(apb);
+        #[cfg(feature = "has-can2")]
+        if CAN2::is_enabled() {
+            return;
+        }
+        CAN1::reset(apb);
+    }
+}
+
+#[cfg(feature = "has-can2")]
+impl sealed::Sealed for CAN2 {}
+#[cfg(feature = "has-can2")]
+impl CanInstance for CAN2 {
+    fn enable_clock(apb: &mut APB1) {
+        // CAN2 is a slave of CAN1: the filters and part of the control logic
+        // are in CAN1, which must be clocked too
+        if CAN1::is_disabled() {
+            CAN1::enable(apb);
+        }
+        CAN2::enable(apb);
+        CAN2::reset(apb);
+    }
 }
 
 unsafe impl bxcan::Instance for Can<CAN1> {
     const REGISTERS: *mut bxcan::RegisterBlock = CAN1::ptr() as *mut _;
 }
 
+#[cfg(feature = "has-can2")]
 unsafe impl bxcan::Instance for Can<CAN2> {
     const REGISTERS: *mut bxcan::RegisterBlock = CAN2::ptr() as *mut _;
 }
@@ -87,4 +203,345 @@ unsafe impl bxcan::FilterOwner for Can<CAN1> {
     const NUM_FILTER_BANKS: u8 = 28;
 }
 
+#[cfg(feature = "has-can2")]
 unsafe impl bxcan::MasterInstance for Can<CAN1> {}
+
+/// Operating mode, for testing without a physical bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Normal operation on the bus
+    Normal,
+    /// The transmitted frames are received back and are also sent on the bus,
+    /// the RX pin is ignored
+    Loopback,
+    /// The frames are received from the bus but nothing is sent, TX is held
+    /// recessive
+    Silent,
+    /// The transmitted frames are received back, the node is disconnected from
+    /// the bus
+    SilentLoopback,
+}
+
+impl Mode {
+    fn loopback(self) -> bool {
+        matches!(self, Mode::Loopback | Mode::SilentLoopback)
+    }
+
+    fn silent(self) -> bool {
+        matches!(self, Mode::Silent | Mode::SilentLoopback)
+    }
+}
+
+/// Selection of the operating mode on the `bxcan` configuration builders
+pub trait ModeExt {
+    /// Sets the loopback and silent bits for `mode`
+    fn set_mode(self, mode: Mode) -> Self;
+}
+
+impl<I: bxcan::Instance> ModeExt for bxcan::CanBuilder<I> {
+    fn set_mode(self, mode: Mode) -> Self {
+        self.set_loopback(mode.loopback()).set_silent(mode.silent())
+    }
+}
+
+impl<I: bxcan::Instance> ModeExt for bxcan::CanConfig<'_, I> {
+    fn set_mode(self, mode: Mode) -> Self {
+        self.set_loopback(mode.loopback()).set_silent(mode.silent())
+    }
+}
+
+/// Fault confinement state, from the error counters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorState {
+    /// Both counters are below 96
+    Active,
+    /// A counter reached the warning limit of 96
+    Warning,
+    /// A counter is above 127: the node only sends passive error flags
+    Passive,
+    /// The transmit error counter went above 255: the node is off the bus
+    BusOff,
+}
+
+/// Last error code (LEC) seen on the bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusError {
+    Stuff,
+    Form,
+    Acknowledgement,
+    BitRecessive,
+    BitDominant,
+    Crc,
+}
+
+/// Error counters and state (ESR)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorStatus {
+    /// Transmit error counter
+    pub tec: u8,
+    /// Receive error counter
+    pub rec: u8,
+    pub state: ErrorState,
+    /// Last error seen, if any
+    pub last_error: Option<BusError>,
+}
+
+fn registers<I: bxcan::Instance>() -> &'static can1::RegisterBlock {
+    // NOTE(unsafe) the bxcan register block has the same layout as the PAC one
+    unsafe { &*(I::REGISTERS as *const can1::RegisterBlock) }
+}
+
+/// Error monitoring and bus-off recovery on `bxcan::Can`
+pub trait ErrorStatusExt {
+    /// Reads the error counters and state
+    fn error_status(&self) -> ErrorStatus;
+
+    /// Clears the last error code, so a new error can be told from an old one
+    fn clear_last_error(&mut self);
+
+    /// Recovers from the bus-off state, when the automatic recovery is
+    /// disabled. This blocks until 128 occurrences of 11 recessive bits have
+    /// been seen on the bus.
+    fn recover_from_bus_off(&mut self);
+}
+
+impl<I: bxcan::Instance> ErrorStatusExt for bxcan::Can<I> {
+    fn error_status(&self) -> ErrorStatus {
+        let esr = registers::<I>().esr.read();
+        let state = if esr.boff().bit_is_set() {
+            ErrorState::BusOff
+        } else if esr.epvf().bit_is_set() {
+            ErrorState::Passive
+        } else if esr.ewgf().bit_is_set() {
+            ErrorState::Warning
+        } else {
+            ErrorState::Active
+        };
+        let last_error = match esr.lec().bits() {
+            1 => Some(BusError::Stuff),
+            2 => Some(BusError::Form),
+            3 => Some(BusError::Acknowledgement),
+            4 => Some(BusError::BitRecessive),
+            5 => Some(BusError::BitDominant),
+            6 => Some(BusError::Crc),
+            _ => None,
+        };
+
+        ErrorStatus {
+            tec: esr.tec().bits(),
+            rec: esr.rec().bits(),
+            state,
+            last_error,
+        }
+    }
+
+    fn clear_last_error(&mut self) {
+        // LEC 7 is never set by hardware and stands for no new error
+        registers::<I>().esr.modify(|_, w| w.lec().bits(0b111));
+    }
+
+    fn recover_from_bus_off(&mut self) {
+        // Leaving initialization mode starts the recovery sequence
+        self.modify_config().enable();
+    }
+}
+
+/// Bus-off recovery configuration on `bxcan::CanConfig`
+pub trait BusOffRecoveryExt {
+    /// Enables or disables the automatic recovery from bus-off (ABOM).
+    ///
+    /// `bxcan` enables it when the peripheral leaves sleep mode, so disable it
+    /// through `bxcan::Can::modify_config` once the peripheral is enabled.
+    /// Without it, call [`ErrorStatusExt::recover_from_bus_off`].
+    fn set_automatic_bus_off_recovery(self, enabled: bool) -> Self;
+}
+
+impl<I: bxcan::Instance> BusOffRecoveryExt for bxcan::CanConfig<'_, I> {
+    fn set_automatic_bus_off_recovery(self, enabled: bool) -> Self {
+        registers::<I>().mcr.modify(|_, w| w.abom().bit(enabled));
+        self
+    }
+}
+
+/// Bit timing error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitTimingError {
+    /// The bitrate cannot be obtained exactly from the APB1 clock
+    Bitrate,
+    /// The sample point is not within 50..100%
+    SamplePoint,
+}
+
+/// Bit timing, the BTR register content
+///
+/// A bit is made of the synchronization segment, of one time quantum, then
+/// `seg1` and `seg2`, the sample point being between the two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitTiming {
+    /// Baud rate prescaler, 1..=1024
+    pub prescaler: u16,
+    /// Time quanta before the sample point, 1..=16
+    pub seg1: u8,
+    /// Time quanta after the sample point, 1..=8
+    pub seg2: u8,
+    /// Resynchronization jump width, 1..=4
+    pub sjw: u8,
+}
+
+impl BitTiming {
+    /// Computes the timing for `bitrate` with the sample point closest to
+    /// `sample_point`, in per mille (875 for the usual 87.5%).
+    ///
+    /// Most time quanta per bit are preferred for a given sample point
+    /// accuracy. The bitrate must be exact.
+    pub fn new(
+        clocks: &Clocks,
+        bitrate: BitsPerSecond,
+        sample_point: u16,
+    ) -> Result<Self, BitTimingError> {
+        Self::from_clock(clocks.pclk1().raw(), bitrate.raw(), sample_point)
+    }
+
+    fn from_clock(pclk1: u32, bitrate: u32, sample_point: u16) -> Result<Self, BitTimingError> {
+        if !(500..1000).contains(&sample_point) {
+            return Err(BitTimingError::SamplePoint);
+        }
+        let sample_point = u32::from(sample_point);
+
+        let mut best: Option<(u32, Self)> = None;
+        for quanta in (8..=25).rev() {
+            let ticks = match bitrate.checked_mul(quanta) {
+                Some(ticks) => ticks,
+                None => continue,
+            };
+            if ticks == 0 || pclk1 % ticks != 0 || pclk1 / ticks > 1024 {
+                continue;
+            }
+
+            // Quanta up to the sample point, including the sync segment
+            let before = ((quanta * sample_point + 500) / 1000)
+                .clamp(2, 17)
+                .min(quanta - 1);
+            let (seg1, seg2) = (before - 1, quanta - before);
+            if seg2 > 8 {
+                continue;
+            }
+
+            let point = before * 1000 / quanta;
+            let error = point.max(sample_point) - point.min(sample_point);
+            if best.map_or(true, |(best_error, _)| error < best_error) {
+                let timing = Self {
+                    prescaler: (pclk1 / ticks) as u16,
+                    seg1: seg1 as u8,
+                    seg2: seg2 as u8,
+                    sjw: seg2.min(4) as u8,
+                };
+                best = Some((error, timing));
+            }
+        }
+
+        best.map(|(_, timing)| timing)
+            .ok_or(BitTimingError::Bitrate)
+    }
+
+    /// Returns the BTR register value, for `bxcan::CanConfig::set_bit_timing`
+    pub const fn btr(&self) -> u32 {
+        (self.prescaler as u32 - 1)
+            | (self.seg1 as u32 - 1) << 16
+            | (self.seg2 as u32 - 1) << 20
+            | (self.sjw as u32 - 1) << 24
+    }
+}
+
+/// Receive FIFO
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fifo {
+    Fifo0,
+    Fifo1,
+}
+
+/// Assignment of the filter banks to the receive FIFOs, which `bxcan` leaves
+/// to FIFO 0
+pub trait FilterFifo {
+    /// Makes the frames accepted by the filter bank `index` go to `fifo`.
+    ///
+    /// Panics if the bank is not owned by this instance.
+    fn set_bank_fifo(&mut self, index: u8, fifo: Fifo) -> &mut Self;
+}
+
+fn set_bank_fifo(index: u8, fifo: Fifo) {
+    // NOTE(unsafe) the filter banks are in initialization mode and exclusively
+    // borrowed by the filters handle
+    let can1 = unsafe { &*CAN1::ptr() };
+    can1.ffa1r.modify(|r, w| {
+        let bits = match fifo {
+            Fifo::Fifo0 => r.bits() & !(1 << index),
+            Fifo::Fifo1 => r.bits() | (1 << index),
+        };
+        unsafe { w.bits(bits) }
+    });
+}
+
+impl FilterFifo for bxcan::filter::MasterFilters<'_, Can<CAN1>> {
+    fn set_bank_fifo(&mut self, index: u8, fifo: Fifo) -> &mut Self {
+        assert!(index < self.num_banks());
+        set_bank_fifo(index, fifo);
+        self
+    }
+}
+
+#[cfg(feature = "has-can2")]
+impl FilterFifo for bxcan::filter::SlaveFilters<'_, Can<CAN1>> {
+    fn set_bank_fifo(&mut self, index: u8, fifo: Fifo) -> &mut Self {
+        let start = <Can<CAN1> as bxcan::FilterOwner>::NUM_FILTER_BANKS - self.num_banks();
+        assert!((start..start + self.num_banks()).contains(&index));
+        set_bank_fifo(index, fifo);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bit_timing() {
+        let timing = BitTiming::from_clock(54_000_000, 500_000, 875).unwrap();
+        assert_eq!(
+            timing,
+            BitTiming {
+                prescaler: 6,
+                seg1: 15,
+                seg2: 2,
+                sjw: 2
+            }
+        );
+        assert_eq!(timing.btr(), 0x011e_0005);
+
+        let timing = BitTiming::from_clock(48_000_000, 1_000_000, 750).unwrap();
+        assert_eq!((timing.prescaler, timing.seg1, timing.seg2), (3, 11, 4));
+
+        assert_eq!(
+            BitTiming::from_clock(54_000_000, 1_000_001, 875),
+            Err(BitTimingError::Bitrate)
+        );
+        assert_eq!(
+            BitTiming::from_clock(54_000_000, u32::MAX, 875),
+            Err(BitTimingError::Bitrate)
+        );
+        assert_eq!(
+            BitTiming::from_clock(54_000_000, 500_000, 1000),
+            Err(BitTimingError::SamplePoint)
+        );
+    }
+}
+
+mod queue;
+pub use queue::{TxOrder, TxQueue};
+
+// The `async` feature needs Rust 1.75
+#[cfg(feature = "async")]
+#[clippy::msrv = "1.75"]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::{on_rx_interrupt, on_tx_interrupt, receive, transmit, Instance};
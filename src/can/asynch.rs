@@ -0,0 +1,130 @@
+use core::future::poll_fn;
+use core::task::Poll;
+
+use bxcan::{Frame, Rx, Tx};
+use nb::Error::{Other, WouldBlock};
+
+use super::Can;
+#[cfg(feature = "has-can2")]
+use crate::pac::CAN2;
+use crate::pac::{can1, CAN1};
+use crate::waker::WakerCell;
+
+/// TMEIE
+const TX_IRQ: u32 = 1 << 0;
+/// FMPIE0 and FMPIE1
+const RX_IRQ: u32 = (1 << 1) | (1 << 4);
+
+static RX_WAKERS: [WakerCell; 2] = [WakerCell::new(), WakerCell::new()];
+static TX_WAKERS: [WakerCell; 2] = [WakerCell::new(), WakerCell::new()];
+
+/// CAN peripherals usable with the async API
+pub trait Instance: crate::Sealed {
+    #[doc(hidden)]
+    const INDEX: usize;
+    #[doc(hidden)]
+    fn ptr() -> *const can1::RegisterBlock;
+}
+
+macro_rules! instance {
+    ($($CANX:ident: $index:expr,)+) => {
+        $(
+            impl Instance for $CANX {
+                const INDEX: usize = $index;
+                fn ptr() -> *const can1::RegisterBlock {
+                    $CANX::ptr()
+                }
+            }
+        )+
+    };
+}
+
+instance! {
+    CAN1: 0,
+}
+
+#[cfg(feature = "has-can2")]
+instance! {
+    CAN2: 1,
+}
+
+fn mask<CAN: Instance>(irqs: u32) {
+    // NOTE(unsafe) only the interrupt enable bits owned by the async API are
+    // touched, atomically
+    let can = unsafe { &*CAN::ptr() };
+    cortex_m::interrupt::free(|_| can.ier.modify(|r, w| unsafe { w.bits(r.bits() & !irqs) }));
+}
+
+fn unmask<CAN: Instance>(irqs: u32) {
+    let can = unsafe { &*CAN::ptr() };
+    cortex_m::interrupt::free(|_| can.ier.modify(|r, w| unsafe { w.bits(r.bits() | irqs) }));
+}
+
+/// Wakes the task awaiting `receive` on the `CAN` peripheral.
+///
+/// Must be called from both the RX0 and RX1 interrupt handlers. The message
+/// pending interrupts are masked until the next poll.
+pub fn on_rx_interrupt<CAN: Instance>() {
+    mask::<CAN>(RX_IRQ);
+    RX_WAKERS[CAN::INDEX].wake();
+}
+
+/// Wakes the task awaiting `transmit` on the `CAN` peripheral.
+///
+/// Must be called from the TX interrupt handler. The request completed flags
+/// are cleared, and the transmit mailbox empty interrupt is masked until the
+/// next poll.
+pub fn on_tx_interrupt<CAN: Instance>() {
+    // NOTE(unsafe) write-1-to-clear of the RQCPx flags, as
+    // `bxcan::Tx::clear_interrupt_flags`
+    let can = unsafe { &*CAN::ptr() };
+    can.tsr
+        .write(|w| w.rqcp2().set_bit().rqcp1().set_bit().rqcp0().set_bit());
+    mask::<CAN>(TX_IRQ);
+    TX_WAKERS[CAN::INDEX].wake();
+}
+
+/// Waits for a frame in either receive FIFO.
+///
+/// Returns `Err` when a frame was lost due to a FIFO overrun.
+pub async fn receive<CAN>(rx: &mut Rx<Can<CAN>>) -> Result<Frame, ()>
+where
+    CAN: Instance,
+    Can<CAN>: bxcan::Instance,
+{
+    poll_fn(|cx| {
+        RX_WAKERS[CAN::INDEX].register(cx.waker());
+        match rx.receive() {
+            Ok(frame) => Poll::Ready(Ok(frame)),
+            Err(Other(())) => Poll::Ready(Err(())),
+            Err(WouldBlock) => {
+                unmask::<CAN>(RX_IRQ);
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+/// Waits for a transmit mailbox to put `frame` in.
+///
+/// As with `bxcan::Tx::transmit`, a pending lower priority frame may be
+/// dequeued to make room, it is then returned.
+pub async fn transmit<CAN>(tx: &mut Tx<Can<CAN>>, frame: &Frame) -> Option<Frame>
+where
+    CAN: Instance,
+    Can<CAN>: bxcan::Instance,
+{
+    poll_fn(|cx| {
+        TX_WAKERS[CAN::INDEX].register(cx.waker());
+        match tx.transmit(frame) {
+            Ok(status) => Poll::Ready(status.dequeued_frame().cloned()),
+            Err(Other(never)) => match never {},
+            Err(WouldBlock) => {
+                unmask::<CAN>(TX_IRQ);
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
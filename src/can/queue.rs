@@ -0,0 +1,168 @@
+use bxcan::{Frame, Tx};
+
+use super::registers;
+
+/// Order in which a [`TxQueue`] sends its frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxOrder {
+    /// In the order they were queued. Only one frame is in the transmit
+    /// mailboxes at a time, as the hardware sends the mailboxes by priority.
+    Fifo,
+    /// Highest priority first, per identifier. The frames already in the
+    /// mailboxes are sent first.
+    Priority,
+    /// Highest priority first, aborting and requeuing lower priority frames
+    /// that are pending in the mailboxes
+    PriorityPreemptive,
+}
+
+/// Software transmit queue feeding the three transmit mailboxes
+///
+/// Frames are queued with `push` and moved to the mailboxes by `poll`, which
+/// is called from the transmit mailbox empty interrupt handler, or after
+/// `push` when the interrupt is not used.
+pub struct TxQueue<const N: usize> {
+    /// Frames with their queuing sequence number
+    frames: [Option<(u32, Frame)>; N],
+    sequence: u32,
+    /// Sequence numbers of the frames last submitted to each mailbox
+    mailboxes: [u32; 3],
+    order: TxOrder,
+}
+
+impl<const N: usize> TxQueue<N> {
+    const EMPTY: Option<(u32, Frame)> = None;
+
+    /// Creates an empty queue of `N` frames
+    pub fn new(order: TxOrder) -> Self {
+        Self {
+            frames: [Self::EMPTY; N],
+            sequence: 0,
+            mailboxes: [0; 3],
+            order,
+        }
+    }
+
+    /// Queues `frame`. Returns it back if the queue is full.
+    pub fn push(&mut self, frame: Frame) -> Result<(), Frame> {
+        match self.frames.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some((self.sequence, frame));
+                self.sequence = self.sequence.wrapping_add(1);
+                Ok(())
+            }
+            None => Err(frame),
+        }
+    }
+
+    /// Returns the number of queued frames, not counting the ones in the
+    /// mailboxes
+    pub fn len(&self) -> usize {
+        self.frames.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the queued frames. The frames in the mailboxes are still sent.
+    pub fn clear(&mut self) {
+        self.frames = [Self::EMPTY; N];
+    }
+
+    /// Index of the next frame to send
+    fn next(&self) -> Option<usize> {
+        let key = |(sequence, frame): &(u32, Frame)| {
+            // Oldest first for equal priorities, wrapping with the sequence
+            let age = self.sequence.wrapping_sub(*sequence);
+            match self.order {
+                TxOrder::Fifo => (None, age),
+                _ => (Some(frame.priority()), age),
+            }
+        };
+
+        self.frames
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| slot.as_ref().map(|entry| (i, key(entry))))
+            .max_by(|(_, a), (_, b)| a.cmp(b))
+            .map(|(i, _)| i)
+    }
+
+    /// Moves queued frames to the free transmit mailboxes, and with
+    /// `TxOrder::PriorityPreemptive`, in place of lower priority frames.
+    pub fn poll<I: bxcan::Instance>(&mut self, tx: &mut Tx<I>) {
+        while let Some(i) = self.next() {
+            let can_submit = match self.order {
+                TxOrder::Fifo => tx.is_idle(),
+                TxOrder::Priority => {
+                    let tsr = registers::<I>().tsr.read();
+                    tsr.tme0().bit_is_set() || tsr.tme1().bit_is_set() || tsr.tme2().bit_is_set()
+                }
+                TxOrder::PriorityPreemptive => true,
+            };
+            if !can_submit {
+                break;
+            }
+
+            let (sequence, frame) = self.frames[i].take().unwrap();
+            match tx.transmit(&frame) {
+                Ok(status) => {
+                    // Requeue the aborted frame in the slot freed by the
+                    // submitted one, keeping its place among the frames of
+                    // the same priority
+                    let mailbox = status.mailbox() as usize;
+                    if let Some(dequeued) = status.dequeued_frame() {
+                        self.frames[i] = Some((self.mailboxes[mailbox], dequeued.clone()));
+                    }
+                    self.mailboxes[mailbox] = sequence;
+                }
+                Err(_) => {
+                    // All mailboxes hold higher priority frames
+                    self.frames[i] = Some((sequence, frame));
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bxcan::StandardId;
+
+    fn frame(id: u16) -> Frame {
+        Frame::new_data(StandardId::new(id).unwrap(), [])
+    }
+
+    fn drain<const N: usize>(queue: &mut TxQueue<N>) -> [u16; N] {
+        let mut ids = [0; N];
+        for id in ids.iter_mut() {
+            let i = queue.next().unwrap();
+            let (_, frame) = queue.frames[i].take().unwrap();
+            *id = match frame.id() {
+                bxcan::Id::Standard(id) => id.as_raw(),
+                bxcan::Id::Extended(_) => unreachable!(),
+            };
+        }
+        ids
+    }
+
+    #[test]
+    fn test_tx_queue_order() {
+        let mut queue = TxQueue::<4>::new(TxOrder::Fifo);
+        for id in [5, 1, 5, 3] {
+            queue.push(frame(id)).unwrap();
+        }
+        assert!(queue.push(frame(0)).is_err());
+        assert_eq!(drain(&mut queue), [5, 1, 5, 3]);
+
+        let mut queue = TxQueue::<4>::new(TxOrder::Priority);
+        for id in [5, 1, 4, 3] {
+            queue.push(frame(id)).unwrap();
+        }
+        assert_eq!(drain(&mut queue), [1, 3, 4, 5]);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+//! CRC calculation unit
+//!
+//! By default the unit computes the CRC-32 used by Ethernet, with the
+//! polynomial 0x04C11DB7 and an initial value of 0xFFFFFFFF, without bit
+//! reversal nor final XOR. Large buffers, such as firmware images in flash,
+//! can be fed with DMA2 in memory-to-memory mode to keep the CPU free.
+
+use as_slice::AsSlice;
+use core::{ops::Deref, pin::Pin};
+
+use crate::pac::CRC;
+use crate::{
+    dma,
+    rcc::{Enable, Reset, AHB1},
+    state,
+};
+
+/// CRC calculation unit
+pub struct Crc {
+    crc: CRC,
+}
+
+impl Crc {
+    /// Enables the CRC clock and resets the unit to its default configuration
+    pub fn new(crc: CRC, ahb1: &mut AHB1) -> Self {
+        CRC::enable(ahb1);
+        CRC::reset(ahb1);
+
+        Self { crc }
+    }
+
+    /// Sets the initial value and restarts the computation
+    pub fn set_initial_value(&mut self, init: u32) {
+        self.crc.init.write(|w| w.init().bits(init));
+        self.reset();
+    }
+
+    /// Restarts the computation from the initial value
+    pub fn reset(&mut self) {
+        self.crc.cr.write(|w| w.reset().set_bit());
+    }
+
+    /// Feeds 32-bit words to the unit
+    pub fn feed_words(&mut self, data: &[u32]) {
+        for word in data {
+            self.crc.dr().write(|w| w.dr().bits(*word));
+        }
+    }
+
+    /// Feeds bytes to the unit
+    pub fn feed_bytes(&mut self, data: &[u8]) {
+        for byte in data {
+            self.crc.dr8().write(|w| w.dr8().bits(*byte));
+        }
+    }
+
+    /// Returns the CRC of the data fed since the last reset
+    pub fn result(&self) -> u32 {
+        self.crc.dr().read().dr().bits()
+    }
+
+    /// Feeds `data` to the unit with a DMA2 memory-to-memory transfer.
+    ///
+    /// The words are written with their own size, so `u8` data gives the same
+    /// result as `feed_bytes`. The computation is not reset first. Read the
+    /// result once the transfer is done, after getting the `Crc` back from the
+    /// transfer resources.
+    pub fn feed_slice_dma<B, Word>(
+        self,
+        data: Pin<B>,
+        dma: &dma::Handle<<Self as dma::Target>::Instance, state::Enabled>,
+        stream: <Self as dma::Target>::Stream,
+    ) -> dma::Transfer<Self, B, dma::Ready>
+    where
+        B: Deref + 'static,
+        B::Target: AsSlice<Element = Word>,
+        Word: dma::SupportedWordSize,
+    {
+        let address = self.crc.dr() as *const _ as _;
+
+        // Safe, because the trait bounds on this method guarantee that `data`
+        // can be read from safely.
+        unsafe {
+            dma::Transfer::new(
+                dma,
+                stream,
+                data,
+                self,
+                address,
+                dma::Direction::MemoryToMemory,
+            )
+        }
+    }
+
+    /// Releases the CRC peripheral
+    pub fn free(self) -> CRC {
+        self.crc
+    }
+}
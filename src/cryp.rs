@@ -0,0 +1,471 @@
+//! Cryptographic processor, for AES
+//!
+//! The unit encrypts and decrypts with 128, 192 or 256-bit keys, in the ECB,
+//! CBC and CTR chaining modes. The data are exchanged as bytes, in the order
+//! of the AES specifications.
+//!
+//! ```ignore
+//! let mut cryp = Cryp::new(dp.CRYP, &mut rcc.ahb2);
+//! cryp.start(&key, Mode::Cbc(iv), Direction::Encrypt)?;
+//! cryp.process(&plaintext, &mut ciphertext)?;
+//! cryp.stop();
+//! ```
+//!
+//! Successive calls to [`Cryp::process`] continue the chaining, so a message
+//! can be processed in several parts, as long as all of them but the last
+//! one are made of whole blocks. Large messages can be processed with DMA2,
+//! see [`Cryp::process_dma`].
+//!
+//! The authenticated encryption modes, GCM, GMAC and CCM, are started with
+//! [`Cryp::start_gcm`] and [`Cryp::start_ccm`].
+
+use as_slice::{AsMutSlice, AsSlice};
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+
+use crate::pac::CRYP;
+use crate::rcc::{Enable, Reset, AHB2};
+use crate::{dma, state};
+
+mod aead;
+
+/// Size of the AES blocks, in bytes
+pub const BLOCK_SIZE: usize = 16;
+
+/// Chaining mode, with its initialization vector
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Electronic codebook, each block processed independently
+    Ecb,
+    /// Cipher block chaining
+    Cbc([u8; BLOCK_SIZE]),
+    /// Counter mode, with the initial counter block. The hardware increments
+    /// its last 32 bits.
+    Ctr([u8; BLOCK_SIZE]),
+}
+
+impl Mode {
+    fn algomode(&self) -> u8 {
+        match self {
+            Mode::Ecb => 0b100,
+            Mode::Cbc(_) => 0b101,
+            Mode::Ctr(_) => 0b110,
+        }
+    }
+
+    fn iv(&self) -> Option<&[u8; BLOCK_SIZE]> {
+        match self {
+            Mode::Ecb => None,
+            Mode::Cbc(iv) | Mode::Ctr(iv) => Some(iv),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The key is not 16, 24 or 32 bytes long
+    KeyLength,
+    /// The data are not made of whole blocks, in ECB or CBC mode, the
+    /// output is shorter than the input, or the payload doesn't match the
+    /// length given to CCM
+    DataLength,
+    /// The CCM nonce is not 7 to 13 bytes long
+    NonceLength,
+    /// The tag is not 4 to 16 bytes long, an even number of them, for CCM,
+    /// nor 12 to 16 bytes long, for GCM
+    TagLength,
+    /// The tag of the message doesn't match
+    Authentication,
+}
+
+/// Key preparation for the decryption, in ECB and CBC modes
+const ALGOMODE_KEY_PREPARATION: u8 = 0b111;
+/// Data swapped as bytes
+const DATATYPE_BYTES: u8 = 0b10;
+
+type Handle = dma::Handle<<DmaIn as dma::Target>::Instance, state::Enabled>;
+
+/// Cryptographic processor
+pub struct Cryp {
+    cryp: CRYP,
+    mode: Mode,
+    /// Authenticated encryption in progress, instead of `mode`
+    aead: Option<aead::Aead>,
+}
+
+impl Cryp {
+    /// Enables the CRYP clock and resets the unit
+    pub fn new(cryp: CRYP, ahb2: &mut AHB2) -> Self {
+        CRYP::enable(ahb2);
+        CRYP::reset(ahb2);
+
+        Self {
+            cryp,
+            mode: Mode::Ecb,
+            aead: None,
+        }
+    }
+
+    /// Loads `key` and the initialization vector of `mode`, and starts
+    /// processing in `direction`
+    ///
+    /// Any processing in progress is abandoned.
+    pub fn start(&mut self, key: &[u8], mode: Mode, direction: Direction) -> Result<(), Error> {
+        self.load_key(key)?;
+
+        // The decryption key schedule is derived from the encryption key,
+        // except in CTR mode, which only uses the encryption
+        let decrypt = direction == Direction::Decrypt;
+        if decrypt && !matches!(mode, Mode::Ctr(_)) {
+            self.cryp.cr.modify(|_, w| unsafe {
+                w.algodir()
+                    .set_bit()
+                    .algomode0()
+                    .bits(ALGOMODE_KEY_PREPARATION)
+                    .crypen()
+                    .set_bit()
+            });
+            while self.cryp.sr.read().busy().bit_is_set() {}
+        }
+
+        self.cryp.cr.modify(|_, w| unsafe {
+            w.algodir()
+                .bit(decrypt)
+                .algomode0()
+                .bits(mode.algomode())
+                .crypen()
+                .clear_bit()
+        });
+        if let Some(iv) = mode.iv() {
+            self.load_iv(iv);
+        }
+        self.mode = mode;
+
+        self.cryp.cr.modify(|_, w| w.fflush().set_bit());
+        self.cryp.cr.modify(|_, w| w.crypen().set_bit());
+        Ok(())
+    }
+
+    /// Stops the unit and loads `key`, with the data type and key size
+    fn load_key(&mut self, key: &[u8]) -> Result<(), Error> {
+        let (words, keysize) = key_words(key)?;
+
+        self.stop();
+        for (i, key) in self.cryp.key.iter().enumerate() {
+            key.klr.write(|w| unsafe { w.bits(words[2 * i]) });
+            key.krr.write(|w| unsafe { w.bits(words[2 * i + 1]) });
+        }
+        self.cryp
+            .cr
+            .write(|w| unsafe { w.datatype().bits(DATATYPE_BYTES).keysize().bits(keysize) });
+        Ok(())
+    }
+
+    fn load_iv(&mut self, iv: &[u8; BLOCK_SIZE]) {
+        let mut words = iv
+            .chunks_exact(4)
+            .map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]]));
+        for init in &self.cryp.init {
+            // The iterator yields exactly 4 words
+            init.ivlr
+                .write(|w| unsafe { w.bits(words.next().unwrap()) });
+            init.ivrr
+                .write(|w| unsafe { w.bits(words.next().unwrap()) });
+        }
+    }
+
+    /// Processes `input` into `output`, which must be at least as long
+    ///
+    /// In ECB and CBC modes, `input` must be made of whole blocks. In CTR,
+    /// GCM and CCM modes, it can end with a partial block, which ends the
+    /// message.
+    pub fn process(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Error> {
+        let partial_block = self.aead.is_some() || matches!(self.mode, Mode::Ctr(_));
+        if output.len() < input.len() || (!partial_block && input.len() % BLOCK_SIZE != 0) {
+            return Err(Error::DataLength);
+        }
+        if let Some(aead) = &mut self.aead {
+            if aead.ended {
+                return Err(Error::DataLength);
+            }
+            aead.ended = input.len() % BLOCK_SIZE != 0;
+        }
+        self.start_payload(input.len());
+
+        for (input, output) in input.chunks(BLOCK_SIZE).zip(output.chunks_mut(BLOCK_SIZE)) {
+            let mut block = [0; BLOCK_SIZE];
+            block[..input.len()].copy_from_slice(input);
+            if input.len() < BLOCK_SIZE && self.aead.is_some() {
+                self.process_last_block(&mut block, input.len());
+            } else {
+                self.transform_block(&mut block);
+            }
+            output[..input.len()].copy_from_slice(&block[..input.len()]);
+        }
+        Ok(())
+    }
+
+    /// Processes a block in place
+    ///
+    /// In GCM and CCM modes, the block must not follow a partial block.
+    pub fn process_block(&mut self, block: &mut [u8; BLOCK_SIZE]) {
+        self.start_payload(BLOCK_SIZE);
+        self.transform_block(block);
+    }
+
+    /// Processes `input` into `output` with DMA2
+    ///
+    /// The transfer must be started, and the `Cryp` is given back once it
+    /// is done. Both buffers hold the data in the order of the AES
+    /// specifications, 4 bytes per word.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffers have different lengths or are not made of
+    /// whole blocks, or in GCM and CCM modes, if a partial block was
+    /// processed.
+    pub fn process_dma<I, O>(
+        mut self,
+        input: Pin<I>,
+        mut output: Pin<O>,
+        dma: &Handle,
+        input_stream: <DmaIn as dma::Target>::Stream,
+        output_stream: <DmaOut as dma::Target>::Stream,
+    ) -> Transfer<I, O, dma::Ready>
+    where
+        I: Deref + 'static,
+        I::Target: AsSlice<Element = u32>,
+        O: DerefMut + 'static,
+        O::Target: AsMutSlice<Element = u32>,
+    {
+        let len = input.as_slice().len();
+        // Only borrowed to check the length
+        let output_len = unsafe { output.as_mut().get_unchecked_mut() }
+            .as_mut_slice()
+            .len();
+        assert_eq!(len, output_len);
+        assert_eq!(len % (BLOCK_SIZE / 4), 0);
+        assert!(!self.aead.as_ref().map_or(false, |aead| aead.ended));
+        self.start_payload(len * 4);
+
+        let din = &self.cryp.din as *const _ as u32;
+        let dout = &self.cryp.dout as *const _ as u32;
+        // Safe, because the input is only read, and the output is only
+        // written by the DMA until the transfer is done
+        let input = unsafe {
+            dma::Transfer::new(
+                dma,
+                input_stream,
+                input,
+                DmaIn(()),
+                din,
+                dma::Direction::MemoryToPeripheral,
+            )
+        };
+        let output = unsafe {
+            dma::Transfer::new(
+                dma,
+                output_stream,
+                output,
+                DmaOut(()),
+                dout,
+                dma::Direction::PeripheralToMemory,
+            )
+        };
+
+        Transfer {
+            cryp: self,
+            input,
+            output,
+        }
+    }
+
+    /// Starts the payload phase, on the first block of GCM and CCM, and
+    /// counts its length
+    fn start_payload(&mut self, len: usize) {
+        if let Some(aead) = &mut self.aead {
+            if !aead.payload {
+                aead.payload = true;
+                while self.cryp.sr.read().busy().bit_is_set() {}
+                self.cryp.cr.modify(|_, w| w.crypen().clear_bit());
+                self.cryp.cr.modify(|_, w| unsafe {
+                    w.gcm_ccmph()
+                        .bits(aead::Phase::Payload as u8)
+                        .crypen()
+                        .set_bit()
+                });
+            }
+            aead.payload_len += len;
+        }
+    }
+
+    /// Writes a block to the input FIFO
+    fn write_block(&mut self, block: &[u8; BLOCK_SIZE]) {
+        for word in block.chunks_exact(4) {
+            while self.cryp.sr.read().ifnf().bit_is_clear() {}
+            // The unit swaps the bytes of the words, for the AES order
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            self.cryp.din.write(|w| unsafe { w.bits(word) });
+        }
+    }
+
+    /// Processes a block in place, in the current phase
+    fn transform_block(&mut self, block: &mut [u8; BLOCK_SIZE]) {
+        self.write_block(block);
+        for word in block.chunks_exact_mut(4) {
+            while self.cryp.sr.read().ofne().bit_is_clear() {}
+            word.copy_from_slice(&self.cryp.dout.read().bits().to_le_bytes());
+        }
+    }
+
+    /// Stops the processing, once the unit is idle
+    ///
+    /// An authenticated encryption in progress is abandoned.
+    pub fn stop(&mut self) {
+        while self.cryp.sr.read().busy().bit_is_set() {}
+        self.cryp.cr.modify(|_, w| w.crypen().clear_bit());
+        self.aead = None;
+    }
+
+    /// Releases the CRYP peripheral
+    pub fn free(mut self) -> CRYP {
+        self.stop();
+        self.cryp
+    }
+}
+
+/// DMA token of the input FIFO
+pub struct DmaIn(());
+
+/// DMA token of the output FIFO
+pub struct DmaOut(());
+
+/// A DMA transfer of the CRYP peripheral, from the input buffer to the
+/// output buffer
+pub struct Transfer<I, O, State> {
+    cryp: Cryp,
+    input: dma::Transfer<DmaIn, I, State>,
+    output: dma::Transfer<DmaOut, O, State>,
+}
+
+impl<I: 'static, O: 'static> Transfer<I, O, dma::Ready> {
+    /// Starts the DMA transfer
+    pub fn start(self, handle: &Handle) -> Transfer<I, O, dma::Started> {
+        let output = self.output.start(handle);
+        let input = self.input.start(handle);
+        self.cryp
+            .cryp
+            .dmacr
+            .write(|w| w.doen().set_bit().dien().set_bit());
+
+        Transfer {
+            cryp: self.cryp,
+            input,
+            output,
+        }
+    }
+}
+
+impl<I: 'static, O: 'static> Transfer<I, O, dma::Started> {
+    /// Checks whether the transfer is still ongoing
+    pub fn is_active(&self, handle: &Handle) -> bool {
+        self.input.is_active(handle) || self.output.is_active(handle)
+    }
+
+    /// Waits for the transfer to end, and returns the `Cryp`, the buffers
+    /// and the DMA streams
+    pub fn wait(
+        self,
+        handle: &Handle,
+    ) -> Result<TransferResources<I, O>, (TransferResources<I, O>, dma::Error)> {
+        let (output, output_error) = match self.output.wait(handle) {
+            Ok(resources) => (resources, None),
+            Err((resources, error)) => (resources, Some(error)),
+        };
+        let (input, input_error) = match self.input.wait(handle) {
+            Ok(resources) => (resources, None),
+            Err((resources, error)) => (resources, Some(error)),
+        };
+        self.cryp.cryp.dmacr.reset();
+
+        let resources = TransferResources {
+            cryp: self.cryp,
+            input: input.buffer,
+            output: output.buffer,
+            input_stream: input.stream,
+            output_stream: output.stream,
+        };
+        match input_error.or(output_error) {
+            Some(error) => Err((resources, error)),
+            None => Ok(resources),
+        }
+    }
+}
+
+/// The resources of a DMA transfer of the CRYP peripheral
+pub struct TransferResources<I, O> {
+    pub cryp: Cryp,
+    pub input: Pin<I>,
+    pub output: Pin<O>,
+    pub input_stream: <DmaIn as dma::Target>::Stream,
+    pub output_stream: <DmaOut as dma::Target>::Stream,
+}
+
+impl<I, O> core::fmt::Debug for TransferResources<I, O> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "TransferResources {{ .. }}")
+    }
+}
+
+/// Returns the words to load in the key registers, where the key is aligned
+/// on the right, and the value of KEYSIZE
+fn key_words(key: &[u8]) -> Result<([u32; 8], u8), Error> {
+    let keysize = match key.len() {
+        16 => 0b00,
+        24 => 0b01,
+        32 => 0b10,
+        _ => return Err(Error::KeyLength),
+    };
+
+    let mut words = [0; 8];
+    let first = 8 - key.len() / 4;
+    for (word, bytes) in words[first..].iter_mut().zip(key.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    Ok((words, keysize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_words() {
+        let key: [u8; 16] = [
+            0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+            0x4f, 0x3c,
+        ];
+        assert_eq!(
+            key_words(&key),
+            Ok((
+                [0, 0, 0, 0, 0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c],
+                0b00
+            ))
+        );
+
+        let key = [0xff; 24];
+        let (words, keysize) = key_words(&key).unwrap();
+        assert_eq!(keysize, 0b01);
+        assert_eq!(words[..2], [0, 0]);
+        assert!(words[2..].iter().all(|&word| word == 0xffff_ffff));
+
+        assert_eq!(key_words(&[0; 32]).map(|(_, keysize)| keysize), Ok(0b10));
+        assert_eq!(key_words(&[0; 20]), Err(Error::KeyLength));
+    }
+}
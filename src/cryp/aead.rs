@@ -0,0 +1,413 @@
+//! Authenticated encryption, in GCM, GMAC and CCM modes
+//!
+//! The additional data, authenticated but not encrypted, are given when
+//! starting. The payload is then processed as in the other modes, with
+//! [`Cryp::process`] or [`Cryp::process_dma`], and the tag is generated or
+//! checked at the end. GMAC is GCM without payload.
+//!
+//! ```ignore
+//! cryp.start_gcm(&key, &iv, &header, Direction::Encrypt)?;
+//! cryp.process(&packet, &mut encrypted)?;
+//! cryp.finish(&mut tag)?;
+//!
+//! cryp.start_gcm(&key, &iv, &header, Direction::Decrypt)?;
+//! cryp.process(&encrypted, &mut packet)?;
+//! cryp.verify(&tag)?;
+//! ```
+//!
+//! In GCM encryption and CCM decryption, the unit would authenticate the
+//! padding of a partial last block of the output. This block is processed
+//! in CTR mode instead, and the chained mode is resumed in the other
+//! direction to authenticate it, padded with zeros.
+
+use super::{Cryp, Direction, Error, BLOCK_SIZE};
+
+/// Phases of GCM and CCM, values of GCM_CCMPH
+#[derive(Clone, Copy)]
+pub(super) enum Phase {
+    Init = 0b00,
+    Header = 0b01,
+    Payload = 0b10,
+    Final = 0b11,
+}
+
+/// Values of ALGOMODE[2:0], with ALGOMODE[3] set for GCM and CCM
+const ALGOMODE_GCM: u8 = 0b000;
+const ALGOMODE_CCM: u8 = 0b001;
+const ALGOMODE_CTR: u8 = 0b110;
+
+/// Authenticated encryption in progress
+pub(super) struct Aead {
+    kind: Kind,
+    direction: Direction,
+    aad_len: usize,
+    /// Length of the payload processed
+    pub(super) payload_len: usize,
+    /// Whether the payload phase started
+    pub(super) payload: bool,
+    /// Whether a partial block ended the payload
+    pub(super) ended: bool,
+}
+
+enum Kind {
+    Gcm,
+    Ccm {
+        /// Counter block encrypting the tag
+        ctr0: [u8; BLOCK_SIZE],
+        payload_len: usize,
+        tag_len: usize,
+    },
+}
+
+impl Aead {
+    fn new(kind: Kind, direction: Direction, aad_len: usize) -> Self {
+        Self {
+            kind,
+            direction,
+            aad_len,
+            payload_len: 0,
+            payload: false,
+            ended: false,
+        }
+    }
+}
+
+impl Cryp {
+    /// Starts GCM with the 96-bit `iv`, and authenticates `aad`, the
+    /// additional data
+    ///
+    /// For GMAC, all the data are given as `aad`, and no payload is
+    /// processed.
+    pub fn start_gcm(
+        &mut self,
+        key: &[u8],
+        iv: &[u8; 12],
+        aad: &[u8],
+        direction: Direction,
+    ) -> Result<(), Error> {
+        // The payload starts at the counter 2, the first one encrypts the tag
+        let mut counter = [0; BLOCK_SIZE];
+        counter[..12].copy_from_slice(iv);
+        counter[15] = 2;
+        self.init(key, ALGOMODE_GCM, &counter, direction)?;
+        // The unit computes the hash subkey
+        while self.cryp.cr.read().crypen().bit_is_set() {}
+
+        self.authenticate(&[], aad);
+        self.aead = Some(Aead::new(Kind::Gcm, direction, aad.len()));
+        Ok(())
+    }
+
+    /// Starts CCM with `nonce`, of 7 to 13 bytes, for a payload of
+    /// `payload_len` bytes and a tag of `tag_len` bytes, and authenticates
+    /// `aad`, the additional data
+    pub fn start_ccm(
+        &mut self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        payload_len: usize,
+        tag_len: usize,
+        direction: Direction,
+    ) -> Result<(), Error> {
+        let b0 = ccm_b0(nonce, aad.len(), payload_len, tag_len)?;
+        self.init(key, ALGOMODE_CCM, &ccm_counter(&b0, 1), direction)?;
+        self.write_block(&b0);
+        while self.cryp.cr.read().crypen().bit_is_set() {}
+
+        let (prefix, prefix_len) = ccm_aad_prefix(aad.len());
+        self.authenticate(&prefix[..prefix_len], aad);
+        let kind = Kind::Ccm {
+            ctr0: ccm_counter(&b0, 0),
+            payload_len,
+            tag_len,
+        };
+        self.aead = Some(Aead::new(kind, direction, aad.len()));
+        Ok(())
+    }
+
+    /// Generates the tag of the message in `tag`, and ends it
+    ///
+    /// # Panics
+    ///
+    /// Panics if no GCM or CCM message was started.
+    pub fn finish(&mut self, tag: &mut [u8]) -> Result<(), Error> {
+        let computed = self.final_phase(tag.len())?;
+        tag.copy_from_slice(&computed[..tag.len()]);
+        Ok(())
+    }
+
+    /// Checks the tag of the message, and ends it
+    ///
+    /// The decrypted payload must be discarded if the tag doesn't match.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no GCM or CCM message was started.
+    pub fn verify(&mut self, tag: &[u8]) -> Result<(), Error> {
+        let computed = self.final_phase(tag.len())?;
+        // All the bytes are compared, for a time independent of the tag
+        let difference = tag
+            .iter()
+            .zip(&computed)
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        if difference == 0 {
+            Ok(())
+        } else {
+            Err(Error::Authentication)
+        }
+    }
+
+    /// Loads `key` and `counter`, and starts the initialization phase
+    fn init(
+        &mut self,
+        key: &[u8],
+        algomode: u8,
+        counter: &[u8; BLOCK_SIZE],
+        direction: Direction,
+    ) -> Result<(), Error> {
+        self.load_key(key)?;
+        self.cryp.cr.modify(|_, w| unsafe {
+            w.algomode3()
+                .set_bit()
+                .algomode0()
+                .bits(algomode)
+                .algodir()
+                .bit(direction == Direction::Decrypt)
+                .gcm_ccmph()
+                .bits(Phase::Init as u8)
+        });
+        self.load_iv(counter);
+
+        self.cryp.cr.modify(|_, w| w.fflush().set_bit());
+        self.cryp.cr.modify(|_, w| w.crypen().set_bit());
+        Ok(())
+    }
+
+    /// Runs the header phase on `prefix` then `aad`, padded with zeros
+    fn authenticate(&mut self, prefix: &[u8], aad: &[u8]) {
+        if aad.is_empty() {
+            return;
+        }
+
+        self.cryp
+            .cr
+            .modify(|_, w| unsafe { w.gcm_ccmph().bits(Phase::Header as u8).crypen().set_bit() });
+        let mut block = [0; BLOCK_SIZE];
+        let mut len = 0;
+        for &byte in prefix.iter().chain(aad) {
+            block[len] = byte;
+            len += 1;
+            if len == BLOCK_SIZE {
+                self.write_block(&block);
+                block = [0; BLOCK_SIZE];
+                len = 0;
+            }
+        }
+        if len > 0 {
+            self.write_block(&block);
+        }
+        while self.cryp.sr.read().busy().bit_is_set() {}
+    }
+
+    /// Runs the final phase, and returns the tag
+    fn final_phase(&mut self, tag_len: usize) -> Result<[u8; BLOCK_SIZE], Error> {
+        let aead = self.aead.as_ref().expect("No GCM or CCM message started");
+        let block = match aead.kind {
+            Kind::Gcm => {
+                if !(12..=BLOCK_SIZE).contains(&tag_len) {
+                    return Err(Error::TagLength);
+                }
+                // Lengths of the additional data and of the payload, in bits
+                let mut block = [0; BLOCK_SIZE];
+                block[..8].copy_from_slice(&(aead.aad_len as u64 * 8).to_be_bytes());
+                block[8..].copy_from_slice(&(aead.payload_len as u64 * 8).to_be_bytes());
+                block
+            }
+            Kind::Ccm {
+                ctr0,
+                payload_len,
+                tag_len: expected,
+            } => {
+                if tag_len != expected {
+                    return Err(Error::TagLength);
+                }
+                if aead.payload_len != payload_len {
+                    return Err(Error::DataLength);
+                }
+                ctr0
+            }
+        };
+        self.aead = None;
+
+        while self.cryp.sr.read().busy().bit_is_set() {}
+        self.cryp.cr.modify(|_, w| w.crypen().clear_bit());
+        self.cryp.cr.modify(|_, w| unsafe {
+            w.gcm_ccmph()
+                .bits(Phase::Final as u8)
+                .algodir()
+                .clear_bit()
+                .crypen()
+                .set_bit()
+        });
+        let mut tag = block;
+        self.transform_block(&mut tag);
+        self.cryp.cr.modify(|_, w| w.crypen().clear_bit());
+        Ok(tag)
+    }
+
+    /// Processes the last block of the payload, partial, of `len` bytes
+    /// padded with zeros
+    pub(super) fn process_last_block(&mut self, block: &mut [u8; BLOCK_SIZE], len: usize) {
+        let output_authenticated = matches!(
+            self.aead.as_ref().map(|aead| (&aead.kind, aead.direction)),
+            Some((Kind::Gcm, Direction::Encrypt)) | Some((Kind::Ccm { .. }, Direction::Decrypt))
+        );
+        if !output_authenticated {
+            self.transform_block(block);
+            return;
+        }
+
+        // Suspends the chained mode, saving its context
+        while self.cryp.sr.read().busy().bit_is_set() {}
+        self.cryp.cr.modify(|_, w| w.crypen().clear_bit());
+        let cr = self.cryp.cr.read().bits();
+        let mut iv = [0; 4];
+        for (words, init) in iv.chunks_exact_mut(2).zip(&self.cryp.init) {
+            words[0] = init.ivlr.read().bits();
+            words[1] = init.ivrr.read().bits();
+        }
+        let mut ccm_context = [0; 8];
+        for (word, register) in ccm_context.iter_mut().zip(&self.cryp.csgcmccmr) {
+            *word = register.read().bits();
+        }
+        let mut gcm_context = [0; 8];
+        for (word, register) in gcm_context.iter_mut().zip(&self.cryp.csgcmr) {
+            *word = register.read().bits();
+        }
+
+        // Gets the output with the key stream of the same counter
+        self.cryp.cr.modify(|_, w| unsafe {
+            w.algomode3()
+                .clear_bit()
+                .algomode0()
+                .bits(ALGOMODE_CTR)
+                .crypen()
+                .set_bit()
+        });
+        self.transform_block(block);
+        block[len..].fill(0);
+        while self.cryp.sr.read().busy().bit_is_set() {}
+        self.cryp.cr.modify(|_, w| w.crypen().clear_bit());
+
+        // Resumes the chained mode in the other direction, which
+        // authenticates the input, on the padded output
+        for (word, register) in ccm_context.iter().zip(&self.cryp.csgcmccmr) {
+            register.write(|w| unsafe { w.bits(*word) });
+        }
+        for (word, register) in gcm_context.iter().zip(&self.cryp.csgcmr) {
+            register.write(|w| unsafe { w.bits(*word) });
+        }
+        for (words, init) in iv.chunks_exact(2).zip(&self.cryp.init) {
+            init.ivlr.write(|w| unsafe { w.bits(words[0]) });
+            init.ivrr.write(|w| unsafe { w.bits(words[1]) });
+        }
+        self.cryp.cr.write(|w| unsafe { w.bits(cr) });
+        self.cryp
+            .cr
+            .modify(|r, w| w.algodir().bit(!r.algodir().bit()).crypen().set_bit());
+        let mut discarded = *block;
+        self.transform_block(&mut discarded);
+
+        while self.cryp.sr.read().busy().bit_is_set() {}
+        self.cryp.cr.write(|w| unsafe { w.bits(cr) });
+    }
+}
+
+/// Returns B0, the first block of CCM, with the flags, the nonce and the
+/// length of the payload
+fn ccm_b0(
+    nonce: &[u8],
+    aad_len: usize,
+    payload_len: usize,
+    tag_len: usize,
+) -> Result<[u8; BLOCK_SIZE], Error> {
+    if !(7..=13).contains(&nonce.len()) {
+        return Err(Error::NonceLength);
+    }
+    if !(4..=16).contains(&tag_len) || tag_len % 2 != 0 {
+        return Err(Error::TagLength);
+    }
+    // Size of the length of the payload, and of the counter
+    let q = 15 - nonce.len();
+    if q < 8 && (payload_len as u64) >> (8 * q) != 0 {
+        return Err(Error::DataLength);
+    }
+
+    let mut b0 = [0; BLOCK_SIZE];
+    b0[0] = u8::from(aad_len > 0) << 6 | (((tag_len - 2) / 2) as u8) << 3 | (q - 1) as u8;
+    b0[1..=nonce.len()].copy_from_slice(nonce);
+    b0[BLOCK_SIZE - q..].copy_from_slice(&(payload_len as u64).to_be_bytes()[8 - q..]);
+    Ok(b0)
+}
+
+/// Returns the counter block `i` of CCM, from B0
+fn ccm_counter(b0: &[u8; BLOCK_SIZE], i: u8) -> [u8; BLOCK_SIZE] {
+    let q = usize::from(b0[0] & 0b111) + 1;
+    let mut counter = *b0;
+    counter[0] &= 0b111;
+    counter[BLOCK_SIZE - q..].fill(0);
+    counter[BLOCK_SIZE - 1] = i;
+    counter
+}
+
+/// Returns the encoding of the length of the additional data, which
+/// precedes them in CCM, and its size
+fn ccm_aad_prefix(aad_len: usize) -> ([u8; 6], usize) {
+    if aad_len == 0 {
+        ([0; 6], 0)
+    } else if aad_len < 0xff00 {
+        let [a, b] = (aad_len as u16).to_be_bytes();
+        ([a, b, 0, 0, 0, 0], 2)
+    } else {
+        let [a, b, c, d] = (aad_len as u32).to_be_bytes();
+        ([0xff, 0xfe, a, b, c, d], 6)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ccm_blocks() {
+        // Packet vector #1 of RFC 3610
+        let nonce = [
+            0x00, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5,
+        ];
+        let b0 = ccm_b0(&nonce, 8, 23, 8).unwrap();
+        let mut expected = [0x59, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x17];
+        expected[1..14].copy_from_slice(&nonce);
+        assert_eq!(b0, expected);
+
+        let ctr1 = ccm_counter(&b0, 1);
+        assert_eq!(ctr1[0], 0x01);
+        assert_eq!(ctr1[1..14], nonce);
+        assert_eq!(ctr1[14..], [0x00, 0x01]);
+        assert_eq!(ccm_counter(&b0, 0)[14..], [0x00, 0x00]);
+
+        assert_eq!(ccm_b0(&nonce[..6], 0, 0, 8), Err(Error::NonceLength));
+        assert_eq!(ccm_b0(&nonce, 0, 0, 7), Err(Error::TagLength));
+        assert_eq!(ccm_b0(&nonce, 0, 0x1_0000, 8), Err(Error::DataLength));
+    }
+
+    #[test]
+    fn test_ccm_aad_prefix() {
+        assert_eq!(ccm_aad_prefix(0).1, 0);
+        assert_eq!(ccm_aad_prefix(8), ([0x00, 0x08, 0, 0, 0, 0], 2));
+        assert_eq!(
+            ccm_aad_prefix(0x1_0000),
+            ([0xff, 0xfe, 0x00, 0x01, 0x00, 0x00], 6)
+        );
+    }
+}
@@ -1,12 +1,17 @@
 use crate::pac::DAC;
 use crate::{
+    dma,
     gpio::{
         gpioa::{PA4, PA5},
         Analog,
     },
     rcc::{Enable, Reset},
+    state,
 };
 
+use as_slice::AsSlice;
+use core::{ops::Deref, pin::Pin};
+
 /// DAC Errors
 #[derive(Debug)]
 pub enum Error {
@@ -19,6 +24,27 @@ use core::mem;
 pub struct C1;
 pub struct C2;
 
+/// Event starting a DAC conversion (TSEL)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// TIM6 TRGO
+    Tim6 = 0b000,
+    /// TIM8 TRGO
+    Tim8 = 0b001,
+    /// TIM7 TRGO
+    Tim7 = 0b010,
+    /// TIM5 TRGO
+    Tim5 = 0b011,
+    /// TIM2 TRGO
+    Tim2 = 0b100,
+    /// TIM4 TRGO
+    Tim4 = 0b101,
+    /// EXTI line 9
+    Exti9 = 0b110,
+    /// Software trigger, see `trigger`
+    Software = 0b111,
+}
+
 pub trait DacOut<V> {
     fn set_value(&mut self, val: V);
     fn get_value(&mut self) -> V;
@@ -60,7 +86,7 @@ where
 }
 
 macro_rules! dac {
-    ($CX:ident, $en:ident, $cen:ident, $cal_flag:ident, $trim:ident, $mode:ident, $dhrx:ident, $dac_dor:ident, $daccxdhr:ident) => {
+    ($CX:ident, $en:ident, $cen:ident, $cal_flag:ident, $trim:ident, $mode:ident, $dhrx:ident, $dac_dor:ident, $daccxdhr:ident, $ten:ident, $tsel:ident, $dmaen:ident, $swtrig:ident, $boff:ident) => {
         impl DacPin for $CX {
             fn enable(&mut self) {
                 let dac = unsafe { &(*DAC::ptr()) };
@@ -79,9 +105,117 @@ macro_rules! dac {
                 dac.$dac_dor.read().bits() as u16
             }
         }
+
+        impl $CX {
+            /// Enables or disables the output buffer (BOFF), which is enabled
+            /// after reset.
+            ///
+            /// The buffer lowers the output impedance so that the output can
+            /// drive external loads without an external op-amp, at the cost of
+            /// an output range limited to about 0.2 V from the supply rails and
+            /// a higher offset. Without the buffer the output is rail to rail
+            /// and more accurate, but its impedance is about 15 kOhm so only
+            /// high impedance loads can be driven.
+            ///
+            /// Should be called before the channel is enabled.
+            pub fn set_output_buffer(&mut self, enabled: bool) {
+                let dac = unsafe { &(*DAC::ptr()) };
+                dac.cr.modify(|_, w| w.$boff().bit(!enabled));
+            }
+
+            /// Only updates the output when `trigger` occurs. A value written
+            /// with `set_value` is transferred to the output 3 APB1 clock
+            /// cycles after the trigger instead of 1 cycle after the write.
+            pub fn set_trigger(&mut self, trigger: Trigger) {
+                let dac = unsafe { &(*DAC::ptr()) };
+                // TSEL2 is not marked unsafe in all SVDs
+                #[allow(unused_unsafe)]
+                dac.cr
+                    .modify(|_, w| unsafe { w.$tsel().bits(trigger as u8).$ten().set_bit() });
+            }
+
+            /// Updates the output as soon as a value is written
+            pub fn disable_trigger(&mut self) {
+                let dac = unsafe { &(*DAC::ptr()) };
+                dac.cr.modify(|_, w| w.$ten().clear_bit());
+            }
+
+            /// Generates a software trigger, when `Trigger::Software` is
+            /// selected
+            pub fn trigger(&mut self) {
+                let dac = unsafe { &(*DAC::ptr()) };
+                dac.swtrigr.write(|w| w.$swtrig().set_bit());
+            }
+
+            /// Plays `data` (12-bit right aligned samples) using DMA, one
+            /// sample per `trigger` event.
+            ///
+            /// The sample rate is set by the TRGO output of the trigger timer,
+            /// which must be configured by the user. Call
+            /// [`dma::Transfer::enable_circular`] on the returned transfer to
+            /// repeat the waveform continuously.
+            ///
+            /// DMA supports transfers up to 65535 samples. If `data` is
+            /// longer, this method will panic.
+            pub fn write_all<B>(
+                self,
+                data: Pin<B>,
+                trigger: Trigger,
+                dma: &dma::Handle<<Self as dma::Target>::Instance, state::Enabled>,
+                stream: <Self as dma::Target>::Stream,
+            ) -> dma::Transfer<Self, B, dma::Ready>
+            where
+                B: Deref + 'static,
+                B::Target: AsSlice<Element = u16>,
+            {
+                let mut channel = self;
+                channel.set_trigger(trigger);
+
+                // NOTE(unsafe) atomic read-modify-write of this channel's bits
+                // only
+                let dac = unsafe { &(*DAC::ptr()) };
+                dac.cr.modify(|_, w| w.$dmaen().set_bit());
+
+                // Safe, because the trait bounds on this method guarantee that
+                // `data` can be read from safely.
+                unsafe {
+                    dma::Transfer::new(
+                        dma,
+                        stream,
+                        data,
+                        channel,
+                        &dac.$dhrx as *const _ as _,
+                        dma::Direction::MemoryToPeripheral,
+                    )
+                }
+            }
+        }
     };
 }
 
+impl DacPin for (C1, C2) {
+    fn enable(&mut self) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.cr.modify(|_, w| w.en1().set_bit().en2().set_bit());
+    }
+}
+
+/// Dual channel mode: both channels are written at once through the dual
+/// holding register (`DHR12RD`), as `(channel 1, channel 2)` values. When both
+/// channels use the same trigger, the outputs are updated simultaneously.
+impl DacOut<(u16, u16)> for (C1, C2) {
+    fn set_value(&mut self, val: (u16, u16)) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.dhr12rd
+            .write(|w| unsafe { w.bits((val.0 as u32 & 0xfff) | ((val.1 as u32 & 0xfff) << 16)) });
+    }
+
+    fn get_value(&mut self) -> (u16, u16) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        (dac.dor1.read().bits() as u16, dac.dor2.read().bits() as u16)
+    }
+}
+
 pub trait DacExt {
     fn constrain<PINS>(self, pins: PINS) -> PINS::Output
     where
@@ -97,5 +231,11 @@ impl DacExt for DAC {
     }
 }
 
-dac!(C1, en1, cen1, cal_flag1, otrim1, mode1, dhr12r1, dor1, dacc1dhr);
-dac!(C2, en2, cen2, cal_flag2, otrim2, mode2, dhr12r2, dor2, dacc2dhr);
+dac!(
+    C1, en1, cen1, cal_flag1, otrim1, mode1, dhr12r1, dor1, dacc1dhr, ten1, tsel1, dmaen1, swtrig1,
+    boff1
+);
+dac!(
+    C2, en2, cen2, cal_flag2, otrim2, mode2, dhr12r2, dor2, dacc2dhr, ten2, tsel2, dmaen2, swtrig2,
+    boff2
+);
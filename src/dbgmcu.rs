@@ -0,0 +1,130 @@
+//! Debug support (DBGMCU)
+//!
+//! Keeps the debugger connected in the low-power modes, and stops the timers
+//! and watchdogs while the core is halted by the debugger.
+//!
+//! The DBGMCU registers are only reset by a power-on reset.
+//!
+//! See the "Debug support" chapter of RM0410.
+
+use crate::pac::DBGMCU;
+
+/// Peripheral stopped while the core is halted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Freeze {
+    Tim1,
+    Tim2,
+    Tim3,
+    Tim4,
+    Tim5,
+    Tim6,
+    Tim7,
+    Tim8,
+    Tim9,
+    Tim10,
+    Tim11,
+    Tim12,
+    Tim13,
+    Tim14,
+    Lptim1,
+    Rtc,
+    Wwdg,
+    Iwdg,
+    /// SMBus timeout of I2C1
+    I2c1,
+    /// SMBus timeout of I2C2
+    I2c2,
+    /// SMBus timeout of I2C3
+    I2c3,
+    /// SMBus timeout of I2C4
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    I2c4,
+    Can1,
+    Can2,
+    #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+    Can3,
+}
+
+impl Freeze {
+    /// Returns `true` for the APB2 peripherals, and the bit of the peripheral
+    /// in the APB1_FZ or APB2_FZ register.
+    fn bit(self) -> (bool, u8) {
+        match self {
+            Freeze::Tim1 => (true, 0),
+            Freeze::Tim8 => (true, 1),
+            Freeze::Tim9 => (true, 16),
+            Freeze::Tim10 => (true, 17),
+            Freeze::Tim11 => (true, 18),
+            Freeze::Tim2 => (false, 0),
+            Freeze::Tim3 => (false, 1),
+            Freeze::Tim4 => (false, 2),
+            Freeze::Tim5 => (false, 3),
+            Freeze::Tim6 => (false, 4),
+            Freeze::Tim7 => (false, 5),
+            Freeze::Tim12 => (false, 6),
+            Freeze::Tim13 => (false, 7),
+            Freeze::Tim14 => (false, 8),
+            Freeze::Lptim1 => (false, 9),
+            Freeze::Rtc => (false, 10),
+            Freeze::Wwdg => (false, 11),
+            Freeze::Iwdg => (false, 12),
+            #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+            Freeze::Can3 => (false, 13),
+            Freeze::I2c1 => (false, 21),
+            Freeze::I2c2 => (false, 22),
+            Freeze::I2c3 => (false, 23),
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            Freeze::I2c4 => (false, 24),
+            Freeze::Can1 => (false, 25),
+            Freeze::Can2 => (false, 26),
+        }
+    }
+}
+
+/// Debug support
+pub struct DebugMcu {
+    dbgmcu: DBGMCU,
+}
+
+impl DebugMcu {
+    /// Wraps the DBGMCU peripheral.
+    pub fn new(dbgmcu: DBGMCU) -> Self {
+        Self { dbgmcu }
+    }
+
+    /// Keeps the clocks needed by the debugger running in the Sleep, Stop and
+    /// Standby modes, which increases the consumption.
+    pub fn set_low_power_debug(&mut self, enable: bool) {
+        self.dbgmcu.cr.modify(|_, w| {
+            w.dbg_sleep()
+                .bit(enable)
+                .dbg_stop()
+                .bit(enable)
+                .dbg_standby()
+                .bit(enable)
+        });
+    }
+
+    /// Stops `peripheral` while the core is halted by the debugger, if
+    /// `freeze`.
+    pub fn set_freeze(&mut self, peripheral: Freeze, freeze: bool) {
+        let (apb2, bit) = peripheral.bit();
+        let mask = 1 << bit;
+        let update = |bits: u32| if freeze { bits | mask } else { bits & !mask };
+
+        if apb2 {
+            self.dbgmcu
+                .apb2_fz
+                .modify(|r, w| unsafe { w.bits(update(r.bits())) });
+        } else {
+            self.dbgmcu
+                .apb1_fz
+                .modify(|r, w| unsafe { w.bits(update(r.bits())) });
+        }
+    }
+
+    /// Releases the DBGMCU peripheral.
+    pub fn free(self) -> DBGMCU {
+        self.dbgmcu
+    }
+}
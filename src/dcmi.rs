@@ -0,0 +1,624 @@
+//! Digital camera interface (DCMI)
+//!
+//! The DCMI captures the pixels of a parallel camera sensor, 8, 10, 12 or 14
+//! bits per pixel clock, depending on the data [`Pins`] given. The frames
+//! and lines are delimited either by the HSYNC and VSYNC signals, or by the
+//! codes embedded in the data stream (ITU-R BT.656), with no HSYNC and VSYNC
+//! pins.
+//!
+//! The data register packs the pixels in 32-bit words, read by the DMA:
+//! [`Dcmi::capture`] captures one frame in a buffer, and
+//! [`Dcmi::capture_continuous`] captures the frames in two buffers in turn,
+//! for live video. The capture rate can be reduced to every other frame, or
+//! one frame in four.
+//!
+//! Only a window of the frames is captured with [`Dcmi::set_crop`]. With
+//! [`Config::jpeg`], the DCMI captures the compressed stream of the sensors
+//! which output JPEG images, of variable size, ended by
+//! [`Dcmi::finish_jpeg`].
+//!
+//! The sensor itself is configured through its own control interface,
+//! usually I2C (SCCB), and must output its pixel clock before the capture.
+//!
+//! ```ignore
+//! // OV9655 on the STM32F746G-DISCO
+//! let pins = (pixclk, hsync, vsync, (d0, d1, d2, d3, d4, d5, d6, d7));
+//! let mut dcmi = Dcmi::new(dp.DCMI, pins, Config::default(), &mut rcc.ahb2);
+//! let transfer = dcmi.capture(frame, &dma.handle, dma.streams.stream1);
+//! let resources = transfer.wait(&dma.handle).unwrap();
+//! ```
+//!
+//! See chapter 18 in the STM32F76xxx Reference Manual (RM0410).
+
+use core::ops::DerefMut;
+use core::pin::Pin;
+
+use as_slice::{AsMutSlice, AsSlice};
+
+use crate::gpio::{
+    gpioa::{PA10, PA4, PA6, PA9},
+    gpiob::{PB5, PB6, PB7, PB8, PB9},
+    gpioc::{PC10, PC11, PC12, PC6, PC7, PC8, PC9},
+    gpiod::{PD2, PD3, PD6},
+    gpioe::{PE0, PE1, PE4, PE5, PE6},
+    gpiof::{PF10, PF11},
+    gpiog::{PG10, PG11, PG15, PG6, PG7, PG9},
+    gpioh::{PH10, PH11, PH12, PH14, PH15, PH8, PH9},
+    gpioi::{PI0, PI1, PI2, PI3, PI4, PI5, PI6, PI7},
+    Alternate,
+};
+use crate::pac::DCMI;
+use crate::rcc::{Enable, Reset, AHB2};
+use crate::{dma, state};
+
+mod continuous;
+
+pub use continuous::ContinuousCapture;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// DCMI pixel clock
+pub trait PixClk: sealed::Sealed {}
+/// DCMI horizontal synchronization
+pub trait Hsync: sealed::Sealed {}
+/// DCMI vertical synchronization
+pub trait Vsync: sealed::Sealed {}
+/// DCMI data 0
+pub trait D0: sealed::Sealed {}
+pub trait D1: sealed::Sealed {}
+pub trait D2: sealed::Sealed {}
+pub trait D3: sealed::Sealed {}
+pub trait D4: sealed::Sealed {}
+pub trait D5: sealed::Sealed {}
+pub trait D6: sealed::Sealed {}
+pub trait D7: sealed::Sealed {}
+pub trait D8: sealed::Sealed {}
+pub trait D9: sealed::Sealed {}
+pub trait D10: sealed::Sealed {}
+pub trait D11: sealed::Sealed {}
+pub trait D12: sealed::Sealed {}
+pub trait D13: sealed::Sealed {}
+
+macro_rules! pins {
+    ($($PIN:ident: [$($Signal:ident),+],)+) => {
+        $(
+            impl sealed::Sealed for $PIN<Alternate<13>> {}
+            $(
+                impl $Signal for $PIN<Alternate<13>> {}
+            )+
+        )+
+    };
+}
+
+pins! {
+    PA4: [Hsync],
+    PA6: [PixClk],
+    PA9: [D0],
+    PA10: [D1],
+    PB5: [D10],
+    PB6: [D5],
+    PB7: [Vsync],
+    PB8: [D6],
+    PB9: [D7],
+    PC6: [D0],
+    PC7: [D1],
+    PC8: [D2],
+    PC9: [D3],
+    PC10: [D8],
+    PC11: [D4],
+    PC12: [D9],
+    PD2: [D11],
+    PD3: [D5],
+    PD6: [D10],
+    PE0: [D2],
+    PE1: [D3],
+    PE4: [D4],
+    PE5: [D6],
+    PE6: [D7],
+    PF10: [D11],
+    PF11: [D12],
+    PG6: [D12],
+    PG7: [D13],
+    PG9: [Vsync],
+    PG10: [D2],
+    PG11: [D3],
+    PG15: [D13],
+    PH8: [Hsync],
+    PH9: [D0],
+    PH10: [D1],
+    PH11: [D2],
+    PH12: [D3],
+    PH14: [D4],
+    PH15: [D11],
+    PI0: [D13],
+    PI1: [D8],
+    PI2: [D9],
+    PI3: [D10],
+    PI4: [D5],
+    PI5: [Vsync],
+    PI6: [D6],
+    PI7: [D7],
+}
+
+/// Number of data bits per pixel clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataWidth {
+    Bits8 = 0b00,
+    Bits10 = 0b01,
+    Bits12 = 0b10,
+    Bits14 = 0b11,
+}
+
+/// Data pins: D0 to D7, D9, D11 or D13
+pub trait DataPins: sealed::Sealed {
+    #[doc(hidden)]
+    const WIDTH: DataWidth;
+}
+
+macro_rules! data_pins {
+    ($($width:ident: ($($DAT:ident: $Signal:ident),+),)+) => {
+        $(
+            impl<$($DAT: $Signal),+> sealed::Sealed for ($($DAT,)+) {}
+            impl<$($DAT: $Signal),+> DataPins for ($($DAT,)+) {
+                const WIDTH: DataWidth = DataWidth::$width;
+            }
+        )+
+    };
+}
+
+data_pins! {
+    Bits8: (
+        DAT0: D0, DAT1: D1, DAT2: D2, DAT3: D3, DAT4: D4, DAT5: D5, DAT6: D6, DAT7: D7
+    ),
+    Bits10: (
+        DAT0: D0, DAT1: D1, DAT2: D2, DAT3: D3, DAT4: D4, DAT5: D5, DAT6: D6, DAT7: D7,
+        DAT8: D8, DAT9: D9
+    ),
+    Bits12: (
+        DAT0: D0, DAT1: D1, DAT2: D2, DAT3: D3, DAT4: D4, DAT5: D5, DAT6: D6, DAT7: D7,
+        DAT8: D8, DAT9: D9, DAT10: D10, DAT11: D11
+    ),
+    Bits14: (
+        DAT0: D0, DAT1: D1, DAT2: D2, DAT3: D3, DAT4: D4, DAT5: D5, DAT6: D6, DAT7: D7,
+        DAT8: D8, DAT9: D9, DAT10: D10, DAT11: D11, DAT12: D12, DAT13: D13
+    ),
+}
+
+/// Pins of the camera bus: the pixel clock, HSYNC and VSYNC, and the data
+/// pins, or the pixel clock and the data pins with the embedded
+/// synchronization
+pub trait Pins: sealed::Sealed {
+    #[doc(hidden)]
+    const WIDTH: DataWidth;
+    #[doc(hidden)]
+    const HARDWARE_SYNC: bool;
+}
+
+impl<PIXCLK: PixClk, DATA: DataPins> sealed::Sealed for (PIXCLK, DATA) {}
+impl<PIXCLK: PixClk, DATA: DataPins> Pins for (PIXCLK, DATA) {
+    const WIDTH: DataWidth = DATA::WIDTH;
+    const HARDWARE_SYNC: bool = false;
+}
+
+impl<PIXCLK, HSYNC, VSYNC, DATA> sealed::Sealed for (PIXCLK, HSYNC, VSYNC, DATA)
+where
+    PIXCLK: PixClk,
+    HSYNC: Hsync,
+    VSYNC: Vsync,
+    DATA: DataPins,
+{
+}
+
+impl<PIXCLK, HSYNC, VSYNC, DATA> Pins for (PIXCLK, HSYNC, VSYNC, DATA)
+where
+    PIXCLK: PixClk,
+    HSYNC: Hsync,
+    VSYNC: Vsync,
+    DATA: DataPins,
+{
+    const WIDTH: DataWidth = DATA::WIDTH;
+    const HARDWARE_SYNC: bool = true;
+}
+
+/// Edge of the pixel clock on which the data is captured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Falling,
+    Rising,
+}
+
+/// Level of a synchronization signal during the blanking, when the data is
+/// not valid
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveLow,
+    ActiveHigh,
+}
+
+/// Codes delimiting the frames and lines, embedded in the data stream
+///
+/// A code is recognized in the 4th byte of the `0xff, 0x00, 0x00, code`
+/// sequence, comparing the bits set in its mask only.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmbeddedCodes {
+    pub frame_start: u8,
+    pub line_start: u8,
+    pub line_end: u8,
+    /// With `0xff`, every code not recognized as another delimiter ends the
+    /// frame
+    pub frame_end: u8,
+    pub frame_start_mask: u8,
+    pub line_start_mask: u8,
+    pub line_end_mask: u8,
+    pub frame_end_mask: u8,
+}
+
+impl EmbeddedCodes {
+    /// Codes compared on all their bits
+    pub fn new(frame_start: u8, line_start: u8, line_end: u8, frame_end: u8) -> Self {
+        EmbeddedCodes {
+            frame_start,
+            line_start,
+            line_end,
+            frame_end,
+            frame_start_mask: 0xff,
+            line_start_mask: 0xff,
+            line_end_mask: 0xff,
+            frame_end_mask: 0xff,
+        }
+    }
+}
+
+/// Synchronization of the frames and lines
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Synchronization {
+    /// By the HSYNC and VSYNC signals
+    Hardware { hsync: Polarity, vsync: Polarity },
+    /// By codes embedded in the data
+    Embedded(EmbeddedCodes),
+}
+
+/// Frames captured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureRate {
+    All = 0b00,
+    /// Every other frame
+    Half = 0b01,
+    /// One frame in four
+    Quarter = 0b10,
+}
+
+/// Configuration of the interface
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub pixel_clock: Edge,
+    /// Must match the [`Pins`]: hardware synchronization with the HSYNC and
+    /// VSYNC pins, embedded without
+    pub synchronization: Synchronization,
+    pub capture_rate: CaptureRate,
+    /// The sensor outputs a JPEG stream, HSYNC marking the valid data and
+    /// VSYNC the image, instead of lines of pixels. Requires the hardware
+    /// synchronization.
+    pub jpeg: bool,
+}
+
+impl Default for Config {
+    /// Data captured on the rising edge, VSYNC active high and HSYNC active
+    /// low, as the OV9655 sensor, every frame captured
+    fn default() -> Self {
+        Config {
+            pixel_clock: Edge::Rising,
+            synchronization: Synchronization::Hardware {
+                hsync: Polarity::ActiveLow,
+                vsync: Polarity::ActiveHigh,
+            },
+            capture_rate: CaptureRate::All,
+            jpeg: false,
+        }
+    }
+}
+
+/// Window of the frames captured
+///
+/// The horizontal position and size are counted in pixel clocks, 2 per
+/// pixel for an RGB565 sensor on 8 data pins, and the vertical ones in lines.
+/// The window must contain a whole number of 32-bit words.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crop {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// DCMI error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The data was not read by the DMA before the next one, and the frame
+    /// is corrupted
+    Overrun,
+    /// The embedded synchronization codes came in an unexpected order
+    SyncError,
+    /// The DMA stream reported a transfer error
+    DmaTransfer,
+}
+
+/// DCMI interrupt
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A frame was captured
+    FrameComplete,
+    /// The data was not read by the DMA before the next one
+    Overrun,
+    /// The embedded synchronization codes came in an unexpected order
+    SyncError,
+    /// A vertical synchronization, at the start of a frame
+    Vsync,
+    /// A line was received
+    Line,
+}
+
+/// DCMI driver
+pub struct Dcmi<PINS> {
+    dcmi: DCMI,
+    pins: PINS,
+}
+
+impl<PINS: Pins> Dcmi<PINS> {
+    /// Enables and configures the interface
+    ///
+    /// # Panics
+    ///
+    /// Panics if the synchronization of `config` doesn't match `pins`.
+    pub fn new(dcmi: DCMI, pins: PINS, config: Config, ahb2: &mut AHB2) -> Self {
+        DCMI::enable(ahb2);
+        DCMI::reset(ahb2);
+
+        assert!(!config.jpeg || PINS::HARDWARE_SYNC);
+        let (hspol, vspol) = match config.synchronization {
+            Synchronization::Hardware { hsync, vsync } => {
+                assert!(PINS::HARDWARE_SYNC, "The HSYNC and VSYNC pins are missing");
+                (hsync == Polarity::ActiveHigh, vsync == Polarity::ActiveHigh)
+            }
+            Synchronization::Embedded(codes) => {
+                assert!(!PINS::HARDWARE_SYNC, "The codes are embedded in the data");
+                dcmi.escr.write(|w| unsafe {
+                    w.fsc()
+                        .bits(codes.frame_start)
+                        .lsc()
+                        .bits(codes.line_start)
+                        .lec()
+                        .bits(codes.line_end)
+                        .fec()
+                        .bits(codes.frame_end)
+                });
+                dcmi.esur.write(|w| unsafe {
+                    w.fsu()
+                        .bits(codes.frame_start_mask)
+                        .lsu()
+                        .bits(codes.line_start_mask)
+                        .leu()
+                        .bits(codes.line_end_mask)
+                        .feu()
+                        .bits(codes.frame_end_mask)
+                });
+                (false, false)
+            }
+        };
+
+        dcmi.cr.write(|w| unsafe {
+            w.edm()
+                .bits(PINS::WIDTH as u8)
+                .fcrc()
+                .bits(config.capture_rate as u8)
+                .pckpol()
+                .bit(config.pixel_clock == Edge::Rising)
+                .hspol()
+                .bit(hspol)
+                .vspol()
+                .bit(vspol)
+                .ess()
+                .bit(!PINS::HARDWARE_SYNC)
+                .jpeg()
+                .bit(config.jpeg)
+        });
+        dcmi.cr.modify(|_, w| w.enable().set_bit());
+
+        Dcmi { dcmi, pins }
+    }
+
+    /// Captures the next frame to `buffer` with the DMA
+    ///
+    /// The buffer must hold exactly one frame, which ends the DMA transfer,
+    /// of up to 65535 words. The capture stops after the frame, flagging
+    /// [`Event::FrameComplete`].
+    pub fn capture<B>(
+        &mut self,
+        buffer: Pin<B>,
+        dma: &dma::Handle<<Capture as dma::Target>::Instance, state::Enabled>,
+        stream: <Capture as dma::Target>::Stream,
+    ) -> dma::Transfer<Capture, B, dma::Started>
+    where
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u32>,
+    {
+        self.stop_capture();
+        self.dcmi.cr.modify(|_, w| w.cm().clear_bit());
+
+        // Safe, because the trait bounds on this method guarantee that `buffer`
+        // can be written to safely.
+        let transfer = unsafe {
+            dma::Transfer::new(
+                dma,
+                stream,
+                buffer,
+                Capture(()),
+                self.dr_address(),
+                dma::Direction::PeripheralToMemory,
+            )
+        };
+        let transfer = transfer.start(dma);
+
+        self.dcmi.cr.modify(|_, w| w.capture().set_bit());
+        transfer
+    }
+
+    /// Waits for the end of the JPEG image captured by [`Dcmi::capture`],
+    /// stops the DMA transfer, and returns the resources with the number of
+    /// words of the image
+    ///
+    /// The buffer must be large enough for the largest image. The last word
+    /// is padded with zeros.
+    #[allow(clippy::type_complexity)]
+    pub fn finish_jpeg<B>(
+        &mut self,
+        transfer: dma::Transfer<Capture, B, dma::Started>,
+        handle: &dma::Handle<<Capture as dma::Target>::Instance, state::Enabled>,
+    ) -> Result<
+        (dma::TransferResources<Capture, B>, usize),
+        (dma::TransferResources<Capture, B>, Error),
+    >
+    where
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u32>,
+    {
+        let error = loop {
+            if self.is_pending(Event::FrameComplete) {
+                self.clear_interrupt(Event::FrameComplete);
+                break None;
+            }
+            if self.is_pending(Event::Overrun) {
+                self.clear_interrupt(Event::Overrun);
+                break Some(Error::Overrun);
+            }
+            if transfer.check_errors(handle).is_err() {
+                break Some(Error::DmaTransfer);
+            }
+            if !transfer.is_active(handle) {
+                // The buffer is full
+                break Some(Error::Overrun);
+            }
+        };
+
+        self.stop_capture();
+        transfer.cancel(handle);
+        while transfer.is_active(handle) {}
+        let remaining = usize::from(transfer.remaining(handle));
+        let resources = match transfer.wait(handle) {
+            Ok(resources) | Err((resources, _)) => resources,
+        };
+        match error {
+            None => {
+                let len = resources.buffer.as_slice().len() - remaining;
+                Ok((resources, len))
+            }
+            Some(error) => Err((resources, error)),
+        }
+    }
+
+    /// Captures only the `crop` window of the frames, or the whole frames
+    /// with `None`
+    ///
+    /// Must not be called during a capture.
+    pub fn set_crop(&mut self, crop: Option<Crop>) {
+        assert!(!self.is_capturing());
+
+        match crop {
+            Some(crop) => {
+                assert!(crop.width > 0 && crop.height > 0);
+                assert!(crop.x < 1 << 14 && crop.y < 1 << 13);
+                assert!(crop.width <= 1 << 14 && crop.height <= 1 << 14);
+                self.dcmi
+                    .cwstrt
+                    .write(|w| unsafe { w.hoffcnt().bits(crop.x).vst().bits(crop.y) });
+                self.dcmi.cwsize.write(|w| unsafe {
+                    w.capcnt()
+                        .bits(crop.width - 1)
+                        .vline()
+                        .bits(crop.height - 1)
+                });
+                self.dcmi.cr.modify(|_, w| w.crop().set_bit());
+            }
+            None => self.dcmi.cr.modify(|_, w| w.crop().clear_bit()),
+        }
+    }
+
+    fn dr_address(&self) -> u32 {
+        &self.dcmi.dr as *const _ as u32
+    }
+
+    /// Returns whether a capture is ongoing
+    pub fn is_capturing(&self) -> bool {
+        self.dcmi.cr.read().capture().bit_is_set()
+    }
+
+    /// Requests the end of the capture, which stops once the current frame
+    /// is captured
+    pub fn stop_capture(&mut self) {
+        self.dcmi.cr.modify(|_, w| w.capture().clear_bit());
+    }
+
+    /// Returns the level of the VSYNC signal, set during the blanking
+    /// between frames, or between the frame end and start codes
+    pub fn is_vsync_active(&self) -> bool {
+        self.dcmi.sr.read().vsync().bit_is_set()
+    }
+
+    /// Starts listening for an interrupt event
+    pub fn listen(&mut self, event: Event) {
+        self.dcmi.ier.modify(|_, w| match event {
+            Event::FrameComplete => w.frame_ie().set_bit(),
+            Event::Overrun => w.ovr_ie().set_bit(),
+            Event::SyncError => w.err_ie().set_bit(),
+            Event::Vsync => w.vsync_ie().set_bit(),
+            Event::Line => w.line_ie().set_bit(),
+        });
+    }
+
+    /// Stops listening for an interrupt event
+    pub fn unlisten(&mut self, event: Event) {
+        self.dcmi.ier.modify(|_, w| match event {
+            Event::FrameComplete => w.frame_ie().clear_bit(),
+            Event::Overrun => w.ovr_ie().clear_bit(),
+            Event::SyncError => w.err_ie().clear_bit(),
+            Event::Vsync => w.vsync_ie().clear_bit(),
+            Event::Line => w.line_ie().clear_bit(),
+        });
+    }
+
+    /// Returns whether the flag of an interrupt event is set
+    pub fn is_pending(&self, event: Event) -> bool {
+        let ris = self.dcmi.ris.read();
+        match event {
+            Event::FrameComplete => ris.frame_ris().bit_is_set(),
+            Event::Overrun => ris.ovr_ris().bit_is_set(),
+            Event::SyncError => ris.err_ris().bit_is_set(),
+            Event::Vsync => ris.vsync_ris().bit_is_set(),
+            Event::Line => ris.line_ris().bit_is_set(),
+        }
+    }
+
+    /// Clears the flag of an interrupt event
+    pub fn clear_interrupt(&mut self, event: Event) {
+        self.dcmi.icr.write(|w| match event {
+            Event::FrameComplete => w.frame_isc().set_bit(),
+            Event::Overrun => w.ovr_isc().set_bit(),
+            Event::SyncError => w.err_isc().set_bit(),
+            Event::Vsync => w.vsync_isc().set_bit(),
+            Event::Line => w.line_isc().set_bit(),
+        });
+    }
+
+    /// Disables the interface, and returns the peripheral and the pins
+    pub fn free(self) -> (DCMI, PINS) {
+        self.dcmi
+            .cr
+            .modify(|_, w| w.capture().clear_bit().enable().clear_bit());
+        (self.dcmi, self.pins)
+    }
+}
+
+/// Token for the DMA transfers of the captured data
+pub struct Capture(());
@@ -0,0 +1,154 @@
+//! Continuous capture in two frame buffers
+//!
+//! The DMA writes the frames in the two buffers in turn, in double-buffer
+//! mode. Once a frame is complete, it stays in its buffer while the next one
+//! is captured in the other buffer, so it can be processed, or shown by the
+//! LTDC, for one frame duration.
+//!
+//! ```ignore
+//! let mut capture = dcmi.capture_continuous(buffers, &dma.handle, dma.streams.stream1);
+//! capture.listen(Event::FrameComplete);
+//!
+//! // In the DCMI interrupt handler
+//! if let Some(frame) = capture.take_frame(&dma.handle)? {
+//!     // QVGA RGB565 frame, copied to the layer by the DMA2D
+//!     let bytes = unsafe { slice::from_raw_parts(frame.as_ptr() as *const u8, frame.len() * 4) };
+//!     display.blit(Layer::L1, &Image::new(bytes, InputFormat::RGB565, 320, 240), (0, 0));
+//! }
+//! ```
+
+use core::ops::DerefMut;
+use core::pin::Pin;
+
+use as_slice::AsMutSlice;
+
+use super::{Capture, Dcmi, Error, Event, Pins};
+use crate::{dma, state};
+
+type Handle = dma::Handle<<Capture as dma::Target>::Instance, state::Enabled>;
+
+/// Continuous capture in two frame buffers
+pub struct ContinuousCapture<PINS, B> {
+    dcmi: Dcmi<PINS>,
+    transfer: dma::Transfer<Capture, B, dma::Started>,
+    second: Pin<B>,
+    buffers: [*mut u32; 2],
+    len: usize,
+}
+
+impl<PINS: Pins> Dcmi<PINS> {
+    /// Captures the frames continuously, in the two `buffers` in turn
+    ///
+    /// Each buffer must hold exactly one frame, of up to 65535 words.
+    pub fn capture_continuous<B>(
+        mut self,
+        mut buffers: (Pin<B>, Pin<B>),
+        dma: &Handle,
+        stream: <Capture as dma::Target>::Stream,
+    ) -> ContinuousCapture<PINS, B>
+    where
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u32>,
+    {
+        // The slices are only accessed through these pointers from now on
+        let first = unsafe { buffers.0.as_mut().get_unchecked_mut() }.as_mut_slice();
+        let (first, len) = (first.as_mut_ptr(), first.len());
+        let second = unsafe { buffers.1.as_mut().get_unchecked_mut() }.as_mut_slice();
+        assert_eq!(second.len(), len);
+        let second_ptr = second.as_mut_ptr();
+
+        self.stop_capture();
+        self.dcmi.cr.modify(|_, w| w.cm().set_bit());
+        self.clear_interrupt(Event::Overrun);
+        self.clear_interrupt(Event::SyncError);
+
+        // Safe, because the buffers are owned by the capture, and only
+        // accessed by the DMA or by `take_frame`, one at a time
+        let mut transfer = unsafe {
+            dma::Transfer::new(
+                dma,
+                stream,
+                buffers.0,
+                Capture(()),
+                self.dr_address(),
+                dma::Direction::PeripheralToMemory,
+            )
+        };
+        unsafe { transfer.enable_double_buffer(dma, second_ptr as u32) };
+        let transfer = transfer.start(dma);
+
+        self.dcmi.cr.modify(|_, w| w.capture().set_bit());
+        ContinuousCapture {
+            dcmi: self,
+            transfer,
+            second: buffers.1,
+            buffers: [first, second_ptr],
+            len,
+        }
+    }
+}
+
+impl<PINS: Pins, B> ContinuousCapture<PINS, B> {
+    /// Returns the last frame captured, once per frame
+    ///
+    /// The frame stays valid until the end of the next one, when the DMA
+    /// writes to its buffer again. An overrun, which corrupts the frame
+    /// being captured, or a synchronization error is reported, and the
+    /// capture goes on.
+    pub fn take_frame(&mut self, handle: &Handle) -> Result<Option<&mut [u32]>, Error> {
+        self.transfer
+            .check_errors(handle)
+            .map_err(|_| Error::DmaTransfer)?;
+        if self.dcmi.is_pending(Event::Overrun) {
+            self.dcmi.clear_interrupt(Event::Overrun);
+            return Err(Error::Overrun);
+        }
+        if self.dcmi.is_pending(Event::SyncError) {
+            self.dcmi.clear_interrupt(Event::SyncError);
+            return Err(Error::SyncError);
+        }
+        if self.dcmi.is_pending(Event::FrameComplete) {
+            self.dcmi.clear_interrupt(Event::FrameComplete);
+        }
+
+        if !self.transfer.take_transfer_complete(handle) {
+            return Ok(None);
+        }
+        // The DMA moved on to the other buffer
+        let complete = 1 - self.transfer.current_buffer(handle);
+        // Safe, because the DMA is writing to the other buffer
+        let frame = unsafe { core::slice::from_raw_parts_mut(self.buffers[complete], self.len) };
+        Ok(Some(frame))
+    }
+
+    /// Starts listening for an interrupt event of the DCMI
+    pub fn listen(&mut self, event: Event) {
+        self.dcmi.listen(event);
+    }
+
+    /// Stops listening for an interrupt event of the DCMI
+    pub fn unlisten(&mut self, event: Event) {
+        self.dcmi.unlisten(event);
+    }
+
+    /// Stops the capture at the end of the current frame and the DMA
+    /// transfer, and returns the DCMI, the DMA stream and the buffers
+    #[allow(clippy::type_complexity)]
+    pub fn stop(
+        mut self,
+        handle: &Handle,
+    ) -> (
+        Dcmi<PINS>,
+        <Capture as dma::Target>::Stream,
+        (Pin<B>, Pin<B>),
+    ) {
+        self.dcmi.stop_capture();
+        while self.dcmi.is_capturing() {}
+
+        self.transfer.cancel(handle);
+        let resources = match self.transfer.wait(handle) {
+            Ok(resources) | Err((resources, _)) => resources,
+        };
+        (self.dcmi, resources.stream, (resources.buffer, self.second))
+    }
+}
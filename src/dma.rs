@@ -11,15 +11,15 @@ use core::{
 use as_slice::AsSlice;
 
 use crate::{
+    crc, dac,
     pac::{
         self,
         dma2::{self, st::cr},
         Interrupt, DMA1, DMA2, NVIC,
     },
-    adc,
     qspi,
     rcc::{Enable, RccBus, Reset},
-    serial, spi, state,
+    sai, serial, spi, state,
 };
 
 /// Entry point to the DMA API
@@ -130,11 +130,19 @@ where
 
         T::Stream::clear_status_flags(&handle.dma);
 
+        // In memory-to-memory mode, the peripheral port is the source and the
+        // memory port the destination
+        let (peripheral_address, memory_address) = match direction {
+            Direction::MemoryToMemory => (buffer.as_ptr() as u32, address),
+            _ => (address, buffer.as_ptr() as u32),
+        };
+
         // Set peripheral port register address
-        handle.dma.st[nr].par.write(|w| w.pa().bits(address));
+        handle.dma.st[nr]
+            .par
+            .write(|w| w.pa().bits(peripheral_address));
 
         // Set memory address
-        let memory_address = buffer.as_ptr() as u32;
         handle.dma.st[nr]
             .m0ar
             .write(|w| w.m0a().bits(memory_address));
@@ -149,13 +157,17 @@ where
 
         // Configure FIFO
         handle.dma.st[nr].fcr.modify(|_, w| {
-            w
+            let w = w
                 // Interrupt disabled
                 .feie()
-                .disabled()
+                .disabled();
+
+            match direction {
+                // Direct mode is not available in memory-to-memory mode
+                Direction::MemoryToMemory => w.dmdis().disabled(),
                 // Direct mode enabled (FIFO disabled)
-                .dmdis()
-                .enabled()
+                _ => w.dmdis().enabled(),
+            }
         });
 
         // Select channel
@@ -165,6 +177,13 @@ where
             let w = match direction {
                 Direction::MemoryToPeripheral => w.dir().memory_to_peripheral(),
                 Direction::PeripheralToMemory => w.dir().peripheral_to_memory(),
+                Direction::MemoryToMemory => w.dir().memory_to_memory(),
+            };
+
+            // The buffer address is incremented, the target's is fixed
+            let w = match direction {
+                Direction::MemoryToMemory => w.minc().fixed().pinc().incremented(),
+                _ => w.minc().incremented().pinc().fixed(),
             };
 
             w
@@ -185,12 +204,6 @@ where
                 // Peripheral data size
                 .psize()
                 .variant(Word::psize())
-                // Memory increment mode
-                .minc()
-                .incremented()
-                // Peripheral increment mode
-                .pinc()
-                .fixed()
                 // Circular mode disabled
                 .circ()
                 .disabled()
@@ -260,6 +273,51 @@ where
         unsafe { NVIC::unmask(T::INTERRUPT) };
     }
 
+    /// Enables circular mode for this DMA transfer
+    ///
+    /// The buffer is transferred over and over again until the transfer is
+    /// cancelled, so [`Transfer::wait`] never returns. The half transfer and
+    /// transfer complete interrupts can be used to refill the buffer.
+    pub fn enable_circular(&mut self, handle: &Handle<T::Instance, state::Enabled>) {
+        handle.dma.st[T::Stream::number()]
+            .cr
+            .modify(|_, w| w.circ().enabled());
+    }
+
+    /// Enables double-buffer mode for this DMA transfer, with `memory1` as
+    /// the second buffer
+    ///
+    /// The DMA switches between the buffer of the transfer and the second
+    /// one each time it is full, setting the transfer complete flag, until
+    /// the transfer is cancelled. [`Transfer::current_buffer`] tells which
+    /// one is being transferred.
+    ///
+    /// # Safety
+    ///
+    /// `memory1` must be the address of a buffer of the same length as the
+    /// buffer of the transfer, which can be read or written safely until the
+    /// transfer ends.
+    #[cfg(any(
+        feature = "stm32f745",
+        feature = "stm32f746",
+        feature = "stm32f756",
+        feature = "stm32f765",
+        feature = "stm32f767",
+        feature = "stm32f769",
+        feature = "stm32f777",
+        feature = "stm32f778",
+        feature = "stm32f779",
+    ))]
+    pub(crate) unsafe fn enable_double_buffer(
+        &mut self,
+        handle: &Handle<T::Instance, state::Enabled>,
+        memory1: u32,
+    ) {
+        let st = &handle.dma.st[T::Stream::number()];
+        st.m1ar.write(|w| w.m1a().bits(memory1));
+        st.cr.modify(|_, w| w.dbm().enabled());
+    }
+
     /// Start the DMA transfer
     ///
     /// Consumes this instance of `Transfer` and returns another instance with
@@ -292,6 +350,55 @@ where
             .is_enabled()
     }
 
+    /// Checks and clears the half transfer flag
+    ///
+    /// In circular mode, the flag is set each time the first half of the
+    /// buffer has been transferred.
+    pub fn take_half_transfer(&mut self, handle: &Handle<T::Instance, state::Enabled>) -> bool {
+        let set = T::Stream::is_half_transfer(&handle.dma);
+        if set {
+            T::Stream::clear_half_transfer(&handle.dma);
+        }
+        set
+    }
+
+    /// Checks and clears the transfer complete flag
+    ///
+    /// In circular mode, the flag is set each time the whole buffer has been
+    /// transferred.
+    pub fn take_transfer_complete(&mut self, handle: &Handle<T::Instance, state::Enabled>) -> bool {
+        let set = T::Stream::is_transfer_complete(&handle.dma);
+        if set {
+            T::Stream::clear_transfer_complete(&handle.dma);
+        }
+        set
+    }
+
+    /// Returns the number of items left to transfer
+    pub fn remaining(&self, handle: &Handle<T::Instance, state::Enabled>) -> u16 {
+        handle.dma.st[T::Stream::number()].ndtr.read().ndt().bits()
+    }
+
+    /// Returns the buffer being transferred in double-buffer mode: 0 for the
+    /// buffer of the transfer, 1 for the second one
+    pub fn current_buffer(&self, handle: &Handle<T::Instance, state::Enabled>) -> usize {
+        if handle.dma.st[T::Stream::number()]
+            .cr
+            .read()
+            .ct()
+            .is_memory1()
+        {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Checks for a transfer or direct mode error
+    pub fn check_errors(&self, handle: &Handle<T::Instance, state::Enabled>) -> Result<(), Error> {
+        Error::check::<T::Stream>(&handle.dma)
+    }
+
     /// Try to cancel an in process transfer. Check is_active to verify cancellation
     pub fn cancel(&self, handle: &Handle<T::Instance, state::Enabled>) {
         handle.dma.st[T::Stream::number()]
@@ -355,6 +462,9 @@ where
 pub(crate) enum Direction {
     MemoryToPeripheral,
     PeripheralToMemory,
+    /// Only supported by DMA2. The buffer is the source and the target
+    /// address the destination.
+    MemoryToMemory,
 }
 
 /// Implemented for all peripheral APIs that support DMA transfers
@@ -450,10 +560,21 @@ impl_target!(
     // QUADSPI is half-duplex, uses one channel for both send/receive
     qspi::RxTx<pac::QUADSPI>, DMA2, Stream7, Channel3, DMA2_STREAM7;
 
-    // ADC receive
-    adc::Adc<pac::ADC1>, DMA2, Stream0, Channel0, DMA2_STREAM0;
-    adc::Adc<pac::ADC2>, DMA2, Stream2, Channel1, DMA2_STREAM2;
-    adc::Adc<pac::ADC3>, DMA2, Stream0, Channel2, DMA2_STREAM0;
+    // DAC transmit
+    dac::C1, DMA1, Stream5, Channel7, DMA1_STREAM5;
+    dac::C2, DMA1, Stream6, Channel7, DMA1_STREAM6;
+
+    // CRC, memory-to-memory so any DMA2 stream would do
+    crc::Crc, DMA2, Stream4, Channel0, DMA2_STREAM4;
+
+    // SAI transmit or receive, depending on the sub-block mode
+    sai::SubBlock<pac::SAI1, sai::BlockA>, DMA2, Stream1, Channel0, DMA2_STREAM1;
+    // SAI1_A for DMA2, stream 3, channel 0 is unsupported
+    sai::SubBlock<pac::SAI1, sai::BlockB>, DMA2, Stream5, Channel0, DMA2_STREAM5;
+    // SAI1_B for DMA2, stream 4, channel 1 is unsupported
+    sai::SubBlock<pac::SAI2, sai::BlockA>, DMA2, Stream4, Channel3, DMA2_STREAM4;
+    sai::SubBlock<pac::SAI2, sai::BlockB>, DMA2, Stream7, Channel0, DMA2_STREAM7;
+    // SAI2_B for DMA2, stream 6, channel 3 is unsupported
 );
 
 #[cfg(any(
@@ -470,6 +591,33 @@ impl_target!(
 impl_target!(
     spi::Rx<pac::SPI6>, DMA2, Stream6, Channel1, DMA2_STREAM6;
     spi::Tx<pac::SPI6>, DMA2, Stream5, Channel1, DMA2_STREAM5;
+
+    // SPDIFRX receive, of the samples only
+    crate::spdifrx::SpdifRx, DMA1, Stream1, Channel0, DMA1_STREAM1;
+
+    // DCMI receive
+    crate::dcmi::Capture, DMA2, Stream1, Channel1, DMA2_STREAM1;
+    // DCMI for DMA2, stream 7, channel 1 is unsupported
+);
+
+#[cfg(any(feature = "stm32f765", feature = "stm32f767", feature = "stm32f769"))]
+impl_target!(
+    // ADC receive
+    crate::adc::Adc<pac::ADC1>, DMA2, Stream0, Channel0, DMA2_STREAM0;
+    crate::adc::Adc<pac::ADC2>, DMA2, Stream2, Channel1, DMA2_STREAM2;
+    crate::adc::Adc<pac::ADC3>, DMA2, Stream0, Channel2, DMA2_STREAM0;
+);
+
+#[cfg(any(
+    feature = "stm32f756",
+    feature = "stm32f777",
+    feature = "stm32f778",
+    feature = "stm32f779",
+))]
+impl_target!(
+    // CRYP input and output
+    crate::cryp::DmaIn, DMA2, Stream6, Channel2, DMA2_STREAM6;
+    crate::cryp::DmaOut, DMA2, Stream5, Channel2, DMA2_STREAM5;
 );
 
 /// Implemented for all types that represent DMA streams
@@ -480,6 +628,8 @@ pub trait Stream {
     fn number() -> usize;
 
     fn clear_status_flags(dma: &dma2::RegisterBlock);
+    fn clear_half_transfer(dma: &dma2::RegisterBlock);
+    fn clear_transfer_complete(dma: &dma2::RegisterBlock);
 
     fn is_transfer_complete(dma: &dma2::RegisterBlock) -> bool;
     fn is_half_transfer(dma: &dma2::RegisterBlock) -> bool;
@@ -501,7 +651,7 @@ macro_rules! impl_stream {
             $htif:ident,
             $tcif:ident,
             $flag_clear_reg:ident,
-            ($($flag_clear_field:ident,)*);
+            ($cfeif:ident, $cdmeif:ident, $cteif:ident, $chtif:ident, $ctcif:ident,);
         )*
     ) => {
         pub struct Streams<I> {
@@ -526,10 +676,22 @@ macro_rules! impl_stream {
                 fn clear_status_flags(dma: &dma2::RegisterBlock) {
                     dma.$flag_clear_reg.write(|w|
                         w
-                            $(.$flag_clear_field().clear())*
+                            .$cfeif().clear()
+                            .$cdmeif().clear()
+                            .$cteif().clear()
+                            .$chtif().clear()
+                            .$ctcif().clear()
                     );
                 }
 
+                fn clear_half_transfer(dma: &dma2::RegisterBlock) {
+                    dma.$flag_clear_reg.write(|w| w.$chtif().clear());
+                }
+
+                fn clear_transfer_complete(dma: &dma2::RegisterBlock) {
+                    dma.$flag_clear_reg.write(|w| w.$ctcif().clear());
+                }
+
                 fn is_transfer_complete(dma: &dma2::RegisterBlock) -> bool {
                     dma.$flag_reg.read().$tcif().is_complete()
                 }
@@ -766,6 +928,17 @@ impl SupportedWordSize for u16 {
     }
 }
 
+impl private::Sealed for u32 {}
+impl SupportedWordSize for u32 {
+    fn msize() -> cr::MSIZE_A {
+        cr::MSIZE_A::BITS32
+    }
+
+    fn psize() -> cr::PSIZE_A {
+        cr::MSIZE_A::BITS32
+    }
+}
+
 mod private {
     /// Prevents code outside of the parent module from implementing traits
     ///
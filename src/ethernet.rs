@@ -0,0 +1,376 @@
+//! Ethernet MAC and DMA
+//!
+//! The MAC is driven through two chained rings of DMA descriptors, one for
+//! reception and one for transmission, each descriptor having a buffer for a
+//! whole frame. The rings are provided by the application, usually as
+//! statics:
+//!
+//! - received frames are read with [`Ethernet::recv_next`], which returns an
+//!   [`RxPacket`] that gives its buffer back to the DMA when dropped,
+//! - frames are sent with [`Ethernet::send`], the closure filling the buffer
+//!   of the next free descriptor.
+//!
+//! The PHY is connected through the RMII or the MII, [`RmiiPins`] or
+//! [`MiiPins`] being passed to [`Ethernet::new`] which selects the interface
+//! in `SYSCFG_PMC`. Each pin is checked for its signal at compile time, all
+//! in alternate function 11. The speed and duplex mode must match the ones
+//! negotiated by the PHY.
+//!
+//! The PHY registers are accessed through [`Ethernet::smi`], with the MDIO
+//! (PA2) and MDC (PC1) pins. [`Phy`] covers the reset, autonegotiation and
+//! link status of the [`Lan8742a`] and [`Dp83848`], the negotiated speed
+//! being then applied with [`Ethernet::set_speed`].
+//!
+//! The received frames are filtered on their destination address according
+//! to [`Filter`]: up to three more unicast or multicast addresses can be
+//! matched with [`Ethernet::set_address_filter`], and any number through the
+//! 64-bit hash table, indexed by [`hash_index`].
+//!
+//! In power-down mode, entered with [`Ethernet::power_down`], the MAC only
+//! waits for a magic packet, a wakeup frame or a unicast frame. The wakeup is
+//! signalled on the EXTI line 19 with [`Ethernet::listen_wakeup`], which can
+//! bring the MCU out of Stop mode.
+//!
+//! The DMA bypasses the data cache of the Cortex-M7: the rings must either be
+//! placed in a memory region configured as non-cacheable with the MPU, or the
+//! data cache must be left disabled.
+//!
+//! See section 42 of RM0410.
+
+use crate::pac::{ETHERNET_DMA, ETHERNET_MAC, ETHERNET_MMC, RCC, SYSCFG};
+use crate::rcc::{Clocks, Enable, Reset, AHB1};
+
+mod desc;
+mod filter;
+mod phy;
+mod pins;
+mod pmt;
+mod rx;
+mod smi;
+mod tx;
+
+pub use filter::{hash_index, Filter, MulticastFilter};
+pub use phy::{Dp83848, Lan8742a, Phy};
+pub use pins::{
+    Col, Crs, CrsDv, Mdc, Mdio, MiiPins, Pins, RefClk, RmiiPins, RxD0, RxD1, RxD2, RxD3, RxEr,
+    TxClk, TxD0, TxD1, TxD2, TxD3, TxEn,
+};
+pub use pmt::{WakeupEvents, WakeupFilter};
+pub use rx::{RxError, RxPacket, RxRingEntry};
+pub use smi::{Smi, StationManagement};
+pub use tx::{TxError, TxRingEntry};
+
+use rx::RxRing;
+use tx::TxRing;
+
+/// Largest frame size, with a VLAN tag and the CRC
+pub const MTU: usize = 1522;
+/// Size of the descriptor buffers, a multiple of the bus width
+const BUFFER_SIZE: usize = 1524;
+
+/// Link speed and duplex mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speed {
+    HalfDuplexBase10T,
+    FullDuplexBase10T,
+    HalfDuplexBase100Tx,
+    FullDuplexBase100Tx,
+}
+
+/// Ethernet configuration
+pub struct Config {
+    /// Station address, the destination address of the unicast frames
+    /// accepted by the MAC
+    pub mac_address: [u8; 6],
+    pub speed: Speed,
+    /// Insert the IPv4 header, TCP, UDP and ICMP checksums in the sent frames,
+    /// and report the received frames with invalid checksums as
+    /// [`RxError::Checksum`], so the network stack can skip them
+    pub checksum_offload: bool,
+    pub filter: Filter,
+}
+
+/// Initialization errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// HCLK is below the 25 MHz needed by the MAC
+    ClockTooSlow,
+}
+
+/// Interrupt sources reported by [`Ethernet::clear_interrupts`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InterruptReason {
+    /// A frame was received
+    pub rx: bool,
+    /// A frame was sent
+    pub tx: bool,
+    /// The DMA stopped on a bus error
+    pub dma_error: bool,
+}
+
+/// Peripherals of the Ethernet MAC
+pub struct Parts {
+    pub mac: ETHERNET_MAC,
+    pub mmc: ETHERNET_MMC,
+    pub dma: ETHERNET_DMA,
+}
+
+/// Ethernet MAC with its DMA rings
+pub struct Ethernet<'rx, 'tx> {
+    mac: ETHERNET_MAC,
+    mmc: ETHERNET_MMC,
+    dma: ETHERNET_DMA,
+    rx_ring: RxRing<'rx>,
+    tx_ring: TxRing<'tx>,
+}
+
+impl<'rx, 'tx> Ethernet<'rx, 'tx> {
+    /// Selects the PHY interface, resets the MAC, sets up the descriptor rings
+    /// and starts the reception and transmission.
+    pub fn new<PINS: Pins>(
+        parts: Parts,
+        _pins: PINS,
+        rx_entries: &'rx mut [RxRingEntry],
+        tx_entries: &'tx mut [TxRingEntry],
+        config: Config,
+        ahb1: &mut AHB1,
+        clocks: &Clocks,
+    ) -> Result<Self, Error> {
+        if clocks.hclk().raw() < 25_000_000 {
+            return Err(Error::ClockTooSlow);
+        }
+
+        let Parts { mac, mmc, dma } = parts;
+
+        // NOTE(unsafe) SYSCFG_PMC and the transmit and receive clocks are
+        // only modified atomically here
+        cortex_m::interrupt::free(|_| {
+            // The interface can only be changed while the MAC clocks are
+            // stopped, the MAC is reset once they are enabled
+            let syscfg = unsafe {
+                SYSCFG::enable_unchecked();
+                &(*SYSCFG::ptr())
+            };
+            ETHERNET_MAC::disable(ahb1);
+            syscfg.pmc.modify(|_, w| w.mii_rmii_sel().bit(PINS::RMII));
+        });
+
+        ETHERNET_MAC::enable(ahb1);
+        cortex_m::interrupt::free(|_| {
+            let rcc = unsafe { &*RCC::ptr() };
+            rcc.ahb1enr
+                .modify(|_, w| w.ethmactxen().set_bit().ethmacrxen().set_bit());
+        });
+        ETHERNET_MAC::reset(ahb1);
+
+        // Software reset of the MAC and DMA, which needs the PHY clocks
+        dma.dmabmr.modify(|_, w| w.sr().set_bit());
+        while dma.dmabmr.read().sr().bit_is_set() {}
+
+        let mut eth = Self {
+            mac,
+            mmc,
+            dma,
+            rx_ring: RxRing::new(rx_entries, config.checksum_offload),
+            tx_ring: TxRing::new(tx_entries, config.checksum_offload),
+        };
+        eth.init(&config, clocks);
+        Ok(eth)
+    }
+
+    fn init(&mut self, config: &Config, clocks: &Clocks) {
+        let cr = smi::mdc_clock_range(clocks.hclk().raw());
+        self.mac.macmiiar.write(|w| unsafe { w.cr().bits(cr) });
+
+        self.mac.maccr.modify(|_, w| {
+            w
+                // CRC stripping for type frames
+                .cstf()
+                .set_bit()
+                // Automatic pad and CRC stripping for length frames
+                .apcs()
+                .set_bit()
+                // No retry after a collision in half duplex mode
+                .rd()
+                .set_bit()
+                .ipco()
+                .bit(config.checksum_offload)
+        });
+        self.set_speed(config.speed);
+        self.set_mac_address(config.mac_address);
+        self.set_filter(&config.filter);
+
+        // The MMC counters interrupts are enabled after reset, and would
+        // never be cleared
+        self.mmc
+            .mmcrimr
+            .write(|w| unsafe { w.bits((1 << 0) | (1 << 5) | (1 << 6) | (1 << 17)) });
+        self.mmc
+            .mmctimr
+            .write(|w| unsafe { w.bits((1 << 14) | (1 << 15) | (1 << 21)) });
+
+        self.dma.dmabmr.modify(|_, w| unsafe {
+            w
+                // Address-aligned beats
+                .aab()
+                .set_bit()
+                // Fixed burst
+                .fb()
+                .set_bit()
+                // Separate receive and transmit burst lengths, of 32 beats
+                .usp()
+                .set_bit()
+                .rdp()
+                .bits(32)
+                .pbl()
+                .bits(32)
+                // Normal 4-word descriptors
+                .edfe()
+                .clear_bit()
+        });
+        self.dma.dmaomr.modify(|_, w| {
+            w
+                // Store and forward, for the checksum offload
+                .rsf()
+                .set_bit()
+                .tsf()
+                .set_bit()
+                // Operate on second frame
+                .osf()
+                .set_bit()
+        });
+
+        self.dma
+            .dmardlar
+            .write(|w| unsafe { w.srl().bits(self.rx_ring.address()) });
+        self.dma
+            .dmatdlar
+            .write(|w| unsafe { w.stl().bits(self.tx_ring.address()) });
+
+        // Start the transmission, after flushing the transmit FIFO
+        self.mac.maccr.modify(|_, w| w.te().set_bit());
+        self.dma.dmaomr.modify(|_, w| w.ftf().set_bit());
+        while self.dma.dmaomr.read().ftf().bit_is_set() {}
+        self.dma.dmaomr.modify(|_, w| w.st().set_bit());
+
+        // Start the reception
+        self.mac.maccr.modify(|_, w| w.re().set_bit());
+        self.dma.dmaomr.modify(|_, w| w.sr().set_bit());
+        self.dma.dmarpdr.write(|w| unsafe { w.rpd().bits(1) });
+    }
+
+    /// Sets the speed and duplex mode, which must follow the PHY after each
+    /// autonegotiation
+    pub fn set_speed(&mut self, speed: Speed) {
+        let (fast, full_duplex) = match speed {
+            Speed::HalfDuplexBase10T => (false, false),
+            Speed::FullDuplexBase10T => (false, true),
+            Speed::HalfDuplexBase100Tx => (true, false),
+            Speed::FullDuplexBase100Tx => (true, true),
+        };
+        self.mac
+            .maccr
+            .modify(|_, w| w.fes().bit(fast).dm().bit(full_duplex));
+    }
+
+    /// Sets the station address
+    pub fn set_mac_address(&mut self, address: [u8; 6]) {
+        let [a0, a1, a2, a3, a4, a5] = address;
+        self.mac
+            .maca0hr
+            .write(|w| w.maca0h().bits(u16::from_le_bytes([a4, a5])));
+        self.mac
+            .maca0lr
+            .write(|w| w.maca0l().bits(u32::from_le_bytes([a0, a1, a2, a3])));
+    }
+
+    /// Returns the station management interface, to access the PHY
+    /// registers. The MDIO and MDC pins are borrowed for its lifetime.
+    pub fn smi<'a, MDIO: Mdio, MDC: Mdc>(
+        &'a mut self,
+        _mdio: &'a mut MDIO,
+        _mdc: &'a mut MDC,
+    ) -> Smi<'a> {
+        Smi::new(&self.mac)
+    }
+
+    /// Returns the next received frame
+    pub fn recv_next(&mut self) -> Result<RxPacket<'_>, RxError> {
+        self.rx_ring.recv_next(&self.dma)
+    }
+
+    /// Returns `true` if a transmit descriptor is free
+    pub fn tx_is_available(&self) -> bool {
+        self.tx_ring.is_available()
+    }
+
+    /// Sends a frame of `length` bytes, filled by `f`. The frame must hold
+    /// the destination and source addresses, and the Ethertype or length, but
+    /// not the CRC which is appended by the MAC.
+    pub fn send<F, R>(&mut self, length: usize, f: F) -> Result<R, TxError>
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        self.tx_ring.send(&self.dma, length, f)
+    }
+
+    /// Enables the receive, transmit and fatal bus error interrupts
+    pub fn listen(&mut self) {
+        self.dma.dmaier.modify(|_, w| {
+            w.nise()
+                .set_bit()
+                .rie()
+                .set_bit()
+                .tie()
+                .set_bit()
+                .aise()
+                .set_bit()
+                .fbeie()
+                .set_bit()
+        });
+    }
+
+    /// Disables the interrupts
+    pub fn unlisten(&mut self) {
+        self.dma.dmaier.reset();
+    }
+
+    /// Clears the pending interrupts, to be called from the `ETH` interrupt
+    /// handler
+    pub fn clear_interrupts(&mut self) -> InterruptReason {
+        let status = self.dma.dmasr.read();
+        let reason = InterruptReason {
+            rx: status.rs().bit_is_set(),
+            tx: status.ts().bit_is_set(),
+            dma_error: status.fbes().bit_is_set(),
+        };
+        self.dma.dmasr.write(|w| {
+            w.nis()
+                .set_bit()
+                .ais()
+                .set_bit()
+                .rs()
+                .bit(reason.rx)
+                .ts()
+                .bit(reason.tx)
+                .fbes()
+                .bit(reason.dma_error)
+        });
+        reason
+    }
+
+    /// Stops the MAC and releases the peripherals
+    pub fn free(self) -> Parts {
+        self.dma
+            .dmaomr
+            .modify(|_, w| w.st().clear_bit().sr().clear_bit());
+        self.mac
+            .maccr
+            .modify(|_, w| w.te().clear_bit().re().clear_bit());
+        Parts {
+            mac: self.mac,
+            mmc: self.mmc,
+            dma: self.dma,
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use core::ptr;
+
+/// DMA descriptor, in the normal 4-word format
+///
+/// The descriptors are shared with the DMA, so the words are only accessed
+/// with volatile operations.
+#[repr(C, align(4))]
+pub(super) struct Descriptor {
+    words: [u32; 4],
+}
+
+impl Descriptor {
+    pub const fn new() -> Self {
+        Self { words: [0; 4] }
+    }
+
+    pub fn read(&self, index: usize) -> u32 {
+        unsafe { ptr::read_volatile(&self.words[index]) }
+    }
+
+    pub fn write(&mut self, index: usize, value: u32) {
+        unsafe { ptr::write_volatile(&mut self.words[index], value) }
+    }
+}
@@ -0,0 +1,133 @@
+use super::Ethernet;
+
+/// Filtering of the multicast frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MulticastFilter {
+    /// Accept all the multicast frames
+    PassAll,
+    /// Accept the multicast frames matching one of the perfect address
+    /// filters
+    Perfect,
+    /// Accept the multicast frames matching the hash table, or one of the
+    /// perfect address filters
+    Hash,
+}
+
+/// Destination address filtering of the received frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Filter {
+    /// Accept all the frames, disabling the other settings
+    pub promiscuous: bool,
+    /// Accept the broadcast frames
+    pub broadcast: bool,
+    pub multicast: MulticastFilter,
+    /// Also accept the unicast frames matching the hash table
+    pub unicast_hash: bool,
+}
+
+impl Default for Filter {
+    /// The station address, broadcast and all multicast frames are accepted,
+    /// as needed by IPv4 and IPv6
+    fn default() -> Self {
+        Self {
+            promiscuous: false,
+            broadcast: true,
+            multicast: MulticastFilter::PassAll,
+            unicast_hash: false,
+        }
+    }
+}
+
+impl Ethernet<'_, '_> {
+    /// Sets the destination address filtering
+    pub fn set_filter(&mut self, filter: &Filter) {
+        let multicast_hash = filter.multicast == MulticastFilter::Hash;
+        self.mac.macffr.modify(|_, w| {
+            w.pm()
+                .bit(filter.promiscuous)
+                .bfd()
+                .bit(!filter.broadcast)
+                .pam()
+                .bit(filter.multicast == MulticastFilter::PassAll)
+                .hm()
+                .bit(multicast_hash)
+                .hu()
+                .bit(filter.unicast_hash)
+                // The perfect filters keep matching along with the hash table
+                .hpf()
+                .bit(multicast_hash || filter.unicast_hash)
+        });
+    }
+
+    /// Sets the 64-bit hash table, bit `n` accepting the addresses for which
+    /// [`hash_index`] is `n`
+    pub fn set_hash_table(&mut self, table: u64) {
+        self.mac
+            .machthr
+            .write(|w| w.hth().bits((table >> 32) as u32));
+        self.mac.machtlr.write(|w| w.htl().bits(table as u32));
+    }
+
+    /// Sets the perfect destination address filter `index`, from 1 to 3,
+    /// accepting the frames sent to `address`, or disables it
+    ///
+    /// Panics if `index` is out of range.
+    pub fn set_address_filter(&mut self, index: usize, address: Option<[u8; 6]>) {
+        let (high, low) = match address {
+            // AE
+            Some([a0, a1, a2, a3, a4, a5]) => (
+                (1 << 31) | u32::from(u16::from_le_bytes([a4, a5])),
+                u32::from_le_bytes([a0, a1, a2, a3]),
+            ),
+            None => (0, 0),
+        };
+        // The address is latched when the low register is written
+        match index {
+            1 => {
+                self.mac.maca1hr.write(|w| unsafe { w.bits(high) });
+                self.mac.maca1lr.write(|w| unsafe { w.bits(low) });
+            }
+            2 => {
+                self.mac.maca2hr.write(|w| unsafe { w.bits(high) });
+                self.mac.maca2lr.write(|w| unsafe { w.bits(low) });
+            }
+            3 => {
+                self.mac.maca3hr.write(|w| unsafe { w.bits(high) });
+                self.mac.maca3lr.write(|w| unsafe { w.bits(low) });
+            }
+            _ => panic!("Invalid address filter index"),
+        }
+    }
+}
+
+/// Returns the bit of the hash table selected by `address`: the 6 upper bits
+/// of its bit-reversed CRC-32
+pub fn hash_index(address: &[u8; 6]) -> u8 {
+    (crc32(address).reverse_bits() >> 26) as u8
+}
+
+/// Ethernet CRC-32
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
@@ -0,0 +1,134 @@
+use super::{Speed, StationManagement};
+
+/// Basic control register
+const BMCR: u8 = 0;
+/// Basic status register
+const BMSR: u8 = 1;
+/// Autonegotiation advertisement register
+const ANAR: u8 = 4;
+
+const BMCR_RESET: u16 = 1 << 15;
+const BMCR_AN_ENABLE: u16 = 1 << 12;
+const BMCR_AN_RESTART: u16 = 1 << 9;
+const BMSR_AN_COMPLETE: u16 = 1 << 5;
+const BMSR_LINK_UP: u16 = 1 << 2;
+/// 10BASE-T and 100BASE-TX in half and full duplex, IEEE 802.3 selector
+const ANAR_ALL: u16 = (0b1111 << 5) | 0b00001;
+
+/// Ethernet PHY, built on the IEEE 802.3 clause 22 registers. The speed
+/// negotiated is read from the vendor specific registers.
+pub trait Phy {
+    /// Returns the address of the PHY on the MDIO bus
+    fn address(&self) -> u8;
+
+    /// Returns the negotiated speed and duplex mode, or `None` if the
+    /// autonegotiation is not done
+    fn negotiated_speed<S: StationManagement>(&self, smi: &mut S) -> Option<Speed>;
+
+    /// Resets the PHY and waits for the reset to complete
+    fn reset<S: StationManagement>(&self, smi: &mut S) {
+        smi.smi_write(self.address(), BMCR, BMCR_RESET);
+        while smi.smi_read(self.address(), BMCR) & BMCR_RESET != 0 {}
+    }
+
+    /// Advertises all the 10 and 100 Mbit/s modes and restarts the
+    /// autonegotiation
+    fn start_autonegotiation<S: StationManagement>(&self, smi: &mut S) {
+        smi.smi_write(self.address(), ANAR, ANAR_ALL);
+        smi.smi_write(self.address(), BMCR, BMCR_AN_ENABLE | BMCR_AN_RESTART);
+    }
+
+    /// Returns `true` if the autonegotiation is complete
+    fn is_autonegotiation_done<S: StationManagement>(&self, smi: &mut S) -> bool {
+        smi.smi_read(self.address(), BMSR) & BMSR_AN_COMPLETE != 0
+    }
+
+    /// Returns `true` if the link is up
+    fn is_link_up<S: StationManagement>(&self, smi: &mut S) -> bool {
+        // The link status is latched low until read, the second read gives
+        // the current state
+        smi.smi_read(self.address(), BMSR);
+        smi.smi_read(self.address(), BMSR) & BMSR_LINK_UP != 0
+    }
+
+    /// Returns the link speed if the link is up
+    fn link_speed<S: StationManagement>(&self, smi: &mut S) -> Option<Speed> {
+        if self.is_link_up(smi) {
+            self.negotiated_speed(smi)
+        } else {
+            None
+        }
+    }
+}
+
+/// Microchip LAN8742A, as found on the Nucleo-144 and Discovery boards
+pub struct Lan8742a {
+    address: u8,
+}
+
+impl Lan8742a {
+    /// Special control/status register
+    const PSCSR: u8 = 31;
+    const PSCSR_AUTODONE: u16 = 1 << 12;
+
+    pub fn new(address: u8) -> Self {
+        Self { address }
+    }
+}
+
+impl Phy for Lan8742a {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn negotiated_speed<S: StationManagement>(&self, smi: &mut S) -> Option<Speed> {
+        let pscsr = smi.smi_read(self.address, Self::PSCSR);
+        if pscsr & Self::PSCSR_AUTODONE == 0 {
+            return None;
+        }
+        match (pscsr >> 2) & 0b111 {
+            0b001 => Some(Speed::HalfDuplexBase10T),
+            0b101 => Some(Speed::FullDuplexBase10T),
+            0b010 => Some(Speed::HalfDuplexBase100Tx),
+            0b110 => Some(Speed::FullDuplexBase100Tx),
+            _ => None,
+        }
+    }
+}
+
+/// Texas Instruments DP83848
+pub struct Dp83848 {
+    address: u8,
+}
+
+impl Dp83848 {
+    /// PHY status register
+    const PHYSTS: u8 = 0x10;
+    const PHYSTS_AN_COMPLETE: u16 = 1 << 4;
+    const PHYSTS_FULL_DUPLEX: u16 = 1 << 2;
+    const PHYSTS_10MBPS: u16 = 1 << 1;
+
+    pub fn new(address: u8) -> Self {
+        Self { address }
+    }
+}
+
+impl Phy for Dp83848 {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn negotiated_speed<S: StationManagement>(&self, smi: &mut S) -> Option<Speed> {
+        let physts = smi.smi_read(self.address, Self::PHYSTS);
+        if physts & Self::PHYSTS_AN_COMPLETE == 0 {
+            return None;
+        }
+        let full_duplex = physts & Self::PHYSTS_FULL_DUPLEX != 0;
+        Some(match (physts & Self::PHYSTS_10MBPS != 0, full_duplex) {
+            (true, false) => Speed::HalfDuplexBase10T,
+            (true, true) => Speed::FullDuplexBase10T,
+            (false, false) => Speed::HalfDuplexBase100Tx,
+            (false, true) => Speed::FullDuplexBase100Tx,
+        })
+    }
+}
@@ -0,0 +1,183 @@
+use crate::gpio::{
+    gpioa::{PA0, PA1, PA2, PA3, PA7},
+    gpiob::{PB0, PB1, PB10, PB11, PB12, PB13, PB8},
+    gpioc::{PC1, PC2, PC3, PC4, PC5},
+    gpioe::PE2,
+    gpiog::{PG11, PG13, PG14},
+    gpioh::{PH2, PH3, PH6, PH7},
+    gpioi::PI10,
+    Alternate,
+};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// RMII reference clock, or MII receive clock
+pub trait RefClk: sealed::Sealed {}
+/// RMII carrier sense and receive data valid, or MII receive data valid
+pub trait CrsDv: sealed::Sealed {}
+pub trait TxEn: sealed::Sealed {}
+pub trait TxD0: sealed::Sealed {}
+pub trait TxD1: sealed::Sealed {}
+pub trait TxD2: sealed::Sealed {}
+pub trait TxD3: sealed::Sealed {}
+pub trait RxD0: sealed::Sealed {}
+pub trait RxD1: sealed::Sealed {}
+pub trait RxD2: sealed::Sealed {}
+pub trait RxD3: sealed::Sealed {}
+/// MII transmit clock
+pub trait TxClk: sealed::Sealed {}
+/// MII receive error
+pub trait RxEr: sealed::Sealed {}
+/// MII carrier sense
+pub trait Crs: sealed::Sealed {}
+/// MII collision detection
+pub trait Col: sealed::Sealed {}
+/// Station management data
+pub trait Mdio: sealed::Sealed {}
+/// Station management clock
+pub trait Mdc: sealed::Sealed {}
+
+macro_rules! pins {
+    ($($PIN:ident: [$($Signal:ident),+],)+) => {
+        $(
+            impl sealed::Sealed for $PIN<Alternate<11>> {}
+            $(
+                impl $Signal for $PIN<Alternate<11>> {}
+            )+
+        )+
+    };
+}
+
+pins! {
+    PA0: [Crs],
+    PA1: [RefClk],
+    PA2: [Mdio],
+    PA3: [Col],
+    PA7: [CrsDv],
+    PB0: [RxD2],
+    PB1: [RxD3],
+    PB8: [TxD3],
+    PB10: [RxEr],
+    PB11: [TxEn],
+    PB12: [TxD0],
+    PB13: [TxD1],
+    PC1: [Mdc],
+    PC2: [TxD2],
+    PC3: [TxClk],
+    PC4: [RxD0],
+    PC5: [RxD1],
+    PE2: [TxD3],
+    PG11: [TxEn],
+    PG13: [TxD0],
+    PG14: [TxD1],
+    PH2: [Crs],
+    PH3: [Col],
+    PH6: [RxD2],
+    PH7: [RxD3],
+    PI10: [RxEr],
+}
+
+/// Interface to the PHY, selected in `SYSCFG_PMC`
+pub trait Pins: sealed::Sealed {
+    #[doc(hidden)]
+    const RMII: bool;
+}
+
+/// Reduced media-independent interface pins, the PHY providing the 50 MHz
+/// reference clock
+pub struct RmiiPins<REFCLK, CRSDV, TXEN, TXD0, TXD1, RXD0, RXD1> {
+    pub ref_clk: REFCLK,
+    pub crs_dv: CRSDV,
+    pub tx_en: TXEN,
+    pub txd0: TXD0,
+    pub txd1: TXD1,
+    pub rxd0: RXD0,
+    pub rxd1: RXD1,
+}
+
+impl<REFCLK, CRSDV, TXEN, TXD0, TXD1, RXD0, RXD1> sealed::Sealed
+    for RmiiPins<REFCLK, CRSDV, TXEN, TXD0, TXD1, RXD0, RXD1>
+where
+    REFCLK: RefClk,
+    CRSDV: CrsDv,
+    TXEN: TxEn,
+    TXD0: TxD0,
+    TXD1: TxD1,
+    RXD0: RxD0,
+    RXD1: RxD1,
+{
+}
+
+impl<REFCLK, CRSDV, TXEN, TXD0, TXD1, RXD0, RXD1> Pins
+    for RmiiPins<REFCLK, CRSDV, TXEN, TXD0, TXD1, RXD0, RXD1>
+where
+    REFCLK: RefClk,
+    CRSDV: CrsDv,
+    TXEN: TxEn,
+    TXD0: TxD0,
+    TXD1: TxD1,
+    RXD0: RxD0,
+    RXD1: RxD1,
+{
+    const RMII: bool = true;
+}
+
+/// Media-independent interface pins, the PHY providing the 25 MHz transmit
+/// and receive clocks.
+///
+/// The carrier sense and collision pins, only used in half duplex mode, and
+/// the receive error pin are optional: the [`Crs`], [`Col`] and [`RxEr`]
+/// pins are configured on their own.
+pub struct MiiPins<TXCLK, RXCLK, RXDV, TXEN, TXD0, TXD1, TXD2, TXD3, RXD0, RXD1, RXD2, RXD3> {
+    pub tx_clk: TXCLK,
+    pub rx_clk: RXCLK,
+    pub rx_dv: RXDV,
+    pub tx_en: TXEN,
+    pub txd0: TXD0,
+    pub txd1: TXD1,
+    pub txd2: TXD2,
+    pub txd3: TXD3,
+    pub rxd0: RXD0,
+    pub rxd1: RXD1,
+    pub rxd2: RXD2,
+    pub rxd3: RXD3,
+}
+
+impl<TXCLK, RXCLK, RXDV, TXEN, TXD0, TXD1, TXD2, TXD3, RXD0, RXD1, RXD2, RXD3> sealed::Sealed
+    for MiiPins<TXCLK, RXCLK, RXDV, TXEN, TXD0, TXD1, TXD2, TXD3, RXD0, RXD1, RXD2, RXD3>
+where
+    TXCLK: TxClk,
+    RXCLK: RefClk,
+    RXDV: CrsDv,
+    TXEN: TxEn,
+    TXD0: TxD0,
+    TXD1: TxD1,
+    TXD2: TxD2,
+    TXD3: TxD3,
+    RXD0: RxD0,
+    RXD1: RxD1,
+    RXD2: RxD2,
+    RXD3: RxD3,
+{
+}
+
+impl<TXCLK, RXCLK, RXDV, TXEN, TXD0, TXD1, TXD2, TXD3, RXD0, RXD1, RXD2, RXD3> Pins
+    for MiiPins<TXCLK, RXCLK, RXDV, TXEN, TXD0, TXD1, TXD2, TXD3, RXD0, RXD1, RXD2, RXD3>
+where
+    TXCLK: TxClk,
+    RXCLK: RefClk,
+    RXDV: CrsDv,
+    TXEN: TxEn,
+    TXD0: TxD0,
+    TXD1: TxD1,
+    TXD2: TxD2,
+    TXD3: TxD3,
+    RXD0: RxD0,
+    RXD1: RxD1,
+    RXD2: RxD2,
+    RXD3: RxD3,
+{
+    const RMII: bool = false;
+}
@@ -0,0 +1,138 @@
+use super::Ethernet;
+use crate::pac::EXTI;
+
+/// EXTI line of the Ethernet wakeup event
+const EXTI_LINE: u32 = 19;
+
+/// Wakeup events detected in power-down mode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WakeupEvents {
+    /// Magic packet: 6 bytes of 0xFF followed by the station address repeated
+    /// 16 times
+    pub magic_packet: bool,
+    /// Frame matching one of the wakeup frame filters
+    pub wakeup_frame: bool,
+    /// Any unicast frame passing the destination address filter
+    pub global_unicast: bool,
+}
+
+/// Remote wakeup frame filter
+///
+/// Bit `n` of `mask` selects the byte `offset + n` of the frame. The CRC-16
+/// of the selected bytes (polynomial 0x8005, bit-reversed, as computed by
+/// the Ethernet CRC logic of the MAC) is compared against `crc`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WakeupFilter {
+    pub enabled: bool,
+    /// Match multicast frames only
+    pub multicast: bool,
+    pub mask: u32,
+    /// Offset of the first byte checked, at least 12 to skip the addresses
+    pub offset: u8,
+    pub crc: u16,
+}
+
+impl Ethernet<'_, '_> {
+    /// Loads the four remote wakeup frame filters
+    pub fn set_wakeup_filters(&mut self, filters: &[WakeupFilter; 4]) {
+        let mut command = 0;
+        let mut offsets = 0;
+        for (i, filter) in filters.iter().enumerate() {
+            let enable = u32::from(filter.enabled) | (u32::from(filter.multicast) << 3);
+            command |= enable << (8 * i);
+            offsets |= u32::from(filter.offset) << (8 * i);
+        }
+
+        // The eight words are written in sequence through the same register
+        self.mac.macpmtcsr.modify(|_, w| w.wffrpr().set_bit());
+        for filter in filters {
+            self.mac
+                .macrwuffer
+                .write(|w| unsafe { w.bits(filter.mask) });
+        }
+        for word in [
+            command,
+            offsets,
+            u32::from(filters[0].crc) | (u32::from(filters[1].crc) << 16),
+            u32::from(filters[2].crc) | (u32::from(filters[3].crc) << 16),
+        ] {
+            self.mac.macrwuffer.write(|w| unsafe { w.bits(word) });
+        }
+    }
+
+    /// Routes the wakeup event to the EXTI line 19, to be handled by the
+    /// `ETH_WKUP` interrupt or to wake the MCU from Stop mode
+    pub fn listen_wakeup(&mut self, exti: &mut EXTI) {
+        self.mac.macimr.modify(|_, w| w.pmtim().clear_bit());
+        exti.imr
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << EXTI_LINE)) });
+        exti.rtsr
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << EXTI_LINE)) });
+    }
+
+    /// Clears the EXTI line 19 pending bit, and the wakeup status of the MAC.
+    /// Returns the events received, the global unicast wakeups are not
+    /// reported by the MAC.
+    pub fn clear_wakeup(&mut self, exti: &mut EXTI) -> WakeupEvents {
+        exti.pr.write(|w| unsafe { w.bits(1 << EXTI_LINE) });
+        // The flags are cleared by reading the register
+        let pmtcsr = self.mac.macpmtcsr.read();
+        WakeupEvents {
+            magic_packet: pmtcsr.mpr().bit_is_set(),
+            wakeup_frame: pmtcsr.wfr().bit_is_set(),
+            global_unicast: false,
+        }
+    }
+
+    /// Stops the transmission and the DMA, and puts the MAC in power-down
+    /// mode until one of the `events` is received. Only the MAC receiver
+    /// keeps running, the PHY clocks must stay enabled.
+    ///
+    /// Once woken up, the MAC leaves the power-down mode by itself, call
+    /// [`Ethernet::resume`] to restart the DMA.
+    pub fn power_down(&mut self, events: WakeupEvents) {
+        // Let the pending frames go out
+        self.dma.dmaomr.modify(|_, w| w.st().clear_bit());
+        while self.dma.dmasr.read().tps().bits() != 0 {}
+        self.mac
+            .maccr
+            .modify(|_, w| w.te().clear_bit().re().clear_bit());
+
+        // Let the DMA empty the receive FIFO (RFFL)
+        while (self.mac.macdbgr.read().bits() >> 8) & 0b11 != 0 {}
+        self.dma.dmaomr.modify(|_, w| w.sr().clear_bit());
+
+        self.mac.macpmtcsr.modify(|_, w| {
+            w.mpe()
+                .bit(events.magic_packet)
+                .wfe()
+                .bit(events.wakeup_frame)
+                .gu()
+                .bit(events.global_unicast)
+                .pd()
+                .set_bit()
+        });
+        self.mac.maccr.modify(|_, w| w.re().set_bit());
+    }
+
+    /// Restarts the transmission and reception after a wakeup
+    pub fn resume(&mut self) {
+        self.mac.macpmtcsr.modify(|_, w| {
+            w.pd()
+                .clear_bit()
+                .mpe()
+                .clear_bit()
+                .wfe()
+                .clear_bit()
+                .gu()
+                .clear_bit()
+        });
+        self.mac
+            .maccr
+            .modify(|_, w| w.te().set_bit().re().set_bit());
+        self.dma
+            .dmaomr
+            .modify(|_, w| w.st().set_bit().sr().set_bit());
+        self.dma.dmarpdr.write(|w| unsafe { w.rpd().bits(1) });
+    }
+}
@@ -0,0 +1,191 @@
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{fence, Ordering};
+
+use super::desc::Descriptor;
+use super::BUFFER_SIZE;
+use crate::pac::{ethernet_dma, ETHERNET_DMA};
+
+/// Owned by the DMA
+const RDES0_OWN: u32 = 1 << 31;
+/// Error summary
+const RDES0_ES: u32 = 1 << 15;
+/// First descriptor of the frame
+const RDES0_FS: u32 = 1 << 9;
+/// Last descriptor of the frame
+const RDES0_LS: u32 = 1 << 8;
+/// IPv4 or IPv6 frame, with the checksum offload
+const RDES0_FT: u32 = 1 << 5;
+/// IP header checksum error, with the checksum offload
+const RDES0_IPHCE: u32 = 1 << 7;
+/// TCP, UDP or ICMP checksum error, with the checksum offload
+const RDES0_PCE: u32 = 1 << 0;
+const RDES0_FL_SHIFT: u32 = 16;
+const RDES0_FL_MASK: u32 = 0x3fff;
+/// Second address chained
+const RDES1_RCH: u32 = 1 << 14;
+
+/// Receive errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RxError {
+    /// No frame was received
+    WouldBlock,
+    /// The frame did not fit in one buffer and was dropped
+    Truncated,
+    /// The frame was received with a CRC, length or overflow error
+    DmaError,
+    /// The IP header or payload checksum is invalid
+    Checksum,
+}
+
+/// Receive descriptor with its buffer
+#[repr(C)]
+pub struct RxRingEntry {
+    desc: Descriptor,
+    buffer: [u8; BUFFER_SIZE],
+}
+
+impl RxRingEntry {
+    /// Entry to initialize the ring arrays with
+    pub const INIT: Self = Self::new();
+
+    pub const fn new() -> Self {
+        Self {
+            desc: Descriptor::new(),
+            buffer: [0; BUFFER_SIZE],
+        }
+    }
+
+    fn is_owned_by_dma(&self) -> bool {
+        self.desc.read(0) & RDES0_OWN != 0
+    }
+
+    /// Gives the descriptor back to the DMA
+    fn release(&mut self) {
+        // The buffer accesses must be done before the DMA owns it again
+        fence(Ordering::Release);
+        self.desc.write(0, RDES0_OWN);
+        fence(Ordering::SeqCst);
+    }
+}
+
+impl Default for RxRingEntry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Chained ring of receive descriptors
+pub(super) struct RxRing<'a> {
+    entries: &'a mut [RxRingEntry],
+    next: usize,
+    checksum_offload: bool,
+}
+
+impl<'a> RxRing<'a> {
+    pub fn new(entries: &'a mut [RxRingEntry], checksum_offload: bool) -> Self {
+        assert!(!entries.is_empty());
+
+        let len = entries.len();
+        for i in 0..len {
+            let next = &entries[(i + 1) % len].desc as *const Descriptor as u32;
+            let entry = &mut entries[i];
+            let buffer = entry.buffer.as_ptr() as u32;
+            entry.desc.write(1, RDES1_RCH | BUFFER_SIZE as u32);
+            entry.desc.write(2, buffer);
+            entry.desc.write(3, next);
+            entry.release();
+        }
+
+        Self {
+            entries,
+            next: 0,
+            checksum_offload,
+        }
+    }
+
+    /// Address of the first descriptor, for the DMA list address register
+    pub fn address(&self) -> u32 {
+        &self.entries[0].desc as *const Descriptor as u32
+    }
+
+    pub fn recv_next(
+        &mut self,
+        dma: &ethernet_dma::RegisterBlock,
+    ) -> Result<RxPacket<'_>, RxError> {
+        let index = self.next;
+        if self.entries[index].is_owned_by_dma() {
+            return Err(RxError::WouldBlock);
+        }
+        // The buffer must be read after the descriptor status
+        fence(Ordering::Acquire);
+
+        self.next = (index + 1) % self.entries.len();
+        let entry = &mut self.entries[index];
+
+        let status = entry.desc.read(0);
+        let result = if status & (RDES0_FS | RDES0_LS) != RDES0_FS | RDES0_LS {
+            Err(RxError::Truncated)
+        } else if self.checksum_offload
+            && status & RDES0_FT != 0
+            && status & (RDES0_IPHCE | RDES0_PCE) != 0
+        {
+            Err(RxError::Checksum)
+        } else if status & RDES0_ES != 0 {
+            Err(RxError::DmaError)
+        } else {
+            Ok(((status >> RDES0_FL_SHIFT) & RDES0_FL_MASK) as usize)
+        };
+
+        match result {
+            Ok(length) => Ok(RxPacket {
+                entry,
+                length: length.min(BUFFER_SIZE),
+            }),
+            Err(e) => {
+                entry.release();
+                resume(dma);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Restarts the reception if it was suspended for lack of descriptors
+fn resume(dma: &ethernet_dma::RegisterBlock) {
+    dma.dmarpdr.write(|w| unsafe { w.rpd().bits(1) });
+}
+
+/// Received frame, giving its buffer back to the DMA when dropped
+pub struct RxPacket<'a> {
+    entry: &'a mut RxRingEntry,
+    length: usize,
+}
+
+impl RxPacket<'_> {
+    /// Returns the raw receive status word (RDES0) of the frame
+    pub fn status(&self) -> u32 {
+        self.entry.desc.read(0)
+    }
+}
+
+impl Deref for RxPacket<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.entry.buffer[..self.length]
+    }
+}
+
+impl DerefMut for RxPacket<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.entry.buffer[..self.length]
+    }
+}
+
+impl Drop for RxPacket<'_> {
+    fn drop(&mut self) {
+        self.entry.release();
+        // NOTE(unsafe) the demand poll register is write-only and stateless
+        resume(unsafe { &*ETHERNET_DMA::ptr() });
+    }
+}
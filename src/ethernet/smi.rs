@@ -0,0 +1,86 @@
+use crate::pac::{ethernet_mac, ETHERNET_MAC};
+
+/// Access to the PHY registers through the station management interface
+/// (MDIO)
+pub trait StationManagement {
+    /// Reads the register `reg` of the PHY at address `phy`
+    fn smi_read(&mut self, phy: u8, reg: u8) -> u16;
+    /// Writes `value` to the register `reg` of the PHY at address `phy`
+    fn smi_write(&mut self, phy: u8, reg: u8, value: u16);
+}
+
+/// Station management interface of the MAC, borrowing the MDIO and MDC pins
+pub struct Smi<'a> {
+    mac: &'a ETHERNET_MAC,
+}
+
+impl<'a> Smi<'a> {
+    pub(super) fn new(mac: &'a ETHERNET_MAC) -> Self {
+        Self { mac }
+    }
+
+    fn wait_ready(mac: &ethernet_mac::RegisterBlock) {
+        while mac.macmiiar.read().mb().bit_is_set() {}
+    }
+}
+
+impl StationManagement for Smi<'_> {
+    fn smi_read(&mut self, phy: u8, reg: u8) -> u16 {
+        Self::wait_ready(self.mac);
+        // The MDC clock range is kept
+        self.mac.macmiiar.modify(|_, w| {
+            w.pa()
+                .bits(phy)
+                .mr()
+                .bits(reg)
+                .mw()
+                .clear_bit()
+                .mb()
+                .set_bit()
+        });
+        Self::wait_ready(self.mac);
+        self.mac.macmiidr.read().md().bits()
+    }
+
+    fn smi_write(&mut self, phy: u8, reg: u8, value: u16) {
+        Self::wait_ready(self.mac);
+        self.mac.macmiidr.write(|w| w.md().bits(value));
+        self.mac.macmiiar.modify(|_, w| {
+            w.pa()
+                .bits(phy)
+                .mr()
+                .bits(reg)
+                .mw()
+                .set_bit()
+                .mb()
+                .set_bit()
+        });
+        Self::wait_ready(self.mac);
+    }
+}
+
+/// Returns the MACMIIAR CR value keeping MDC within 2.5 MHz, for `hclk` in
+/// Hz
+pub(super) fn mdc_clock_range(hclk: u32) -> u8 {
+    match hclk {
+        0..=34_999_999 => 0b010,
+        35_000_000..=59_999_999 => 0b011,
+        60_000_000..=99_999_999 => 0b000,
+        100_000_000..=149_999_999 => 0b001,
+        _ => 0b100,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mdc_clock_range() {
+        assert_eq!(mdc_clock_range(25_000_000), 0b010);
+        assert_eq!(mdc_clock_range(48_000_000), 0b011);
+        assert_eq!(mdc_clock_range(96_000_000), 0b000);
+        assert_eq!(mdc_clock_range(100_000_000), 0b001);
+        assert_eq!(mdc_clock_range(216_000_000), 0b100);
+    }
+}
@@ -0,0 +1,139 @@
+use core::sync::atomic::{fence, Ordering};
+
+use super::desc::Descriptor;
+use super::BUFFER_SIZE;
+use crate::pac::ethernet_dma;
+
+/// Owned by the DMA
+const TDES0_OWN: u32 = 1 << 31;
+/// Interrupt on completion
+const TDES0_IC: u32 = 1 << 30;
+/// Last segment of the frame
+const TDES0_LS: u32 = 1 << 29;
+/// First segment of the frame
+const TDES0_FS: u32 = 1 << 28;
+/// Checksum insertion of the IP header and of the payload, with the
+/// pseudo-header
+const TDES0_CIC_FULL: u32 = 0b11 << 22;
+/// Second address chained
+const TDES0_TCH: u32 = 1 << 20;
+
+/// Transmit errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxError {
+    /// All the transmit descriptors are in use
+    WouldBlock,
+    /// The frame is longer than a buffer
+    TooLong,
+}
+
+/// Transmit descriptor with its buffer
+#[repr(C)]
+pub struct TxRingEntry {
+    desc: Descriptor,
+    buffer: [u8; BUFFER_SIZE],
+}
+
+impl TxRingEntry {
+    /// Entry to initialize the ring arrays with
+    pub const INIT: Self = Self::new();
+
+    pub const fn new() -> Self {
+        Self {
+            desc: Descriptor::new(),
+            buffer: [0; BUFFER_SIZE],
+        }
+    }
+
+    fn is_owned_by_dma(&self) -> bool {
+        self.desc.read(0) & TDES0_OWN != 0
+    }
+}
+
+impl Default for TxRingEntry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Chained ring of transmit descriptors
+pub(super) struct TxRing<'a> {
+    entries: &'a mut [TxRingEntry],
+    next: usize,
+    checksum_offload: bool,
+}
+
+impl<'a> TxRing<'a> {
+    pub fn new(entries: &'a mut [TxRingEntry], checksum_offload: bool) -> Self {
+        assert!(!entries.is_empty());
+
+        let len = entries.len();
+        for i in 0..len {
+            let next = &entries[(i + 1) % len].desc as *const Descriptor as u32;
+            let entry = &mut entries[i];
+            let buffer = entry.buffer.as_ptr() as u32;
+            entry.desc.write(0, TDES0_TCH);
+            entry.desc.write(1, 0);
+            entry.desc.write(2, buffer);
+            entry.desc.write(3, next);
+        }
+
+        Self {
+            entries,
+            next: 0,
+            checksum_offload,
+        }
+    }
+
+    /// Address of the first descriptor, for the DMA list address register
+    pub fn address(&self) -> u32 {
+        &self.entries[0].desc as *const Descriptor as u32
+    }
+
+    /// Returns `true` if a descriptor is free for a new frame
+    pub fn is_available(&self) -> bool {
+        !self.entries[self.next].is_owned_by_dma()
+    }
+
+    pub fn send<F, R>(
+        &mut self,
+        dma: &ethernet_dma::RegisterBlock,
+        length: usize,
+        f: F,
+    ) -> Result<R, TxError>
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        if length > BUFFER_SIZE {
+            return Err(TxError::TooLong);
+        }
+        let entry = &mut self.entries[self.next];
+        if entry.is_owned_by_dma() {
+            return Err(TxError::WouldBlock);
+        }
+        // The buffer must be written after the descriptor status is read
+        fence(Ordering::Acquire);
+
+        let result = f(&mut entry.buffer[..length]);
+
+        let cic = if self.checksum_offload {
+            TDES0_CIC_FULL
+        } else {
+            0
+        };
+        entry.desc.write(1, length as u32);
+        fence(Ordering::Release);
+        entry.desc.write(
+            0,
+            TDES0_OWN | TDES0_IC | TDES0_LS | TDES0_FS | cic | TDES0_TCH,
+        );
+        fence(Ordering::SeqCst);
+
+        self.next = (self.next + 1) % self.entries.len();
+
+        // Restart the transmission if it was suspended for lack of frames
+        dma.dmatpdr.write(|w| unsafe { w.tpd().bits(1) });
+
+        Ok(result)
+    }
+}
@@ -1,13 +1,34 @@
 //! Flash memory
+//!
+//! The sectors are erased one at a time, or all at once, and programmed with
+//! the parallelism allowed by the supply voltage, see [`Parallelism`]. The
+//! option bytes are read and programmed with [`Flash::option_bytes`] and
+//! [`Flash::program_option_bytes`].
+//!
+//! On the STM32F76x and STM32F77x, the flash memory can be split in two banks,
+//! to update the firmware of one bank while executing the other one.
 
 use crate::pac::FLASH;
+use crate::signature::FlashSize;
 use nb::block;
 
+#[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+mod dual_bank;
+mod option_bytes;
+
+#[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+pub use dual_bank::Bank;
+pub use option_bytes::{BorLevel, Irreversible, OptionBytes, Persistent, ReadProtection};
+
 /// Base address of flash memory on AXIM interface.
 const FLASH_BASE: *mut u8 = 0x800_0000 as *mut u8;
 
-/// The last valid flash address in any STM32F7 device
-const MAX_FLASH_ADDRESS: *mut u8 = 0x81F_FFFF as *mut u8;
+/// Size of the first sectors, in KB. The fifth sector is 4 times larger, and
+/// the next ones 8 times.
+#[cfg(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730"))]
+const SMALL_SECTOR_KB: u32 = 16;
+#[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+const SMALL_SECTOR_KB: u32 = 32;
 
 /// Flash programming error.
 #[derive(Debug, PartialEq, Eq)]
@@ -18,17 +39,101 @@ pub enum Error {
     ProgrammingParallelism,
     ProgrammingAlignment,
     WriteProtection,
+    /// The operation was not started, because of a previous error
+    Operation,
+    /// A read of a proprietary code protected sector
+    #[cfg(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730"))]
+    ReadProtection,
+    /// The sector doesn't exist on this device
+    InvalidSector,
+    /// The data are not aligned on the parallelism
+    Unaligned,
+    /// The data go past the end of the flash memory
+    OutOfBounds,
+    /// The option bytes can't be changed with the read protection level 2
+    OptionBytesLocked,
+    /// The programmed value doesn't match
+    Verification,
+    /// The flash memory is not in dual bank mode
+    #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+    SingleBank,
+}
+
+/// Number of bits programmed at once, and erased in parallel
+///
+/// It is limited by the supply voltage:
+/// - `X8` from 1.7 V,
+/// - `X16` from 2.1 V,
+/// - `X32` from 2.7 V,
+/// - `X64` with an external programming voltage on VPP, of 8 to 9 V.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parallelism {
+    X8,
+    X16,
+    X32,
+    X64,
+}
+
+impl Parallelism {
+    /// Returns the number of bytes programmed at once
+    pub fn bytes(self) -> usize {
+        match self {
+            Parallelism::X8 => 1,
+            Parallelism::X16 => 2,
+            Parallelism::X32 => 4,
+            Parallelism::X64 => 8,
+        }
+    }
+}
+
+/// Sector of the flash memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sector {
+    pub number: u8,
+    /// Offset from the beginning of the flash memory
+    pub offset: usize,
+    /// Size in bytes
+    pub size: usize,
 }
 
 /// Embedded flash memory.
 pub struct Flash {
     registers: FLASH,
+    parallelism: Parallelism,
 }
 
 impl Flash {
-    /// Creates a new Flash instance.
+    /// Creates a new Flash instance, programming bytes one at a time.
     pub fn new(flash: FLASH) -> Self {
-        Self { registers: flash }
+        Self {
+            registers: flash,
+            parallelism: Parallelism::X8,
+        }
+    }
+
+    /// Sets the parallelism of the erase and programming operations, which
+    /// must be allowed by the supply voltage.
+    pub fn set_parallelism(&mut self, parallelism: Parallelism) {
+        self.parallelism = parallelism;
+    }
+
+    /// Returns the size of the flash memory, in bytes.
+    pub fn size(&self) -> usize {
+        FlashSize::get().bytes()
+    }
+
+    /// Returns the sector `number`, if it exists on this device.
+    ///
+    /// In dual bank mode, the sectors of the second bank are numbered from 12.
+    pub fn sector(&self, number: u8) -> Option<Sector> {
+        let flash_kb = FlashSize::get().kilo_bytes().into();
+
+        #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+        if self.is_dual_bank() {
+            return dual_bank_sector(flash_kb, number);
+        }
+
+        sector(SMALL_SECTOR_KB, flash_kb, number)
     }
 
     /// Unlocks the flash memory.
@@ -58,6 +163,20 @@ impl Flash {
         self.registers.sr.read().bsy().bit_is_set()
     }
 
+    /// Sets PSIZE for the parallelism.
+    fn set_psize(&mut self) {
+        let parallelism = self.parallelism;
+        self.registers.cr.modify(|_, w| {
+            let psize = w.psize();
+            match parallelism {
+                Parallelism::X8 => psize.psize8(),
+                Parallelism::X16 => psize.psize16(),
+                Parallelism::X32 => psize.psize32(),
+                Parallelism::X64 => psize.psize64(),
+            }
+        });
+    }
+
     /// Starts a sector erase sequence.
     ///
     /// The returned `EraseSequence` object can be used to wait for the completion of the
@@ -94,6 +213,9 @@ impl Flash {
     ///
     /// Note that you must block on the `wait` method in the returned `ProgrammingSequence` object
     /// in order to program all bytes.
+    ///
+    /// The offset and the length of the data must be multiples of the parallelism, or
+    /// `Error::Unaligned` is returned.
     pub fn program<'a, 'b>(
         &'a mut self,
         start_offset: usize,
@@ -130,7 +252,14 @@ impl Flash {
     fn check_errors(&self) -> Result<(), Error> {
         let sr = self.registers.sr.read();
 
-        if sr.erserr().bit_is_set() {
+        #[cfg(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730"))]
+        if sr.rderr().bit_is_set() {
+            return Err(Error::ReadProtection);
+        }
+
+        if sr.operr().bit_is_set() {
+            Err(Error::Operation)
+        } else if sr.erserr().bit_is_set() {
             Err(Error::EraseSequence)
         } else if sr.pgperr().bit_is_set() {
             Err(Error::ProgrammingParallelism)
@@ -146,7 +275,11 @@ impl Flash {
     /// Clears all error flags.
     fn clear_errors(&mut self) {
         self.registers.sr.write(|w| {
-            w.erserr()
+            #[cfg(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730"))]
+            w.rderr().set_bit();
+            w.operr()
+                .set_bit()
+                .erserr()
                 .set_bit()
                 .pgperr()
                 .set_bit()
@@ -158,6 +291,45 @@ impl Flash {
     }
 }
 
+/// Returns the sector `number`, where the first sectors are `small_sector_kb`
+/// KB large, of a flash memory of `flash_kb` KB
+fn sector(small_sector_kb: u32, flash_kb: u32, number: u8) -> Option<Sector> {
+    let size_kb = |number| match number {
+        0..=3 => small_sector_kb,
+        4 => small_sector_kb * 4,
+        _ => small_sector_kb * 8,
+    };
+    let offset_kb: u32 = (0..number).map(size_kb).sum();
+    let size_kb = size_kb(number);
+    if offset_kb + size_kb > flash_kb {
+        return None;
+    }
+
+    Some(Sector {
+        number,
+        offset: offset_kb as usize * 1024,
+        size: size_kb as usize * 1024,
+    })
+}
+
+/// Returns the sector `number` of a flash memory of `flash_kb` KB in dual bank
+/// mode, where each bank has 12 sectors of half the single bank size
+#[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+fn dual_bank_sector(flash_kb: u32, number: u8) -> Option<Sector> {
+    let bank_kb = flash_kb / 2;
+    let (index, bank_offset) = if number < 12 {
+        (number, 0)
+    } else {
+        (number - 12, bank_kb as usize * 1024)
+    };
+
+    sector(SMALL_SECTOR_KB / 2, bank_kb, index).map(|sector| Sector {
+        number,
+        offset: bank_offset + sector.offset,
+        ..sector
+    })
+}
+
 /// Erase sequence.
 pub struct EraseSequence<'a> {
     flash: &'a mut Flash,
@@ -167,9 +339,19 @@ impl<'a> EraseSequence<'a> {
     /// Creates a sector erase sequence.
     fn new_erase_sector(flash: &'a mut Flash, sector_number: u8) -> Result<Self, Error> {
         flash.check_locked_or_busy()?;
+        if flash.sector(sector_number).is_none() {
+            return Err(Error::InvalidSector);
+        }
         flash.clear_errors();
+        flash.set_psize();
 
-        //TODO: This should check if sector_number is valid for this device
+        // the sectors of the second bank start at 0b10000
+        #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+        let sector_number = if sector_number < 12 {
+            sector_number
+        } else {
+            sector_number - 12 + 0x10
+        };
 
         flash.registers.cr.modify(|_, w| unsafe {
             #[cfg(any(
@@ -201,6 +383,7 @@ impl<'a> EraseSequence<'a> {
     fn new_mass_erase(flash: &'a mut Flash) -> Result<Self, Error> {
         flash.check_locked_or_busy()?;
         flash.clear_errors();
+        flash.set_psize();
 
         flash.registers.cr.modify(|_, w| unsafe {
             #[cfg(any(
@@ -220,7 +403,7 @@ impl<'a> EraseSequence<'a> {
                 feature = "stm32f778",
                 feature = "stm32f779",
             )))]
-            w.mer().clear_bit();
+            w.mer().set_bit();
             w.ser().clear_bit()
         });
 
@@ -229,6 +412,22 @@ impl<'a> EraseSequence<'a> {
         Ok(Self { flash })
     }
 
+    /// Creates an erase sequence of the selected banks.
+    #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+    fn new_bank_erase(flash: &'a mut Flash, bank1: bool, bank2: bool) -> Result<Self, Error> {
+        flash.check_locked_or_busy()?;
+        flash.clear_errors();
+        flash.set_psize();
+
+        flash
+            .registers
+            .cr
+            .modify(|_, w| w.mer1().bit(bank1).mer2().bit(bank2).ser().clear_bit());
+        flash.registers.cr.modify(|_, w| w.strt().start());
+
+        Ok(Self { flash })
+    }
+
     /// Waits until the erase sequence is finished.
     pub fn wait(&mut self) -> nb::Result<(), Error> {
         self.flash.check_errors().map_err(nb::Error::from)?;
@@ -252,12 +451,19 @@ impl<'a, 'b> ProgrammingSequence<'a, 'b> {
     /// Creates a programming sequence.
     fn new(flash: &'a mut Flash, start_offset: usize, data: &'b [u8]) -> Result<Self, Error> {
         flash.check_locked_or_busy()?;
-        flash.clear_errors();
 
-        flash
-            .registers
-            .cr
-            .modify(|_, w| w.psize().psize8().pg().set_bit());
+        let unit = flash.parallelism.bytes();
+        if start_offset % unit != 0 || data.len() % unit != 0 {
+            return Err(Error::Unaligned);
+        }
+        match start_offset.checked_add(data.len()) {
+            Some(end) if end <= flash.size() => {}
+            _ => return Err(Error::OutOfBounds),
+        }
+
+        flash.clear_errors();
+        flash.set_psize();
+        flash.registers.cr.modify(|_, w| w.pg().set_bit());
 
         let address = unsafe { FLASH_BASE.add(start_offset) };
 
@@ -281,17 +487,39 @@ impl<'a, 'b> ProgrammingSequence<'a, 'b> {
             return Err(error.into());
         }
 
-        if let Some((first, rest)) = self.data.split_first() {
-            if self.address >= FLASH_BASE && self.address <= MAX_FLASH_ADDRESS {
-                unsafe {
-                    core::ptr::write_volatile(self.address, *first);
+        if !self.data.is_empty() {
+            let (unit, rest) = self.data.split_at(self.flash.parallelism.bytes());
+            unsafe {
+                match self.flash.parallelism {
+                    Parallelism::X8 => core::ptr::write_volatile(self.address, unit[0]),
+                    Parallelism::X16 => core::ptr::write_volatile(
+                        self.address as *mut u16,
+                        u16::from_le_bytes([unit[0], unit[1]]),
+                    ),
+                    Parallelism::X32 => core::ptr::write_volatile(
+                        self.address as *mut u32,
+                        u32::from_le_bytes([unit[0], unit[1], unit[2], unit[3]]),
+                    ),
+                    Parallelism::X64 => {
+                        let address = self.address as *mut u32;
+                        core::ptr::write_volatile(
+                            address,
+                            u32::from_le_bytes([unit[0], unit[1], unit[2], unit[3]]),
+                        );
+                        // the double word is written as two words, in order
+                        cortex_m::asm::isb();
+                        core::ptr::write_volatile(
+                            address.add(1),
+                            u32::from_le_bytes([unit[4], unit[5], unit[6], unit[7]]),
+                        );
+                    }
                 }
             }
 
-            // ensure data is written byte by byte to prevent programming parallelism errors
+            // ensure data is written unit by unit to prevent programming parallelism errors
             cortex_m::asm::dmb();
 
-            self.address = unsafe { self.address.add(1) };
+            self.address = unsafe { self.address.add(unit.len()) };
             self.data = rest;
 
             Err(nb::Error::WouldBlock)
@@ -302,3 +530,52 @@ impl<'a, 'b> ProgrammingSequence<'a, 'b> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sectors() {
+        // STM32F722 with 512 KB
+        assert_eq!(
+            sector(16, 512, 4),
+            Some(Sector {
+                number: 4,
+                offset: 0x1_0000,
+                size: 0x1_0000
+            })
+        );
+        assert_eq!(sector(16, 512, 7).map(|s| s.offset), Some(0x6_0000));
+        assert_eq!(sector(16, 512, 8), None);
+        // STM32F767 with 2 MB in single bank mode
+        assert_eq!(sector(32, 2048, 11).map(|s| s.offset), Some(0x1C_0000));
+        assert_eq!(sector(32, 2048, 12), None);
+        // STM32F746 with 512 KB
+        assert_eq!(sector(32, 512, 6), None);
+    }
+
+    #[test]
+    #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+    fn dual_bank_sectors() {
+        // STM32F767 with 2 MB
+        assert_eq!(dual_bank_sector(2048, 11).map(|s| s.offset), Some(0xE_0000));
+        assert_eq!(
+            dual_bank_sector(2048, 12),
+            Some(Sector {
+                number: 12,
+                offset: 0x10_0000,
+                size: 0x4000
+            })
+        );
+        assert_eq!(
+            dual_bank_sector(2048, 23).map(|s| s.offset),
+            Some(0x1E_0000)
+        );
+        assert_eq!(dual_bank_sector(2048, 24), None);
+        // STM32F765 with 1 MB
+        assert_eq!(dual_bank_sector(1024, 8), None);
+        assert_eq!(dual_bank_sector(1024, 19).map(|s| s.offset), Some(0xE_0000));
+        assert_eq!(dual_bank_sector(1024, 20), None);
+    }
+}
@@ -0,0 +1,114 @@
+//! Dual bank mode
+//!
+//! With the nDBANK option byte cleared, the flash memory is split in two banks
+//! of 12 sectors each, numbered 0 to 11 and 12 to 23. One bank can be erased
+//! and programmed while the code is executed from the other one, and the
+//! device boots from the bank selected by the BOOT_ADD0 option byte.
+
+use super::{EraseSequence, Error, Flash, FlashSize, FLASH_BASE};
+use nb::block;
+
+/// Base address of flash memory on ITCM interface.
+const FLASH_ITCM_BASE: usize = 0x0020_0000;
+
+/// Flash memory bank
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bank {
+    Bank1,
+    Bank2,
+}
+
+impl Bank {
+    /// Returns the other bank.
+    pub fn other(self) -> Self {
+        match self {
+            Bank::Bank1 => Bank::Bank2,
+            Bank::Bank2 => Bank::Bank1,
+        }
+    }
+}
+
+impl Flash {
+    /// Returns `true` if the flash memory is in dual bank mode.
+    pub fn is_dual_bank(&self) -> bool {
+        self.registers.optcr.read().n_dbank().bit_is_clear()
+    }
+
+    /// Returns the offset of `bank` from the beginning of the flash memory, or
+    /// `None` in single bank mode.
+    pub fn bank_offset(&self, bank: Bank) -> Option<usize> {
+        if !self.is_dual_bank() {
+            return None;
+        }
+
+        match bank {
+            Bank::Bank1 => Some(0),
+            Bank::Bank2 => Some(FlashSize::get().bytes() / 2),
+        }
+    }
+
+    /// Returns the bank the code is executed from, or `None` in single bank
+    /// mode or when executing from RAM.
+    pub fn executing_bank(&self) -> Option<Bank> {
+        let bank2 = self.bank_offset(Bank::Bank2)?;
+        let address = Self::executing_bank as fn(&Self) -> Option<Bank> as usize;
+        let offset = match address.checked_sub(FLASH_BASE as usize) {
+            Some(offset) if offset < 2 * bank2 => offset,
+            _ => address
+                .checked_sub(FLASH_ITCM_BASE)
+                .filter(|&offset| offset < 2 * bank2)?,
+        };
+
+        if offset < bank2 {
+            Some(Bank::Bank1)
+        } else {
+            Some(Bank::Bank2)
+        }
+    }
+
+    /// Starts an erase sequence of `bank`.
+    ///
+    /// The returned `EraseSequence` object can be used to wait for the completion of the
+    /// erase sequence by blocking on the `wait` method.
+    pub fn erase_bank(&mut self, bank: Bank) -> Result<EraseSequence<'_>, Error> {
+        if !self.is_dual_bank() {
+            return Err(Error::SingleBank);
+        }
+
+        EraseSequence::new_bank_erase(self, bank == Bank::Bank1, bank == Bank::Bank2)
+    }
+
+    /// Erases `bank`.
+    ///
+    /// This method blocks until the bank is erased or an error occurred.
+    pub fn blocking_erase_bank(&mut self, bank: Bank) -> Result<(), Error> {
+        let mut sequence = self.erase_bank(bank)?;
+        block!(sequence.wait())
+    }
+
+    /// Returns the bank the device boots from, when the BOOT pin is low.
+    pub fn boot_bank(&self) -> Option<Bank> {
+        let bank2 = self.bank_offset(Bank::Bank2)?;
+        let address = self.option_bytes().boot_address0 as usize;
+
+        if address == FLASH_BASE as usize + bank2 || address == FLASH_ITCM_BASE + bank2 {
+            Some(Bank::Bank2)
+        } else {
+            Some(Bank::Bank1)
+        }
+    }
+
+    /// Selects the bank the device boots from after the next reset, when the
+    /// BOOT pin is low, by programming the BOOT_ADD0 option byte.
+    ///
+    /// Together with [`Flash::executing_bank`], it allows to program an update
+    /// in the other bank, and to switch to it once it is complete. The code
+    /// must be linked at the address of the bank it is executed from.
+    pub fn set_boot_bank(&mut self, bank: Bank) -> Result<(), Error> {
+        let offset = self.bank_offset(bank).ok_or(Error::SingleBank)?;
+
+        let mut options = self.option_bytes();
+        options.boot_address0 = (FLASH_BASE as usize + offset) as u32;
+        self.program_option_bytes(&options)
+    }
+}
@@ -0,0 +1,244 @@
+//! Option bytes
+//!
+//! The option bytes are programmed as a whole, from the values returned by
+//! [`Flash::option_bytes`], and are loaded by the next system reset.
+
+use super::{Error, Flash};
+
+/// First key of the option bytes unlock sequence
+const OPTKEY1: u32 = 0x0819_2A3B;
+/// Second key of the option bytes unlock sequence
+const OPTKEY2: u32 = 0x4C5D_6E7F;
+
+/// RDP value of the level 0
+const RDP_LEVEL_0: u8 = 0xAA;
+/// RDP value of the level 2
+const RDP_LEVEL_2: u8 = 0xCC;
+
+/// Read protection level
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadProtection {
+    /// No protection
+    Level0,
+    /// The flash memory can't be read by the debugger, or when booting from
+    /// RAM or the system memory. Going back to the level 0 mass erases the
+    /// flash memory.
+    Level1,
+    /// Same as the level 1, with the debug interface, the boot from RAM or the
+    /// system memory and the option bytes changes permanently disabled.
+    Level2(Irreversible),
+}
+
+/// Confirmation that the read protection level 2 may be programmed
+///
+/// The level 2 can never be removed: the device can't be debugged nor
+/// reprogrammed from the outside anymore.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Irreversible(());
+
+impl Irreversible {
+    /// Confirms that the read protection level 2 may be programmed.
+    pub fn confirm() -> Self {
+        Self(())
+    }
+}
+
+/// Confirmation that an option byte may be changed, the value being kept
+/// after a reset and a power loss until it is programmed again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Persistent(());
+
+impl Persistent {
+    /// Confirms that the option byte may be changed.
+    pub fn confirm() -> Self {
+        Self(())
+    }
+}
+
+/// Brownout reset threshold level
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorLevel {
+    /// Reset when the supply voltage is below 1.7 V, the POR/PDR threshold
+    Off,
+    /// Reset below about 2.1 V
+    Level1,
+    /// Reset below about 2.4 V
+    Level2,
+    /// Reset below about 2.7 V
+    Level3,
+}
+
+impl BorLevel {
+    fn bits(self) -> u8 {
+        match self {
+            BorLevel::Level3 => 0b00,
+            BorLevel::Level2 => 0b01,
+            BorLevel::Level1 => 0b10,
+            BorLevel::Off => 0b11,
+        }
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => BorLevel::Level3,
+            0b01 => BorLevel::Level2,
+            0b10 => BorLevel::Level1,
+            _ => BorLevel::Off,
+        }
+    }
+}
+
+/// Option bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OptionBytes {
+    pub read_protection: ReadProtection,
+    pub bor_level: BorLevel,
+    /// Write protected sectors, one bit per sector
+    ///
+    /// In dual bank mode, the bit `n` protects the sectors `n` and `n + 12`.
+    pub write_protection: u16,
+    /// Boot address when the BOOT pin is low, aligned on 16 KB
+    pub boot_address0: u32,
+    /// Boot address when the BOOT pin is high, aligned on 16 KB
+    pub boot_address1: u32,
+    /// The flash memory is split in two banks
+    #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+    pub dual_bank: bool,
+    /// Booting from the second bank is allowed, in dual bank mode
+    #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+    pub dual_boot: bool,
+}
+
+impl Flash {
+    /// Returns the current option bytes.
+    ///
+    /// These are the values loaded at reset, or the last programmed ones.
+    pub fn option_bytes(&self) -> OptionBytes {
+        let optcr = self.registers.optcr.read();
+        let optcr1 = self.registers.optcr1.read();
+
+        let read_protection = match optcr.rdp().bits() {
+            RDP_LEVEL_0 => ReadProtection::Level0,
+            RDP_LEVEL_2 => ReadProtection::Level2(Irreversible(())),
+            _ => ReadProtection::Level1,
+        };
+
+        // nWRP is only 8 bits on the single bank devices
+        #[allow(clippy::useless_conversion)]
+        let write_protection = !u16::from(optcr.n_wrp().bits()) & sectors_mask();
+
+        OptionBytes {
+            read_protection,
+            bor_level: BorLevel::from_bits(optcr.bor_lev().bits()),
+            write_protection,
+            boot_address0: u32::from(optcr1.boot_add0().bits()) << 14,
+            boot_address1: u32::from(optcr1.boot_add1().bits()) << 14,
+            #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+            dual_bank: optcr.n_dbank().bit_is_clear(),
+            #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+            dual_boot: optcr.n_dboot().bit_is_clear(),
+        }
+    }
+
+    /// Programs the option bytes, which take effect after the next reset.
+    ///
+    /// The other option bytes, the watchdog and low-power reset options, are
+    /// kept. This method blocks until the option bytes are programmed or an
+    /// error occurred.
+    ///
+    /// Going from the read protection level 1 to the level 0 mass erases the
+    /// flash memory, and changing the banks mode makes its content invalid.
+    pub fn program_option_bytes(&mut self, options: &OptionBytes) -> Result<(), Error> {
+        if self.is_busy() {
+            return Err(Error::Busy);
+        }
+        self.clear_errors();
+        self.unlock_option_bytes();
+
+        let rdp = match options.read_protection {
+            ReadProtection::Level0 => RDP_LEVEL_0,
+            ReadProtection::Level1 => 0x00,
+            ReadProtection::Level2(_) => RDP_LEVEL_2,
+        };
+
+        self.registers.optcr1.modify(|_, w| unsafe {
+            w.boot_add0()
+                .bits((options.boot_address0 >> 14) as u16)
+                .boot_add1()
+                .bits((options.boot_address1 >> 14) as u16)
+        });
+        self.registers.optcr.modify(|_, w| unsafe {
+            #[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+            w.n_dbank()
+                .bit(!options.dual_bank)
+                .n_dboot()
+                .bit(!options.dual_boot);
+            w.rdp()
+                .bits(rdp)
+                .bor_lev()
+                .bits(options.bor_level.bits())
+                .n_wrp()
+                .bits(!(options.write_protection & sectors_mask()) as _)
+        });
+        self.registers.optcr.modify(|_, w| w.optstrt().set_bit());
+
+        while self.is_busy() {}
+        let result = self.check_errors();
+
+        self.registers.optcr.modify(|_, w| w.optlock().set_bit());
+
+        result
+    }
+
+    /// Sets the brownout reset threshold, which takes effect after the next
+    /// reset.
+    ///
+    /// The threshold must be below the lowest supply voltage, or the device
+    /// stays in reset. Nothing is programmed if `level` is already set.
+    pub fn set_brownout_level(
+        &mut self,
+        level: BorLevel,
+        _confirm: Persistent,
+    ) -> Result<(), Error> {
+        let mut options = self.option_bytes();
+        if options.bor_level == level {
+            return Ok(());
+        }
+        if let ReadProtection::Level2(_) = options.read_protection {
+            return Err(Error::OptionBytesLocked);
+        }
+
+        options.bor_level = level;
+        self.program_option_bytes(&options)?;
+
+        if self.option_bytes().bor_level != level {
+            return Err(Error::Verification);
+        }
+
+        Ok(())
+    }
+
+    /// Unlocks the option bytes.
+    fn unlock_option_bytes(&mut self) {
+        if self.registers.optcr.read().optlock().bit_is_clear() {
+            // same as the flash memory, unlocking twice causes a HardFault
+            return;
+        }
+
+        self.registers.optkeyr.write(|w| w.optkeyr().bits(OPTKEY1));
+        self.registers.optkeyr.write(|w| w.optkeyr().bits(OPTKEY2));
+    }
+}
+
+/// Returns the mask of the nWRP bits
+fn sectors_mask() -> u16 {
+    if cfg!(any(
+        feature = "svd-f765",
+        feature = "svd-f7x7",
+        feature = "svd-f7x9"
+    )) {
+        0x0FFF
+    } else {
+        0x00FF
+    }
+}
@@ -1,6 +1,10 @@
 //! HAL for Flexible memory controller (FMC)
 //!
-//! See the stm32-fmc [usage guide](https://github.com/stm32-rs/stm32-fmc#usage)
+//! See the stm32-fmc [usage guide](https://github.com/stm32-rs/stm32-fmc#usage) for the SDRAM
+//! memories, whose low-power modes are controlled with [`SdramLowPower`]. The asynchronous SRAM,
+//! PSRAM and NOR flash memories of the bank 1 are configured with [`NorSram`].
+//!
+//! With the `fmc-alloc` feature, `ExternalHeap` is a global allocator over an external memory.
 
 // From stm32_fmc
 use stm32_fmc::FmcPeripheral;
@@ -12,6 +16,19 @@ use fugit::HertzU32 as Hertz;
 
 use crate::gpio::{self, Alternate};
 
+#[cfg(feature = "fmc-alloc")]
+mod heap;
+mod sdram;
+mod sram;
+mod timing;
+
+#[cfg(feature = "fmc-alloc")]
+pub use heap::ExternalHeap;
+
+pub use sdram::{SdramLowPower, SdramMode};
+pub use sram::{MemoryType, MemoryWidth, NorSram, NorSramBank, NorSramConfig, WaitPolarity};
+pub use timing::{AccessMode, AsyncTimingNs, SdramTimingNs, Timing, TimingError};
+
 /// Storage type for Flexible Memory Controller and its clocks
 pub struct FMC {
     pub fmc: pac::FMC,
@@ -48,6 +65,20 @@ pub trait FmcExt: Sized {
         let fmc = self.fmc(clocks);
         Sdram::new_unchecked(fmc, bank, chip)
     }
+
+    /// A new SRAM, PSRAM or NOR flash memory on a sub-bank of the bank 1, via the Flexible
+    /// Memory Controller
+    fn nor_sram(
+        self,
+        bank: NorSramBank,
+        config: &NorSramConfig,
+        read_timing: &Timing,
+        write_timing: &Timing,
+        clocks: &Clocks,
+    ) -> NorSram {
+        let fmc = self.fmc(clocks);
+        NorSram::new(fmc, bank, config, read_timing, write_timing)
+    }
 }
 
 impl FmcExt for pac::FMC {
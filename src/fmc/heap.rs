@@ -0,0 +1,143 @@
+//! Heap in the external memory
+//!
+//! The SDRAM banks are mapped as device memory by default, where the
+//! unaligned accesses of the `alloc` collections fault, and are not cached.
+//! [`ExternalHeap::init`] maps the memory as normal memory with the MPU,
+//! cached or not, before handing it to the allocator.
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static HEAP: ExternalHeap = ExternalHeap::empty();
+//!
+//! let ram = sdram.init(&mut delay);
+//! unsafe { HEAP.init(&mut cp.MPU, 0, ram as *mut u8, 8 * 1024 * 1024, true) };
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::RefCell;
+use core::ptr::{self, NonNull};
+
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::peripheral::MPU;
+use linked_list_allocator::Heap;
+
+/// Region enable, in the MPU_RASR register
+const RASR_ENABLE: u32 = 1 << 0;
+/// Full access, privileged and unprivileged, in the MPU_RASR register
+const RASR_AP_FULL: u32 = 0b011 << 24;
+/// Normal memory, in the MPU_RASR register
+const RASR_TEX_NORMAL: u32 = 0b001 << 19;
+/// Write-back, write and read allocate, in the MPU_RASR register
+const RASR_CB_WRITE_BACK: u32 = 0b11 << 16;
+/// MPU enabled, with the default memory map for the other regions
+const CTRL_ENABLE: u32 = 1 << 0;
+const CTRL_PRIVDEFENA: u32 = 1 << 2;
+
+/// Allocator over an external memory, such as an SDRAM or an SRAM
+///
+/// The heap is shared with the interrupt handlers, each allocation running
+/// in a critical section.
+pub struct ExternalHeap {
+    heap: Mutex<RefCell<Heap>>,
+}
+
+impl ExternalHeap {
+    /// Creates an empty heap, to be initialized with [`ExternalHeap::init`]
+    /// before the first allocation
+    pub const fn empty() -> Self {
+        ExternalHeap {
+            heap: Mutex::new(RefCell::new(Heap::empty())),
+        }
+    }
+
+    /// Maps the `size` bytes at `start` as normal memory with the MPU region
+    /// `region`, cached in write-back mode if `cacheable`, and hands them to
+    /// the allocator.
+    ///
+    /// The region covers `size` rounded up to a power of two, and `start`
+    /// must be aligned on it. The memory shared with the DMA must not be
+    /// cacheable, or the cache must be cleaned and invalidated around the
+    /// transfers.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be initialized, and used by nothing else. This must be
+    /// called only once.
+    pub unsafe fn init(
+        &self,
+        mpu: &mut MPU,
+        region: u8,
+        start: *mut u8,
+        size: usize,
+        cacheable: bool,
+    ) {
+        configure_region(mpu, region, start as u32, size, cacheable);
+        interrupt::free(|cs| self.heap.borrow(cs).borrow_mut().init(start, size));
+    }
+
+    /// Returns the number of bytes allocated
+    pub fn used(&self) -> usize {
+        interrupt::free(|cs| self.heap.borrow(cs).borrow().used())
+    }
+
+    /// Returns the number of bytes left
+    pub fn free(&self) -> usize {
+        interrupt::free(|cs| self.heap.borrow(cs).borrow().free())
+    }
+}
+
+unsafe impl GlobalAlloc for ExternalHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        interrupt::free(|cs| {
+            self.heap
+                .borrow(cs)
+                .borrow_mut()
+                .allocate_first_fit(layout)
+                .map_or(ptr::null_mut(), NonNull::as_ptr)
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        interrupt::free(|cs| {
+            self.heap
+                .borrow(cs)
+                .borrow_mut()
+                .deallocate(NonNull::new_unchecked(ptr), layout)
+        });
+    }
+}
+
+/// Maps `size` bytes, rounded up to a power of two, at `address` as normal
+/// memory, and enables the MPU
+unsafe fn configure_region(mpu: &mut MPU, region: u8, address: u32, size: usize, cacheable: bool) {
+    let size = size.next_power_of_two().max(32);
+    assert!(address as usize % size == 0);
+
+    cortex_m::asm::dmb();
+    mpu.rnr.write(u32::from(region));
+    mpu.rbar.write(address);
+    mpu.rasr.write(rasr(size.trailing_zeros() as u8, cacheable));
+    mpu.ctrl.write(CTRL_ENABLE | CTRL_PRIVDEFENA);
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+/// Returns the MPU_RASR value of a normal memory region of 2^`size_log2`
+/// bytes
+fn rasr(size_log2: u8, cacheable: bool) -> u32 {
+    let cache = if cacheable { RASR_CB_WRITE_BACK } else { 0 };
+    RASR_AP_FULL | RASR_TEX_NORMAL | cache | (u32::from(size_log2 - 1) << 1) | RASR_ENABLE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rasr() {
+        // 8 MB, normal memory, write-back
+        assert_eq!(rasr(23, true), 0x030b_002d);
+        // 8 MB, normal memory, not cacheable
+        assert_eq!(rasr(23, false), 0x0308_002d);
+    }
+}
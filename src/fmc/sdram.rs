@@ -0,0 +1,105 @@
+//! Low-power modes of the SDRAM memories
+//!
+//! An SDRAM in self-refresh mode keeps its contents without the FMC clock,
+//! so the MCU can enter Stop mode: the memory is put in self-refresh mode
+//! with [`SdramLowPower::enter_self_refresh`] before, and returned to normal
+//! mode with [`SdramLowPower::exit_low_power`] after waking up, once the
+//! clocks are configured again. In power-down mode, the memory must still
+//! be refreshed by the FMC, but draws less current between the accesses.
+
+use stm32_fmc::{Sdram, SdramChip, SdramTargetBank};
+
+use super::FMC;
+use crate::pac;
+
+/// Mode of an SDRAM bank
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SdramMode {
+    Normal,
+    SelfRefresh,
+    PowerDown,
+}
+
+/// Low-power commands of an initialized SDRAM
+pub trait SdramLowPower {
+    /// Puts the SDRAM of `bank` in self-refresh mode, and waits until it is
+    fn enter_self_refresh(&mut self, bank: SdramTargetBank);
+
+    /// Puts the SDRAM of `bank` in power-down mode, left automatically on
+    /// the next access
+    fn enter_power_down(&mut self, bank: SdramTargetBank);
+
+    /// Returns the SDRAM of `bank` to normal mode, from the self-refresh or
+    /// power-down mode
+    fn exit_low_power(&mut self, bank: SdramTargetBank);
+
+    /// Sends `number` consecutive auto-refresh commands, from 1 to 15, to the
+    /// SDRAM of `bank`, such as after leaving the self-refresh mode
+    fn auto_refresh(&mut self, bank: SdramTargetBank, number: u8);
+
+    /// Returns the mode of the SDRAM of `bank`, which must be `Bank1` or
+    /// `Bank2`
+    fn mode(&self, bank: SdramTargetBank) -> SdramMode;
+}
+
+impl<IC: SdramChip> SdramLowPower for Sdram<FMC, IC> {
+    fn enter_self_refresh(&mut self, bank: SdramTargetBank) {
+        send_command(bank, |w| w.mode().self_refresh_command());
+
+        let banks: &[SdramTargetBank] = match bank {
+            SdramTargetBank::Both => &[SdramTargetBank::Bank1, SdramTargetBank::Bank2],
+            _ => &[bank],
+        };
+        for bank in banks {
+            while self.mode(*bank) != SdramMode::SelfRefresh {}
+        }
+    }
+
+    fn enter_power_down(&mut self, bank: SdramTargetBank) {
+        send_command(bank, |w| w.mode().power_down_command());
+    }
+
+    fn exit_low_power(&mut self, bank: SdramTargetBank) {
+        send_command(bank, |w| w.mode().normal());
+    }
+
+    fn auto_refresh(&mut self, bank: SdramTargetBank, number: u8) {
+        assert!((1..=15).contains(&number));
+        send_command(bank, |w| {
+            w.mode().auto_refresh_command().nrfs().bits(number - 1)
+        });
+    }
+
+    fn mode(&self, bank: SdramTargetBank) -> SdramMode {
+        // The SDRAM is owned by `self`
+        let sdsr = unsafe { &(*pac::FMC::ptr()).sdsr }.read();
+        let mode = match bank {
+            SdramTargetBank::Bank1 => sdsr.modes1().bits(),
+            SdramTargetBank::Bank2 => sdsr.modes2().bits(),
+            SdramTargetBank::Both => panic!("The mode of a single bank must be read"),
+        };
+        match mode {
+            0 => SdramMode::Normal,
+            1 => SdramMode::SelfRefresh,
+            _ => SdramMode::PowerDown,
+        }
+    }
+}
+
+/// Waits for the SDRAM controller to be ready, and sends the command written
+/// by `f` to `bank`
+fn send_command<F>(bank: SdramTargetBank, f: F)
+where
+    F: FnOnce(&mut pac::fmc::sdcmr::W) -> &mut pac::fmc::sdcmr::W,
+{
+    // Only called with the SDRAM, which owns the FMC, borrowed mutably
+    let fmc = unsafe { &*pac::FMC::ptr() };
+    while fmc.sdsr.read().busy().bit_is_set() {}
+
+    let (ctb1, ctb2) = match bank {
+        SdramTargetBank::Bank1 => (true, false),
+        SdramTargetBank::Bank2 => (false, true),
+        SdramTargetBank::Both => (true, true),
+    };
+    fmc.sdcmr.write(|w| f(w.ctb1().bit(ctb1).ctb2().bit(ctb2)));
+}
@@ -0,0 +1,208 @@
+//! Asynchronous SRAM, PSRAM and NOR flash memories on the bank 1
+//!
+//! The bank 1 is split in four sub-banks of 64 MB, each with its own chip
+//! select output (NE1 to NE4) and configuration. The memory is accessed in
+//! asynchronous mode, with separate read and write [`Timing`]s, the data
+//! phase being optionally extended by the memory with the NWAIT signal.
+//!
+//! The bank 1 is mapped as normal, cacheable memory by default. The command
+//! sequences of the NOR flash memories must go through an MPU region marked
+//! as device memory, or the writes may be merged or reordered by the cache.
+
+use stm32_fmc::FmcPeripheral;
+
+use super::{Timing, FMC};
+
+/// Size of a sub-bank of the bank 1
+const SUB_BANK_SIZE: usize = 0x0400_0000;
+
+/// Sub-bank of the bank 1, selected by the NE1 to NE4 chip select outputs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NorSramBank {
+    Bank1,
+    Bank2,
+    Bank3,
+    Bank4,
+}
+
+impl NorSramBank {
+    /// Returns the start address of the sub-bank
+    pub fn address(self) -> usize {
+        0x6000_0000 + SUB_BANK_SIZE * self as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryType {
+    Sram,
+    Psram,
+    NorFlash,
+}
+
+/// Width of the data bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryWidth {
+    Bits8,
+    Bits16,
+    Bits32,
+}
+
+/// Level of the NWAIT signal while the memory inserts wait states
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitPolarity {
+    ActiveLow,
+    ActiveHigh,
+}
+
+/// Configuration of an asynchronous memory
+#[derive(Clone, Copy, Debug)]
+pub struct NorSramConfig {
+    pub memory_type: MemoryType,
+    pub width: MemoryWidth,
+    /// Size of the memory in bytes, up to 64 MB
+    pub size: usize,
+    /// The low address bits are multiplexed on the data lines, and latched
+    /// by the memory on the NADV signal
+    pub multiplexed: bool,
+    /// Polarity of the NWAIT signal, or `None` to ignore it
+    pub wait: Option<WaitPolarity>,
+    /// Allows the writes, which may be disabled for a NOR flash memory
+    pub write_enable: bool,
+}
+
+/// Asynchronous memory on a sub-bank of the bank 1
+pub struct NorSram {
+    fmc: FMC,
+    bank: NorSramBank,
+    size: usize,
+}
+
+macro_rules! configure_bcr {
+    ($bcr:expr, $config:expr) => {
+        $bcr.write(|w| {
+            let w = match $config.memory_type {
+                MemoryType::Sram => w.mtyp().sram(),
+                MemoryType::Psram => w.mtyp().psram(),
+                // The NOR flash memories need the NOR flash access enabled
+                MemoryType::NorFlash => w.mtyp().flash().faccen().enabled(),
+            };
+            let w = match $config.width {
+                MemoryWidth::Bits8 => w.mwid().bits8(),
+                MemoryWidth::Bits16 => w.mwid().bits16(),
+                MemoryWidth::Bits32 => w.mwid().bits32(),
+            };
+            let w = match $config.wait {
+                Some(WaitPolarity::ActiveLow) => w.asyncwait().enabled().waitpol().active_low(),
+                Some(WaitPolarity::ActiveHigh) => w.asyncwait().enabled().waitpol().active_high(),
+                None => w.asyncwait().disabled(),
+            };
+            w
+                // Asynchronous mode, without bursts
+                .cburstrw()
+                .disabled()
+                .bursten()
+                .disabled()
+                .waiten()
+                .disabled()
+                // Extended mode, for different read and write timings
+                .extmod()
+                .enabled()
+                .wren()
+                .bit($config.write_enable)
+                .muxen()
+                .bit($config.multiplexed)
+                // Enable this memory bank
+                .mbken()
+                .enabled()
+        })
+    };
+}
+
+impl NorSram {
+    /// Configures the sub-bank `bank` for the memory described by `config`.
+    /// The timings are in HCLK cycles.
+    ///
+    /// The FMC pins of the memory must have been configured in alternate
+    /// function 12.
+    pub fn new(
+        mut fmc: FMC,
+        bank: NorSramBank,
+        config: &NorSramConfig,
+        read_timing: &Timing,
+        write_timing: &Timing,
+    ) -> Self {
+        assert!(config.size > 0 && config.size <= SUB_BANK_SIZE);
+        fmc.enable();
+
+        let regs = &fmc.fmc;
+        match bank {
+            NorSramBank::Bank1 => configure_bcr!(regs.bcr1, config),
+            NorSramBank::Bank2 => configure_bcr!(regs.bcr2, config),
+            NorSramBank::Bank3 => configure_bcr!(regs.bcr3, config),
+            NorSramBank::Bank4 => configure_bcr!(regs.bcr4, config),
+        }
+        let (btr, bwtr) = match bank {
+            NorSramBank::Bank1 => (&regs.btr1, &regs.bwtr1),
+            NorSramBank::Bank2 => (&regs.btr2, &regs.bwtr2),
+            NorSramBank::Bank3 => (&regs.btr3, &regs.bwtr3),
+            NorSramBank::Bank4 => (&regs.btr4, &regs.bwtr4),
+        };
+        btr.write(|w| unsafe {
+            w.accmod()
+                .variant(read_timing.access_mode.as_read_variant())
+                .busturn()
+                .bits(read_timing.bus_turnaround)
+                .datast()
+                .bits(read_timing.data)
+                .addhld()
+                .bits(read_timing.address_hold)
+                .addset()
+                .bits(read_timing.address_setup)
+        });
+        bwtr.write(|w| unsafe {
+            w.accmod()
+                .variant(write_timing.access_mode.as_write_variant())
+                .busturn()
+                .bits(write_timing.bus_turnaround)
+                .datast()
+                .bits(write_timing.data)
+                .addhld()
+                .bits(write_timing.address_hold)
+                .addset()
+                .bits(write_timing.address_setup)
+        });
+
+        NorSram {
+            fmc,
+            bank,
+            size: config.size,
+        }
+    }
+
+    /// Returns a pointer to the start of the memory, for the accesses
+    /// narrower than the data bus or with side effects, such as the NOR flash
+    /// commands
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.bank.address() as *mut u8
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.bank.address() as *const u8, self.size) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.bank.address() as *mut u8, self.size) }
+    }
+
+    /// Disables the sub-bank, and releases the FMC
+    pub fn free(self) -> FMC {
+        let regs = &self.fmc.fmc;
+        match self.bank {
+            NorSramBank::Bank1 => regs.bcr1.modify(|_, w| w.mbken().disabled()),
+            NorSramBank::Bank2 => regs.bcr2.modify(|_, w| w.mbken().disabled()),
+            NorSramBank::Bank3 => regs.bcr3.modify(|_, w| w.mbken().disabled()),
+            NorSramBank::Bank4 => regs.bcr4.modify(|_, w| w.mbken().disabled()),
+        }
+        self.fmc
+    }
+}
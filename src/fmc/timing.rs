@@ -0,0 +1,311 @@
+//! FMC timing
+
+use stm32_fmc::SdramTiming;
+
+use crate::pac::fmc;
+use crate::rcc::Clocks;
+
+/// Memory access modes
+///
+/// These define the general shape of a transaction and the meanings of some of the time fields.
+/// Refer to the microcontroller reference manual for more details.
+#[derive(Debug, Clone)]
+pub enum AccessMode {
+    ModeA,
+    ModeB,
+    ModeC,
+    ModeD,
+}
+
+impl AccessMode {
+    pub(crate) fn as_read_variant(&self) -> fmc::btr::ACCMOD_A {
+        use fmc::btr::ACCMOD_A;
+        match *self {
+            AccessMode::ModeA => ACCMOD_A::A,
+            AccessMode::ModeB => ACCMOD_A::B,
+            AccessMode::ModeC => ACCMOD_A::C,
+            AccessMode::ModeD => ACCMOD_A::D,
+        }
+    }
+    pub(crate) fn as_write_variant(&self) -> fmc::bwtr::ACCMOD_A {
+        use fmc::bwtr::ACCMOD_A;
+        match *self {
+            AccessMode::ModeA => ACCMOD_A::A,
+            AccessMode::ModeB => ACCMOD_A::B,
+            AccessMode::ModeC => ACCMOD_A::C,
+            AccessMode::ModeD => ACCMOD_A::D,
+        }
+    }
+}
+
+/// Timing configuration for reading or writing
+///
+/// A `Timing` object can be created using `Timing::default()` or `Default::default()`.
+///
+/// The default timing uses access mode C and the slowest possible timings, for maximum
+/// compatibility.
+///
+/// If the memory or LCD controller and the wiring allow, you can reduce the times to make
+/// transactions faster.
+///
+/// All time fields are in units of HCLK cycles.
+#[derive(Debug, Clone)]
+pub struct Timing {
+    pub(crate) access_mode: AccessMode,
+    pub(crate) bus_turnaround: u8,
+    pub(crate) data: u8,
+    pub(crate) address_hold: u8,
+    pub(crate) address_setup: u8,
+}
+
+impl Default for Timing {
+    /// Returns a conservative (slow) timing configuration with access mode C
+    fn default() -> Self {
+        Timing {
+            access_mode: AccessMode::ModeC,
+            bus_turnaround: Timing::BUS_TURNAROUND_MAX,
+            data: 255,
+            address_hold: Timing::ADDRESS_HOLD_MAX,
+            address_setup: Timing::ADDRESS_SETUP_MAX,
+        }
+    }
+}
+
+impl Timing {
+    /// Maximum allowed value of the bus turnaround time
+    pub const BUS_TURNAROUND_MAX: u8 = 15;
+    /// Minimum allowed value of the data phase time
+    pub const DATA_MIN: u8 = 1;
+    /// Maximum allowed value of the address hold time
+    pub const ADDRESS_HOLD_MIN: u8 = 1;
+    /// Maximum allowed value of the address hold time
+    pub const ADDRESS_HOLD_MAX: u8 = 15;
+    /// Maximum allowed value of the address setup time
+    pub const ADDRESS_SETUP_MAX: u8 = 15;
+
+    /// Sets the access mode
+    pub fn access_mode(self, access_mode: AccessMode) -> Self {
+        Timing {
+            access_mode,
+            ..self
+        }
+    }
+    /// Sets the bus turnaround time, in units of HCLK cycles
+    ///
+    /// This corresponds to the BUSTURN field of FMC_BTR or FMC_BWTR.
+    pub fn bus_turnaround(self, bus_turnaround: u8) -> Self {
+        Timing {
+            bus_turnaround,
+            ..self
+        }
+    }
+    /// Sets the data phase time, in units of HCLK cycles
+    ///
+    /// This corresponds to the DATAST field of FMC_BTR or FMC_BWTR.
+    pub fn data(self, data: u8) -> Self {
+        Timing { data, ..self }
+    }
+    /// Sets the address hold phase time, in units of HCLK cycles
+    ///
+    /// This corresponds to the ADDHLD field of FMC_BTR or FMC_BWTR.
+    pub fn address_hold(self, address_hold: u8) -> Self {
+        Timing {
+            address_hold,
+            ..self
+        }
+    }
+    /// Sets the address setup phase time, in units of HCLK cycles
+    ///
+    /// This corresponds to the ADDSET field of FMC_BTR or FMC_BWTR.
+    pub fn address_setup(self, address_setup: u8) -> Self {
+        Timing {
+            address_setup,
+            ..self
+        }
+    }
+}
+
+/// Error of the conversion of nanosecond timings into FMC cycles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingError {
+    /// A timing needs more cycles than its register field holds
+    TooLong,
+    /// HCLK divided by 3 is still faster than the SDRAM clock
+    SdClockTooFast,
+    /// The CAS latency is not 1, 2 or 3 cycles
+    CasLatency,
+}
+
+/// Asynchronous memory timings, in nanoseconds
+///
+/// The times are rounded up to whole HCLK cycles by [`Timing::from_ns`].
+#[derive(Debug, Clone, Copy)]
+pub struct AsyncTimingNs {
+    pub address_setup: u32,
+    pub address_hold: u32,
+    pub data: u32,
+    pub bus_turnaround: u32,
+}
+
+impl Timing {
+    /// Converts the nanosecond timings of a memory or LCD controller into HCLK cycles, rounding
+    /// up, or fails if they don't fit in the timing registers
+    pub fn from_ns(
+        clocks: &Clocks,
+        access_mode: AccessMode,
+        timing: &AsyncTimingNs,
+    ) -> Result<Self, TimingError> {
+        let hclk = clocks.hclk().raw();
+        Ok(Timing {
+            access_mode,
+            bus_turnaround: cycles(timing.bus_turnaround, hclk, 0, Self::BUS_TURNAROUND_MAX)?,
+            data: cycles(timing.data, hclk, Self::DATA_MIN, 255)?,
+            address_hold: cycles(
+                timing.address_hold,
+                hclk,
+                Self::ADDRESS_HOLD_MIN,
+                Self::ADDRESS_HOLD_MAX,
+            )?,
+            address_setup: cycles(timing.address_setup, hclk, 0, Self::ADDRESS_SETUP_MAX)?,
+        })
+    }
+}
+
+/// SDRAM timings, in nanoseconds as given by the datasheets
+///
+/// The times are converted into cycles of the SDRAM clock, HCLK divided by 2 or 3 as chosen by
+/// `stm32-fmc`, to build the `TIMING` of an `SdramChip`. The CAS latency is computed from the
+/// access time, and checked against the one of the `SdramChip` configuration.
+#[derive(Debug, Clone, Copy)]
+pub struct SdramTimingNs {
+    /// Time between applying a valid clock and any command other than COMMAND INHIBIT or NOP
+    pub startup_delay: u32,
+    /// Maximum SDRAM clock frequency
+    pub max_sd_clock_hz: u32,
+    /// Period between refresh cycles, the refresh time divided by the number of rows
+    pub refresh_period: u32,
+    /// Delay between a LOAD MODE register command and an ACTIVATE command, tMRD
+    pub mode_register_to_active: u32,
+    /// Delay from releasing self refresh to next command, tXSR
+    pub exit_self_refresh: u32,
+    /// Delay between an ACTIVATE and a PRECHARGE command, tRAS
+    pub active_to_precharge: u32,
+    /// Auto refresh command duration, tRC
+    pub row_cycle: u32,
+    /// Delay between a PRECHARGE command and another command, tRP
+    pub row_precharge: u32,
+    /// Delay between an ACTIVATE command and READ/WRITE command, tRCD
+    pub row_to_column: u32,
+    /// Delay between the last data written and a PRECHARGE command, tWR
+    pub write_recovery: u32,
+    /// Access time from the READ command, giving the CAS latency
+    pub cas_access: u32,
+}
+
+impl SdramTimingNs {
+    /// Converts the timings into cycles of the SDRAM clock derived from the HCLK of `clocks`
+    pub fn timing(&self, clocks: &Clocks) -> Result<SdramTiming, TimingError> {
+        self.timing_for_hclk(clocks.hclk().raw())
+    }
+
+    /// Converts the timings into cycles of the SDRAM clock derived from `hclk`, in Hz
+    pub fn timing_for_hclk(&self, hclk: u32) -> Result<SdramTiming, TimingError> {
+        let sd_clock = sd_clock(hclk, self.max_sd_clock_hz)?;
+
+        // The SDTR fields hold 1 to 16 cycles
+        let sdtr = |ns| cycles(ns, sd_clock, 1, 16).map(u32::from);
+        let row_to_column = sdtr(self.row_to_column)?;
+        let row_precharge = sdtr(self.row_precharge)?;
+        // The FMC derives the write recovery time from tRAS and tRC: tRAS is lengthened until
+        // it covers tWR
+        let write_recovery = sdtr(self.write_recovery)?;
+        let active_to_precharge =
+            sdtr(self.active_to_precharge)?.max(row_to_column + write_recovery);
+        let row_cycle = sdtr(self.row_cycle)?.max(row_to_column + row_precharge + 1);
+        if active_to_precharge > 16 || row_cycle > 16 {
+            return Err(TimingError::TooLong);
+        }
+
+        Ok(SdramTiming {
+            startup_delay_ns: self.startup_delay,
+            max_sd_clock_hz: self.max_sd_clock_hz,
+            refresh_period_ns: self.refresh_period,
+            mode_register_to_active: sdtr(self.mode_register_to_active)?,
+            exit_self_refresh: sdtr(self.exit_self_refresh)?,
+            active_to_precharge,
+            row_cycle,
+            row_precharge,
+            row_to_column,
+        })
+    }
+
+    /// Returns the CAS latency, in cycles of the SDRAM clock derived from the HCLK of `clocks`
+    pub fn cas_latency(&self, clocks: &Clocks) -> Result<u8, TimingError> {
+        let sd_clock = sd_clock(clocks.hclk().raw(), self.max_sd_clock_hz)?;
+        cycles(self.cas_access, sd_clock, 1, 3).map_err(|_| TimingError::CasLatency)
+    }
+}
+
+/// Returns the SDRAM clock, in Hz, derived from `hclk` as by `stm32-fmc`
+fn sd_clock(hclk: u32, max_sd_clock_hz: u32) -> Result<u32, TimingError> {
+    let divide = ((hclk + max_sd_clock_hz - 1) / max_sd_clock_hz).max(2);
+    if divide > 3 {
+        return Err(TimingError::SdClockTooFast);
+    }
+    Ok(hclk / divide)
+}
+
+/// Converts `ns` into cycles of `clock`, in Hz, rounding up, and checks that the result is at
+/// most `max`, raising it to `min`
+fn cycles(ns: u32, clock: u32, min: u8, max: u8) -> Result<u8, TimingError> {
+    let cycles = (u64::from(ns) * u64::from(clock) + 999_999_999) / 1_000_000_000;
+    let cycles = cycles.max(u64::from(min));
+    if cycles > u64::from(max) {
+        return Err(TimingError::TooLong);
+    }
+    Ok(cycles as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycles() {
+        // 216 MHz HCLK, 4.63 ns per cycle
+        assert_eq!(cycles(0, 216_000_000, 0, 15), Ok(0));
+        assert_eq!(cycles(0, 216_000_000, 1, 15), Ok(1));
+        assert_eq!(cycles(10, 216_000_000, 0, 15), Ok(3));
+        assert_eq!(cycles(100, 216_000_000, 0, 15), Err(TimingError::TooLong));
+    }
+
+    #[test]
+    fn test_sdram_timing() {
+        // IS42S32800G-6, with a 108 MHz SDRAM clock
+        let timing = SdramTimingNs {
+            startup_delay: 100_000,
+            max_sd_clock_hz: 108_000_000,
+            refresh_period: 15_625,
+            mode_register_to_active: 12,
+            exit_self_refresh: 70,
+            active_to_precharge: 42,
+            row_cycle: 60,
+            row_precharge: 18,
+            row_to_column: 18,
+            write_recovery: 12,
+            cas_access: 18,
+        };
+        let cycles = timing.timing_for_hclk(216_000_000).unwrap();
+        assert_eq!(cycles.mode_register_to_active, 2);
+        assert_eq!(cycles.exit_self_refresh, 8);
+        assert_eq!(cycles.active_to_precharge, 5);
+        assert_eq!(cycles.row_cycle, 7);
+        assert_eq!(cycles.row_precharge, 2);
+        assert_eq!(cycles.row_to_column, 2);
+
+        assert_eq!(
+            timing.timing_for_hclk(400_000_000),
+            Err(TimingError::SdClockTooFast)
+        );
+    }
+}
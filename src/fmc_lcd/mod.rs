@@ -39,8 +39,15 @@
 //! hardware supports.
 //!
 //! The default access mode is mode C. For an example timing diagram, refer to reference manual
-//! [RM0090](https://www.st.com/resource/en/reference_manual/dm00031020.pdf),
-//! figures 443 and 444 (on page 1562), or your microcontroller reference manual.
+//! [RM0410](https://www.st.com/resource/en/reference_manual/dm00224583.pdf),
+//! "NOR flash/PSRAM controller asynchronous transactions" in the FMC section, or your
+//! microcontroller reference manual.
+//!
+//! # Data cache
+//!
+//! The bank 1 of the FMC is mapped as normal, cacheable memory by default. With the data cache
+//! enabled, the writes to the LCD controller may be merged, reordered or never leave the cache:
+//! the bank 1 must then be configured as device memory with the MPU.
 //!
 //! Access modes A, B, and D are also supported.
 //!
@@ -53,15 +60,17 @@
 //!     a. (Optional) Adjust the timing to make read and write operations faster, within the limits
 //!        of the wiring and LCD controller
 //!
-//! 3. Pass the FMC peripheral object, pins, read timing, and write timing to `FmcLcd::new`.
+//! 3. Pass the FMC peripheral object, clocks, pins, read timing, and write timing to
+//!    `FmcLcd::new`.
 //!    This function will return an `FmcLcd` and one or more `Lcd` objects.
 //!
-//! 4. Use the returned `Lcd` object(s) to configure the controller(s) and display graphics
+//! 4. Use the returned `Lcd` object(s) to configure the controller(s) and display graphics.
+//!    `Lcd` implements the `WriteOnlyDataCommand` trait of `display-interface`, so it can be
+//!    given to the display drivers built on it, such as `st7789` or `ili9341`.
 
 mod display_interface_impl;
 mod pins;
 mod sealed;
-mod timing;
 
 use core::marker::PhantomData;
 
@@ -73,7 +82,7 @@ pub use self::pins::{
     PinD1, PinD10, PinD11, PinD12, PinD13, PinD14, PinD15, PinD2, PinD3, PinD4, PinD5, PinD6,
     PinD7, PinD8, PinD9, PinReadEnable, PinWriteEnable, Pins,
 };
-pub use self::timing::{AccessMode, Timing};
+pub use crate::fmc::{AccessMode, Timing};
 
 use crate::prelude::_stm327xx_hal_fmc_FmcExt;
 
@@ -186,7 +195,13 @@ where
     ///     ),
     /// };
     ///
-    /// let (_fmc, mut lcds) = FmcLcd::new(dp.FMC, lcd_pins, &Timing::default(), &Timing::default());
+    /// let (_fmc, mut lcds) = FmcLcd::new(
+    ///     dp.FMC,
+    ///     &clocks,
+    ///     lcd_pins,
+    ///     &Timing::default(),
+    ///     &Timing::default(),
+    /// );
     /// // lcds is a tuple of four `Lcd` objects. Each one can be accessed independently.
     /// // This is just a basic example of some things that can be done.
     /// lcds.0.write_command(37);
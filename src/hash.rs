@@ -0,0 +1,450 @@
+//! Hash processor, for SHA-1, SHA-224, SHA-256 and MD5
+//!
+//! The message is fed in any number of parts, and the unit processes the
+//! blocks as they are complete.
+//!
+//! ```ignore
+//! let mut hash = Hash::new(dp.HASH, &mut rcc.ahb2);
+//! let mut hasher = hash.start(Algorithm::Sha256);
+//! hasher.update(header);
+//! hasher.update(payload);
+//! let mut digest = [0; 32];
+//! hasher.finalize_into(&mut digest);
+//! ```
+//!
+//! The unit also computes HMACs, with keys of any length, with
+//! [`Hash::start_hmac`].
+//!
+//! With the `hash-digest` feature, [`Sha1`], [`Sha224`], [`Sha256`] and
+//! [`Md5`] implement the `digest` traits used by the RustCrypto crates:
+//! `Update`, `FixedOutput`, `FixedOutputReset` and `Reset`. They borrow the
+//! peripheral, so they can't implement `Default`, nor `Digest` which
+//! requires it: generic code takes them with `Update + FixedOutput` bounds.
+//! [`HmacSha1`], [`HmacSha224`], [`HmacSha256`] and [`HmacMd5`] implement
+//! `Mac` the same way, without `KeyInit`.
+
+use crate::pac::HASH;
+use crate::rcc::{Enable, AHB2};
+
+/// Hash algorithm
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha1,
+    Md5,
+    Sha224,
+    Sha256,
+}
+
+impl Algorithm {
+    /// Returns the size of the digest, in bytes
+    pub fn output_size(self) -> usize {
+        match self {
+            Algorithm::Sha1 => 20,
+            Algorithm::Md5 => 16,
+            Algorithm::Sha224 => 28,
+            Algorithm::Sha256 => 32,
+        }
+    }
+
+    /// Returns the value of ALGO[1:0]
+    fn bits(self) -> (bool, bool) {
+        match self {
+            Algorithm::Sha1 => (false, false),
+            Algorithm::Md5 => (false, true),
+            Algorithm::Sha224 => (true, false),
+            Algorithm::Sha256 => (true, true),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The tag of the message doesn't match
+    Authentication,
+}
+
+/// Data swapped as bytes
+const DATATYPE_BYTES: u8 = 0b10;
+/// Size of the blocks of all the algorithms, in bytes, above which the HMAC
+/// key is hashed first
+const BLOCK_SIZE: usize = 64;
+
+/// Hash processor
+pub struct Hash {
+    hash: HASH,
+}
+
+impl Hash {
+    /// Enables the HASH clock. The unit has no reset in the RCC, it is
+    /// initialized for each message.
+    pub fn new(hash: HASH, ahb2: &mut AHB2) -> Self {
+        HASH::enable(ahb2);
+
+        Self { hash }
+    }
+
+    /// Starts a new message digest, abandoning any in progress
+    pub fn start(&mut self, algorithm: Algorithm) -> Hasher<'_> {
+        self.init(algorithm, None);
+        Hasher {
+            hash: self,
+            algorithm,
+            partial: [0; 4],
+            partial_len: 0,
+        }
+    }
+
+    /// Starts a new HMAC with `key`, abandoning any message in progress
+    pub fn start_hmac<'k>(&mut self, algorithm: Algorithm, key: &'k [u8]) -> Hmac<'_, 'k> {
+        self.init(algorithm, Some(key));
+        Hmac {
+            hasher: Hasher {
+                hash: self,
+                algorithm,
+                partial: [0; 4],
+                partial_len: 0,
+            },
+            key,
+        }
+    }
+
+    /// Returns the digest of `data` in `output`, which must be at least
+    /// `algorithm.output_size()` bytes long
+    pub fn digest(&mut self, algorithm: Algorithm, data: &[u8], output: &mut [u8]) {
+        let mut hasher = self.start(algorithm);
+        hasher.update(data);
+        hasher.finalize_into(output);
+    }
+
+    /// Initializes the unit for a message, and for an HMAC, processes the
+    /// key for the inner hash
+    fn init(&mut self, algorithm: Algorithm, hmac_key: Option<&[u8]>) {
+        let (algo1, algo0) = algorithm.bits();
+        let long_key = hmac_key.map_or(false, |key| key.len() > BLOCK_SIZE);
+        self.hash.cr.write(|w| unsafe {
+            w.algo1()
+                .bit(algo1)
+                .algo0()
+                .bit(algo0)
+                .mode()
+                .bit(hmac_key.is_some())
+                .lkey()
+                .bit(long_key)
+                .datatype()
+                .bits(DATATYPE_BYTES)
+                .init()
+                .set_bit()
+        });
+
+        if let Some(key) = hmac_key {
+            self.write_all(key);
+            while self.hash.sr.read().busy().bit_is_set() {}
+        }
+    }
+
+    fn write_word(&mut self, bytes: [u8; 4]) {
+        // The unit swaps the bytes of the words, for the order of the message
+        self.hash
+            .din
+            .write(|w| unsafe { w.bits(u32::from_le_bytes(bytes)) });
+    }
+
+    /// Writes `data`, then ends the message, or the HMAC key
+    fn write_all(&mut self, data: &[u8]) {
+        let mut words = data.chunks_exact(4);
+        for word in &mut words {
+            self.write_word([word[0], word[1], word[2], word[3]]);
+        }
+        self.end(words.remainder());
+    }
+
+    /// Writes the `last` bytes, less than a word, and starts the
+    /// calculation
+    fn end(&mut self, last: &[u8]) {
+        if !last.is_empty() {
+            let mut word = [0; 4];
+            word[..last.len()].copy_from_slice(last);
+            self.write_word(word);
+        }
+        self.hash
+            .str
+            .write(|w| unsafe { w.nblw().bits(last.len() as u8 * 8).dcal().set_bit() });
+    }
+
+    /// Waits for the end of the calculation, and reads the digest
+    fn read_digest(&mut self, output: &mut [u8]) {
+        while self.hash.sr.read().dcis().bit_is_clear() {}
+
+        for (bytes, hr) in output.chunks_exact_mut(4).zip(&self.hash.hash_hr) {
+            bytes.copy_from_slice(&hr.read().bits().to_be_bytes());
+        }
+    }
+
+    /// Releases the HASH peripheral
+    pub fn free(self) -> HASH {
+        self.hash
+    }
+}
+
+/// Message digest in progress
+pub struct Hasher<'a> {
+    hash: &'a mut Hash,
+    algorithm: Algorithm,
+    /// Bytes of the message not written yet, up to a whole word
+    partial: [u8; 4],
+    partial_len: usize,
+}
+
+impl Hasher<'_> {
+    /// Feeds a part of the message
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.partial_len > 0 {
+            let len = data.len().min(4 - self.partial_len);
+            self.partial[self.partial_len..][..len].copy_from_slice(&data[..len]);
+            self.partial_len += len;
+            data = &data[len..];
+            if self.partial_len < 4 {
+                return;
+            }
+            self.hash.write_word(self.partial);
+            self.partial_len = 0;
+        }
+
+        let mut words = data.chunks_exact(4);
+        for word in &mut words {
+            self.hash.write_word([word[0], word[1], word[2], word[3]]);
+        }
+        let remainder = words.remainder();
+        self.partial[..remainder.len()].copy_from_slice(remainder);
+        self.partial_len = remainder.len();
+    }
+
+    /// Returns the algorithm of the digest
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Ends the message and returns its digest in `output`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `output` is shorter than the digest.
+    pub fn finalize_into(mut self, output: &mut [u8]) {
+        self.finish(output);
+    }
+
+    /// Ends the message, returns its digest in `output` and starts a new one
+    ///
+    /// # Panics
+    ///
+    /// Panics if `output` is shorter than the digest.
+    pub fn finalize_into_reset(&mut self, output: &mut [u8]) {
+        self.finish(output);
+        self.reset();
+    }
+
+    fn finish(&mut self, output: &mut [u8]) {
+        let output = &mut output[..self.algorithm.output_size()];
+        self.hash.end(&self.partial[..self.partial_len]);
+        self.hash.read_digest(output);
+    }
+
+    /// Abandons the message and starts a new one
+    pub fn reset(&mut self) {
+        self.hash.init(self.algorithm, None);
+        self.partial_len = 0;
+    }
+}
+
+/// HMAC in progress
+pub struct Hmac<'a, 'k> {
+    hasher: Hasher<'a>,
+    /// The key, processed again for the outer hash
+    key: &'k [u8],
+}
+
+impl Hmac<'_, '_> {
+    /// Feeds a part of the message
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    /// Returns the algorithm of the HMAC
+    pub fn algorithm(&self) -> Algorithm {
+        self.hasher.algorithm
+    }
+
+    /// Ends the message and returns its tag in `output`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `output` is shorter than the tag.
+    pub fn finalize_into(mut self, output: &mut [u8]) {
+        self.finish(output);
+    }
+
+    /// Ends the message, returns its tag in `output` and starts a new one,
+    /// with the same key
+    ///
+    /// # Panics
+    ///
+    /// Panics if `output` is shorter than the tag.
+    pub fn finalize_into_reset(&mut self, output: &mut [u8]) {
+        self.finish(output);
+        self.reset();
+    }
+
+    fn finish(&mut self, output: &mut [u8]) {
+        let output = &mut output[..self.hasher.algorithm.output_size()];
+        let hasher = &mut self.hasher;
+        hasher.hash.end(&hasher.partial[..hasher.partial_len]);
+        while hasher.hash.hash.sr.read().busy().bit_is_set() {}
+        // Key for the outer hash
+        hasher.hash.write_all(self.key);
+        hasher.hash.read_digest(output);
+    }
+
+    /// Ends the message and checks its tag, of the full size
+    pub fn verify(self, tag: &[u8]) -> Result<(), Error> {
+        let mut computed = [0; 32];
+        let len = self.hasher.algorithm.output_size();
+        self.finalize_into(&mut computed);
+        if tag.len() != len {
+            return Err(Error::Authentication);
+        }
+        // All the bytes are compared, for a time independent of the tag
+        let difference = tag
+            .iter()
+            .zip(&computed)
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        if difference == 0 {
+            Ok(())
+        } else {
+            Err(Error::Authentication)
+        }
+    }
+
+    /// Abandons the message and starts a new one, with the same key
+    pub fn reset(&mut self) {
+        self.hasher.hash.init(self.hasher.algorithm, Some(self.key));
+        self.hasher.partial_len = 0;
+    }
+}
+
+#[cfg(feature = "hash-digest")]
+pub use digest_traits::{HmacMd5, HmacSha1, HmacSha224, HmacSha256, Md5, Sha1, Sha224, Sha256};
+
+#[cfg(feature = "hash-digest")]
+mod digest_traits {
+    use digest::{
+        consts::{U16, U20, U28, U32},
+        FixedOutput, FixedOutputReset, HashMarker, MacMarker, Output, OutputSizeUser, Reset,
+        Update,
+    };
+
+    use super::{Algorithm, Hash, Hasher, Hmac};
+
+    macro_rules! digests {
+        ($($name:ident: $algorithm:ident, $size:ty;)+) => {
+            $(
+                #[doc = concat!("`", stringify!($algorithm), "` message digest with the `digest` traits")]
+                pub struct $name<'a>(Hasher<'a>);
+
+                impl<'a> $name<'a> {
+                    pub fn new(hash: &'a mut Hash) -> Self {
+                        Self(hash.start(Algorithm::$algorithm))
+                    }
+                }
+
+                impl HashMarker for $name<'_> {}
+
+                impl OutputSizeUser for $name<'_> {
+                    type OutputSize = $size;
+                }
+
+                impl Update for $name<'_> {
+                    fn update(&mut self, data: &[u8]) {
+                        self.0.update(data);
+                    }
+                }
+
+                impl FixedOutput for $name<'_> {
+                    fn finalize_into(self, out: &mut Output<Self>) {
+                        self.0.finalize_into(out);
+                    }
+                }
+
+                impl Reset for $name<'_> {
+                    fn reset(&mut self) {
+                        self.0.reset();
+                    }
+                }
+
+                impl FixedOutputReset for $name<'_> {
+                    fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+                        self.0.finalize_into_reset(out);
+                    }
+                }
+            )+
+        };
+    }
+
+    digests! {
+        Sha1: Sha1, U20;
+        Sha224: Sha224, U28;
+        Sha256: Sha256, U32;
+        Md5: Md5, U16;
+    }
+
+    macro_rules! macs {
+        ($($name:ident: $algorithm:ident, $size:ty;)+) => {
+            $(
+                #[doc = concat!("HMAC-`", stringify!($algorithm), "` with the `digest` traits")]
+                pub struct $name<'a, 'k>(Hmac<'a, 'k>);
+
+                impl<'a, 'k> $name<'a, 'k> {
+                    pub fn new(hash: &'a mut Hash, key: &'k [u8]) -> Self {
+                        Self(hash.start_hmac(Algorithm::$algorithm, key))
+                    }
+                }
+
+                impl MacMarker for $name<'_, '_> {}
+
+                impl OutputSizeUser for $name<'_, '_> {
+                    type OutputSize = $size;
+                }
+
+                impl Update for $name<'_, '_> {
+                    fn update(&mut self, data: &[u8]) {
+                        self.0.update(data);
+                    }
+                }
+
+                impl FixedOutput for $name<'_, '_> {
+                    fn finalize_into(self, out: &mut Output<Self>) {
+                        self.0.finalize_into(out);
+                    }
+                }
+
+                impl Reset for $name<'_, '_> {
+                    fn reset(&mut self) {
+                        self.0.reset();
+                    }
+                }
+
+                impl FixedOutputReset for $name<'_, '_> {
+                    fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+                        self.0.finalize_into_reset(out);
+                    }
+                }
+            )+
+        };
+    }
+
+    macs! {
+        HmacSha1: Sha1, U20;
+        HmacSha224: Sha224, U28;
+        HmacSha256: Sha256, U32;
+        HmacMd5: Md5, U16;
+    }
+}
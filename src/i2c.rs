@@ -1,17 +1,29 @@
 //! Inter-Integrated Circuit (I2C) bus
 //! For now, only master mode is implemented
+//!
+//! `BlockingI2c` implements the blocking embedded-hal traits. `I2c` offers a
+//! non-blocking API (`start_write`, `write_byte`, `read_byte`, ...) which,
+//! together with `listen`, allows transfers to be driven from the I2C event and
+//! error interrupt handlers. `BlockingI2c::transaction` chains arbitrary read
+//! and write operations with repeated STARTs.
+//!
+//! With the `eh1` feature, `BlockingI2c` also implements the embedded-hal 1.0
+//! `I2c` trait, including `transaction` with repeated STARTs. With the `async`
+//! feature, `I2c` implements the embedded-hal-async `I2c` trait; the transfer
+//! is driven by the I2C interrupts, whose handlers must call `on_interrupt`.
 
 // NB : this implementation started as a modified copy of https://github.com/stm32-rs/stm32f1xx-hal/blob/master/src/i2c.rs
 
 use crate::gpio::{self, Alternate, OpenDrain};
 use crate::hal::blocking::i2c::{Read, Write, WriteRead};
-use crate::pac::{DWT, I2C1, I2C2, I2C3};
-use crate::rcc::{BusClock, Clocks, Enable, RccBus, Reset};
+use crate::pac::{i2c1, DWT, I2C1, I2C2, I2C3, RCC};
+use crate::rcc::{Clocks, Enable, I2cClock, RccBus, Reset, UpdateClocks, HSI_FREQUENCY};
 use fugit::HertzU32 as Hertz;
 use nb::Error::{Other, WouldBlock};
 use nb::{Error as NbError, Result as NbResult};
 
 use cast::u16;
+use core::ops::Deref;
 
 /// I2C error
 #[derive(Debug, Eq, PartialEq)]
@@ -27,13 +39,23 @@ pub enum Error {
     Overrun,
     /// Bus is busy
     Busy,
+    /// A flag was not set within the configured data timeout
+    Timeout,
     // Pec, // SMBUS mode only
-    // Timeout, // SMBUS mode only
     // Alert, // SMBUS mode only
 }
 
-/// SPI mode. The user should make sure that the requested frequency can be
+/// I2C mode. The user should make sure that the requested frequency can be
 /// generated considering the buses clocks.
+///
+/// The timing computed for `Standard`, `Fast` and `FastPlus` is validated
+/// against the I2C specification: the peripheral constructors panic with the
+/// [`TimingError`] if the SCL frequency exceeds the maximum of the mode, if it
+/// is too low to be divided from the I2C kernel clock, or if the kernel clock
+/// is too slow to meet the minimum SCL low/high times (see RM0410 33.4.4).
+///
+/// `FastPlus` (up to 1 MHz) also enables the 20 mA Fm+ drive of the I2C pins
+/// through `SYSCFG_PMC` on the devices that provide it.
 #[derive(Debug, PartialEq)]
 pub enum Mode {
     Standard { frequency: Hertz },
@@ -54,6 +76,20 @@ impl Mode {
     pub fn fast_plus(frequency: Hertz) -> Self {
         Mode::FastPlus { frequency }
     }
+
+    /// Uses a raw `TIMINGR` value, e.g. computed with STM32CubeMX for a bus
+    /// with unusual capacitance. The value is not validated.
+    pub fn custom(timing_r: u32) -> Self {
+        Mode::Custom { timing_r }
+    }
+
+    /// Uses hand-tuned timings instead of the computed ones. The timings are
+    /// not validated.
+    pub fn timing(timing: I2cTiming) -> Self {
+        Mode::Custom {
+            timing_r: timing.bits(),
+        }
+    }
 }
 
 /// Marker trait to define SCL pins for an I2C interface.
@@ -78,6 +114,48 @@ impl PinSda<I2C2> for gpio::PH5<Alternate<4, OpenDrain>> {}
 impl PinSda<I2C3> for gpio::PC9<Alternate<4, OpenDrain>> {}
 impl PinSda<I2C3> for gpio::PH8<Alternate<4, OpenDrain>> {}
 
+/// I2C interrupt events
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Transmit data register ready for the next byte (TXIS)
+    Txis,
+    /// Receive data register not empty (RXNE)
+    Rxne,
+    /// Transfer complete or transfer complete reload (TC, TCR)
+    TransferComplete,
+    /// STOP condition detected (STOPF)
+    Stop,
+    /// NACK received (NACKF)
+    Nack,
+    /// Bus error, arbitration loss or overrun/underrun (BERR, ARLO, OVR)
+    Error,
+    /// Own address matched in slave mode (ADDR)
+    AddressMatch,
+}
+
+/// Operation of an I2C transaction, see [`BlockingI2c::transaction`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Operation<'a> {
+    /// Read data into the buffer
+    Read(&'a mut [u8]),
+    /// Write the data of the buffer
+    Write(&'a [u8]),
+}
+
+/// Types that can be executed as an operation of a transaction
+trait AsOperation {
+    fn as_operation(&mut self) -> Operation<'_>;
+}
+
+impl AsOperation for Operation<'_> {
+    fn as_operation(&mut self) -> Operation<'_> {
+        match self {
+            Operation::Read(buffer) => Operation::Read(buffer),
+            Operation::Write(bytes) => Operation::Write(bytes),
+        }
+    }
+}
+
 /// I2C peripheral operating in master mode
 pub struct I2c<I2C, SCL, SDA> {
     i2c: I2C,
@@ -89,6 +167,7 @@ pub struct I2c<I2C, SCL, SDA> {
 /// embedded-hal compatible blocking I2C implementation
 pub struct BlockingI2c<I2C, SCL, SDA> {
     nb: I2c<I2C, SCL, SDA>,
+    data_timeout_us: u32,
     data_timeout: u32,
 }
 
@@ -206,44 +285,95 @@ fn blocking_i2c<I2C, SCL, SDA>(
     let sysclk_mhz = clocks.sysclk().to_MHz();
     BlockingI2c {
         nb: i2c,
+        data_timeout_us,
         data_timeout: data_timeout_us * sysclk_mhz,
     }
 }
 
+impl<I2C, SCL, SDA> UpdateClocks for BlockingI2c<I2C, SCL, SDA>
+where
+    I2c<I2C, SCL, SDA>: UpdateClocks,
+{
+    /// Computes the timing and the data timeout again for the new clocks
+    fn update_clocks(&mut self, clocks: &Clocks) {
+        self.nb.update_clocks(clocks);
+        self.data_timeout = self.data_timeout_us * clocks.sysclk().to_MHz();
+    }
+}
+
+/// Reason why the timing of a mode can't be generated from the kernel clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimingError {
+    /// The SCL frequency exceeds the maximum of the mode
+    FrequencyTooHigh,
+    /// The SCL period is longer than the prescaler and SCL low/high times allow
+    FrequencyTooLow,
+    /// The kernel clock is too slow to meet the minimum SCL low/high times
+    KernelClockTooSlow,
+}
+
 // hddat and vddat are removed because SDADEL is always going to be 0 in this implementation so
 // condition is always met
 struct I2cSpec {
     freq_max: u32,
     sudat_min: u32,
-    _lscl_min: u32,
-    _hscl_min: u32,
+    lscl_min: u32,
+    hscl_min: u32,
     trise_max: u32, // in ns
     _tfall_max: u32,
 }
 
-#[derive(Debug)]
-struct I2cTiming {
-    presc: u8,
-    scldel: u8,
-    sdadel: u8,
-    sclh: u8,
-    scll: u8,
+/// Content of the `TIMINGR` register (see RM0410 33.4.9)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct I2cTiming {
+    /// Timing prescaler, `t_presc = (presc + 1) * t_i2cclk`
+    pub presc: u8,
+    /// Data setup time, `(scldel + 1) * t_presc`
+    pub scldel: u8,
+    /// Data hold time, `sdadel * t_presc`
+    pub sdadel: u8,
+    /// SCL high period, `(sclh + 1) * t_presc`
+    pub sclh: u8,
+    /// SCL low period, `(scll + 1) * t_presc`
+    pub scll: u8,
+}
+
+impl I2cTiming {
+    /// Decodes a raw `TIMINGR` value
+    pub const fn from_bits(timing_r: u32) -> Self {
+        I2cTiming {
+            presc: ((timing_r & 0xf000_0000) >> 28) as u8,
+            scldel: ((timing_r & 0x00f0_0000) >> 20) as u8,
+            sdadel: ((timing_r & 0x000f_0000) >> 16) as u8,
+            sclh: ((timing_r & 0x0000_ff00) >> 8) as u8,
+            scll: (timing_r & 0x0000_00ff) as u8,
+        }
+    }
+
+    /// Encodes the timing as a raw `TIMINGR` value
+    pub const fn bits(&self) -> u32 {
+        ((self.presc as u32 & 0xf) << 28)
+            | ((self.scldel as u32 & 0xf) << 20)
+            | ((self.sdadel as u32 & 0xf) << 16)
+            | ((self.sclh as u32) << 8)
+            | (self.scll as u32)
+    }
 }
 
 // everything is in nano seconds
 const I2C_STANDARD_MODE_SPEC: I2cSpec = I2cSpec {
     freq_max: 102400,
     sudat_min: 250,
-    _lscl_min: 4700,
-    _hscl_min: 4000,
+    lscl_min: 4700,
+    hscl_min: 4000,
     trise_max: 640,
     _tfall_max: 20,
 };
 const I2C_FAST_MODE_SPEC: I2cSpec = I2cSpec {
     freq_max: 409600,
     sudat_min: 100,
-    _lscl_min: 1300,
-    _hscl_min: 600,
+    lscl_min: 1300,
+    hscl_min: 600,
     trise_max: 250,
     _tfall_max: 100,
 };
@@ -251,8 +381,8 @@ const I2C_FAST_MODE_SPEC: I2cSpec = I2cSpec {
 const I2C_FAST_PLUS_MODE_SPEC: I2cSpec = I2cSpec {
     freq_max: 1024000,
     sudat_min: 50,
-    _lscl_min: 500,
-    _hscl_min: 260,
+    lscl_min: 500,
+    hscl_min: 260,
     trise_max: 60,
     _tfall_max: 100,
 };
@@ -263,14 +393,16 @@ fn calculate_timing(
     scl_freq: u32,
     an_filter: bool,
     dnf: u8,
-) -> I2cTiming {
+) -> Result<I2cTiming, TimingError> {
     // This dependency is not used when `cargo test`ing. More info:
     // https://docs.rs/micromath/1.1.1/micromath/index.html#unused-import-warnings-when-linking-std
     #[cfg(not(test))]
     use micromath::F32Ext as _;
 
     // frequency limit check
-    assert!(scl_freq <= spec.freq_max);
+    if scl_freq > spec.freq_max {
+        return Err(TimingError::FrequencyTooHigh);
+    }
     // T_sync or delay introduced in SCL
     // generally it is 2-3 clock cycles
     // t_sync + dnf delay
@@ -281,6 +413,14 @@ fn calculate_timing(
     } else {
         0.0
     };
+    // kernel clock limit check, see RM0410 33.4.4 "I2C clock requirements":
+    // t_I2CCLK < (t_LOW - t_filters) / 4 and t_I2CCLK < t_HIGH
+    let t_i2cclk = 1.0 / i2c_freq as f32;
+    let t_low_min = spec.lscl_min as f32 / 1_000_000_000f32;
+    let t_high_min = spec.hscl_min as f32 / 1_000_000_000f32;
+    if t_i2cclk >= (t_low_min - t_af - t_dnf) / 4.0 || t_i2cclk >= t_high_min {
+        return Err(TimingError::KernelClockTooSlow);
+    }
     // t_sync = 2 to 3 * i2cclk
     let t_sync = 2.0 / (i2c_freq as f32);
     // fall or rise time
@@ -293,48 +433,79 @@ fn calculate_timing(
     // be changed later
     // (scl_l+scl_h+2)(presc +1 ) ==> as scl_width*presc ==F_i2cclk/F/F_scl_clk
     let product: f32 = (1.0 - t_delay * (scl_freq as f32)) * (i2c_freq / scl_freq) as f32;
-    let scl_l: u8;
-    let scl_h: u8;
-    let mut presc: u8;
-    // if ratio is > (scll+sclh)*presc. that frequancy is not possible to generate. so
-    // minimum frequancy possible is generated
+    // if ratio is > (scll+sclh)*presc. that frequancy is not possible to generate
     if product > 8192_f32 {
-        // TODO: should we panic or use minimum allowed frequancy
-        scl_l = 0x7fu8;
-        scl_h = 0x7fu8;
-        presc = 0xfu8;
-    } else {
-        // smaller the minimum devition less difference between expected vs
-        // actual scl clock
-        let mut min_deviation = 16f32;
-        // TODO: use duty cycle and based on that use precstart
-        let presc_start = (product / 512.0).ceil() as u8;
-        presc = presc_start;
-        for tmp_presc in presc_start..17 {
-            let deviation = product % tmp_presc as f32;
-            if min_deviation > deviation {
-                min_deviation = deviation;
-                presc = tmp_presc as u8;
-            }
+        return Err(TimingError::FrequencyTooLow);
+    }
+    // smaller the minimum devition less difference between expected vs
+    // actual scl clock
+    let mut min_deviation = 16f32;
+    // TODO: use duty cycle and based on that use precstart
+    let presc_start = (product / 512.0).ceil() as u8;
+    let mut presc = presc_start;
+    for tmp_presc in presc_start..17 {
+        let deviation = product % tmp_presc as f32;
+        if min_deviation > deviation {
+            min_deviation = deviation;
+            presc = tmp_presc as u8;
         }
-        // now that we have optimal prescalar value. optimal scl_l and scl_h
-        // needs to be calculated
-        let scl_width = (product / presc as f32) as u16; // it will be always less than 256
-        scl_h = (scl_width / 2 - 1) as u8;
-        scl_l = (scl_width - scl_h as u16 - 1) as u8; // This is to get max precision
-        presc -= 1;
     }
+    // now that we have optimal prescalar value. optimal scl_l and scl_h
+    // needs to be calculated. The period is split following the ratio of
+    // the minimum low and high times of the spec, as a 50% duty cycle
+    // cannot meet t_LOW in fast mode and fast mode plus.
+    let scl_width = (product / presc as f32) as u16; // it will be always less than 256
+    let low_ratio = spec.lscl_min as f32 / (spec.lscl_min + spec.hscl_min) as f32;
+    let scl_width_l = ((scl_width as f32 * low_ratio).ceil() as u16).min(scl_width - 1);
+    let scl_l = (scl_width_l - 1) as u8;
+    let scl_h = (scl_width - scl_width_l - 1) as u8;
+    presc -= 1;
     let scldel: u8 = (((spec.trise_max + spec.sudat_min) as f32 / 1_000_000_000.0)
         / ((presc + 1) as f32 / i2c_freq as f32)
         - 1.0)
         .ceil() as u8;
-    I2cTiming {
+    Ok(I2cTiming {
         presc,
         scldel,
         sdadel: 0,
         sclh: scl_h,
         scll: scl_l,
-    }
+    })
+}
+
+/// Enables or disables the Fm+ drive capability (20 mA) of the pins used by the
+/// I2C instance, with the `$i2cx_fmp` field of `SYSCFG_PMC`.
+///
+/// The F74x/F75x devices don't have these bits, there this is a no-op.
+macro_rules! set_fast_plus_drive {
+    ($i2cx_fmp:ident, $enable:expr) => {{
+        let enable: bool = $enable;
+        #[cfg(any(
+            feature = "svd-f730",
+            feature = "svd-f7x2",
+            feature = "svd-f7x3",
+            feature = "svd-f765",
+            feature = "svd-f7x7",
+            feature = "svd-f7x9",
+        ))]
+        cortex_m::interrupt::free(|_| {
+            // NOTE(unsafe) SYSCFG_PMC is only modified in a critical section
+            let syscfg = unsafe {
+                crate::pac::SYSCFG::enable_unchecked();
+                &(*crate::pac::SYSCFG::ptr())
+            };
+            syscfg.pmc.modify(|_, w| w.$i2cx_fmp().bit(enable));
+        });
+        #[cfg(not(any(
+            feature = "svd-f730",
+            feature = "svd-f7x2",
+            feature = "svd-f7x3",
+            feature = "svd-f765",
+            feature = "svd-f7x7",
+            feature = "svd-f7x9",
+        )))]
+        let _ = enable;
+    }};
 }
 
 macro_rules! check_status_flag {
@@ -385,7 +556,7 @@ macro_rules! busy_wait_cycles {
 
 // Generate the same code for both I2Cs
 macro_rules! hal {
-    ($($I2CX:ident: ($i2cX:ident),)+) => {
+    ($($I2CX:ident: ($i2cX:ident, $i2cx_fmp:ident, $i2cXsel:ident, $i2c:ident),)+) => {
         $(
             impl<SCL, SDA> I2c<$I2CX, SCL, SDA> {
                 /// Configures the I2C peripheral to work in master mode
@@ -399,7 +570,7 @@ macro_rules! hal {
                     $I2CX::enable(apb);
                     $I2CX::reset(apb);
 
-                    let pclk = <$I2CX>::clock(clocks);
+                    let pclk = Self::select_kernel_clock(clocks);
 
                     let mut i2c = I2c { i2c, pins, mode, pclk };
                     i2c.init();
@@ -425,16 +596,8 @@ macro_rules! hal {
                         Mode::Standard{ frequency } => calculate_timing(I2C_STANDARD_MODE_SPEC, self.pclk.raw(), frequency.raw(), an_filter, dnf ),
                         Mode::Fast{ frequency } => calculate_timing(I2C_FAST_MODE_SPEC, self.pclk.raw(), frequency.raw(), an_filter, dnf),
                         Mode::FastPlus{ frequency } => calculate_timing(I2C_FAST_PLUS_MODE_SPEC, self.pclk.raw(), frequency.raw(), an_filter, dnf ),
-                        Mode::Custom{ timing_r } => {
-                            I2cTiming{
-                                presc:  ((timing_r & 0xf000_0000) >> 28 ) as u8,
-                                scldel: ((timing_r & 0x00f0_0000) >> 20 ) as u8 ,
-                                sdadel: ((timing_r & 0x000f_0000) >> 16 ) as u8,
-                                sclh:   ((timing_r & 0x0000_ff00) >> 8 ) as u8,
-                                scll:   ((timing_r & 0x0000_00ff) >> 0 ) as u8,
-                            }
-                        }
-                    };
+                        Mode::Custom{ timing_r } => Ok(I2cTiming::from_bits(timing_r)),
+                    }.unwrap_or_else(|error| panic!("I2C: {:?} for the selected mode", error));
                     self.i2c.timingr.write(|w|
                         w.presc()
                             .bits(i2c_timingr.presc)
@@ -448,6 +611,8 @@ macro_rules! hal {
                             .bits(i2c_timingr.scldel)
                     );
 
+                    set_fast_plus_drive!($i2cx_fmp, matches!(self.mode, Mode::FastPlus { .. }));
+
                     self.i2c.cr1.modify(|_, w| w.pe().enabled());
                 }
 
@@ -496,12 +661,195 @@ macro_rules! hal {
                     });
                 }
 
+                /// Starts listening for an interrupt event
+                pub fn listen(&mut self, event: Event) {
+                    self.i2c.cr1.modify(|_, w| match event {
+                        Event::Txis => w.txie().enabled(),
+                        Event::Rxne => w.rxie().enabled(),
+                        Event::TransferComplete => w.tcie().enabled(),
+                        Event::Stop => w.stopie().enabled(),
+                        Event::Nack => w.nackie().enabled(),
+                        Event::Error => w.errie().enabled(),
+                        Event::AddressMatch => w.addrie().enabled(),
+                    });
+                }
+
+                /// Stops listening for an interrupt event
+                pub fn unlisten(&mut self, event: Event) {
+                    self.i2c.cr1.modify(|_, w| match event {
+                        Event::Txis => w.txie().disabled(),
+                        Event::Rxne => w.rxie().disabled(),
+                        Event::TransferComplete => w.tcie().disabled(),
+                        Event::Stop => w.stopie().disabled(),
+                        Event::Nack => w.nackie().disabled(),
+                        Event::Error => w.errie().disabled(),
+                        Event::AddressMatch => w.addrie().disabled(),
+                    });
+                }
+
+                /// Starts a write transfer of `n_bytes` bytes to the slave at
+                /// 7-bit address `addr`.
+                ///
+                /// With `auto_stop` set, a STOP condition is generated after the
+                /// last byte, otherwise the transfer ends with the `TransferComplete`
+                /// event and can be followed by a repeated start.
+                pub fn start_write(&mut self, addr: u8, n_bytes: u8, auto_stop: bool) {
+                    self.start(addr, n_bytes, false, auto_stop);
+                }
+
+                /// Starts a read transfer of `n_bytes` bytes from the slave at
+                /// 7-bit address `addr`. See [`start_write`](Self::start_write)
+                /// for `auto_stop`.
+                pub fn start_read(&mut self, addr: u8, n_bytes: u8, auto_stop: bool) {
+                    self.start(addr, n_bytes, true, auto_stop);
+                }
+
+                /// Writes the next byte of the current transfer if the transmit
+                /// data register is ready (TXIS)
+                pub fn write_byte(&mut self, byte: u8) -> NbResult<(), Error> {
+                    check_status_flag!(self.i2c, txis, is_empty)?;
+                    self.i2c.txdr.write(|w| w.txdata().bits(byte));
+                    Ok(())
+                }
+
+                /// Reads the next byte of the current transfer if one has been
+                /// received (RXNE)
+                pub fn read_byte(&mut self) -> NbResult<u8, Error> {
+                    check_status_flag!(self.i2c, rxne, is_not_empty)?;
+                    Ok(self.i2c.rxdr.read().rxdata().bits())
+                }
+
+                /// Checks if a transfer without automatic STOP has completed (TC)
+                pub fn check_transfer_complete(&mut self) -> NbResult<(), Error> {
+                    check_status_flag!(self.i2c, tc, is_complete)
+                }
+
+                /// Checks if a STOP condition has been detected, and clears the
+                /// flag if so (STOPF)
+                pub fn check_stop(&mut self) -> NbResult<(), Error> {
+                    check_status_flag!(self.i2c, stopf, is_stop)?;
+                    self.i2c.icr.write(|w| w.stopcf().set_bit());
+                    Ok(())
+                }
+
+                /// Generates a STOP condition after the current byte, to end a
+                /// transfer started without `auto_stop`
+                pub fn stop(&mut self) {
+                    self.i2c.cr2.modify(|_, w| w.stop().set_bit());
+                }
+
+                /// Returns `true` if the bus is busy
+                pub fn is_busy(&self) -> bool {
+                    self.i2c.isr.read().busy().bit_is_set()
+                }
+
+                /// Sets the 7-bit own address acknowledged by the peripheral
+                /// when addressed as a slave
+                pub fn set_own_address(&mut self, addr: u8) {
+                    // OA1 can only be changed while OA1EN is cleared
+                    self.i2c.oar1.write(|w| w.oa1en().clear_bit());
+                    self.i2c.oar1.write(|w| {
+                        w.oa1()
+                            .bits(u16(addr) << 1)
+                            .oa1mode()
+                            .clear_bit()
+                            .oa1en()
+                            .set_bit()
+                    });
+                }
+
+                /// Checks whether the own address has been matched and clears
+                /// the `AddressMatch` event. Returns `true` if the master
+                /// requested a read.
+                pub fn check_address_match(&mut self) -> NbResult<bool, Error> {
+                    let isr = self.i2c.isr.read();
+                    if isr.addr().bit_is_set() {
+                        self.i2c.icr.write(|w| w.addrcf().set_bit());
+                        Ok(isr.dir().bit_is_set())
+                    } else {
+                        Err(WouldBlock)
+                    }
+                }
+
+                /// Enables the wakeup from Stop mode on own address match.
+                ///
+                /// The kernel clock of the peripheral is switched to HSI, which
+                /// keeps running in Stop mode, and the timing is computed again
+                /// for it. The digital noise filter is disabled as required by
+                /// the wakeup feature. Enabled interrupt events are kept.
+                ///
+                /// # Panics
+                ///
+                /// Panics if the mode cannot be achieved with the 16 MHz HSI
+                /// kernel clock.
+                pub fn enable_wakeup_from_stop(&mut self) {
+                    // NOTE(unsafe) atomic read-modify-write of this peripheral's
+                    // clock selection only
+                    let rcc = unsafe { &*RCC::ptr() };
+                    rcc.dckcfgr2.modify(|_, w| w.$i2cXsel().hsi());
+                    self.pclk = HSI_FREQUENCY;
+                    self.reinit(true);
+                }
+
+                /// Disables the wakeup from Stop mode and switches the kernel
+                /// clock of the peripheral back to the one selected in `clocks`
+                pub fn disable_wakeup_from_stop(&mut self, clocks: &Clocks) {
+                    self.pclk = Self::select_kernel_clock(clocks);
+                    self.reinit(false);
+                }
+
+                /// Selects the kernel clock of the peripheral set in `clocks`,
+                /// and returns its frequency
+                fn select_kernel_clock(clocks: &Clocks) -> Hertz {
+                    let source = clocks.kernel_clocks().$i2c;
+                    // NOTE(unsafe) atomic read-modify-write of this peripheral's
+                    // clock selection only
+                    let rcc = unsafe { &*RCC::ptr() };
+                    rcc.dckcfgr2.modify(|_, w| match source {
+                        I2cClock::Apb => w.$i2cXsel().apb(),
+                        I2cClock::Sysclk => w.$i2cXsel().sysclk(),
+                        I2cClock::Hsi => w.$i2cXsel().hsi(),
+                    });
+                    source.frequency(clocks)
+                }
+
+                /// Configures the peripheral again, keeping the enabled
+                /// interrupt events
+                fn reinit(&mut self, wakeup: bool) {
+                    // TXIE, RXIE, ADDRIE, NACKIE, STOPIE, TCIE and ERRIE
+                    let irqs = self.i2c.cr1.read().bits() & 0xfe;
+                    self.init();
+                    // WUPEN, bit 18, is missing from the F72x/F73x PACs
+                    let wupen = u32::from(wakeup) << 18;
+                    self.i2c.cr1.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(1 << 18)) | irqs | wupen)
+                    });
+                }
+
+                /// Returns the timing currently programmed in `TIMINGR`
+                pub fn timing(&self) -> I2cTiming {
+                    I2cTiming::from_bits(self.i2c.timingr.read().bits())
+                }
+
                 /// Releases the I2C peripheral and associated pins
                 pub fn free(self) -> ($I2CX, (SCL, SDA)) {
                     (self.i2c, self.pins)
                 }
             }
 
+            impl<SCL, SDA> UpdateClocks for I2c<$I2CX, SCL, SDA> {
+                /// Computes the timing again for the new kernel clock, unless
+                /// the wakeup from Stop mode is enabled and the kernel clock
+                /// is the HSI
+                fn update_clocks(&mut self, clocks: &Clocks) {
+                    // WUPEN, bit 18
+                    if self.i2c.cr1.read().bits() & (1 << 18) == 0 {
+                        self.pclk = Self::select_kernel_clock(clocks);
+                        self.reinit(false);
+                    }
+                }
+            }
+
             impl<SCL, SDA> BlockingI2c<$I2CX, SCL, SDA> {
                 fn $i2cX(
                     i2c: $I2CX,
@@ -649,7 +997,303 @@ macro_rules! hal {
 }
 
 hal! {
-    I2C1: (_i2c1),
-    I2C2: (_i2c2),
-    I2C3: (_i2c3),
+    I2C1: (_i2c1, i2c1_fmp, i2c1sel, i2c1),
+    I2C2: (_i2c2, i2c2_fmp, i2c2sel, i2c2),
+    I2C3: (_i2c3, i2c3_fmp, i2c3sel, i2c3),
+}
+
+/// Largest value of the `NBYTES` field
+const MAX_NBYTES: usize = 255;
+
+/// Returns `Ok` once the address sequence of the previous START has ended
+fn start_done(i2c: &i2c1::RegisterBlock) -> NbResult<(), Error> {
+    if i2c.cr2.read().start().bit_is_set() {
+        Err(WouldBlock)
+    } else {
+        Ok(())
+    }
+}
+
+/// Framing of a transfer, the operations of a transaction between two
+/// direction changes, shared by the blocking and async transactions
+///
+/// The transfer begins with a (repeated) START and is split in chunks of up
+/// to 255 bytes with `RELOAD`. The last transfer of the transaction ends with
+/// an automatic STOP.
+struct Transfer {
+    /// Index following the last operation of the transfer
+    end: usize,
+    /// Last transfer of the transaction
+    last: bool,
+    /// Bytes left in the current chunk
+    chunk: usize,
+    /// Bytes left in the next chunks
+    remaining: usize,
+}
+
+impl Transfer {
+    /// Programs the (repeated) START of the transfer beginning with
+    /// `operations[first]`, and of its first chunk
+    fn start<O: AsOperation>(
+        i2c: &i2c1::RegisterBlock,
+        addr: u8,
+        operations: &mut [O],
+        first: usize,
+    ) -> Self {
+        let read = matches!(operations[first].as_operation(), Operation::Read(_));
+        let mut end = first + 1;
+        while end < operations.len()
+            && matches!(operations[end].as_operation(), Operation::Read(_)) == read
+        {
+            end += 1;
+        }
+        let remaining: usize = operations[first..end]
+            .iter_mut()
+            .map(|op| match op.as_operation() {
+                Operation::Read(buffer) => buffer.len(),
+                Operation::Write(bytes) => bytes.len(),
+            })
+            .sum();
+        let chunk = remaining.min(MAX_NBYTES);
+        let transfer = Self {
+            end,
+            last: end == operations.len(),
+            chunk,
+            remaining: remaining - chunk,
+        };
+
+        // AUTOEND is only set once the last chunk of the last transfer is
+        // programmed, as it is ignored while RELOAD is set
+        i2c.cr2.write(|w| {
+            w.sadd()
+                .bits(u16(addr << 1))
+                .add10()
+                .clear_bit()
+                .rd_wrn()
+                .bit(read)
+                .nbytes()
+                .bits(transfer.chunk as u8)
+                .reload()
+                .bit(transfer.remaining > 0)
+                .autoend()
+                .bit(transfer.autoend())
+                .start()
+                .set_bit()
+        });
+        transfer
+    }
+
+    fn autoend(&self) -> bool {
+        self.last && self.remaining == 0
+    }
+
+    /// Returns `true` once the bytes of the current chunk are all counted,
+    /// `reload` must then be called when TCR is set
+    fn needs_reload(&self) -> bool {
+        self.chunk == 0
+    }
+
+    /// Programs the next chunk
+    fn reload(&mut self, i2c: &i2c1::RegisterBlock) {
+        self.chunk = self.remaining.min(MAX_NBYTES);
+        self.remaining -= self.chunk;
+        i2c.cr2.modify(|_, w| {
+            w.nbytes()
+                .bits(self.chunk as u8)
+                .reload()
+                .bit(self.remaining > 0)
+                .autoend()
+                .bit(self.autoend())
+        });
+    }
+
+    /// Counts a byte of the current chunk
+    fn count_byte(&mut self) {
+        self.chunk -= 1;
+    }
+}
+
+impl<I2C, SCL, SDA> BlockingI2c<I2C, SCL, SDA>
+where
+    I2C: Deref<Target = i2c1::RegisterBlock>,
+{
+    /// Returns the timing currently programmed in `TIMINGR`
+    pub fn timing(&self) -> I2cTiming {
+        I2cTiming::from_bits(self.nb.i2c.timingr.read().bits())
+    }
+
+    /// Executes `operations` as a single transaction with the slave at `addr`.
+    ///
+    /// Adjacent operations of the same direction are merged into one transfer
+    /// without any START in between, a repeated START is emitted whenever the
+    /// direction changes and the last transfer ends with an automatic STOP.
+    /// Transfers longer than 255 bytes use the `RELOAD` mechanism of the
+    /// peripheral. Empty operations are allowed, e.g. a single empty `Write`
+    /// checks whether a slave acknowledges its address.
+    pub fn transaction(&mut self, addr: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        self.transaction_ops(addr, operations)
+    }
+
+    fn transaction_ops<O: AsOperation>(
+        &mut self,
+        addr: u8,
+        operations: &mut [O],
+    ) -> Result<(), Error> {
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        let i2c = &*self.nb.i2c;
+        let data_timeout = self.data_timeout;
+        // A flag that does not come up within `data_timeout` is reported as
+        // a timeout
+        let timeout = |err: NbError<Error>| match err {
+            Other(e) => e,
+            WouldBlock => Error::Timeout,
+        };
+
+        // Wait for any previous address sequence to end automatically
+        busy_wait_cycles!(start_done(i2c), data_timeout).map_err(timeout)?;
+
+        let mut first = 0;
+        while first < operations.len() {
+            let mut transfer = Transfer::start(i2c, addr, operations, first);
+
+            for op in &mut operations[first..transfer.end] {
+                match op.as_operation() {
+                    Operation::Write(bytes) => {
+                        for byte in bytes {
+                            if transfer.needs_reload() {
+                                busy_wait_cycles!(
+                                    check_status_flag!(i2c, tcr, is_complete),
+                                    data_timeout
+                                )
+                                .map_err(timeout)?;
+                                transfer.reload(i2c);
+                            }
+                            transfer.count_byte();
+                            busy_wait_cycles!(
+                                check_status_flag!(i2c, txis, is_empty),
+                                data_timeout
+                            )
+                            .map_err(timeout)?;
+                            i2c.txdr.write(|w| w.txdata().bits(*byte));
+                        }
+                    }
+                    Operation::Read(buffer) => {
+                        for byte in buffer {
+                            if transfer.needs_reload() {
+                                busy_wait_cycles!(
+                                    check_status_flag!(i2c, tcr, is_complete),
+                                    data_timeout
+                                )
+                                .map_err(timeout)?;
+                                transfer.reload(i2c);
+                            }
+                            transfer.count_byte();
+                            busy_wait_cycles!(
+                                check_status_flag!(i2c, rxne, is_not_empty),
+                                data_timeout
+                            )
+                            .map_err(timeout)?;
+                            *byte = i2c.rxdr.read().rxdata().bits();
+                        }
+                    }
+                }
+            }
+
+            if transfer.last {
+                // Wait for the automatic STOP, this also catches a NACK of the
+                // address on empty transfers
+                busy_wait_cycles!(check_status_flag!(i2c, stopf, is_stop), data_timeout)
+                    .map_err(timeout)?;
+                i2c.icr.write(|w| w.stopcf().set_bit());
+            } else {
+                busy_wait_cycles!(check_status_flag!(i2c, tc, is_complete), data_timeout)
+                    .map_err(timeout)?;
+            }
+
+            first = transfer.end;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "eh1")]
+mod hal_1;
+
+// The `async` feature needs Rust 1.75
+#[cfg(feature = "async")]
+#[clippy::msrv = "1.75"]
+mod asynch;
+#[cfg(feature = "async")]
+pub use asynch::{on_interrupt, Instance};
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        calculate_timing, I2cSpec, I2cTiming, TimingError, I2C_FAST_MODE_SPEC,
+        I2C_FAST_PLUS_MODE_SPEC, I2C_STANDARD_MODE_SPEC,
+    };
+
+    /// Returns the (SCL low, SCL high) times in ns
+    fn scl_times(spec: I2cSpec, i2c_freq: u32, scl_freq: u32) -> (u32, u32) {
+        let timing = calculate_timing(spec, i2c_freq, scl_freq, false, 0).unwrap();
+        let t_presc = (timing.presc as u32 + 1) * 1_000_000_000 / i2c_freq;
+        (
+            (timing.scll as u32 + 1) * t_presc,
+            (timing.sclh as u32 + 1) * t_presc,
+        )
+    }
+
+    #[test]
+    fn test_timing_bits() {
+        let timing_r = 0x3042_0f13;
+        let timing = I2cTiming::from_bits(timing_r);
+        assert_eq!(timing.presc, 3);
+        assert_eq!(timing.scldel, 4);
+        assert_eq!(timing.sdadel, 2);
+        assert_eq!(timing.sclh, 0x0f);
+        assert_eq!(timing.scll, 0x13);
+        assert_eq!(timing.bits(), timing_r);
+    }
+
+    #[test]
+    fn test_fast_plus_timing() {
+        let (low, high) = scl_times(I2C_FAST_PLUS_MODE_SPEC, 54_000_000, 1_000_000);
+        assert!(low >= I2C_FAST_PLUS_MODE_SPEC.lscl_min);
+        assert!(high >= I2C_FAST_PLUS_MODE_SPEC.hscl_min);
+    }
+
+    #[test]
+    fn test_fast_timing() {
+        let (low, high) = scl_times(I2C_FAST_MODE_SPEC, 54_000_000, 400_000);
+        assert!(low >= I2C_FAST_MODE_SPEC.lscl_min);
+        assert!(high >= I2C_FAST_MODE_SPEC.hscl_min);
+    }
+
+    #[test]
+    fn test_fast_plus_kernel_clock_too_slow() {
+        assert_eq!(
+            calculate_timing(I2C_FAST_PLUS_MODE_SPEC, 4_000_000, 1_000_000, false, 0),
+            Err(TimingError::KernelClockTooSlow)
+        );
+    }
+
+    #[test]
+    fn test_fast_plus_frequency_too_high() {
+        assert_eq!(
+            calculate_timing(I2C_FAST_PLUS_MODE_SPEC, 54_000_000, 2_000_000, false, 0),
+            Err(TimingError::FrequencyTooHigh)
+        );
+    }
+
+    #[test]
+    fn test_standard_frequency_too_low() {
+        assert_eq!(
+            calculate_timing(I2C_STANDARD_MODE_SPEC, 216_000_000, 10_000, false, 0),
+            Err(TimingError::FrequencyTooLow)
+        );
+    }
 }
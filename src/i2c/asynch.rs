@@ -0,0 +1,171 @@
+use core::future::poll_fn;
+use core::ops::Deref;
+use core::task::Poll;
+
+use super::{start_done, Error, I2c, Transfer};
+use crate::pac::{i2c1, I2C1, I2C2, I2C3};
+use crate::waker::WakerCell;
+
+use embedded_hal_one::i2c::{ErrorType, Operation, SevenBitAddress};
+use nb::Error::{Other, WouldBlock};
+use nb::Result as NbResult;
+
+/// TXIE, RXIE, NACKIE, STOPIE, TCIE and ERRIE
+const IRQ_MASK: u32 = 0b1111_0110;
+const TXIE: u32 = 1 << 1;
+const RXIE: u32 = 1 << 2;
+const NACKIE: u32 = 1 << 4;
+const STOPIE: u32 = 1 << 5;
+const TCIE: u32 = 1 << 6;
+const ERRIE: u32 = 1 << 7;
+
+static WAKERS: [WakerCell; 3] = [WakerCell::new(), WakerCell::new(), WakerCell::new()];
+
+/// I2C peripherals usable with the async API
+pub trait Instance: crate::Sealed + Deref<Target = i2c1::RegisterBlock> {
+    #[doc(hidden)]
+    const INDEX: usize;
+    #[doc(hidden)]
+    fn ptr() -> *const i2c1::RegisterBlock;
+}
+
+macro_rules! instance {
+    ($($I2CX:ident: $index:expr,)+) => {
+        $(
+            impl Instance for $I2CX {
+                const INDEX: usize = $index;
+                fn ptr() -> *const i2c1::RegisterBlock {
+                    $I2CX::ptr()
+                }
+            }
+        )+
+    };
+}
+
+instance! {
+    I2C1: 0,
+    I2C2: 1,
+    I2C3: 2,
+}
+
+/// Wakes the task awaiting on the `I2C` peripheral.
+///
+/// Must be called from both the event and the error interrupt handlers of the
+/// peripheral. The interrupts are masked until the next poll of the pending
+/// transfer.
+pub fn on_interrupt<I2C: Instance>() {
+    // NOTE(unsafe) only the interrupt enable bits owned by the async driver
+    // are touched
+    let i2c = unsafe { &*I2C::ptr() };
+    i2c.cr1
+        .modify(|r, w| unsafe { w.bits(r.bits() & !IRQ_MASK) });
+    WAKERS[I2C::INDEX].wake();
+}
+
+/// Waits for `check` to succeed, sleeping on the `irqs` interrupts in between
+async fn wait_for<I2C: Instance>(
+    i2c: &i2c1::RegisterBlock,
+    irqs: u32,
+    check: impl Fn(&i2c1::RegisterBlock) -> NbResult<(), Error>,
+) -> Result<(), Error> {
+    poll_fn(|cx| {
+        WAKERS[I2C::INDEX].register(cx.waker());
+        match check(i2c) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(Other(e)) => Poll::Ready(Err(e)),
+            Err(WouldBlock) => {
+                i2c.cr1
+                    .modify(|r, w| unsafe { w.bits(r.bits() | irqs | NACKIE | ERRIE) });
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+impl<I2C, SCL, SDA> ErrorType for I2c<I2C, SCL, SDA> {
+    type Error = Error;
+}
+
+impl<I2C: Instance, SCL, SDA> embedded_hal_async::i2c::I2c<SevenBitAddress> for I2c<I2C, SCL, SDA> {
+    /// Executes `operations` as a single transaction, with the same framing as
+    /// the blocking implementation, awaiting the I2C interrupts in between.
+    /// `on_interrupt` must be called from the interrupt handlers.
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        let i2c = &*self.i2c;
+
+        // Wait for any previous address sequence to end automatically. No
+        // interrupt signals it, the task is polled again right away.
+        poll_fn(|cx| match start_done(i2c) {
+            Ok(()) => Poll::Ready(()),
+            Err(_) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await;
+
+        let mut first = 0;
+        while first < operations.len() {
+            let mut transfer = Transfer::start(i2c, address, operations, first);
+
+            for op in &mut operations[first..transfer.end] {
+                match op {
+                    Operation::Write(bytes) => {
+                        for byte in bytes.iter() {
+                            Self::next_byte(i2c, &mut transfer).await?;
+                            wait_for::<I2C>(i2c, TXIE, |i2c| {
+                                check_status_flag!(i2c, txis, is_empty)
+                            })
+                            .await?;
+                            i2c.txdr.write(|w| w.txdata().bits(*byte));
+                        }
+                    }
+                    Operation::Read(buffer) => {
+                        for byte in buffer.iter_mut() {
+                            Self::next_byte(i2c, &mut transfer).await?;
+                            wait_for::<I2C>(i2c, RXIE, |i2c| {
+                                check_status_flag!(i2c, rxne, is_not_empty)
+                            })
+                            .await?;
+                            *byte = i2c.rxdr.read().rxdata().bits();
+                        }
+                    }
+                }
+            }
+
+            if transfer.last {
+                // Wait for the automatic STOP, this also catches a NACK of the
+                // address on empty transfers
+                wait_for::<I2C>(i2c, STOPIE, |i2c| check_status_flag!(i2c, stopf, is_stop)).await?;
+                i2c.icr.write(|w| w.stopcf().set_bit());
+            } else {
+                wait_for::<I2C>(i2c, TCIE, |i2c| check_status_flag!(i2c, tc, is_complete)).await?;
+            }
+
+            first = transfer.end;
+        }
+
+        Ok(())
+    }
+}
+
+impl<I2C: Instance, SCL, SDA> I2c<I2C, SCL, SDA> {
+    /// Reloads `NBYTES` once the previous chunk of a long transfer is done
+    async fn next_byte(i2c: &i2c1::RegisterBlock, transfer: &mut Transfer) -> Result<(), Error> {
+        if transfer.needs_reload() {
+            wait_for::<I2C>(i2c, TCIE, |i2c| check_status_flag!(i2c, tcr, is_complete)).await?;
+            transfer.reload(i2c);
+        }
+        transfer.count_byte();
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use core::ops::Deref;
+
+use super::{AsOperation, BlockingI2c, Error};
+use crate::pac::i2c1;
+
+use embedded_hal_one::i2c::{
+    ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation, SevenBitAddress,
+};
+
+impl embedded_hal_one::i2c::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::Bus => ErrorKind::Bus,
+            Error::Arbitration => ErrorKind::ArbitrationLoss,
+            Error::Acknowledge => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            Error::Overrun => ErrorKind::Overrun,
+            Error::Busy | Error::Timeout => ErrorKind::Other,
+        }
+    }
+}
+
+impl AsOperation for Operation<'_> {
+    fn as_operation(&mut self) -> super::Operation<'_> {
+        match self {
+            Operation::Read(buffer) => super::Operation::Read(buffer),
+            Operation::Write(bytes) => super::Operation::Write(bytes),
+        }
+    }
+}
+
+impl<I2C, SCL, SDA> ErrorType for BlockingI2c<I2C, SCL, SDA> {
+    type Error = Error;
+}
+
+impl<I2C, SCL, SDA> I2c<SevenBitAddress> for BlockingI2c<I2C, SCL, SDA>
+where
+    I2C: Deref<Target = i2c1::RegisterBlock>,
+{
+    /// See [`BlockingI2c::transaction`]
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transaction_ops(address, operations)
+    }
+}
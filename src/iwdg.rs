@@ -0,0 +1,114 @@
+//! Independent watchdog (IWDG)
+//!
+//! The independent watchdog is clocked from the LSI and keeps running in Stop
+//! and Standby modes. Besides the timeout, the F7 IWDG has a window register:
+//! feeding it before the window opens also resets the device, which catches
+//! runaway loops that keep feeding the watchdog.
+//!
+//! The timings are computed for the typical 32 kHz LSI frequency, the actual
+//! frequency may vary between 17 and 47 kHz.
+//!
+//! See section 27 of RM0410.
+
+use crate::pac::IWDG;
+
+use embedded_hal::watchdog::{Watchdog, WatchdogEnable};
+use fugit::MillisDurationU32;
+
+const LSI_KHZ: u32 = 32;
+const MAX_PR: u8 = 0b110;
+const MAX_RL: u16 = 0xfff;
+
+/// Independent watchdog
+pub struct IndependentWatchdog {
+    iwdg: IWDG,
+}
+
+impl IndependentWatchdog {
+    /// Wraps the IWDG peripheral. The watchdog is not started.
+    pub fn new(iwdg: IWDG) -> Self {
+        Self { iwdg }
+    }
+
+    /// Starts the watchdog so it resets the device if it is not fed within
+    /// `timeout`, and also if it is fed sooner than `min` after the previous
+    /// feed. Once started, the watchdog can only be stopped by a reset.
+    ///
+    /// Panics if `timeout` is longer than 32 s.
+    pub fn start_windowed(&mut self, min: MillisDurationU32, timeout: MillisDurationU32) {
+        let (pr, rl) = timeout_config(timeout.ticks()).expect("IWDG timeout out of range");
+
+        // Counter value below which the feeds are allowed, the window is
+        // disabled with its reset value
+        let ticks = min.ticks().saturating_mul(LSI_KHZ) / (4 << pr);
+        let win = match ticks {
+            0 => MAX_RL,
+            _ => rl.saturating_sub(ticks.min(u32::from(MAX_RL)) as u16),
+        };
+
+        self.iwdg.kr.write(|w| w.key().start());
+        self.iwdg.kr.write(|w| w.key().enable());
+        // The prescaler and reload register updates are done in the LSI clock
+        // domain
+        while self.iwdg.sr.read().bits() & 0b011 != 0 {}
+        self.iwdg.pr.write(|w| w.pr().bits(pr));
+        self.iwdg.rlr.write(|w| w.rl().bits(rl));
+        while self.iwdg.sr.read().bits() & 0b011 != 0 {}
+        // With the window enabled, writing the window register also reloads
+        // the counter. Feeding now would be too early.
+        self.iwdg.winr.write(|w| w.win().bits(win));
+        // WVU
+        while self.iwdg.sr.read().bits() & 0b100 != 0 {}
+        if win == MAX_RL {
+            self.feed();
+        }
+    }
+
+    /// Feeds the watchdog, reloading its counter
+    pub fn feed(&mut self) {
+        self.iwdg.kr.write(|w| w.key().reset());
+    }
+}
+
+/// Finds the smallest prescaler for which `timeout_ms` fits in the reload
+/// register. Returns it with the reload value.
+fn timeout_config(timeout_ms: u32) -> Option<(u8, u16)> {
+    (0..=MAX_PR).find_map(|pr| {
+        let rl = timeout_ms * LSI_KHZ / (4 << pr);
+        match rl {
+            1..=0x1000 => Some((pr, (rl - 1) as u16)),
+            _ => None,
+        }
+    })
+}
+
+impl WatchdogEnable for IndependentWatchdog {
+    type Time = MillisDurationU32;
+
+    /// Starts the watchdog with an open window: it can be fed at any time
+    /// before `period` elapses
+    fn start<T: Into<Self::Time>>(&mut self, period: T) {
+        self.start_windowed(MillisDurationU32::from_ticks(0), period.into());
+    }
+}
+
+impl Watchdog for IndependentWatchdog {
+    fn feed(&mut self) {
+        self.feed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout_config() {
+        assert_eq!(timeout_config(1), Some((0, 7)));
+        assert_eq!(timeout_config(512), Some((0, 0xfff)));
+        assert_eq!(timeout_config(1000), Some((1, 3999)));
+        assert_eq!(timeout_config(32_768), Some((6, 0xfff)));
+        assert_eq!(timeout_config(0), None);
+        assert_eq!(timeout_config(40_000), None);
+    }
+}
@@ -23,6 +23,11 @@ compile_error!(
                 "
 );
 
+#[cfg(all(feature = "usb_fs", feature = "usb_fs_host"))]
+compile_error!(
+    "The usb_fs and usb_fs_host features are exclusive, both drive the OTG_FS peripheral"
+);
+
 pub(crate) use embedded_hal as hal;
 
 #[cfg(feature = "stm32f722")]
@@ -74,9 +79,26 @@ pub use crate::pac::interrupt;
 #[cfg(all(feature = "device-selected", feature = "has-can"))]
 pub mod can;
 
+#[cfg(all(feature = "device-selected", feature = "has-eth"))]
+pub mod ethernet;
+
+#[cfg(feature = "device-selected")]
+pub mod crc;
+
+#[cfg(any(
+    feature = "stm32f756",
+    feature = "stm32f777",
+    feature = "stm32f778",
+    feature = "stm32f779",
+))]
+pub mod cryp;
+
 #[cfg(feature = "device-selected")]
 pub mod dma;
 
+#[cfg(feature = "device-selected")]
+pub mod dbgmcu;
+
 #[cfg(all(feature = "device-selected", feature = "fmc"))]
 pub mod fmc;
 
@@ -86,9 +108,30 @@ pub mod fmc_lcd;
 #[cfg(feature = "device-selected")]
 pub mod gpio;
 
+#[cfg(any(
+    feature = "stm32f756",
+    feature = "stm32f777",
+    feature = "stm32f778",
+    feature = "stm32f779",
+))]
+pub mod hash;
+
 #[cfg(feature = "device-selected")]
 pub mod dac;
 
+#[cfg(any(
+    feature = "stm32f745",
+    feature = "stm32f746",
+    feature = "stm32f756",
+    feature = "stm32f765",
+    feature = "stm32f767",
+    feature = "stm32f769",
+    feature = "stm32f777",
+    feature = "stm32f778",
+    feature = "stm32f779",
+))]
+pub mod dcmi;
+
 #[cfg(all(
     feature = "usb_fs",
     any(
@@ -103,6 +146,20 @@ pub mod dac;
 ))]
 pub mod otg_fs;
 
+#[cfg(all(
+    feature = "usb_fs_host",
+    any(
+        feature = "stm32f722",
+        feature = "stm32f723",
+        feature = "stm32f730",
+        feature = "stm32f732",
+        feature = "stm32f733",
+        feature = "stm32f746",
+        feature = "stm32f767",
+    )
+))]
+pub mod otg_fs_host;
+
 #[cfg(all(
     feature = "usb_hs",
     any(
@@ -126,12 +183,31 @@ pub mod rcc;
 #[cfg(feature = "device-selected")]
 pub mod rtc;
 
+#[cfg(feature = "device-selected")]
+pub mod sai;
+
+#[cfg(feature = "device-selected")]
+pub mod sdmmc;
+
 #[cfg(feature = "device-selected")]
 pub mod serial;
 
 #[cfg(feature = "device-selected")]
 pub mod spi;
 
+#[cfg(any(
+    feature = "stm32f745",
+    feature = "stm32f746",
+    feature = "stm32f756",
+    feature = "stm32f765",
+    feature = "stm32f767",
+    feature = "stm32f769",
+    feature = "stm32f777",
+    feature = "stm32f778",
+    feature = "stm32f779",
+))]
+pub mod spdifrx;
+
 #[cfg(feature = "device-selected")]
 pub mod timer;
 
@@ -147,6 +223,15 @@ pub mod rng;
 #[cfg(feature = "device-selected")]
 pub mod qspi;
 
+#[cfg(feature = "device-selected")]
+pub mod pwr;
+
+#[cfg(feature = "device-selected")]
+pub mod iwdg;
+
+#[cfg(feature = "device-selected")]
+pub mod wwdg;
+
 #[cfg(any(feature = "stm32f765", feature = "stm32f767", feature = "stm32f769"))]
 pub mod adc;
 
@@ -167,6 +252,9 @@ pub mod state {
     pub struct Disabled;
 }
 
+#[cfg(all(feature = "device-selected", feature = "async"))]
+mod waker;
+
 #[cfg(feature = "device-selected")]
 mod sealed {
     pub trait Sealed {}
@@ -6,6 +6,22 @@ use crate::{
     rcc::{Enable, HSEClock, Reset},
 };
 
+#[cfg(feature = "ltdc-graphics")]
+mod graphics;
+mod image;
+mod swap;
+// The `async` feature needs Rust 1.75
+#[cfg(feature = "async")]
+#[clippy::msrv = "1.75"]
+mod asynch;
+
+#[cfg(feature = "ltdc-graphics")]
+pub use graphics::LayerTarget;
+pub use image::{Clut, Image, InputFormat};
+pub use swap::FrameSwapper;
+#[cfg(feature = "async")]
+pub use asynch::on_interrupt;
+
 /// Display configuration constants
 pub struct DisplayConfig {
     pub active_width: u16,
@@ -29,12 +45,38 @@ pub struct DisplayConfig {
 
 /// Accessible layers
 /// * `L1`: layer 1
-/// * `L2`: layer 2
+/// * `L2`: layer 2, blended over the layer 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Layer {
     L1,
     L2,
 }
 
+/// Blending of a layer with the layers below it (the layer 1 or the background)
+///
+/// * `ConstantAlpha`: the layer is weighted by its constant alpha only
+/// * `PixelAlpha`: the layer is weighted by the alpha of each pixel, multiplied by the constant
+///   alpha, for the pixel formats with an alpha channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Blending {
+    ConstantAlpha,
+    PixelAlpha,
+}
+
+/// LTDC interrupt event
+///
+/// * `Line`: the line set with `set_line_interrupt` is reached
+/// * `FifoUnderrun`: a pixel was requested while the FIFO was empty
+/// * `TransferError`: a bus error occurred while fetching a frame buffer
+/// * `RegisterReload`: the shadow registers were reloaded during the vertical blanking
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Line,
+    FifoUnderrun,
+    TransferError,
+    RegisterReload,
+}
+
 pub struct DisplayController<T: 'static + SupportedWord> {
     /// ltdc instance
     _ltdc: LTDC,
@@ -212,7 +254,9 @@ impl<T: 'static + SupportedWord> DisplayController<T> {
     /// Note : the choice is made (for the sake of simplicity) to make the layer
     /// as big as the screen
     ///
-    /// Color Keying and CLUT are not yet supported
+    /// The layer is opaque, with a constant alpha of 255, until changed with
+    /// `set_layer_alpha`, `set_layer_blending` and `set_color_key`. The indexed formats (`L8`,
+    /// `AL44` and `AL88`) need a CLUT, loaded with `load_clut` and enabled with `enable_clut`.
     pub fn config_layer(
         &mut self,
         layer: Layer,
@@ -288,7 +332,7 @@ impl<T: 'static + SupportedWord> DisplayController<T> {
             // PixelFormat::RGB888 => 24, unsupported for now because u24 does not exist
             PixelFormat::RGB565 => 2,
             PixelFormat::ARGB1555 => 2,
-            PixelFormat::ARGB4444 => 16,
+            PixelFormat::ARGB4444 => 2,
             PixelFormat::L8 => 1,
             PixelFormat::AL44 => 1,
             PixelFormat::AL88 => 2,
@@ -339,6 +383,111 @@ impl<T: 'static + SupportedWord> DisplayController<T> {
         }
     }
 
+    /// Disable the layer
+    pub fn disable_layer(&self, layer: Layer) {
+        self.layer(layer).cr.modify(|_, w| w.len().clear_bit());
+    }
+
+    /// Set the constant alpha of the layer, from 0 (transparent) to 255 (opaque)
+    ///
+    /// Like the other layer settings, it takes effect on the next reload.
+    pub fn set_layer_alpha(&mut self, layer: Layer, alpha: u8) {
+        self.layer(layer).cacr.write(|w| w.consta().bits(alpha));
+    }
+
+    /// Set how the layer is blended with the layers below it
+    pub fn set_layer_blending(&mut self, layer: Layer, blending: Blending) {
+        // BF1 weights the layer, BF2 the layers below
+        let (bf1, bf2) = match blending {
+            Blending::ConstantAlpha => (0b100, 0b101),
+            Blending::PixelAlpha => (0b110, 0b111),
+        };
+        self.layer(layer)
+            .bfcr
+            .write(|w| unsafe { w.bf1().bits(bf1).bf2().bits(bf2) });
+    }
+
+    /// Set the color key of the layer, as a 24-bit RGB888 value, or disable color keying with
+    /// `None`. The pixels of the key color are made transparent, showing the layers below.
+    pub fn set_color_key(&mut self, layer: Layer, key: Option<u32>) {
+        let layer = self.layer(layer);
+        match key {
+            Some(key) => {
+                layer.ckcr.write(|w| {
+                    w.ckred()
+                        .bits((key >> 16) as u8)
+                        .ckgreen()
+                        .bits((key >> 8) as u8)
+                        .ckblue()
+                        .bits(key as u8)
+                });
+                layer.cr.modify(|_, w| w.colken().set_bit());
+            }
+            None => layer.cr.modify(|_, w| w.colken().clear_bit()),
+        }
+    }
+
+    /// Set the ARGB8888 color of the layer outside of its window, or while it is disabled
+    pub fn set_default_color(&mut self, layer: Layer, color: u32) {
+        self.layer(layer).dccr.write(|w| unsafe { w.bits(color) });
+    }
+
+    /// Load the RGB888 `colors` in the color lookup table (CLUT) of the layer, from the index 0,
+    /// to display the indexed pixel formats
+    ///
+    /// The CLUT can only be written while the layer is disabled or during the vertical blanking:
+    /// if the layer is enabled, this waits for the next vertical blanking, so that the colors
+    /// are switched between two frames. A table of 256 colors is written well before its end.
+    pub fn load_clut(&mut self, layer: Layer, colors: &[u32]) {
+        assert!(colors.len() <= 256);
+        if self.layer(layer).cr.read().len().bit_is_set() {
+            self.wait_for_vsync();
+        }
+        for (index, color) in colors.iter().enumerate() {
+            self.write_clut_entry(layer, index as u8, *color);
+        }
+    }
+
+    /// Set the RGB888 color of an entry of the CLUT of the layer
+    ///
+    /// Like `load_clut`, this waits for the next vertical blanking if the layer is enabled.
+    pub fn set_clut_entry(&mut self, layer: Layer, index: u8, color: u32) {
+        if self.layer(layer).cr.read().len().bit_is_set() {
+            self.wait_for_vsync();
+        }
+        self.write_clut_entry(layer, index, color);
+    }
+
+    fn write_clut_entry(&mut self, layer: Layer, index: u8, color: u32) {
+        self.layer(layer).clutwr.write(|w| {
+            w.clutadd()
+                .bits(index)
+                .red()
+                .bits((color >> 16) as u8)
+                .green()
+                .bits((color >> 8) as u8)
+                .blue()
+                .bits(color as u8)
+        });
+    }
+
+    /// Enable the CLUT of the layer, on the next reload
+    pub fn enable_clut(&mut self, layer: Layer) {
+        self.layer(layer).cr.modify(|_, w| w.cluten().set_bit());
+    }
+
+    /// Disable the CLUT of the layer, on the next reload
+    pub fn disable_clut(&mut self, layer: Layer) {
+        self.layer(layer).cr.modify(|_, w| w.cluten().clear_bit());
+    }
+
+    fn layer(&self, layer: Layer) -> &crate::pac::ltdc::LAYER {
+        match layer {
+            Layer::L1 => &self._ltdc.layer1,
+            Layer::L2 => &self._ltdc.layer2,
+        }
+    }
+
     /// Draw a pixel at position (x,y) on the given layer
     pub fn draw_pixel(&mut self, layer: Layer, x: usize, y: usize, color: T) {
         if x >= self.config.active_width as usize || y >= self.config.active_height as usize {
@@ -368,16 +517,8 @@ impl<T: 'static + SupportedWord> DisplayController<T> {
         color: u32,
     ) {
         // Output color format
-        self._dma2d.opfccr.write(|w| {
-            w.cm().bits(match &self.pixel_format {
-                PixelFormat::ARGB8888 => 0b000,
-                // PixelFormat::RGB888 => 0b001, unsupported for now
-                PixelFormat::RGB565 => 0b010,
-                PixelFormat::ARGB1555 => 0b011,
-                PixelFormat::ARGB4444 => 0b100,
-                _ => unreachable!(),
-            })
-        });
+        let output_format = self.output_color_mode();
+        self._dma2d.opfccr.write(|w| w.cm().bits(output_format));
 
         // Output color
         self._dma2d.ocolr.write_with_zero(|w| w.bits(color));
@@ -411,11 +552,111 @@ impl<T: 'static + SupportedWord> DisplayController<T> {
             .modify(|_, w| w.mode().bits(0b11).start().set_bit());
     }
 
+    /// Wait for the end of the DMA2D transfer, such as a hardware accelerated rectangle
+    pub fn wait_transfer(&self) {
+        while self._dma2d.cr.read().start().bit_is_set() {}
+    }
+
+    /// DMA2D output color mode of the pixel format, which must be a direct color format
+    fn output_color_mode(&self) -> u8 {
+        match &self.pixel_format {
+            PixelFormat::ARGB8888 => 0b000,
+            // PixelFormat::RGB888 => 0b001, unsupported for now
+            PixelFormat::RGB565 => 0b010,
+            PixelFormat::ARGB1555 => 0b011,
+            PixelFormat::ARGB4444 => 0b100,
+            _ => unreachable!(),
+        }
+    }
+
     /// Reload display controller immediatly
     pub fn reload(&self) {
         // Reload ltdc config immediatly
         self._ltdc.srcr.modify(|_, w| w.imr().set_bit());
     }
+
+    /// Reload display controller during the next vertical blanking, so that the new frame buffer
+    /// addresses and layer settings are not applied in the middle of a frame
+    pub fn reload_on_vblank(&self) {
+        self._ltdc.srcr.modify(|_, w| w.vbr().set_bit());
+    }
+
+    /// Check if a reload requested with `reload_on_vblank` has not happened yet
+    pub fn is_reload_pending(&self) -> bool {
+        self._ltdc.srcr.read().vbr().bit_is_set()
+    }
+
+    /// Line at which the vertical blanking starts, counted from the start of the vertical
+    /// synchronization like the line interrupt position
+    pub fn vblank_line(&self) -> u16 {
+        self.config.v_sync + self.config.v_back_porch + self.config.active_height
+    }
+
+    /// Set the line at which the `Line` event happens, counted from the start of the vertical
+    /// synchronization (use `vblank_line` for the start of the vertical blanking)
+    pub fn set_line_interrupt(&mut self, line: u16) {
+        self._ltdc.lipcr.write(|w| w.lipos().bits(line));
+    }
+
+    /// Check if the current line is outside of the active display area
+    pub fn is_in_vblank(&self) -> bool {
+        self._ltdc.cdsr.read().vdes().bit_is_clear()
+    }
+
+    /// Wait for the start of the next vertical blanking
+    ///
+    /// The frame buffers can then be updated, or swapped with `reload`, without tearing until
+    /// the next frame starts to be displayed.
+    pub fn wait_for_vsync(&self) {
+        while self.is_in_vblank() {}
+        while !self.is_in_vblank() {}
+    }
+
+    /// Start listening for an `event`
+    ///
+    /// Note, you will also have to enable the LTDC (or LTDC_ER for the errors) interrupt in the
+    /// NVIC to start receiving events.
+    pub fn listen(&mut self, event: Event) {
+        self._ltdc.ier.modify(|_, w| match event {
+            Event::Line => w.lie().set_bit(),
+            Event::FifoUnderrun => w.fuie().set_bit(),
+            Event::TransferError => w.terrie().set_bit(),
+            Event::RegisterReload => w.rrie().set_bit(),
+        });
+    }
+
+    /// Stop listening for an `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self._ltdc.ier.modify(|_, w| match event {
+            Event::Line => w.lie().clear_bit(),
+            Event::FifoUnderrun => w.fuie().clear_bit(),
+            Event::TransferError => w.terrie().clear_bit(),
+            Event::RegisterReload => w.rrie().clear_bit(),
+        });
+    }
+
+    /// Check if an `event` happened
+    pub fn is_pending(&self, event: Event) -> bool {
+        let isr = self._ltdc.isr.read();
+        match event {
+            Event::Line => isr.lif().bit_is_set(),
+            Event::FifoUnderrun => isr.fuif().bit_is_set(),
+            Event::TransferError => isr.terrif().bit_is_set(),
+            Event::RegisterReload => isr.rrif().bit_is_set(),
+        }
+    }
+
+    /// Clear the interrupt flag of an `event`
+    ///
+    /// If the interrupt is not cleared, it will immediately retrigger after the ISR has finished.
+    pub fn clear_interrupt(&mut self, event: Event) {
+        self._ltdc.icr.write(|w| match event {
+            Event::Line => w.clif().set_bit(),
+            Event::FifoUnderrun => w.cfuif().set_bit(),
+            Event::TransferError => w.cterrif().set_bit(),
+            Event::RegisterReload => w.crrif().set_bit(),
+        });
+    }
 }
 
 /// Available PixelFormats to work with
@@ -0,0 +1,45 @@
+use core::future::poll_fn;
+use core::task::Poll;
+
+use super::{DisplayController, SupportedWord};
+use crate::pac::LTDC;
+use crate::waker::WakerCell;
+
+static WAKER: WakerCell = WakerCell::new();
+
+/// Wakes the task awaiting on the vertical blanking.
+///
+/// Must be called from the LTDC interrupt handler. The line interrupt is
+/// masked until the next poll of the pending wait.
+pub fn on_interrupt() {
+    // NOTE(unsafe) only the line interrupt enable bit, owned by the async
+    // API, is touched
+    let ltdc = unsafe { &*LTDC::ptr() };
+    ltdc.ier.modify(|_, w| w.lie().clear_bit());
+    WAKER.wake();
+}
+
+impl<T: 'static + SupportedWord> DisplayController<T> {
+    /// Waits for the start of the next vertical blanking, sleeping on the
+    /// line interrupt
+    ///
+    /// This uses the line interrupt position, which is overwritten.
+    pub async fn wait_for_vblank(&mut self) {
+        let line = self.vblank_line();
+        let ltdc = &self._ltdc;
+        ltdc.icr.write(|w| w.clif().set_bit());
+        ltdc.lipcr.write(|w| w.lipos().bits(line));
+
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if ltdc.isr.read().lif().bit_is_set() {
+                ltdc.icr.write(|w| w.clif().set_bit());
+                Poll::Ready(())
+            } else {
+                ltdc.ier.modify(|_, w| w.lie().set_bit());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
@@ -0,0 +1,125 @@
+use embedded_graphics::{
+    drawable::Pixel,
+    geometry::{Point, Size},
+    pixelcolor::{IntoStorage, Rgb565, Rgb888},
+    primitives::Rectangle,
+    style::{PrimitiveStyle, Styled},
+    DrawTarget,
+};
+
+use super::{DisplayController, Layer, SupportedWord};
+
+/// `embedded-graphics` draw target over a layer of the display
+///
+/// The pixels are written by the CPU, the filled rectangles and the screen
+/// clearing by the DMA2D, which is waited for. The drawings outside of the
+/// screen are clipped.
+///
+/// `LayerTarget<u16>` draws `Rgb565` colors on an RGB565 layer, and
+/// `LayerTarget<u32>` draws opaque `Rgb888` colors on an ARGB8888 layer.
+pub struct LayerTarget<'a, T: 'static + SupportedWord> {
+    controller: &'a mut DisplayController<T>,
+    layer: Layer,
+}
+
+impl<T: 'static + SupportedWord> DisplayController<T> {
+    /// Returns an `embedded-graphics` draw target over `layer`, which must
+    /// have been configured
+    pub fn draw_target(&mut self, layer: Layer) -> LayerTarget<'_, T> {
+        LayerTarget {
+            controller: self,
+            layer,
+        }
+    }
+}
+
+impl<'a, T: 'static + SupportedWord> LayerTarget<'a, T> {
+    fn width(&self) -> i32 {
+        i32::from(self.controller.config.active_width)
+    }
+
+    fn height(&self) -> i32 {
+        i32::from(self.controller.config.active_height)
+    }
+
+    fn set_pixel(&mut self, point: Point, color: T) {
+        if (0..self.width()).contains(&point.x) && (0..self.height()).contains(&point.y) {
+            self.controller
+                .draw_pixel(self.layer, point.x as usize, point.y as usize, color);
+        }
+    }
+
+    /// Fills the rectangle from `top_left` to `bottom_right`, inclusive,
+    /// with the DMA2D, and waits for the end of the transfer
+    fn fill(&mut self, top_left: Point, bottom_right: Point, color: u32) {
+        let left = top_left.x.max(0);
+        let top = top_left.y.max(0);
+        let right = (bottom_right.x + 1).min(self.width());
+        let bottom = (bottom_right.y + 1).min(self.height());
+        if left >= right || top >= bottom {
+            return;
+        }
+
+        // The CPU is blocked until the end of the transfer, so the buffer
+        // isn't accessed meanwhile
+        unsafe {
+            self.controller.draw_rectangle(
+                self.layer,
+                (left as usize, top as usize),
+                (right as usize, bottom as usize),
+                color,
+            );
+        }
+        self.controller.wait_transfer();
+    }
+}
+
+macro_rules! draw_target {
+    ($word:ty, $color:ty, $alpha:expr) => {
+        impl<'a> DrawTarget<$color> for LayerTarget<'a, $word> {
+            type Error = core::convert::Infallible;
+
+            fn draw_pixel(&mut self, pixel: Pixel<$color>) -> Result<(), Self::Error> {
+                let Pixel(point, color) = pixel;
+                self.set_pixel(point, (color.into_storage() | $alpha) as $word);
+                Ok(())
+            }
+
+            fn size(&self) -> Size {
+                Size::new(self.width() as u32, self.height() as u32)
+            }
+
+            fn clear(&mut self, color: $color) -> Result<(), Self::Error> {
+                let bottom_right = Point::new(self.width() - 1, self.height() - 1);
+                self.fill(
+                    Point::zero(),
+                    bottom_right,
+                    u32::from(color.into_storage()) | $alpha,
+                );
+                Ok(())
+            }
+
+            fn draw_rectangle(
+                &mut self,
+                item: &Styled<Rectangle, PrimitiveStyle<$color>>,
+            ) -> Result<(), Self::Error> {
+                let style = &item.style;
+                match style.fill_color {
+                    Some(color) if style.stroke_color.is_none() || style.stroke_width == 0 => {
+                        let rectangle = &item.primitive;
+                        self.fill(
+                            rectangle.top_left,
+                            rectangle.bottom_right,
+                            u32::from(color.into_storage()) | $alpha,
+                        );
+                        Ok(())
+                    }
+                    _ => self.draw_iter(item),
+                }
+            }
+        }
+    };
+}
+
+draw_target!(u16, Rgb565, 0);
+draw_target!(u32, Rgb888, 0xff00_0000);
@@ -0,0 +1,242 @@
+//! Copy of images to the layers with the DMA2D
+//!
+//! The foreground pixel format converter of the DMA2D expands the images
+//! stored in a compact format, such as the indexed L8 or L4 images with
+//! their color lookup table (CLUT), to the format of the layer during the
+//! copy. When blending, the background converter reads the layer back, so
+//! the image is drawn over it according to its alpha channel.
+
+use super::{DisplayController, Layer, SupportedWord};
+
+/// Color format of an image read by the DMA2D
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    ARGB8888,
+    RGB888,
+    RGB565,
+    ARGB1555,
+    ARGB4444,
+    /// 8-bit index in the CLUT
+    L8,
+    /// 4-bit alpha + 4-bit index in the CLUT
+    AL44,
+    /// 8-bit alpha + 8-bit index in the CLUT
+    AL88,
+    /// 4-bit index in the CLUT
+    L4,
+    /// 8-bit alpha, with the color of the image
+    A8,
+    /// 4-bit alpha, with the color of the image
+    A4,
+}
+
+impl InputFormat {
+    /// Returns the value of the CM field in the DMA2D_FGPFCCR register
+    fn bits(self) -> u8 {
+        match self {
+            InputFormat::ARGB8888 => 0b0000,
+            InputFormat::RGB888 => 0b0001,
+            InputFormat::RGB565 => 0b0010,
+            InputFormat::ARGB1555 => 0b0011,
+            InputFormat::ARGB4444 => 0b0100,
+            InputFormat::L8 => 0b0101,
+            InputFormat::AL44 => 0b0110,
+            InputFormat::AL88 => 0b0111,
+            InputFormat::L4 => 0b1000,
+            InputFormat::A8 => 0b1001,
+            InputFormat::A4 => 0b1010,
+        }
+    }
+
+    /// Returns the number of bits per pixel
+    pub fn bits_per_pixel(self) -> usize {
+        match self {
+            InputFormat::ARGB8888 => 32,
+            InputFormat::RGB888 => 24,
+            InputFormat::RGB565
+            | InputFormat::ARGB1555
+            | InputFormat::ARGB4444
+            | InputFormat::AL88 => 16,
+            InputFormat::L8 | InputFormat::AL44 | InputFormat::A8 => 8,
+            InputFormat::L4 | InputFormat::A4 => 4,
+        }
+    }
+
+    fn is_indexed(self) -> bool {
+        matches!(
+            self,
+            InputFormat::L8 | InputFormat::AL44 | InputFormat::AL88 | InputFormat::L4
+        )
+    }
+}
+
+/// Color lookup table of an indexed image, of up to 256 colors
+#[derive(Clone, Copy, Debug)]
+pub enum Clut<'a> {
+    ARGB8888(&'a [u32]),
+    /// 3 bytes per color, blue first
+    RGB888(&'a [u8]),
+}
+
+impl Clut<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Clut::ARGB8888(colors) => colors.len(),
+            Clut::RGB888(bytes) => bytes.len() / 3,
+        }
+    }
+
+    fn address(&self) -> u32 {
+        match self {
+            Clut::ARGB8888(colors) => colors.as_ptr() as u32,
+            Clut::RGB888(bytes) => bytes.as_ptr() as u32,
+        }
+    }
+}
+
+/// Image in memory, to be copied to a layer
+#[derive(Clone, Copy, Debug)]
+pub struct Image<'a> {
+    data: &'a [u8],
+    format: InputFormat,
+    width: u16,
+    height: u16,
+    clut: Option<Clut<'a>>,
+    color: u32,
+}
+
+impl<'a> Image<'a> {
+    /// Creates an image of `width` x `height` pixels, without padding between
+    /// the lines. The width of the 4-bit images must be even.
+    pub fn new(data: &'a [u8], format: InputFormat, width: u16, height: u16) -> Self {
+        let bits = usize::from(width) * usize::from(height) * format.bits_per_pixel();
+        assert!(data.len() * 8 >= bits);
+        assert!(format.bits_per_pixel() != 4 || width % 2 == 0);
+
+        Image {
+            data,
+            format,
+            width,
+            height,
+            clut: None,
+            color: 0,
+        }
+    }
+
+    /// Sets the CLUT of the indexed image, loaded by the DMA2D before each
+    /// copy
+    pub fn with_clut(mut self, clut: Clut<'a>) -> Self {
+        assert!(clut.len() > 0 && clut.len() <= 256);
+        self.clut = Some(clut);
+        self
+    }
+
+    /// Sets the RGB888 color of the A8 or A4 image
+    pub fn with_color(mut self, color: u32) -> Self {
+        self.color = color & 0x00ff_ffff;
+        self
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+}
+
+impl<T: 'static + SupportedWord> DisplayController<T> {
+    /// Copies `image` to `layer`, with its top left corner at `position`,
+    /// converting it to the pixel format of the layer
+    ///
+    /// The image must be fully on the screen. This waits for the end of the
+    /// transfer.
+    pub fn blit(&mut self, layer: Layer, image: &Image, position: (usize, usize)) {
+        self.start_image_transfer(layer, image, position, false);
+    }
+
+    /// Draws `image` over `layer`, blending them according to the alpha
+    /// channel of the image
+    ///
+    /// The image must be fully on the screen. This waits for the end of the
+    /// transfer.
+    pub fn blend(&mut self, layer: Layer, image: &Image, position: (usize, usize)) {
+        self.start_image_transfer(layer, image, position, true);
+    }
+
+    fn start_image_transfer(
+        &mut self,
+        layer: Layer,
+        image: &Image,
+        position: (usize, usize),
+        blend: bool,
+    ) {
+        let width = usize::from(self.config.active_width);
+        assert!(position.0 + usize::from(image.width) <= width);
+        assert!(position.1 + usize::from(image.height) <= usize::from(self.config.active_height));
+        assert!(!image.format.is_indexed() || image.clut.is_some());
+
+        let output_format = self.output_color_mode();
+        let offset = position.0 + width * position.1;
+        let destination = match layer {
+            Layer::L1 => self.buffer1.as_ref().unwrap()[offset..].as_ptr() as u32,
+            Layer::L2 => self.buffer2.as_ref().unwrap()[offset..].as_ptr() as u32,
+        };
+        let line_offset = (width - usize::from(image.width)) as u16;
+        let dma2d = &self._dma2d;
+
+        // Load the CLUT, and wait for the end of its transfer
+        if let Some(clut) = &image.clut {
+            dma2d
+                .fgcmar
+                .write(|w| unsafe { w.ma().bits(clut.address()) });
+            dma2d.fgpfccr.write(|w| {
+                w.cs()
+                    .bits((clut.len() - 1) as u8)
+                    .ccm()
+                    .bit(matches!(clut, Clut::RGB888(_)))
+                    .start()
+                    .set_bit()
+            });
+            while dma2d.fgpfccr.read().start().bit_is_set() {}
+        }
+
+        // Foreground: the image, keeping its alpha channel
+        dma2d
+            .fgmar
+            .write(|w| unsafe { w.ma().bits(image.data.as_ptr() as u32) });
+        dma2d.fgor.write(|w| w.lo().bits(0));
+        dma2d.fgcolr.write(|w| unsafe { w.bits(image.color) });
+        dma2d
+            .fgpfccr
+            .modify(|_, w| unsafe { w.cm().bits(image.format.bits()).am().no_modify() });
+
+        // Background: the layer itself, when blending
+        if blend {
+            dma2d.bgmar.write(|w| unsafe { w.ma().bits(destination) });
+            dma2d.bgor.write(|w| w.lo().bits(line_offset));
+            dma2d
+                .bgpfccr
+                .write(|w| unsafe { w.cm().bits(output_format).am().no_modify() });
+        }
+
+        // Output: the layer
+        dma2d
+            .opfccr
+            .write(|w| unsafe { w.cm().bits(output_format) });
+        dma2d.omar.write(|w| unsafe { w.ma().bits(destination) });
+        dma2d.oor.write(|w| w.lo().bits(line_offset));
+        dma2d
+            .nlr
+            .write(|w| w.pl().bits(image.width).nl().bits(image.height));
+
+        // Start transfer: memory to memory mode with pixel format conversion,
+        // or with blending
+        let mode = if blend { 0b10 } else { 0b01 };
+        dma2d
+            .cr
+            .modify(|_, w| w.mode().bits(mode).start().set_bit());
+        self.wait_transfer();
+    }
+}
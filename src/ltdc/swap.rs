@@ -0,0 +1,105 @@
+//! Double buffering of a layer
+//!
+//! [`FrameSwapper`] displays one frame buffer while the next frame is drawn
+//! on the other one, with the drawing methods of the [`DisplayController`],
+//! which all target the back buffer. [`FrameSwapper::swap`] then switches
+//! the layer to the back buffer during the vertical blanking, so a frame is
+//! never displayed half drawn.
+//!
+//! ```ignore
+//! display.config_layer(Layer::L1, buffer1, PixelFormat::RGB565);
+//! display.enable_layer(Layer::L1);
+//! display.reload();
+//!
+//! let mut swapper = FrameSwapper::new(display, Layer::L1, buffer2);
+//! loop {
+//!     render(swapper.back_buffer());
+//!     swapper.swap();
+//! }
+//! ```
+//!
+//! The frame buffers placed in a cacheable memory must be cleaned from the
+//! data cache before the swap, or the LTDC reads outdated pixels.
+
+use core::mem;
+
+use super::{DisplayController, Layer, SupportedWord};
+
+/// Owner of the two frame buffers of a layer, swapped on the vertical
+/// blanking
+pub struct FrameSwapper<T: 'static + SupportedWord> {
+    display: DisplayController<T>,
+    layer: Layer,
+    /// Frame buffer being displayed, the back buffer being held by the
+    /// display controller
+    front: &'static mut [T],
+}
+
+impl<T: 'static + SupportedWord> FrameSwapper<T> {
+    /// Double buffers `layer`, which must have been configured: its frame
+    /// buffer stays displayed while the first frame is drawn on `back`, of
+    /// the same size
+    pub fn new(mut display: DisplayController<T>, layer: Layer, back: &'static mut [T]) -> Self {
+        let slot = display.buffer_slot(layer);
+        assert!(slot.as_ref().map(|buffer| buffer.len()) == Some(back.len()));
+        let front = slot.replace(back).unwrap();
+
+        FrameSwapper {
+            display,
+            layer,
+            front,
+        }
+    }
+
+    /// Returns the frame buffer to draw the next frame on
+    pub fn back_buffer(&mut self) -> &mut [T] {
+        self.display.buffer_slot(self.layer).as_mut().unwrap()
+    }
+
+    /// Returns the display controller, whose drawing methods target the back
+    /// buffer
+    pub fn display(&mut self) -> &mut DisplayController<T> {
+        &mut self.display
+    }
+
+    /// Displays the back buffer from the next vertical blanking, and waits
+    /// for it, the former front buffer becoming the back buffer
+    ///
+    /// The pending DMA2D transfer, such as a rectangle, is waited for first.
+    pub fn swap(&mut self) {
+        self.display.wait_transfer();
+
+        let back = self.display.buffer_slot(self.layer).take().unwrap();
+        self.display
+            .layer(self.layer)
+            .cfbar
+            .write(|w| w.cfbadd().bits(back.as_ptr() as u32));
+        self.display.reload_on_vblank();
+        while self.display.is_reload_pending() {}
+
+        let front = mem::replace(&mut self.front, back);
+        *self.display.buffer_slot(self.layer) = Some(front);
+    }
+
+    /// Stops the double buffering, and returns the display controller, with
+    /// the displayed frame buffer as the buffer of the layer, and the back
+    /// buffer
+    pub fn free(mut self) -> (DisplayController<T>, &'static mut [T]) {
+        self.display.wait_transfer();
+        let back = self
+            .display
+            .buffer_slot(self.layer)
+            .replace(self.front)
+            .unwrap();
+        (self.display, back)
+    }
+}
+
+impl<T: 'static + SupportedWord> DisplayController<T> {
+    fn buffer_slot(&mut self, layer: Layer) -> &mut Option<&'static mut [T]> {
+        match layer {
+            Layer::L1 => &mut self.buffer1,
+            Layer::L2 => &mut self.buffer2,
+        }
+    }
+}
@@ -2,6 +2,11 @@
 //!
 //! Requires the `usb_fs` feature.
 //! Only one of the `usb_fs`/`usb_hs` features can be selected at the same time.
+//!
+//! VBUS is sensed on PA9 by default. Boards that don't wire VBUS to PA9 can
+//! disable sensing with [`set_vbus_sensing`] once the bus is enabled. The ID
+//! pin (PA10, AF10) is read with [`connector_id`], and the start of frame
+//! pulse is output on PA8 (AF10) once that pin is in alternate mode.
 
 use crate::pac;
 
@@ -10,6 +15,7 @@ use crate::gpio::{
     Alternate,
 };
 use crate::rcc::{BusClock, Clocks, Enable, Reset};
+use embedded_hal::blocking::delay::DelayMs;
 use fugit::HertzU32 as Hertz;
 
 pub use synopsys_usb_otg::UsbBus;
@@ -70,4 +76,107 @@ unsafe impl UsbPeripheral for USB {
     }
 }
 
+/// Role selected by the ID pin of a micro-AB connector
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectorId {
+    /// ID pin grounded, the peripheral should act as host
+    A,
+    /// ID pin floating, the peripheral should act as device
+    B,
+}
+
+/// Enables or disables VBUS sensing.
+///
+/// A self-powered device must sense VBUS to disconnect when the host is
+/// unplugged. A bus-powered device, or a board without VBUS on PA9, can
+/// disable sensing: the B-session valid signal is then forced, so the device
+/// connects as soon as the bus is enabled.
+///
+/// The core is reset when the bus is enabled, so call this after
+/// `UsbDeviceBuilder::build`.
+pub fn set_vbus_sensing(enabled: bool) {
+    // NOTE(unsafe) only the VBUS detection bits are touched, atomically
+    cortex_m::interrupt::free(|_| {
+        let otg = unsafe { &*pac::OTG_FS_GLOBAL::ptr() };
+        let gotgctl = &otg.otg_fs_gotgctl;
+        if enabled {
+            gotgctl.modify(|_, w| w.bvaloen().clear_bit());
+            otg.otg_fs_gccfg.modify(|_, w| w.vbden().set_bit());
+        } else {
+            otg.otg_fs_gccfg.modify(|_, w| w.vbden().clear_bit());
+            gotgctl.modify(|_, w| w.bvaloen().set_bit().bvaloval().set_bit());
+        }
+    });
+}
+
+/// Returns `true` if VBUS is valid for a B-device, or if the B-session valid
+/// signal is forced
+pub fn is_vbus_valid() -> bool {
+    let otg = unsafe { &*pac::OTG_FS_GLOBAL::ptr() };
+    otg.otg_fs_gotgctl.read().bsvld().bit_is_set()
+}
+
+/// Returns the role selected by the ID pin, which must be in alternate mode
+pub fn connector_id() -> ConnectorId {
+    let otg = unsafe { &*pac::OTG_FS_GLOBAL::ptr() };
+    if otg.otg_fs_gotgctl.read().cidsts().bit_is_set() {
+        ConnectorId::B
+    } else {
+        ConnectorId::A
+    }
+}
+
+/// Connects the start of frame pulse to the internal trigger 1 of TIM2, to
+/// measure the frame period against a timer for clock trimming
+pub fn connect_sof_to_tim2() {
+    // NOTE(unsafe) ITR1_RMP is only used for this remap
+    cortex_m::interrupt::free(|_| {
+        let tim2 = unsafe { &*pac::TIM2::ptr() };
+        tim2.or.modify(|_, w| unsafe { w.itr1_rmp().bits(0b10) });
+    });
+}
+
+/// Returns `true` if the bus is suspended by the host.
+///
+/// `UsbDevice::poll` also reports the suspend with `UsbDeviceState::Suspend`.
+pub fn is_suspended() -> bool {
+    let device = unsafe { &*pac::OTG_FS_DEVICE::ptr() };
+    device.otg_fs_dsts.read().suspsts().bit_is_set()
+}
+
+/// Stops the PHY clock and gates the AHB clock of the core, to reduce the
+/// current drawn while the bus is suspended.
+///
+/// Only the resume and reset detection keep running, the USB interrupt still
+/// fires on resume. Call [`exit_low_power`] from there, before polling the
+/// device again.
+pub fn enter_low_power() {
+    let pwrclk = unsafe { &*pac::OTG_FS_PWRCLK::ptr() };
+    pwrclk
+        .otg_fs_pcgcctl
+        .modify(|_, w| w.stppclk().set_bit().gatehclk().set_bit());
+}
+
+/// Restarts the clocks stopped by [`enter_low_power`]
+pub fn exit_low_power() {
+    let pwrclk = unsafe { &*pac::OTG_FS_PWRCLK::ptr() };
+    pwrclk
+        .otg_fs_pcgcctl
+        .modify(|_, w| w.stppclk().clear_bit().gatehclk().clear_bit());
+}
+
+/// Signals a remote wakeup to the host, restarting the clocks first.
+///
+/// The host must have enabled the feature, which `UsbDevice::remote_wakeup_enabled`
+/// reports, and the bus must be suspended. The resume signalling lasts 10 ms.
+pub fn remote_wakeup<D: DelayMs<u32>>(delay: &mut D) {
+    exit_low_power();
+
+    // RWUSIG is not used by the bus driver
+    let device = unsafe { &*pac::OTG_FS_DEVICE::ptr() };
+    device.otg_fs_dctl.modify(|_, w| w.rwusig().set_bit());
+    delay.delay_ms(10);
+    device.otg_fs_dctl.modify(|_, w| w.rwusig().clear_bit());
+}
+
 pub type UsbBusType = UsbBus<USB>;
@@ -0,0 +1,619 @@
+//! USB OTG full-speed peripheral in host mode
+//!
+//! Requires the `usb_fs_host` feature, which cannot be combined with `usb_fs`
+//! as both drive the same peripheral.
+//!
+//! This is a polled, blocking driver in slave mode (no DMA): each transfer is
+//! split into packets, each packet being sent on the channel of its [`Pipe`].
+//! It covers control, bulk and interrupt transfers with full and low speed
+//! devices connected to the root port, which is enough to enumerate and drive
+//! HID keyboards, mass storage devices and USB-serial adapters. The class
+//! drivers are left to the application or to a host stack crate.
+//!
+//! A packet or a channel halt that isn't done within [`TIMEOUT_FRAMES`] frames
+//! fails with `Error::Timeout`, and a disconnection of the device during a
+//! transfer with `Error::NotConnected`.
+//!
+//! The VBUS supply of the device must be switched on by the application, this
+//! driver only sets the port power bit.
+//!
+//! See section 42 of RM0410.
+
+use core::ptr;
+
+use crate::gpio::{
+    gpioa::{PA11, PA12},
+    Alternate,
+};
+use crate::pac;
+use crate::rcc::{Clocks, Enable, Reset};
+
+// The channel registers are not arrays in the PAC, so the core is accessed
+// through its register offsets, like `synopsys-usb-otg` does in device mode.
+const GAHBCFG: usize = 0x008;
+const GUSBCFG: usize = 0x00c;
+const GRSTCTL: usize = 0x010;
+const GINTSTS: usize = 0x014;
+const GRXSTSP: usize = 0x020;
+const GRXFSIZ: usize = 0x024;
+const HNPTXFSIZ: usize = 0x028;
+const HNPTXSTS: usize = 0x02c;
+const GCCFG: usize = 0x038;
+const HPTXFSIZ: usize = 0x100;
+const HCFG: usize = 0x400;
+const HFIR: usize = 0x404;
+const HFNUM: usize = 0x408;
+const HPTXSTS: usize = 0x410;
+const HPRT: usize = 0x440;
+const PCGCCTL: usize = 0xe00;
+
+const fn hcchar(channel: u8) -> usize {
+    0x500 + 0x20 * channel as usize
+}
+const fn hcint(channel: u8) -> usize {
+    0x508 + 0x20 * channel as usize
+}
+const fn hctsiz(channel: u8) -> usize {
+    0x510 + 0x20 * channel as usize
+}
+const fn fifo(channel: u8) -> usize {
+    0x1000 * (channel as usize + 1)
+}
+
+/// HPRT bits cleared by writing 1, masked out when modifying the register
+const HPRT_W1C: u32 = (1 << 1) | (1 << 2) | (1 << 3) | (1 << 5);
+
+const HCINT_XFRC: u32 = 1 << 0;
+const HCINT_CHH: u32 = 1 << 1;
+const HCINT_STALL: u32 = 1 << 3;
+const HCINT_NAK: u32 = 1 << 4;
+const HCINT_TXERR: u32 = 1 << 7;
+const HCINT_BBERR: u32 = 1 << 8;
+const HCINT_FRMOR: u32 = 1 << 9;
+const HCINT_DTERR: u32 = 1 << 10;
+
+const CHANNELS: u8 = 12;
+/// Frames, of 1 ms, after which a packet or a channel halt is abandoned
+pub const TIMEOUT_FRAMES: u32 = 100;
+/// NAKs tolerated on a control transfer stage before giving up
+const CONTROL_NAK_RETRIES: u32 = 10_000;
+
+/// Host mode errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// No device is connected, or the port is not enabled
+    NotConnected,
+    /// The endpoint is halted
+    Stall,
+    /// The device had no data or could not accept it yet, retry later
+    Nak,
+    /// CRC error, timeout, bit stuffing or PID error
+    Transaction,
+    /// The device sent more data than expected
+    Babble,
+    /// Data toggle mismatch
+    DataToggle,
+    /// All the channels are in use
+    NoChannel,
+    /// The packet or the channel halt didn't complete in time
+    Timeout,
+}
+
+/// Speed of the connected device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speed {
+    Low,
+    Full,
+}
+
+/// Endpoint transfer type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferType {
+    Control = 0b00,
+    Bulk = 0b10,
+    Interrupt = 0b11,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pid {
+    Data0 = 0b00,
+    Data1 = 0b10,
+    Setup = 0b11,
+}
+
+/// A device endpoint bound to a host channel
+#[derive(Debug)]
+pub struct Pipe {
+    channel: u8,
+    device: u8,
+    endpoint: u8,
+    transfer_type: TransferType,
+    max_packet_size: u16,
+    /// Next data toggle, DATA1 if set
+    toggle: bool,
+}
+
+impl Pipe {
+    /// Sets the device address, after a SET_ADDRESS request
+    pub fn set_device_address(&mut self, address: u8) {
+        self.device = address;
+    }
+
+    /// Sets the maximum packet size, once read from the descriptors
+    pub fn set_max_packet_size(&mut self, max_packet_size: u16) {
+        self.max_packet_size = max_packet_size;
+    }
+
+    /// Resets the data toggle to DATA0, after a CLEAR_FEATURE(ENDPOINT_HALT)
+    /// or a SET_CONFIGURATION request
+    pub fn reset_toggle(&mut self) {
+        self.toggle = false;
+    }
+}
+
+/// USB OTG FS host
+pub struct UsbHost {
+    usb_global: pac::OTG_FS_GLOBAL,
+    usb_host: pac::OTG_FS_HOST,
+    usb_pwrclk: pac::OTG_FS_PWRCLK,
+    pins: (PA11<Alternate<10>>, PA12<Alternate<10>>),
+    /// Allocated channels
+    channels: u16,
+    /// Core clock cycles per millisecond, for the delays
+    cycles_per_ms: u32,
+    speed: Option<Speed>,
+}
+
+impl UsbHost {
+    /// Initializes the peripheral in host mode and powers the port.
+    ///
+    /// The 48 MHz clock must be configured with `CFGR::use_pll48clk`.
+    pub fn new(
+        usb_global: pac::OTG_FS_GLOBAL,
+        usb_host: pac::OTG_FS_HOST,
+        usb_pwrclk: pac::OTG_FS_PWRCLK,
+        pins: (PA11<Alternate<10>>, PA12<Alternate<10>>),
+        clocks: &Clocks,
+    ) -> Self {
+        assert!(clocks.is_pll48clk_valid());
+
+        cortex_m::interrupt::free(|_| unsafe {
+            pac::OTG_FS_GLOBAL::enable_unchecked();
+            pac::OTG_FS_GLOBAL::reset_unchecked();
+        });
+
+        let mut host = Self {
+            usb_global,
+            usb_host,
+            usb_pwrclk,
+            pins,
+            channels: 0,
+            cycles_per_ms: clocks.sysclk().raw() / 1000,
+            speed: None,
+        };
+        host.init();
+        host
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe {
+            ptr::read_volatile((pac::OTG_FS_GLOBAL::ptr() as *const u8).add(offset) as *const u32)
+        }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe {
+            ptr::write_volatile(
+                (pac::OTG_FS_GLOBAL::ptr() as *mut u8).add(offset) as *mut u32,
+                value,
+            )
+        }
+    }
+
+    fn modify(&self, offset: usize, f: impl FnOnce(u32) -> u32) {
+        self.write(offset, f(self.read(offset)));
+    }
+
+    fn delay_ms(&self, ms: u32) {
+        cortex_m::asm::delay(self.cycles_per_ms * ms);
+    }
+
+    fn init(&mut self) {
+        // Embedded full-speed PHY, forced host mode
+        self.modify(GUSBCFG, |r| (r & !(1 << 30)) | (1 << 29) | (1 << 6));
+        self.delay_ms(50);
+
+        // Core soft reset
+        while self.read(GRSTCTL) & (1 << 31) == 0 {}
+        self.modify(GRSTCTL, |r| r | 1);
+        while self.read(GRSTCTL) & 1 != 0 {}
+
+        // Transceiver on, VBUS is not sensed in host mode
+        self.modify(GCCFG, |r| (r | (1 << 16)) & !(1 << 21));
+        self.write(PCGCCTL, 0);
+        self.delay_ms(50);
+
+        // 48 MHz PHY clock
+        self.modify(HCFG, |r| (r & !0b111) | 0b01);
+
+        // 320 words of FIFO: 128 for reception, 96 for each transmit FIFO
+        self.write(GRXFSIZ, 128);
+        self.write(HNPTXFSIZ, (96 << 16) | 128);
+        self.write(HPTXFSIZ, (96 << 16) | 224);
+
+        // Flush all the transmit FIFOs, then the receive FIFO
+        self.write(GRSTCTL, (0x10 << 6) | (1 << 5));
+        while self.read(GRSTCTL) & (1 << 5) != 0 {}
+        self.write(GRSTCTL, 1 << 4);
+        while self.read(GRSTCTL) & (1 << 4) != 0 {}
+
+        for channel in 0..CHANNELS {
+            self.write(hcint(channel), 0x7ff);
+        }
+        self.write(GINTSTS, 0xffff_ffff);
+        // Polled: the global interrupt stays masked
+        self.write(GAHBCFG, 0);
+
+        // Port power
+        self.modify(HPRT, |r| (r & !HPRT_W1C) | (1 << 12));
+    }
+
+    /// Returns `true` if a device is connected to the port
+    pub fn is_connected(&self) -> bool {
+        self.read(HPRT) & 1 != 0
+    }
+
+    /// Returns the speed of the device, once the port is reset
+    pub fn speed(&self) -> Option<Speed> {
+        self.speed
+    }
+
+    /// Resets the port, which must be done once a device is connected, before
+    /// enumerating it. Returns the speed of the device.
+    pub fn reset_port(&mut self) -> Result<Speed, Error> {
+        if !self.is_connected() {
+            return Err(Error::NotConnected);
+        }
+
+        // The device must be given time to settle after connection
+        self.delay_ms(100);
+        self.modify(HPRT, |r| (r & !HPRT_W1C) | (1 << 8));
+        self.delay_ms(15);
+        self.modify(HPRT, |r| r & !HPRT_W1C & !(1 << 8));
+        self.delay_ms(20);
+
+        let hprt = self.read(HPRT);
+        if hprt & (1 << 2) == 0 {
+            return Err(Error::NotConnected);
+        }
+        // Clear the change flags
+        self.write(HPRT, hprt & !(1 << 2));
+
+        let speed = match (hprt >> 17) & 0b11 {
+            0b10 => Speed::Low,
+            _ => Speed::Full,
+        };
+        // 6 MHz PHY clock and frame interval for low speed devices
+        let (fslspcs, hfir) = match speed {
+            Speed::Low => (0b10, 6000),
+            Speed::Full => (0b01, 48000),
+        };
+        if self.read(HCFG) & 0b11 != fslspcs {
+            self.modify(HCFG, |r| (r & !0b11) | fslspcs);
+            self.write(HFIR, hfir);
+        }
+
+        self.speed = Some(speed);
+        Ok(speed)
+    }
+
+    /// Allocates a channel for `endpoint` (its address, with bit 7 set for an
+    /// IN endpoint) of the device at `device`. Control pipes are used in both
+    /// directions.
+    pub fn alloc_pipe(
+        &mut self,
+        device: u8,
+        endpoint: u8,
+        transfer_type: TransferType,
+        max_packet_size: u16,
+    ) -> Result<Pipe, Error> {
+        let channel = (0..CHANNELS)
+            .find(|channel| self.channels & (1 << channel) == 0)
+            .ok_or(Error::NoChannel)?;
+        self.channels |= 1 << channel;
+
+        Ok(Pipe {
+            channel,
+            device,
+            endpoint,
+            transfer_type,
+            max_packet_size,
+            toggle: false,
+        })
+    }
+
+    /// Releases the channel of `pipe`
+    pub fn free_pipe(&mut self, pipe: Pipe) {
+        self.channels &= !(1 << pipe.channel);
+    }
+
+    /// Programs the channel of `pipe` for one packet and enables it
+    fn start_packet(&self, pipe: &Pipe, is_in: bool, pid: Pid, len: usize) {
+        let ch = pipe.channel;
+        self.write(hcint(ch), 0x7ff);
+        self.write(
+            hctsiz(ch),
+            (pid as u32) << 29 | 1 << 19 | (len as u32 & 0x7_ffff),
+        );
+        let low_speed = self.speed == Some(Speed::Low);
+        // Interrupt transfers happen in the next frame
+        let odd_frame = pipe.transfer_type == TransferType::Interrupt && self.read(HFNUM) & 1 == 0;
+        self.write(
+            hcchar(ch),
+            1 << 31
+                | (odd_frame as u32) << 29
+                | (pipe.device as u32 & 0x7f) << 22
+                | (pipe.transfer_type as u32) << 18
+                | (low_speed as u32) << 17
+                | (is_in as u32) << 15
+                | (pipe.endpoint as u32 & 0xf) << 11
+                | (pipe.max_packet_size as u32 & 0x7ff),
+        );
+    }
+
+    /// Polls `f` until it returns a result, or fails if the port is disabled
+    /// or after `TIMEOUT_FRAMES` frames
+    fn poll<T>(&self, mut f: impl FnMut() -> Option<Result<T, Error>>) -> Result<T, Error> {
+        let start = self.read(HFNUM) & 0xffff;
+        loop {
+            if let Some(result) = f() {
+                return result;
+            }
+            self.check_port()?;
+            // The frame number is 16 bits wide
+            if (self.read(HFNUM).wrapping_sub(start) & 0xffff) >= TIMEOUT_FRAMES {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    /// Polls `f` on `channel`, which is halted if it times out
+    fn poll_channel<T>(
+        &self,
+        channel: u8,
+        f: impl FnMut() -> Option<Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let result = self.poll(f);
+        if let Err(Error::Timeout) = result {
+            self.halt(channel)?;
+        }
+        result
+    }
+
+    /// Disables the channel after an error and waits for it to halt
+    fn halt(&self, channel: u8) -> Result<(), Error> {
+        self.modify(hcchar(channel), |r| r | (1 << 31) | (1 << 30));
+        let result = self.poll(|| {
+            if self.read(hcint(channel)) & HCINT_CHH != 0 {
+                return Some(Ok(()));
+            }
+            // The halt of an IN channel is only reported once its status
+            // entry is popped from the receive FIFO, data is discarded
+            if self.read(GINTSTS) & (1 << 4) != 0 {
+                let status = self.read(GRXSTSP);
+                let count = ((status >> 4) & 0x7ff) as usize;
+                for _ in 0..(count + 3) / 4 {
+                    self.read(fifo(channel));
+                }
+            }
+            None
+        });
+        self.write(hcint(channel), 0x7ff);
+        result
+    }
+
+    fn check_errors(&self, channel: u8, flags: u32) -> Result<(), Error> {
+        let error = if flags & HCINT_STALL != 0 {
+            Error::Stall
+        } else if flags & HCINT_NAK != 0 {
+            Error::Nak
+        } else if flags & HCINT_BBERR != 0 {
+            Error::Babble
+        } else if flags & HCINT_DTERR != 0 {
+            Error::DataToggle
+        } else if flags & (HCINT_TXERR | HCINT_FRMOR) != 0 {
+            Error::Transaction
+        } else if flags & HCINT_CHH != 0 {
+            // Halted without a completion nor an error
+            self.write(hcint(channel), 0x7ff);
+            return Err(Error::Transaction);
+        } else {
+            return Ok(());
+        };
+        self.halt(channel).and(Err(error))
+    }
+
+    fn packet_out(&self, pipe: &Pipe, pid: Pid, data: &[u8]) -> Result<(), Error> {
+        let ch = pipe.channel;
+        let words = (data.len() + 3) / 4;
+
+        // Wait for room in the transmit FIFO
+        let periodic = pipe.transfer_type == TransferType::Interrupt;
+        let status = if periodic { HPTXSTS } else { HNPTXSTS };
+        self.poll(|| ((self.read(status) & 0xffff) >= words as u32).then(|| Ok(())))?;
+
+        self.start_packet(pipe, false, pid, data.len());
+        for chunk in data.chunks(4) {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.write(fifo(ch), u32::from_le_bytes(word));
+        }
+
+        self.poll_channel(ch, || {
+            let flags = self.read(hcint(ch));
+            if flags & HCINT_XFRC != 0 {
+                self.write(hcint(ch), 0x7ff);
+                return Some(Ok(()));
+            }
+            self.check_errors(ch, flags).err().map(Err)
+        })
+    }
+
+    fn packet_in(&self, pipe: &Pipe, pid: Pid, buffer: &mut [u8]) -> Result<usize, Error> {
+        let ch = pipe.channel;
+        let mut received = 0;
+
+        self.start_packet(pipe, true, pid, pipe.max_packet_size as usize);
+        self.poll_channel(ch, || {
+            if self.read(GINTSTS) & (1 << 4) != 0 {
+                let status = self.read(GRXSTSP);
+                let count = ((status >> 4) & 0x7ff) as usize;
+                // IN data packet received, the transfers are not interleaved
+                // so it is for this channel
+                if (status >> 17) & 0xf == 0b0010 {
+                    for i in (0..count).step_by(4) {
+                        let word = self.read(fifo(ch)).to_le_bytes();
+                        let len = (count - i).min(4);
+                        for (j, byte) in word[..len].iter().enumerate() {
+                            if let Some(b) = buffer.get_mut(received + i + j) {
+                                *b = *byte;
+                            }
+                        }
+                    }
+                    received += count;
+                }
+            }
+
+            let flags = self.read(hcint(ch));
+            if flags & HCINT_XFRC != 0 {
+                self.write(hcint(ch), 0x7ff);
+                if received > buffer.len() {
+                    return Some(Err(Error::Babble));
+                }
+                return Some(Ok(received));
+            }
+            self.check_errors(ch, flags).err().map(Err)
+        })
+    }
+
+    /// Sends a packet, retrying on NAK for control pipes
+    fn retry<T>(&self, pipe: &Pipe, mut f: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+        let retries = match pipe.transfer_type {
+            TransferType::Control => CONTROL_NAK_RETRIES,
+            _ => 0,
+        };
+        let mut i = 0;
+        loop {
+            match f() {
+                Err(Error::Nak) if i < retries => i += 1,
+                result => return result,
+            }
+        }
+    }
+
+    fn check_port(&self) -> Result<(), Error> {
+        if self.read(HPRT) & (1 << 2) == 0 {
+            return Err(Error::NotConnected);
+        }
+        Ok(())
+    }
+
+    /// Reads from an IN endpoint until a short packet or until `buffer` is
+    /// full. Returns the number of bytes read.
+    ///
+    /// A NAK from a bulk or interrupt endpoint is returned as `Error::Nak`.
+    pub fn read_in(&mut self, pipe: &mut Pipe, buffer: &mut [u8]) -> Result<usize, Error> {
+        self.check_port()?;
+        let mps = pipe.max_packet_size as usize;
+        let mut received = 0;
+        loop {
+            let pid = if pipe.toggle { Pid::Data1 } else { Pid::Data0 };
+            let end = (received + mps).min(buffer.len());
+            let count = self.retry(pipe, || {
+                self.packet_in(pipe, pid, &mut buffer[received..end])
+            })?;
+            pipe.toggle = !pipe.toggle;
+            received += count;
+            if count < mps || received == buffer.len() {
+                return Ok(received);
+            }
+        }
+    }
+
+    /// Writes `data` to an OUT endpoint, in packets of the maximum packet size
+    pub fn write_out(&mut self, pipe: &mut Pipe, data: &[u8]) -> Result<(), Error> {
+        self.check_port()?;
+        let mps = pipe.max_packet_size as usize;
+        let mut chunks = data.chunks(mps);
+        let empty: &[u8] = &[];
+        // A zero-length packet is sent for empty data
+        let mut next = chunks.next().or(Some(empty));
+        while let Some(chunk) = next {
+            let pid = if pipe.toggle { Pid::Data1 } else { Pid::Data0 };
+            self.retry(pipe, || self.packet_out(pipe, pid, chunk))?;
+            pipe.toggle = !pipe.toggle;
+            next = chunks.next();
+        }
+        Ok(())
+    }
+
+    /// Runs a control transfer with an IN data stage, or no data stage if
+    /// `data` is empty. Returns the number of bytes read.
+    pub fn control_in(
+        &mut self,
+        pipe: &mut Pipe,
+        setup: &[u8; 8],
+        data: &mut [u8],
+    ) -> Result<usize, Error> {
+        self.check_port()?;
+        self.retry(pipe, || self.packet_out(pipe, Pid::Setup, setup))?;
+
+        pipe.toggle = true;
+        let count = if data.is_empty() {
+            0
+        } else {
+            self.read_in(pipe, data)?
+        };
+
+        // Status stage, a zero-length DATA1 OUT packet
+        if data.is_empty() {
+            self.retry(pipe, || self.packet_in(pipe, Pid::Data1, &mut []))?;
+        } else {
+            self.retry(pipe, || self.packet_out(pipe, Pid::Data1, &[]))?;
+        }
+        Ok(count)
+    }
+
+    /// Runs a control transfer with an OUT data stage
+    pub fn control_out(
+        &mut self,
+        pipe: &mut Pipe,
+        setup: &[u8; 8],
+        data: &[u8],
+    ) -> Result<(), Error> {
+        self.check_port()?;
+        self.retry(pipe, || self.packet_out(pipe, Pid::Setup, setup))?;
+
+        pipe.toggle = true;
+        if !data.is_empty() {
+            self.write_out(pipe, data)?;
+        }
+
+        // Status stage, a zero-length DATA1 IN packet
+        self.retry(pipe, || self.packet_in(pipe, Pid::Data1, &mut []))?;
+        Ok(())
+    }
+
+    /// Powers the port off and releases the peripherals and the pins
+    pub fn free(
+        self,
+    ) -> (
+        pac::OTG_FS_GLOBAL,
+        pac::OTG_FS_HOST,
+        pac::OTG_FS_PWRCLK,
+        (PA11<Alternate<10>>, PA12<Alternate<10>>),
+    ) {
+        self.modify(HPRT, |r| r & !HPRT_W1C & !(1 << 12));
+        (self.usb_global, self.usb_host, self.usb_pwrclk, self.pins)
+    }
+}
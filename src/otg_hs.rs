@@ -2,6 +2,18 @@
 //!
 //! Requires the `usb_hs` feature.
 //! Only one of the `usb_fs`/`usb_hs` features can be selected at the same time.
+//!
+//! VBUS is sensed on PB13 by default. Boards that don't wire VBUS to PB13 can
+//! disable sensing with [`set_vbus_sensing`] once the bus is enabled. The ID
+//! pin (PB12, AF12) is read with [`connector_id`], and the start of frame
+//! pulse is output on PA4 (AF12) once that pin is in alternate mode.
+//!
+//! On the STM32F723, F730 and F733, the peripheral can run at high speed with
+//! the on-chip UTMI PHY, enabled with the `usb_hs_phy` feature which these
+//! devices select. Construct the wrapper with `USB::new_with_internal_hs_phy`,
+//! the PHY PLL is then clocked from the HSE, which must run at 12, 12.5, 16,
+//! 24 or 25 MHz. Without the internal PHY, the embedded full-speed PHY is
+//! used on the same pins.
 
 use crate::pac;
 
@@ -10,6 +22,7 @@ use crate::gpio::{
     Alternate,
 };
 use crate::rcc::{BusClock, Clocks, Enable, Reset};
+use embedded_hal::blocking::delay::DelayMs;
 use fugit::{HertzU32 as Hertz, RateExtU32};
 
 #[cfg(feature = "usb_hs_phy")]
@@ -58,6 +71,9 @@ impl USB {
     #[cfg(feature = "usb_hs_phy")]
     /// Construct a USB peripheral wrapper with internal HighSpeed PHY.
     ///
+    /// Panics if the HSE is not enabled, or if its frequency is not supported
+    /// by the PHY PLL.
+    ///
     /// Call `UsbBus::new` to construct and initialize the USB peripheral driver.
     pub fn new_with_internal_hs_phy(
         usb_global: pac::OTG_HS_GLOBAL,
@@ -67,6 +83,12 @@ impl USB {
         pins: (PB14<Alternate<12>>, PB15<Alternate<12>>),
         clocks: &Clocks,
     ) -> Self {
+        let hse = clocks.hse().expect("HSE should be enabled");
+        assert!(
+            pll1sel(hse).is_some(),
+            "HSE frequency is invalid for USBPHYC"
+        );
+
         Self {
             usb_global,
             usb_device,
@@ -75,11 +97,24 @@ impl USB {
             pin_dm: pins.0,
             pin_dp: pins.1,
             hclk: pac::OTG_HS_GLOBAL::clock(clocks),
-            hse: clocks.hse().expect("HSE should be enabled"),
+            hse,
         }
     }
 }
 
+/// Returns the USBPHYC PLL1SEL value for the `hse` frequency
+#[cfg(feature = "usb_hs_phy")]
+fn pll1sel(hse: Hertz) -> Option<u8> {
+    match hse.raw() {
+        12_000_000 => Some(0b000),
+        12_500_000 => Some(0b001),
+        16_000_000 => Some(0b011),
+        24_000_000 => Some(0b100),
+        25_000_000 => Some(0b101),
+        _ => None,
+    }
+}
+
 unsafe impl Sync for USB {}
 
 unsafe impl UsbPeripheral for USB {
@@ -135,14 +170,7 @@ unsafe impl UsbPeripheral for USB {
         };
 
         // Calculate PLL1SEL
-        let pll1sel = match self.hse.raw() {
-            12_000_000 => 0b000,
-            12_500_000 => 0b001,
-            16_000_000 => 0b011,
-            24_000_000 => 0b100,
-            25_000_000 => 0b101,
-            _ => panic!("HSE frequency is invalid for USBPHYC"),
-        };
+        let pll1sel = pll1sel(self.hse).expect("HSE frequency is invalid for USBPHYC");
 
         // Turn on LDO
         // For some reason setting the bit enables the LDO
@@ -160,9 +188,115 @@ unsafe impl UsbPeripheral for USB {
 
         phy.pll1.modify(|_, w| w.pll1en().set_bit());
 
-        // 2ms Delay required to get internal phy clock stable
+        // 2ms Delay required to get internal phy clock stable, counted at the
+        // maximum 216 MHz core clock
         cortex_m::asm::delay(432000);
     }
 }
 
+/// Role selected by the ID pin of a micro-AB connector
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectorId {
+    /// ID pin grounded, the peripheral should act as host
+    A,
+    /// ID pin floating, the peripheral should act as device
+    B,
+}
+
+/// Enables or disables VBUS sensing.
+///
+/// A self-powered device must sense VBUS to disconnect when the host is
+/// unplugged. A bus-powered device, or a board without VBUS on PB13, can
+/// disable sensing: the B-session valid signal is then forced, so the device
+/// connects as soon as the bus is enabled.
+///
+/// The core is reset when the bus is enabled, so call this after
+/// `UsbDeviceBuilder::build`.
+pub fn set_vbus_sensing(enabled: bool) {
+    // NOTE(unsafe) only the VBUS detection bits are touched, atomically
+    cortex_m::interrupt::free(|_| {
+        let otg = unsafe { &*pac::OTG_HS_GLOBAL::ptr() };
+        // BVALOEN and BVALOVAL
+        let overrides = (1 << 6) | (1 << 7);
+        let gotgctl = &otg.otg_hs_gotgctl;
+        if enabled {
+            gotgctl.modify(|r, w| unsafe { w.bits(r.bits() & !overrides) });
+            otg.otg_hs_gccfg.modify(|_, w| w.vbden().set_bit());
+        } else {
+            otg.otg_hs_gccfg.modify(|_, w| w.vbden().clear_bit());
+            gotgctl.modify(|r, w| unsafe { w.bits(r.bits() | overrides) });
+        }
+    });
+}
+
+/// Returns `true` if VBUS is valid for a B-device, or if the B-session valid
+/// signal is forced
+pub fn is_vbus_valid() -> bool {
+    let otg = unsafe { &*pac::OTG_HS_GLOBAL::ptr() };
+    otg.otg_hs_gotgctl.read().bsvld().bit_is_set()
+}
+
+/// Returns the role selected by the ID pin, which must be in alternate mode
+pub fn connector_id() -> ConnectorId {
+    let otg = unsafe { &*pac::OTG_HS_GLOBAL::ptr() };
+    if otg.otg_hs_gotgctl.read().cidsts().bit_is_set() {
+        ConnectorId::B
+    } else {
+        ConnectorId::A
+    }
+}
+
+/// Connects the start of frame pulse to the internal trigger 1 of TIM2, to
+/// measure the frame period against a timer for clock trimming
+pub fn connect_sof_to_tim2() {
+    // NOTE(unsafe) ITR1_RMP is only used for this remap
+    cortex_m::interrupt::free(|_| {
+        let tim2 = unsafe { &*pac::TIM2::ptr() };
+        tim2.or.modify(|_, w| unsafe { w.itr1_rmp().bits(0b11) });
+    });
+}
+
+/// Returns `true` if the bus is suspended by the host.
+///
+/// `UsbDevice::poll` also reports the suspend with `UsbDeviceState::Suspend`.
+pub fn is_suspended() -> bool {
+    let device = unsafe { &*pac::OTG_HS_DEVICE::ptr() };
+    device.otg_hs_dsts.read().suspsts().bit_is_set()
+}
+
+/// Stops the PHY clock and gates the AHB clock of the core, to reduce the
+/// current drawn while the bus is suspended.
+///
+/// Only the resume and reset detection keep running, the USB interrupt still
+/// fires on resume. Call [`exit_low_power`] from there, before polling the
+/// device again.
+pub fn enter_low_power() {
+    let pwrclk = unsafe { &*pac::OTG_HS_PWRCLK::ptr() };
+    pwrclk
+        .otg_hs_pcgcr
+        .modify(|_, w| w.stppclk().set_bit().gatehclk().set_bit());
+}
+
+/// Restarts the clocks stopped by [`enter_low_power`]
+pub fn exit_low_power() {
+    let pwrclk = unsafe { &*pac::OTG_HS_PWRCLK::ptr() };
+    pwrclk
+        .otg_hs_pcgcr
+        .modify(|_, w| w.stppclk().clear_bit().gatehclk().clear_bit());
+}
+
+/// Signals a remote wakeup to the host, restarting the clocks first.
+///
+/// The host must have enabled the feature, which `UsbDevice::remote_wakeup_enabled`
+/// reports, and the bus must be suspended. The resume signalling lasts 10 ms.
+pub fn remote_wakeup<D: DelayMs<u32>>(delay: &mut D) {
+    exit_low_power();
+
+    // RWUSIG is not used by the bus driver
+    let device = unsafe { &*pac::OTG_HS_DEVICE::ptr() };
+    device.otg_hs_dctl.modify(|_, w| w.rwusig().set_bit());
+    delay.delay_ms(10);
+    device.otg_hs_dctl.modify(|_, w| w.rwusig().clear_bit());
+}
+
 pub type UsbBusType = UsbBus<USB>;
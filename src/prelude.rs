@@ -1,5 +1,13 @@
 pub use fugit::{ExtU32 as _, RateExtU32 as _};
 
+#[cfg(feature = "has-can")]
+pub use crate::can::BusOffRecoveryExt as _;
+#[cfg(feature = "has-can")]
+pub use crate::can::ErrorStatusExt as _;
+#[cfg(feature = "has-can")]
+pub use crate::can::FilterFifo as _;
+#[cfg(feature = "has-can")]
+pub use crate::can::ModeExt as _;
 #[cfg(feature = "fmc")]
 pub use crate::fmc::FmcExt as _stm327xx_hal_fmc_FmcExt;
 
@@ -0,0 +1,417 @@
+//! Power controller (PWR)
+//!
+//! The low-power modes are entered with `WFI`:
+//! - Sleep: the core is stopped, the peripherals keep running, any interrupt
+//!   wakes it up.
+//! - Stop: all the clocks of the 1.2 V domain are stopped, the SRAM and
+//!   registers are kept. An EXTI line wakes it up, and the clocks are restored
+//!   before [`Pwr::stop`] returns.
+//! - Standby: the 1.2 V domain is powered off. Only the backup domain is
+//!   kept, and the device is reset when a wakeup pin, the RTC or the NRST pin
+//!   wakes it up.
+//!
+//! The programmable voltage detector (PVD) compares VDD to a threshold, and
+//! triggers the `PVD` interrupt through the EXTI line 16, see
+//! [`Pwr::enable_pvd`].
+//!
+//! The 4 KB backup SRAM, in the backup domain, keeps its content in Standby,
+//! and on VBAT if the backup regulator is enabled, see [`Pwr::backup_sram`].
+//!
+//! See section 4 of RM0410.
+
+use crate::pac::{EXTI, PWR, RCC};
+use crate::rcc::{AHB1, APB1};
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::peripheral::SCB;
+
+/// Base address of the backup SRAM
+const BKPSRAM_BASE: usize = 0x4002_4000;
+/// Size of the backup SRAM in bytes
+pub const BKPSRAM_SIZE: usize = 4 * 1024;
+
+/// The backup SRAM has been handed out
+static BKPSRAM_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// EXTI line of the PVD output
+const PVD_EXTI_LINE: u8 = 16;
+
+/// Voltage regulator mode in Stop mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Regulator {
+    /// Shortest wakeup time
+    Main,
+    /// Lowest consumption
+    LowPower,
+}
+
+/// Stop mode configuration
+///
+/// Each option lowers the consumption and increases the wakeup time, the
+/// under-drive the most, see the "Low-power mode wakeup timings" table of the
+/// datasheet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StopConfig {
+    pub regulator: Regulator,
+    /// Power down the flash memory
+    pub flash_power_down: bool,
+    /// Lower the voltage of the regulator, the 1.2 V domain being only kept
+    /// in retention
+    pub under_drive: bool,
+}
+
+impl Default for StopConfig {
+    /// Lowest consumption: low-power regulator in under-drive mode and flash
+    /// memory powered down
+    fn default() -> Self {
+        StopConfig {
+            regulator: Regulator::LowPower,
+            flash_power_down: true,
+            under_drive: true,
+        }
+    }
+}
+
+/// PVD threshold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PvdLevel {
+    V2_0 = 0b000,
+    V2_1 = 0b001,
+    V2_3 = 0b010,
+    V2_5 = 0b011,
+    V2_6 = 0b100,
+    V2_7 = 0b101,
+    V2_8 = 0b110,
+    V2_9 = 0b111,
+}
+
+/// VDD crossing of the PVD threshold which triggers the interrupt
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PvdTrigger {
+    /// VDD drops below the threshold
+    Below,
+    /// VDD rises above the threshold
+    Above,
+    Both,
+}
+
+/// Power controller
+pub struct Pwr {
+    pwr: PWR,
+}
+
+impl Pwr {
+    /// Enables the clock of the power controller.
+    pub fn new(pwr: PWR, apb1: &mut APB1) -> Self {
+        apb1.enr().modify(|_, w| w.pwren().set_bit());
+
+        Self { pwr }
+    }
+
+    /// Enters the Sleep mode, until an interrupt occurs.
+    pub fn sleep(&mut self, scb: &mut SCB) {
+        scb.clear_sleepdeep();
+        cortex_m::asm::wfi();
+    }
+
+    /// Enters the Sleep mode, or the Stop mode if the SLEEPDEEP bit is set,
+    /// when returning from the last interrupt handler, if `enable`.
+    ///
+    /// Once enabled, an application only driven by interrupts can simply
+    /// call [`Pwr::sleep`] once.
+    pub fn set_sleep_on_exit(&mut self, scb: &mut SCB, enable: bool) {
+        if enable {
+            scb.set_sleeponexit();
+        } else {
+            scb.clear_sleeponexit();
+        }
+    }
+
+    /// Enters the Stop mode, until an interrupt on an EXTI line occurs.
+    ///
+    /// After wakeup, the HSE, the PLLs, the over-drive and the SYSCLK source
+    /// are restored as they were before entering the Stop mode. The interrupt
+    /// handlers run before, with the 16 MHz HSI clock, unless the interrupts
+    /// are disabled around this call.
+    pub fn stop(&mut self, scb: &mut SCB, config: StopConfig) {
+        let clocks = ClockState::save();
+
+        let low_power = config.regulator == Regulator::LowPower;
+        if config.under_drive {
+            // The flag is set by the previous under-drive wakeup
+            self.pwr.csr1.modify(|_, w| unsafe { w.udrdy().bits(0b11) });
+        }
+        self.pwr.cr1.modify(|_, w| unsafe {
+            w.pdds()
+                .stop_mode()
+                .lpds()
+                .bit(low_power)
+                .fpds()
+                .bit(config.flash_power_down)
+                .uden()
+                .bits(if config.under_drive { 0b11 } else { 0b00 })
+                .lpuds()
+                .bit(config.under_drive && low_power)
+                .mruds()
+                .bit(config.under_drive && !low_power)
+        });
+
+        scb.set_sleepdeep();
+        cortex_m::asm::dsb();
+        cortex_m::asm::wfi();
+        scb.clear_sleepdeep();
+
+        clocks.restore(&self.pwr);
+    }
+
+    /// Enters the Standby mode. The device is reset on wakeup.
+    pub fn standby(&mut self, scb: &mut SCB) -> ! {
+        // A pending wakeup flag would wake the device up immediately
+        self.pwr
+            .cr2
+            .modify(|r, w| unsafe { w.bits(r.bits() | 0b11_1111) });
+        self.pwr
+            .cr1
+            .modify(|_, w| w.csbf().set_bit().pdds().standby_mode());
+
+        scb.set_sleepdeep();
+        cortex_m::asm::dsb();
+        loop {
+            cortex_m::asm::wfi();
+        }
+    }
+
+    /// Returns `true` if the device was reset by a wakeup from the Standby
+    /// mode.
+    pub fn is_standby_wakeup(&self) -> bool {
+        self.pwr.csr1.read().sbf().bit_is_set()
+    }
+
+    /// Clears the Standby mode flag.
+    pub fn clear_standby_flag(&mut self) {
+        self.pwr.cr1.modify(|_, w| w.csbf().set_bit());
+    }
+
+    /// Enables the PVD with the threshold `level`.
+    pub fn enable_pvd(&mut self, level: PvdLevel) {
+        self.pwr
+            .cr1
+            .modify(|_, w| unsafe { w.pls().bits(level as u8).pvde().set_bit() });
+    }
+
+    /// Disables the PVD.
+    pub fn disable_pvd(&mut self) {
+        self.pwr.cr1.modify(|_, w| w.pvde().clear_bit());
+    }
+
+    /// Returns `true` if VDD is below the PVD threshold.
+    pub fn is_supply_low(&self) -> bool {
+        self.pwr.csr1.read().pvdo().bit_is_set()
+    }
+
+    /// Starts listening for the PVD interrupt, on the EXTI line 16.
+    pub fn listen_pvd(&mut self, exti: &mut EXTI, trigger: PvdTrigger) {
+        let bit = 1 << PVD_EXTI_LINE;
+        // The PVD output is high when VDD is below the threshold
+        let (rising, falling) = match trigger {
+            PvdTrigger::Below => (true, false),
+            PvdTrigger::Above => (false, true),
+            PvdTrigger::Both => (true, true),
+        };
+
+        exti.rtsr.modify(|r, w| unsafe {
+            w.bits(if rising {
+                r.bits() | bit
+            } else {
+                r.bits() & !bit
+            })
+        });
+        exti.ftsr.modify(|r, w| unsafe {
+            w.bits(if falling {
+                r.bits() | bit
+            } else {
+                r.bits() & !bit
+            })
+        });
+        exti.imr.modify(|r, w| unsafe { w.bits(r.bits() | bit) });
+    }
+
+    /// Stops listening for the PVD interrupt.
+    pub fn unlisten_pvd(&mut self, exti: &mut EXTI) {
+        exti.imr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << PVD_EXTI_LINE)) });
+    }
+
+    /// Returns `true` if the PVD interrupt is pending.
+    pub fn is_pvd_pending(&self) -> bool {
+        // NOTE(unsafe) atomic read of the pending register
+        let exti = unsafe { &(*EXTI::ptr()) };
+        exti.pr.read().bits() & (1 << PVD_EXTI_LINE) != 0
+    }
+
+    /// Clears the pending bit of the EXTI line 16.
+    pub fn clear_pvd_interrupt(&mut self) {
+        // NOTE(unsafe) atomic write to a write-1-to-clear register
+        let exti = unsafe { &(*EXTI::ptr()) };
+        exti.pr.write(|w| unsafe { w.bits(1 << PVD_EXTI_LINE) });
+    }
+
+    /// Enables the write access to the backup domain: the RTC, the backup
+    /// registers and the backup SRAM.
+    pub fn enable_backup_domain(&mut self) {
+        self.pwr.cr1.modify(|_, w| w.dbp().set_bit());
+        while self.pwr.cr1.read().dbp().bit_is_clear() {}
+    }
+
+    /// Enables or disables the backup regulator, which keeps the backup SRAM
+    /// content on VBAT. Requires the write access to the backup domain.
+    pub fn set_backup_regulator(&mut self, enable: bool) {
+        self.pwr.csr1.modify(|_, w| w.bre().bit(enable));
+        if enable {
+            while self.pwr.csr1.read().brr().bit_is_clear() {}
+        }
+    }
+
+    /// Enables the clock of the backup SRAM and the write access to the
+    /// backup domain, and the backup regulator if `retention`.
+    ///
+    /// Returns `None` if the backup SRAM has already been taken.
+    pub fn backup_sram(&mut self, ahb1: &mut AHB1, retention: bool) -> Option<BackupSram> {
+        if BKPSRAM_TAKEN.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+
+        ahb1.enr().modify(|_, w| w.bkpsramen().set_bit());
+        self.enable_backup_domain();
+        self.set_backup_regulator(retention);
+
+        Some(BackupSram { _0: () })
+    }
+
+    /// Releases the PWR peripheral.
+    pub fn free(self) -> PWR {
+        self.pwr
+    }
+}
+
+/// Clocks stopped by the Stop mode
+struct ClockState {
+    hse: bool,
+    pll: bool,
+    pllsai: bool,
+    plli2s: bool,
+    overdrive: bool,
+    sysclk: SysclkSource,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SysclkSource {
+    Hsi,
+    Hse,
+    Pll,
+}
+
+impl ClockState {
+    fn save() -> Self {
+        let rcc = unsafe { &(*RCC::ptr()) };
+        let pwr = unsafe { &(*PWR::ptr()) };
+        let cr = rcc.cr.read();
+        let sws = rcc.cfgr.read().sws();
+
+        Self {
+            hse: cr.hseon().bit_is_set(),
+            pll: cr.pllon().bit_is_set(),
+            pllsai: cr.pllsaion().bit_is_set(),
+            plli2s: cr.plli2son().bit_is_set(),
+            overdrive: pwr.cr1.read().odswen().bit_is_set(),
+            sysclk: if sws.is_pll() {
+                SysclkSource::Pll
+            } else if sws.is_hse() {
+                SysclkSource::Hse
+            } else {
+                SysclkSource::Hsi
+            },
+        }
+    }
+
+    /// Follows the enable sequence of `CFGR::freeze`.
+    fn restore(&self, pwr: &PWR) {
+        let rcc = unsafe { &(*RCC::ptr()) };
+
+        if self.hse {
+            rcc.cr.modify(|_, w| w.hseon().on());
+            while rcc.cr.read().hserdy().is_not_ready() {}
+        }
+
+        if self.pll {
+            rcc.cr.modify(|_, w| w.pllon().on());
+            while rcc.cr.read().pllrdy().is_not_ready() {}
+        }
+
+        if self.overdrive {
+            pwr.cr1.modify(|_, w| w.oden().set_bit());
+            while !pwr.csr1.read().odrdy().bit_is_set() {}
+            pwr.cr1.modify(|_, w| w.odswen().set_bit());
+            while !pwr.csr1.read().odswrdy().bit_is_set() {}
+        }
+
+        if self.pllsai {
+            rcc.cr.modify(|_, w| w.pllsaion().on());
+            while rcc.cr.read().pllsairdy().is_not_ready() {}
+        }
+
+        if self.plli2s {
+            rcc.cr.modify(|_, w| w.plli2son().on());
+            while rcc.cr.read().plli2srdy().is_not_ready() {}
+        }
+
+        match self.sysclk {
+            SysclkSource::Pll => {
+                rcc.cfgr.modify(|_, w| w.sw().pll());
+                while !rcc.cfgr.read().sws().is_pll() {}
+            }
+            SysclkSource::Hse => {
+                rcc.cfgr.modify(|_, w| w.sw().hse());
+                while !rcc.cfgr.read().sws().is_hse() {}
+            }
+            SysclkSource::Hsi => {}
+        }
+    }
+}
+
+/// Backup SRAM
+///
+/// The memory is mapped as device memory, where the unaligned accesses
+/// fault, so it is only accessed as volatile words.
+pub struct BackupSram {
+    _0: (),
+}
+
+impl BackupSram {
+    /// Number of words of the backup SRAM
+    pub const WORDS: usize = BKPSRAM_SIZE / 4;
+
+    /// Reads the word at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` isn't below [`BackupSram::WORDS`].
+    pub fn read(&self, index: usize) -> u32 {
+        assert!(index < Self::WORDS);
+        // NOTE(unsafe) aligned access in the backup SRAM, which is only
+        // handed out once
+        unsafe { core::ptr::read_volatile((BKPSRAM_BASE as *const u32).add(index)) }
+    }
+
+    /// Writes `value` to the word at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` isn't below [`BackupSram::WORDS`].
+    pub fn write(&mut self, index: usize, value: u32) {
+        assert!(index < Self::WORDS);
+        // NOTE(unsafe) aligned access in the backup SRAM, which is only
+        // handed out once
+        unsafe { core::ptr::write_volatile((BKPSRAM_BASE as *mut u32).add(index), value) }
+    }
+}
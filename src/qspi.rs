@@ -1,4 +1,25 @@
-//! QUADSPI driver for the STM32F7. Supports INDIRECT mode only, using DMA or polling I/O.
+//! QUADSPI driver for the STM32F7. Supports INDIRECT mode, using DMA or polling I/O,
+//! AUTO_POLLING mode, and MEMORY_MAPPED mode.
+//!
+//! In memory-mapped mode the flash is read by the CPU or the DMA at `0x9000_0000`, for code
+//! execution or asset storage. Any indirect transaction aborts the memory-mapped mode first, so
+//! the flash can be erased and programmed in between: the data cache, which covers the mapped
+//! region, must then be invalidated before the flash is read again. The Cortex-M7 may issue
+//! speculative reads to the region while it is not mapped, which stall the bus: the MPU should
+//! mark the part of the region beyond the flash size as not accessible.
+//!
+//! In dual-flash mode two identical flash memories, on the bank 1 and bank 2 pins, are accessed
+//! together, each on its own 4 data lines, doubling both the throughput and the capacity. The
+//! commands are sent to both memories at once, and the data is interleaved: the even bytes are
+//! stored in the flash 1 and the odd bytes in the flash 2, at half the address given to the
+//! driver. The data phase of a transaction, such as a status register read, thus returns the
+//! bytes of the two memories in turn, and the programmed pages and erased sectors are twice as
+//! large as those of one memory.
+//!
+//! [`QspiFlash`] implements the standard JEDEC commands of the W25Q, MX25 and N25Q NOR flash
+//! families on top of the driver: identification, 4-byte addressing, quad enable, erase, page
+//! program and status polling. With the `qspi-storage` feature, it implements the `NorFlash`
+//! traits of `embedded-storage`, to mount filesystems on the flash.
 
 use as_slice::AsSlice;
 use core::convert::TryInto;
@@ -11,16 +32,37 @@ use crate::pac::{QUADSPI, RCC};
 use crate::rcc::Enable;
 use crate::state;
 
+mod flash;
+
+pub use flash::{FlashFamily, JedecId, QspiFlash};
+
+/// Start address of the flash in memory-mapped mode.
+pub const MEMORY_MAPPED_ADDRESS: usize = 0x9000_0000;
+/// Size of the memory-mapped region, which bounds the flash mapped.
+pub const MEMORY_MAPPED_LEN: usize = 0x1000_0000;
+
+/// Largest data phase of a DMA transfer, limited by the 16-bit DMA counter and rounded down to a
+/// whole word. Longer reads are split with [`QspiTransaction::chunk`].
+pub const DMA_MAX_LEN: usize = 0xfffc;
+
 /// The QSPI driver interface.
 pub struct Qspi {
     /// QSPI peripheral registers.
     qspi: QUADSPI,
     /// Address size for all transactions.
     adsize: u8,
+    /// log2(flash size in bytes), of both memories in dual-flash mode.
+    size: u8,
+    /// Two flash memories are accessed in parallel.
+    dual_flash: bool,
 }
 
-/// QSPI transaction description. Note that "advanced" settings like DDRM, DHHC,
-/// SIOO, and the use of alternate bytes are not supported at the moment.
+/// QSPI transaction description. Note that "advanced" settings like DDRM, DHHC
+/// and SIOO are not supported at the moment.
+///
+/// The phases are sent in order: instruction, address, alternate bytes, dummy
+/// cycles and data. A phase is skipped when its width is `QspiWidth::NONE`, and the
+/// alternate bytes when `alternate_bytes` is `None`.
 #[derive(Clone)]
 pub struct QspiTransaction {
     pub iwidth: u8,
@@ -28,15 +70,63 @@ pub struct QspiTransaction {
     pub dwidth: u8,
     pub instruction: u8,
     pub address: Option<u32>,
+    pub alternate_bytes: Option<AlternateBytes>,
     pub dummy: u8,
     pub data_len: Option<usize>,
 }
 
+/// Alternate bytes phase, such as the mode bits of the continuous read commands
+#[derive(Clone, Copy, Debug)]
+pub struct AlternateBytes {
+    pub width: u8,
+    /// Number of bytes sent, from 1 to 4
+    pub size: u8,
+    /// Bytes sent, the last one being the least significant
+    pub value: u32,
+}
+
+impl QspiTransaction {
+    /// Returns the transaction for `len` bytes of the data phase, starting `offset` bytes after
+    /// the address of this one. Used to split the long transfers into DMA sized chunks.
+    pub fn chunk(&self, offset: usize, len: usize) -> QspiTransaction {
+        QspiTransaction {
+            address: self.address.map(|address| address + offset as u32),
+            data_len: Some(len),
+            ..self.clone()
+        }
+    }
+}
+
+/// Comparison of the status bytes with the expected value, in automatic polling mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PollingMatch {
+    /// All the unmasked bits must match.
+    And,
+    /// Any of the unmasked bits must match.
+    Or,
+}
+
+/// Automatic polling configuration.
+#[derive(Clone, Copy, Debug)]
+pub struct AutoPolling {
+    /// Bits of the status compared, the first byte received being the least significant.
+    pub mask: u32,
+    /// Expected value of the compared bits.
+    pub value: u32,
+    pub match_mode: PollingMatch,
+    /// Number of QSPI clock cycles between two reads of the status.
+    pub interval: u16,
+}
+
 /// QSPI errors.
 #[derive(Debug)]
 pub enum Error {
     /// Bad input parameters.
     BadParam,
+    /// Flash access not aligned on the program or erase unit.
+    NotAligned,
+    /// Flash access beyond the end of the flash.
+    OutOfBounds,
 }
 
 /// QSPI transactions contain configurable instruction, address, and data fields.
@@ -51,11 +141,9 @@ impl QspiWidth {
     pub const QUAD: u8 = 0b11;
 }
 
-/// QSPI functional mode. Only `INDIRECT_READ` and `INDIRECT_WRITE` are
-/// supported at the moment.
+/// QSPI functional mode.
 struct QspiMode;
 
-#[allow(dead_code)]
 impl QspiMode {
     pub const INDIRECT_WRITE: u8 = 0b00;
     pub const INDIRECT_READ: u8 = 0b01;
@@ -67,7 +155,22 @@ impl Qspi {
     /// Initialize and configure the QSPI flash driver.
     /// - `size` is log2(flash size in bytes), e.g. 16 MB = 24.
     /// - `adsize` is the number of bytes needed to specify the address (1, 2, 3, or 4).
-    pub fn new(_rcc: &mut RCC, qspi: QUADSPI, size: u8, mut adsize: u8) -> Self {
+    pub fn new(_rcc: &mut RCC, qspi: QUADSPI, size: u8, adsize: u8) -> Self {
+        Self::init(qspi, size, adsize, false)
+    }
+
+    /// Initialize and configure the QSPI flash driver in dual-flash mode, for two identical flash
+    /// memories.
+    /// - `size` is log2(size in bytes of one flash), e.g. 2 x 64 MB = 26.
+    /// - `adsize` is the number of bytes needed to specify the address (1, 2, 3, or 4) of one
+    ///   flash.
+    ///
+    /// The addresses given to the driver cover both memories, and are halved by the peripheral.
+    pub fn new_dual(_rcc: &mut RCC, qspi: QUADSPI, size: u8, adsize: u8) -> Self {
+        Self::init(qspi, size + 1, adsize, true)
+    }
+
+    fn init(qspi: QUADSPI, size: u8, mut adsize: u8, dual_flash: bool) -> Self {
         assert!((1..=4).contains(&adsize));
         adsize -= 1;
 
@@ -76,21 +179,76 @@ impl Qspi {
 
         // Configure QSPI
         unsafe {
-            // Single flash mode with a QSPI clock prescaler of 2 (216 / 2 = 108 MHz), FIFO
+            // Single or dual flash mode with a QSPI clock prescaler of 2 (216 / 2 = 108 MHz), FIFO
             // threshold only matters for DMA and is set to 4 to allow word sized DMA requests
-            qspi.cr
-                .write_with_zero(|w| w.prescaler().bits(1).fthres().bits(3).en().set_bit());
+            qspi.cr.write_with_zero(|w| {
+                w.prescaler()
+                    .bits(1)
+                    .fthres()
+                    .bits(3)
+                    .dfm()
+                    .bit(dual_flash)
+                    .en()
+                    .set_bit()
+            });
 
-            // Set the device size
+            // Set the device size, of both memories in dual-flash mode
             qspi.dcr.write_with_zero(|w| w.fsize().bits(size - 1));
         }
 
-        Qspi { qspi, adsize }
+        Qspi {
+            qspi,
+            adsize,
+            size,
+            dual_flash,
+        }
+    }
+
+    /// Changes the number of address bytes (1, 2, 3, or 4) of the next transactions, such as after
+    /// switching the flash to 4-byte addressing.
+    pub fn set_address_size(&mut self, adsize: u8) {
+        assert!((1..=4).contains(&adsize));
+        self.adsize = adsize - 1;
+    }
+
+    /// Returns the number of address bytes of the transactions.
+    pub fn address_size(&self) -> u8 {
+        self.adsize + 1
+    }
+
+    /// Returns log2(flash size in bytes), of both memories in dual-flash mode.
+    pub fn size(&self) -> u8 {
+        self.size
+    }
+
+    /// Returns `true` if two flash memories are accessed in parallel.
+    pub fn is_dual_flash(&self) -> bool {
+        self.dual_flash
+    }
+
+    /// Sets the FIFO threshold, from 1 to 32 bytes, at which a DMA request is raised: the number
+    /// of bytes received in indirect read mode, or of free bytes in indirect write mode. A higher
+    /// threshold lets the DMA move the data in longer bursts, a lower one keeps the latency low
+    /// when the DMA stream is shared. The default of 4 bytes matches the word alignment required
+    /// by the DMA transfers.
+    pub fn set_fifo_threshold(&mut self, threshold: u8) {
+        assert!((1..=32).contains(&threshold));
+
+        // The threshold must not change during a transaction
+        if self.is_memory_mapped() {
+            self.abort();
+        }
+        while self.qspi.sr.read().busy().bit_is_set() {}
+
+        self.qspi
+            .cr
+            .modify(|_, w| unsafe { w.fthres().bits(threshold - 1) });
     }
 
     /// DMA read. Wrapper around the HAL DMA driver. Performs QSPI register programming, creates a
     /// DMA transfer from peripheral to memory, and starts the transfer. Caller can use the DMA
-    /// `wait` API to block until the transfer is complete.
+    /// `wait` API to block until the transfer is complete, or enable the DMA interrupts to be
+    /// notified. The data length must be a multiple of 4 bytes, up to [`DMA_MAX_LEN`].
     pub fn read_all<B>(
         &mut self,
         data: Pin<B>,
@@ -109,6 +267,7 @@ impl Qspi {
                     (data_len as u32) % 4 == 0,
                     "DMA transfer must be word aligned."
                 );
+                assert!(data_len <= DMA_MAX_LEN, "DMA transfer is too long.");
 
                 // Setup the transaction registers
                 self.setup_transaction(QspiMode::INDIRECT_READ, &transaction);
@@ -139,7 +298,8 @@ impl Qspi {
 
     /// DMA write. Wrapper around the HAL DMA driver. Performs QSPI register programming, creates a
     /// DMA transfer from memory to peripheral, and starts the transfer. Caller can use the DMA
-    /// `wait` API to block until the transfer is complete.
+    /// `wait` API to block until the transfer is complete, or enable the DMA interrupts to be
+    /// notified. The data length must be a multiple of 4 bytes, up to [`DMA_MAX_LEN`].
     pub fn write_all<B>(
         &mut self,
         data: Pin<B>,
@@ -158,6 +318,7 @@ impl Qspi {
                     (data_len as u32) % 4 == 0,
                     "DMA transfer must be word aligned."
                 );
+                assert!(data_len <= DMA_MAX_LEN, "DMA transfer is too long.");
 
                 // Setup the transaction registers
                 self.setup_transaction(QspiMode::INDIRECT_WRITE, &transaction);
@@ -251,8 +412,139 @@ impl Qspi {
         Ok(())
     }
 
+    /// Switches to memory-mapped mode. The flash is then read at `MEMORY_MAPPED_ADDRESS`, the
+    /// peripheral issuing the read command described by `transaction`, whose address and data
+    /// length are ignored. Returns the mapped flash.
+    ///
+    /// The memory-mapped mode is left by `abort`, or by the next indirect transaction.
+    pub fn memory_mapped(&mut self, transaction: QspiTransaction) -> Result<&[u8], Error> {
+        if transaction.dwidth == QspiWidth::NONE {
+            return Err(Error::BadParam);
+        }
+
+        self.qspi.cr.modify(|_, w| w.dmaen().clear_bit());
+        self.setup_transaction(QspiMode::MEMORY_MAPPED, &transaction);
+
+        // Safety: the flash stays mapped until the next mutable borrow of the driver
+        Ok(unsafe {
+            core::slice::from_raw_parts(MEMORY_MAPPED_ADDRESS as *const u8, self.mapped_len())
+        })
+    }
+
+    /// Returns the length of the flash in the memory-mapped region, which truncates the flashes
+    /// above 256 MB.
+    fn mapped_len(&self) -> usize {
+        (1u64 << self.size).min(MEMORY_MAPPED_LEN as u64) as usize
+    }
+
+    /// Returns `true` if the flash is memory-mapped.
+    pub fn is_memory_mapped(&self) -> bool {
+        self.qspi.ccr.read().fmode().bits() == QspiMode::MEMORY_MAPPED
+    }
+
+    /// Aborts the ongoing transaction, or leaves the memory-mapped mode, and waits for the
+    /// peripheral to be idle.
+    pub fn abort(&mut self) {
+        self.qspi.cr.modify(|_, w| w.abort().set_bit());
+        // The bit is cleared once the abort is complete
+        while self.qspi.cr.read().abort().bit_is_set() {}
+        while self.qspi.sr.read().busy().bit_is_set() {}
+    }
+
+    /// Starts the automatic polling mode: the peripheral sends `transaction`, a read of 1 to 4
+    /// status bytes, every `polling.interval` clock cycles until the status matches, then stops.
+    /// The match is reported by `is_status_matched`, and by the QUADSPI interrupt once enabled
+    /// with `listen_status_match`. Used to wait for the end of a flash erase or program without
+    /// the CPU.
+    ///
+    /// The next transaction waits for the match: the polling of a status which never matches
+    /// must be stopped with `abort`.
+    pub fn start_auto_polling(
+        &mut self,
+        transaction: QspiTransaction,
+        polling: &AutoPolling,
+    ) -> Result<(), Error> {
+        match transaction.data_len {
+            Some(1..=4) if transaction.dwidth != QspiWidth::NONE => {}
+            _ => return Err(Error::BadParam),
+        }
+
+        // The polling registers must not change during a transaction
+        if self.is_memory_mapped() {
+            self.abort();
+        }
+        while self.qspi.sr.read().busy().bit_is_set() {}
+
+        unsafe {
+            self.qspi.psmkr.write(|w| w.mask().bits(polling.mask));
+            self.qspi.psmar.write(|w| w.match_().bits(polling.value));
+            self.qspi.pir.write(|w| w.interval().bits(polling.interval));
+        }
+        self.qspi.cr.modify(|_, w| {
+            w.dmaen()
+                .clear_bit()
+                .pmm()
+                .bit(polling.match_mode == PollingMatch::Or)
+                .apms()
+                .set_bit()
+        });
+
+        self.setup_transaction(QspiMode::AUTO_POLLING, &transaction);
+        Ok(())
+    }
+
+    /// Returns `true` once the status matched in automatic polling mode.
+    pub fn is_status_matched(&self) -> bool {
+        self.qspi.sr.read().smf().bit_is_set()
+    }
+
+    /// Waits for the status to match in automatic polling mode, clears the match flag, and
+    /// returns the last status read.
+    pub fn wait_status_match(&mut self) -> u32 {
+        while !self.is_status_matched() {}
+        self.qspi.fcr.write(|w| w.csmf().set_bit());
+        self.qspi.dr.read().data().bits()
+    }
+
+    /// Enables the QUADSPI interrupt on a status match, in automatic polling mode. The interrupt
+    /// handler must clear the flag with `wait_status_match`.
+    pub fn listen_status_match(&mut self) {
+        self.qspi.cr.modify(|_, w| w.smie().set_bit());
+    }
+
+    pub fn unlisten_status_match(&mut self) {
+        self.qspi.cr.modify(|_, w| w.smie().clear_bit());
+    }
+
     /// Map from QspiTransaction to QSPI registers.
     fn setup_transaction(&mut self, fmode: u8, transaction: &QspiTransaction) {
+        // The peripheral stays busy in memory-mapped mode until aborted
+        if self.is_memory_mapped() {
+            self.abort();
+        }
+        // Wait for the previous transaction to complete
+        while self.qspi.sr.read().busy().bit_is_set() {}
+
+        // Each flash stores half of the bytes in dual-flash mode
+        if self.dual_flash {
+            assert!(
+                transaction.data_len.map_or(true, |len| len % 2 == 0),
+                "Dual-flash transfers must have an even length."
+            );
+            assert!(
+                transaction.address.map_or(true, |address| address % 2 == 0),
+                "Dual-flash transfers must start at an even address."
+            );
+        }
+
+        let (abwidth, absize) = match transaction.alternate_bytes {
+            Some(alternate_bytes) => {
+                assert!((1..=4).contains(&alternate_bytes.size));
+                (alternate_bytes.width, alternate_bytes.size - 1)
+            }
+            None => (QspiWidth::NONE, 0),
+        };
+
         unsafe {
             // Clear any prior status flags
             self.qspi.fcr.write(|w| w.bits(0x1B));
@@ -275,13 +567,20 @@ impl Qspi {
                     .adsize()
                     .bits(self.adsize)
                     .abmode()
-                    .bits(QspiWidth::NONE)
+                    .bits(abwidth)
+                    .absize()
+                    .bits(absize)
                     .dcyc()
                     .bits(transaction.dummy)
                     .instruction()
                     .bits(transaction.instruction)
             });
 
+            // Update alternate bytes register, if applicable
+            if let Some(alternate_bytes) = transaction.alternate_bytes {
+                self.qspi.abr.write(|w| w.bits(alternate_bytes.value));
+            }
+
             // Update address register, if applicable
             if let Some(addr) = transaction.address {
                 self.qspi.ar.write(|w| w.bits(addr));
@@ -0,0 +1,511 @@
+use super::{AutoPolling, Error, PollingMatch, Qspi, QspiTransaction, QspiWidth};
+
+/// Standard commands, shared by the W25Q, MX25 and N25Q families
+const CMD_WRITE_ENABLE: u8 = 0x06;
+const CMD_READ_STATUS: u8 = 0x05;
+const CMD_WRITE_STATUS: u8 = 0x01;
+const CMD_READ_ID: u8 = 0x9f;
+const CMD_READ_SFDP: u8 = 0x5a;
+const CMD_ENTER_4BYTE: u8 = 0xb7;
+const CMD_PAGE_PROGRAM: u8 = 0x02;
+const CMD_QUAD_OUTPUT_READ: u8 = 0x6b;
+const CMD_SECTOR_ERASE: u8 = 0x20;
+const CMD_BLOCK_ERASE: u8 = 0xd8;
+const CMD_CHIP_ERASE: u8 = 0xc7;
+/// Second status register of the W25Q family
+const CMD_READ_STATUS_2: u8 = 0x35;
+
+/// Write in progress, in the status register
+const STATUS_WIP: u8 = 1 << 0;
+/// Quad enable, in the status register of the MX25 family
+const STATUS_QE_MX25: u8 = 1 << 6;
+/// Quad enable, in the second status register of the W25Q family
+const STATUS_2_QE_W25Q: u8 = 1 << 1;
+
+/// Dummy cycles of the quad output fast read and of the SFDP read
+const READ_DUMMY: u8 = 8;
+/// QSPI clock cycles between two status reads of the automatic polling
+const POLLING_INTERVAL: u16 = 0x10;
+/// Sizes of one flash
+const PAGE_SIZE: usize = 256;
+const SECTOR_SIZE: usize = 4096;
+const BLOCK_SIZE: usize = 65536;
+
+/// Manufacturer, memory type and capacity codes, read with the JEDEC read
+/// identification command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JedecId {
+    pub manufacturer: u8,
+    pub memory_type: u8,
+    pub capacity: u8,
+}
+
+impl JedecId {
+    /// Returns log2(flash size in bytes), or `None` if the capacity code is
+    /// unknown. The codes jump from 0x19 to 0x20 on the 512 Mb and larger
+    /// memories of several manufacturers.
+    pub fn size(&self) -> Option<u8> {
+        match self.capacity {
+            code @ 0x10..=0x1f => Some(code),
+            code @ 0x20..=0x22 => Some(code - 0x20 + 26),
+            _ => None,
+        }
+    }
+}
+
+/// Flash families, which differ on how the quad I/O mode is enabled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlashFamily {
+    /// Winbond W25Q, with the QE bit in the second status register
+    W25q,
+    /// Macronix MX25, with the QE bit in the status register
+    Mx25,
+    /// Micron N25Q and MT25Q, always accepting the quad I/O commands
+    N25q,
+    /// Any other JEDEC compliant flash, assumed to always accept the quad I/O
+    /// commands
+    Other,
+}
+
+impl FlashFamily {
+    /// Returns the family of the flash made by the JEDEC manufacturer
+    /// `manufacturer`
+    pub fn from_manufacturer(manufacturer: u8) -> Self {
+        match manufacturer {
+            0xef => FlashFamily::W25q,
+            0xc2 => FlashFamily::Mx25,
+            0x20 => FlashFamily::N25q,
+            _ => FlashFamily::Other,
+        }
+    }
+}
+
+/// NOR flash driver on top of [`Qspi`], for the W25Q, MX25 and N25Q
+/// families
+///
+/// The flash is identified by its JEDEC ID, switched to 4-byte addressing
+/// when larger than 16 MB, and its quad I/O mode is enabled. The commands
+/// are sent on one line, the data is read on four lines with the quad output
+/// fast read command, and programmed on one line, the memory being busy long
+/// after the page is transferred. Each erase and program waits for the
+/// flash to be ready, the status register being polled by the peripheral.
+///
+/// In dual-flash mode, both memories are identified, configured and polled
+/// together, and the pages and sectors are twice as large.
+pub struct QspiFlash {
+    qspi: Qspi,
+    id: JedecId,
+    family: FlashFamily,
+}
+
+impl QspiFlash {
+    /// Identifies and configures the flash. The QSPI driver must have been
+    /// created with the size of the flash, and 3 address bytes.
+    pub fn new(qspi: Qspi) -> Result<Self, Error> {
+        let mut flash = QspiFlash {
+            qspi,
+            id: JedecId {
+                manufacturer: 0,
+                memory_type: 0,
+                capacity: 0,
+            },
+            family: FlashFamily::Other,
+        };
+
+        let mut id = [0; 3];
+        flash.read_register(CMD_READ_ID, &mut id)?;
+        flash.id = JedecId {
+            manufacturer: id[0],
+            memory_type: id[1],
+            capacity: id[2],
+        };
+        flash.family = FlashFamily::from_manufacturer(id[0]);
+
+        // Size of one flash
+        let size = flash.qspi.size() - u8::from(flash.qspi.is_dual_flash());
+        if size > 24 {
+            flash.enter_4byte_addressing()?;
+        }
+        flash.enable_quad()?;
+
+        Ok(flash)
+    }
+
+    pub fn id(&self) -> JedecId {
+        self.id
+    }
+
+    pub fn family(&self) -> FlashFamily {
+        self.family
+    }
+
+    /// Returns the size of a page, the largest unit programmed at once
+    pub fn page_size(&self) -> usize {
+        self.flash_count() * PAGE_SIZE
+    }
+
+    /// Returns the size of a sector, the smallest unit erased
+    pub fn sector_size(&self) -> usize {
+        self.flash_count() * SECTOR_SIZE
+    }
+
+    /// Returns the size of a block, erased by [`QspiFlash::erase_block`]
+    pub fn block_size(&self) -> usize {
+        self.flash_count() * BLOCK_SIZE
+    }
+
+    /// Reads the serial flash discoverable parameters at `address`, always
+    /// addressed on 3 bytes. In dual-flash mode, the parameters of both
+    /// memories are interleaved.
+    pub fn read_sfdp(&mut self, address: u32, buf: &mut [u8]) -> Result<(), Error> {
+        let adsize = self.qspi.address_size();
+        self.qspi.set_address_size(3);
+        let result = self.qspi.read(
+            buf,
+            QspiTransaction {
+                address: Some(address),
+                awidth: QspiWidth::SING,
+                dwidth: QspiWidth::SING,
+                dummy: READ_DUMMY,
+                data_len: Some(buf.len()),
+                ..command(CMD_READ_SFDP)
+            },
+        );
+        self.qspi.set_address_size(adsize);
+        result
+    }
+
+    /// Returns the transaction of a quad output fast read of `len` bytes at
+    /// `address`, for the DMA transfers of [`Qspi::read_all`] and the
+    /// memory-mapped mode of [`Qspi::memory_mapped`]
+    pub fn read_transaction(&self, address: u32, len: usize) -> QspiTransaction {
+        QspiTransaction {
+            address: Some(address),
+            awidth: QspiWidth::SING,
+            dwidth: QspiWidth::QUAD,
+            dummy: READ_DUMMY,
+            data_len: Some(len),
+            ..command(CMD_QUAD_OUTPUT_READ)
+        }
+    }
+
+    /// Reads `buf` at `address`, polling the FIFO
+    pub fn read(&mut self, address: u32, buf: &mut [u8]) -> Result<(), Error> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let transaction = self.read_transaction(address, buf.len());
+        self.qspi.read(buf, transaction)
+    }
+
+    /// Programs `data` at `address`, page by page. The bytes must have been
+    /// erased first.
+    pub fn program(&mut self, mut address: u32, mut data: &[u8]) -> Result<(), Error> {
+        while !data.is_empty() {
+            // A page program wraps around at the end of the page
+            let page_size = self.page_size();
+            let len = (page_size - address as usize % page_size).min(data.len());
+            let (page, rest) = data.split_at(len);
+
+            self.write_enable()?;
+            self.qspi.write(
+                page,
+                QspiTransaction {
+                    address: Some(address),
+                    awidth: QspiWidth::SING,
+                    dwidth: QspiWidth::SING,
+                    data_len: Some(len),
+                    ..command(CMD_PAGE_PROGRAM)
+                },
+            )?;
+            self.wait_ready()?;
+
+            address += len as u32;
+            data = rest;
+        }
+        Ok(())
+    }
+
+    /// Erases the sector at `address`
+    pub fn erase_sector(&mut self, address: u32) -> Result<(), Error> {
+        self.erase(CMD_SECTOR_ERASE, Some(address))
+    }
+
+    /// Erases the block at `address`
+    pub fn erase_block(&mut self, address: u32) -> Result<(), Error> {
+        self.erase(CMD_BLOCK_ERASE, Some(address))
+    }
+
+    /// Erases the whole flash, which may take minutes
+    pub fn erase_chip(&mut self) -> Result<(), Error> {
+        self.erase(CMD_CHIP_ERASE, None)
+    }
+
+    /// Returns `true` while an erase or a program is in progress
+    pub fn is_busy(&mut self) -> Result<bool, Error> {
+        let mut status = [0];
+        self.read_register(CMD_READ_STATUS, &mut status)?;
+        Ok(status[0] & STATUS_WIP != 0)
+    }
+
+    /// Starts polling the status register in hardware, until the erase or
+    /// program in progress completes. The completion is reported by
+    /// [`Qspi::is_status_matched`], or by the QUADSPI interrupt.
+    pub fn start_ready_polling(&mut self) -> Result<(), Error> {
+        // The WIP bit of both memories in dual-flash mode
+        let count = self.flash_count();
+        let mask = (0..count).fold(0, |mask, i| mask | u32::from(STATUS_WIP) << (8 * i));
+        self.qspi.start_auto_polling(
+            QspiTransaction {
+                dwidth: QspiWidth::SING,
+                data_len: Some(count),
+                ..command(CMD_READ_STATUS)
+            },
+            &AutoPolling {
+                mask,
+                value: 0,
+                match_mode: PollingMatch::And,
+                interval: POLLING_INTERVAL,
+            },
+        )
+    }
+
+    /// Waits for the erase or program in progress to complete, the status
+    /// register being polled in hardware
+    pub fn wait_ready(&mut self) -> Result<(), Error> {
+        self.start_ready_polling()?;
+        self.qspi.wait_status_match();
+        Ok(())
+    }
+
+    pub fn qspi(&mut self) -> &mut Qspi {
+        &mut self.qspi
+    }
+
+    pub fn free(self) -> Qspi {
+        self.qspi
+    }
+
+    fn erase(&mut self, instruction: u8, address: Option<u32>) -> Result<(), Error> {
+        self.write_enable()?;
+        self.qspi.read(
+            &mut [],
+            QspiTransaction {
+                address,
+                awidth: if address.is_some() {
+                    QspiWidth::SING
+                } else {
+                    QspiWidth::NONE
+                },
+                ..command(instruction)
+            },
+        )?;
+        self.wait_ready()
+    }
+
+    fn write_enable(&mut self) -> Result<(), Error> {
+        self.qspi.read(&mut [], command(CMD_WRITE_ENABLE))
+    }
+
+    fn enter_4byte_addressing(&mut self) -> Result<(), Error> {
+        // The N25Q family needs the write enable latch to be set
+        self.write_enable()?;
+        self.qspi.read(&mut [], command(CMD_ENTER_4BYTE))?;
+        self.qspi.set_address_size(4);
+        Ok(())
+    }
+
+    /// Sets the non-volatile quad enable bit, if needed and not already set
+    fn enable_quad(&mut self) -> Result<(), Error> {
+        match self.family {
+            FlashFamily::W25q => {
+                let mut status = [0; 2];
+                self.read_register(CMD_READ_STATUS, &mut status[..1])?;
+                self.read_register(CMD_READ_STATUS_2, &mut status[1..])?;
+                if status[1] & STATUS_2_QE_W25Q == 0 {
+                    status[1] |= STATUS_2_QE_W25Q;
+                    self.write_register(CMD_WRITE_STATUS, &status)?;
+                }
+            }
+            FlashFamily::Mx25 => {
+                let mut status = [0];
+                self.read_register(CMD_READ_STATUS, &mut status)?;
+                if status[0] & STATUS_QE_MX25 == 0 {
+                    status[0] |= STATUS_QE_MX25;
+                    self.write_register(CMD_WRITE_STATUS, &status)?;
+                }
+            }
+            FlashFamily::N25q | FlashFamily::Other => {}
+        }
+        Ok(())
+    }
+
+    /// Reads a register of up to 4 bytes. In dual-flash mode, the bytes of
+    /// both memories are interleaved: those of the flash 1 are returned, with
+    /// the bits of the flash 2 ORed in, so that either flash being busy is
+    /// reported.
+    fn read_register(&mut self, instruction: u8, value: &mut [u8]) -> Result<(), Error> {
+        let count = self.flash_count();
+        let mut buf = [0; 8];
+        let buf = &mut buf[..value.len() * count];
+        self.qspi.read(
+            buf,
+            QspiTransaction {
+                dwidth: QspiWidth::SING,
+                data_len: Some(buf.len()),
+                ..command(instruction)
+            },
+        )?;
+        for (byte, bytes) in value.iter_mut().zip(buf.chunks(count)) {
+            *byte = bytes.iter().fold(0, |byte, b| byte | b);
+        }
+        Ok(())
+    }
+
+    /// Writes a register of up to 4 bytes, to both memories in dual-flash
+    /// mode, and waits for the write to complete
+    fn write_register(&mut self, instruction: u8, value: &[u8]) -> Result<(), Error> {
+        let count = self.flash_count();
+        let mut buf = [0; 8];
+        let buf = &mut buf[..value.len() * count];
+        for (bytes, byte) in buf.chunks_mut(count).zip(value) {
+            bytes.fill(*byte);
+        }
+
+        self.write_enable()?;
+        self.qspi.write(
+            buf,
+            QspiTransaction {
+                dwidth: QspiWidth::SING,
+                data_len: Some(buf.len()),
+                ..command(instruction)
+            },
+        )?;
+        self.wait_ready()
+    }
+
+    fn flash_count(&self) -> usize {
+        if self.qspi.is_dual_flash() {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+/// Returns the transaction of a command without address nor data
+fn command(instruction: u8) -> QspiTransaction {
+    QspiTransaction {
+        iwidth: QspiWidth::SING,
+        awidth: QspiWidth::NONE,
+        dwidth: QspiWidth::NONE,
+        instruction,
+        address: None,
+        alternate_bytes: None,
+        dummy: 0,
+        data_len: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jedec_id_size() {
+        let id = |capacity| JedecId {
+            manufacturer: 0xef,
+            memory_type: 0x40,
+            capacity,
+        };
+        // W25Q128, 16 MB
+        assert_eq!(id(0x18).size(), Some(24));
+        // W25Q512, 64 MB
+        assert_eq!(id(0x20).size(), Some(26));
+        // MT25Q 2 Gb, 256 MB
+        assert_eq!(id(0x22).size(), Some(28));
+        assert_eq!(id(0xff).size(), None);
+    }
+}
+
+#[cfg(feature = "qspi-storage")]
+mod storage {
+    use embedded_storage::nor_flash::{
+        ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+    };
+
+    use super::{Error, QspiFlash, SECTOR_SIZE};
+
+    impl NorFlashError for Error {
+        fn kind(&self) -> NorFlashErrorKind {
+            match self {
+                Error::NotAligned => NorFlashErrorKind::NotAligned,
+                Error::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+                Error::BadParam => NorFlashErrorKind::Other,
+            }
+        }
+    }
+
+    impl ErrorType for QspiFlash {
+        type Error = Error;
+    }
+
+    /// In dual-flash mode, the reads and writes must be aligned on 2 bytes,
+    /// and the erases on 2 sectors, or fail with `Error::NotAligned`
+    impl ReadNorFlash for QspiFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+            self.check(offset, bytes.len(), self.flash_count())?;
+            QspiFlash::read(self, offset, bytes)
+        }
+
+        fn capacity(&self) -> usize {
+            1 << self.qspi.size()
+        }
+    }
+
+    impl NorFlash for QspiFlash {
+        const WRITE_SIZE: usize = 1;
+        const ERASE_SIZE: usize = SECTOR_SIZE;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Error> {
+            if from > to {
+                return Err(Error::OutOfBounds);
+            }
+            let sector_size = self.sector_size();
+            self.check(from, (to - from) as usize, sector_size)?;
+
+            // Whole blocks are erased at once, being much faster
+            let block_size = self.block_size() as u32;
+            let mut address = from;
+            while address < to {
+                if address % block_size == 0 && to - address >= block_size {
+                    self.erase_block(address)?;
+                    address += block_size;
+                } else {
+                    self.erase_sector(address)?;
+                    address += sector_size as u32;
+                }
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error> {
+            self.check(offset, bytes.len(), self.flash_count())?;
+            self.program(offset, bytes)
+        }
+    }
+
+    impl QspiFlash {
+        fn check(&self, offset: u32, len: usize, align: usize) -> Result<(), Error> {
+            if offset as usize % align != 0 || len % align != 0 {
+                return Err(Error::NotAligned);
+            }
+            if offset as usize + len > self.capacity() {
+                return Err(Error::OutOfBounds);
+            }
+            Ok(())
+        }
+    }
+}
@@ -2,7 +2,19 @@
 
 use core::cmp::min;
 
+mod config;
+mod css;
 mod enable;
+mod kernel;
+pub(crate) mod pll;
+mod reset;
+
+pub use config::{BusConfig, PllConfig};
+pub use css::{handle_css_interrupt, is_hse_failed, set_css_handler};
+#[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+pub use kernel::CecClock;
+pub use kernel::{I2cClock, I2sClock, KernelClocks, LptimClock, SdmmcClock, UsartClock};
+pub use reset::ResetReason;
 
 #[cfg_attr(test, allow(unused_imports))]
 use micromath::F32Ext;
@@ -11,7 +23,7 @@ use crate::pac::{rcc, FLASH, PWR, RCC};
 use fugit::{HertzU32 as Hertz, RateExtU32};
 
 /// Typical output frequency of the HSI oscillator.
-const HSI_FREQUENCY: Hertz = Hertz::from_raw(16_000_000);
+pub(crate) const HSI_FREQUENCY: Hertz = Hertz::from_raw(16_000_000);
 
 /// Extension trait that constrains the `RCC` peripheral
 pub trait RccExt {
@@ -46,14 +58,34 @@ impl RccExt for RCC {
                 pllsain: 192,
                 pllsaip: PLLSAIP::Div2,
                 pllsaiq: 2,
+                #[cfg(not(any(
+                    feature = "svd-f7x2",
+                    feature = "svd-f7x3",
+                    feature = "svd-f730"
+                )))]
+                pllsair: 2,
+                #[cfg(not(any(
+                    feature = "svd-f7x2",
+                    feature = "svd-f7x3",
+                    feature = "svd-f730"
+                )))]
+                pllsaidivr: PLLSAIDIVR::Div2,
                 use_plli2s: false,
                 plli2sr: 2,
                 plli2sq: 2,
                 plli2sn: 192,
+                plli2s_sample_rate: None,
                 mco1: MCO1::Hsi,
                 mco1pre: MCOPRE::Div1_no_div,
                 mco2: MCO2::Sysclk,
                 mco2pre: MCOPRE::Div1_no_div,
+                voltage_range: VoltageRange::V2_7,
+                vos_scale: None,
+                art_accelerator: true,
+                prefetch: true,
+                hse_css: false,
+                kernel_clocks: KernelClocks::default(),
+                spread_spectrum: None,
             },
         }
     }
@@ -117,6 +149,37 @@ bus_struct! {
     AHB3 => (AHB3ENR, ahb3enr, AHB3LPENR, ahb3lpenr, AHB3RSTR, ahb3rstr, "Advanced High-performance Bus 3 (AHB3) registers"),
 }
 
+/// Memory interfaces on the AHB1, clocked in Sleep mode on reset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SleepMemory {
+    /// Flash memory interface
+    Flash,
+    Sram1,
+    Sram2,
+    /// Backup SRAM interface
+    BackupSram,
+    /// AXI to AHB bridge
+    Axi,
+    /// DTCM RAM interface
+    Dtcm,
+}
+
+impl AHB1 {
+    /// Keeps the interface of `memory` clocked in Sleep mode, or stops it.
+    ///
+    /// The memories accessed by a DMA during Sleep mode must stay clocked.
+    pub fn set_memory_low_power(&mut self, memory: SleepMemory, enabled: bool) {
+        self.lpenr().modify(|_, w| match memory {
+            SleepMemory::Flash => w.flitflpen().bit(enabled),
+            SleepMemory::Sram1 => w.sram1lpen().bit(enabled),
+            SleepMemory::Sram2 => w.sram2lpen().bit(enabled),
+            SleepMemory::BackupSram => w.bkpsramlpen().bit(enabled),
+            SleepMemory::Axi => w.axilpen().bit(enabled),
+            SleepMemory::Dtcm => w.dtcmlpen().bit(enabled),
+        });
+    }
+}
+
 /// Backup Domain Control register (RCC_BDCR)
 pub struct BDCR {
     _0: (),
@@ -126,6 +189,99 @@ impl BDCR {
     pub(crate) fn new() -> Self {
         Self { _0: () }
     }
+
+    fn bdcr(&self) -> &rcc::BDCR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*RCC::ptr()).bdcr }
+    }
+
+    fn csr(&self) -> &rcc::CSR {
+        // NOTE(unsafe) only the LSI bits of this register are accessed
+        unsafe { &(*RCC::ptr()).csr }
+    }
+
+    /// Enables write access to the backup domain (RTC, LSE, backup registers
+    /// and backup SRAM), which is write protected after reset
+    pub fn enable_access(&mut self, apb1: &mut APB1, pwr: &mut PWR) {
+        apb1.enr().modify(|_, w| w.pwren().set_bit());
+        pwr.cr1.modify(|_, w| w.dbp().set_bit());
+        // The write protection is disabled after a few cycles
+        while pwr.cr1.read().dbp().bit_is_clear() {}
+    }
+
+    /// Write protects the backup domain again
+    pub fn disable_access(&mut self, pwr: &mut PWR) {
+        pwr.cr1.modify(|_, w| w.dbp().clear_bit());
+    }
+
+    /// Starts the LSE and waits for it to be ready.
+    ///
+    /// The backup domain must be writable, see `enable_access`. The LSE is
+    /// stopped first if it runs in another mode. `drive` is only relevant for
+    /// `LSEClockMode::Oscillator`, a higher drive gives a more robust start up
+    /// at the cost of a higher consumption.
+    pub fn enable_lse(&mut self, mode: LSEClockMode, drive: LSEDrive) {
+        let bypass = mode == LSEClockMode::Bypass;
+        let bdcr = self.bdcr().read();
+        if bdcr.lseon().is_on() && bdcr.lsebyp().is_bypassed() == bypass {
+            // The drive can be changed while the LSE runs
+            self.set_lse_drive(drive);
+            while self.bdcr().read().lserdy().is_not_ready() {}
+            return;
+        }
+
+        // LSEBYP can only be written while the LSE is off
+        self.disable_lse();
+        self.bdcr().modify(|_, w| w.lsebyp().bit(bypass));
+        self.set_lse_drive(drive);
+        self.bdcr().modify(|_, w| w.lseon().on());
+        while self.bdcr().read().lserdy().is_not_ready() {}
+    }
+
+    fn set_lse_drive(&mut self, drive: LSEDrive) {
+        self.bdcr().modify(|_, w| match drive {
+            LSEDrive::Low => w.lsedrv().low(),
+            LSEDrive::MediumLow => w.lsedrv().medium_low(),
+            LSEDrive::MediumHigh => w.lsedrv().medium_high(),
+            LSEDrive::High => w.lsedrv().high(),
+        });
+    }
+
+    /// Stops the LSE. The backup domain must be writable.
+    pub fn disable_lse(&mut self) {
+        self.bdcr().modify(|_, w| w.lseon().off());
+        while self.bdcr().read().lserdy().is_ready() {}
+    }
+
+    /// Returns `true` if the LSE is running and stable
+    pub fn is_lse_ready(&self) -> bool {
+        self.bdcr().read().lserdy().is_ready()
+    }
+
+    /// Starts the LSI and waits for it to be ready
+    pub fn enable_lsi(&mut self) {
+        self.csr().modify(|_, w| w.lsion().on());
+        while self.csr().read().lsirdy().is_not_ready() {}
+    }
+
+    /// Stops the LSI. It is kept running by hardware while the independent
+    /// watchdog is enabled.
+    pub fn disable_lsi(&mut self) {
+        self.csr().modify(|_, w| w.lsion().off());
+    }
+
+    /// Returns `true` if the LSI is running and stable
+    pub fn is_lsi_ready(&self) -> bool {
+        self.csr().read().lsirdy().is_ready()
+    }
+
+    /// Resets the backup domain: the RTC, its backup registers and the LSE are
+    /// reset. The backup SRAM is not affected. The backup domain must be
+    /// writable.
+    pub fn reset(&mut self) {
+        self.bdcr().modify(|_, w| w.bdrst().enabled());
+        self.bdcr().modify(|_, w| w.bdrst().disabled());
+    }
 }
 
 /// HSE clock mode.
@@ -176,6 +332,15 @@ pub enum LSEClockMode {
     Bypass,
 }
 
+/// LSE oscillator drive capability (LSEDRV).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LSEDrive {
+    Low,
+    MediumLow,
+    MediumHigh,
+    High,
+}
+
 /// LSE Clock.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct LSEClock {
@@ -240,6 +405,58 @@ pub enum PLLSAIP {
     Div8 = 0b11,
 }
 
+impl PLLSAIP {
+    fn divisor(self) -> u32 {
+        match self {
+            PLLSAIP::Div2 => 2,
+            PLLSAIP::Div4 => 4,
+            PLLSAIP::Div6 => 6,
+            PLLSAIP::Div8 => 8,
+        }
+    }
+}
+
+/// PLLSAIDIVR division factors of the LCD-TFT clock.
+#[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PLLSAIDIVR {
+    Div2 = 0b00,
+    Div4 = 0b01,
+    Div8 = 0b10,
+    Div16 = 0b11,
+}
+
+#[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+impl PLLSAIDIVR {
+    fn divisor(self) -> u32 {
+        match self {
+            PLLSAIDIVR::Div2 => 2,
+            PLLSAIDIVR::Div4 => 4,
+            PLLSAIDIVR::Div8 => 8,
+            PLLSAIDIVR::Div16 => 16,
+        }
+    }
+}
+
+/// Spread of the main PLL frequency modulation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Spread {
+    /// Around the nominal frequency
+    Center,
+    /// Below the nominal frequency
+    Down,
+}
+
+/// Spread spectrum modulation of the main PLL
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpreadSpectrum {
+    /// Modulation frequency, up to 10 kHz
+    pub frequency: Hertz,
+    /// Peak modulation depth, in hundredths of percent, up to 200 (2 %)
+    pub depth: u16,
+    pub spread: Spread,
+}
+
 /// Microcontroller clock output 1
 ///
 /// Value on reset: HSI
@@ -270,13 +487,55 @@ pub enum MCO2 {
     Pll,
 }
 
+/// Supply voltage range, which sets the number of flash wait states
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VoltageRange {
+    /// 1.8 V to 2.1 V, with SYSCLK up to 180 MHz
+    V1_8,
+    /// 2.1 V to 2.4 V
+    V2_1,
+    /// 2.4 V to 2.7 V
+    V2_4,
+    /// 2.7 V to 3.6 V
+    V2_7,
+}
+
+impl VoltageRange {
+    /// Returns the highest HCLK frequency for each wait state.
+    fn frequency_per_wait_state(self) -> u32 {
+        match self {
+            VoltageRange::V1_8 => 20_000_000,
+            VoltageRange::V2_1 => 22_000_000,
+            VoltageRange::V2_4 => 24_000_000,
+            VoltageRange::V2_7 => 30_000_000,
+        }
+    }
+}
+
+/// Main regulator voltage scale, which limits the HCLK frequency
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum VOSscale {
+pub enum VOSscale {
+    /// Up to 180 MHz, 216 MHz with the over-drive
     PwrScale1,
+    /// Up to 168 MHz, 180 MHz with the over-drive
     PwrScale2,
+    /// Up to 144 MHz
     PwrScale3,
 }
 
+impl VOSscale {
+    /// Returns the highest HCLK frequency, with or without the over-drive.
+    fn max_hclk(self, overdrive: bool) -> u32 {
+        match (self, overdrive) {
+            (VOSscale::PwrScale1, false) => 180_000_000,
+            (VOSscale::PwrScale1, true) => 216_000_000,
+            (VOSscale::PwrScale2, false) => 168_000_000,
+            (VOSscale::PwrScale2, true) => 180_000_000,
+            (VOSscale::PwrScale3, _) => 144_000_000,
+        }
+    }
+}
+
 impl Default for VOSscale {
     fn default() -> Self {
         VOSscale::PwrScale3
@@ -300,7 +559,7 @@ struct FreqRequest {
 }
 
 /// Clock configuration register.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct CFGR {
     hse: Option<HSEClock>,
     hclk: Option<u32>,
@@ -319,14 +578,26 @@ pub struct CFGR {
     pllsain: u16,
     pllsaip: PLLSAIP,
     pllsaiq: u8,
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    pllsair: u8,
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    pllsaidivr: PLLSAIDIVR,
     use_plli2s: bool,
     plli2sr: u8,
     plli2sq: u8,
     plli2sn: u16,
+    plli2s_sample_rate: Option<Hertz>,
     mco1: MCO1,
     mco1pre: MCOPRE,
     mco2: MCO2,
     mco2pre: MCOPRE,
+    voltage_range: VoltageRange,
+    vos_scale: Option<VOSscale>,
+    art_accelerator: bool,
+    prefetch: bool,
+    hse_css: bool,
+    kernel_clocks: KernelClocks,
+    spread_spectrum: Option<SpreadSpectrum>,
 }
 
 impl CFGR {
@@ -336,6 +607,19 @@ impl CFGR {
         self
     }
 
+    /// Configures the HSE in bypass mode, for an external clock of frequency
+    /// `freq` on OSC_IN, such as the MCO output of the ST-LINK on the Nucleo
+    /// boards.
+    ///
+    /// Same as `hse(HSEClock::new(freq, HSEClockMode::Bypass))`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the frequency isn't between 1 MHz and 50 MHz.
+    pub fn use_hse_bypass(self, freq: Hertz) -> Self {
+        self.hse(HSEClock::new(freq, HSEClockMode::Bypass))
+    }
+
     /// Sets HCLK frequency.
     ///
     /// The HCLK is used for the AHB bus, core, memory and DMA.
@@ -423,6 +707,17 @@ impl CFGR {
         self
     }
 
+    /// Modulates the main PLL frequency to reduce the electromagnetic
+    /// emissions.
+    ///
+    /// The clocks from the main PLL are modulated, including the 48 MHz clock
+    /// from its Q output. `freeze` computes the MODPER and INCSTEP values of
+    /// SSCGR from the modulation frequency and depth.
+    pub fn spread_spectrum(mut self, spread_spectrum: SpreadSpectrum) -> Self {
+        self.spread_spectrum = Some(spread_spectrum);
+        self
+    }
+
     /// Sets the common PLL division factor.
     ///
     /// # Panics
@@ -461,6 +756,39 @@ impl CFGR {
         self
     }
 
+    /// Sets the SYSCLK clock source to the main PLL, with a configuration
+    /// checked by [`PllConfig::new`].
+    ///
+    /// The factors are recalculated by `freeze` only if the Q output is
+    /// selected for the 48 MHz clock, but isn't within the USB tolerance.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the input frequency of the configuration isn't the one of
+    /// the HSE, which must be set before, or of the HSI.
+    pub fn pll(mut self, config: PllConfig) -> Self {
+        let input = match self.hse.as_ref() {
+            Some(hse) => hse.freq,
+            None => HSI_FREQUENCY,
+        };
+        assert_eq!(config.input, input, "PLL input frequency mismatch");
+        self.pllm = config.m;
+        self.plln = config.n;
+        self.pllp = config.p;
+        self.pllq = config.q;
+        self.use_pll = true;
+        self.sysclk = Some(config.sysclk().raw());
+        self
+    }
+
+    /// Sets HCLK, PCLK1 and PCLK2 to the frequencies checked by
+    /// [`BusConfig::new`].
+    pub fn buses(self, config: BusConfig) -> Self {
+        self.hclk(config.hclk())
+            .pclk1(config.pclk1())
+            .pclk2(config.pclk2())
+    }
+
     /// Enables the PLLSAI clock source.
     pub fn use_pllsai(mut self) -> Self {
         self.use_pllsai = true;
@@ -495,6 +823,27 @@ impl CFGR {
         self
     }
 
+    /// Sets the PLLSAIR division factor for PLLSAI, which clocks the LCD-TFT
+    /// through the PLLSAIDIVR divider.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the division factor isn't between 2 and 7.
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    pub fn pllsair(mut self, pllsair: u8) -> Self {
+        assert!((2..=7).contains(&pllsair));
+        self.pllsair = pllsair;
+        self
+    }
+
+    /// Sets the PLLSAIDIVR division factor of the LCD-TFT clock, after
+    /// PLLSAIR.
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    pub fn pllsaidivr(mut self, pllsaidivr: PLLSAIDIVR) -> Self {
+        self.pllsaidivr = pllsaidivr;
+        self
+    }
+
     /// Enables the PLLI2S clock source.
     pub fn use_plli2s(mut self) -> Self {
         self.use_plli2s = true;
@@ -534,6 +883,18 @@ impl CFGR {
         self
     }
 
+    /// Enables the PLLI2S, with the PLLI2SN and PLLI2SR factors chosen by
+    /// `freeze` for an I2S clock as close as possible to a multiple of 256
+    /// times `sample_rate`, which the I2S prescaler divides down to
+    /// `sample_rate` with the master clock output enabled.
+    ///
+    /// The resulting frequency is returned by [`Clocks::plli2sr`].
+    pub fn plli2s_sample_rate(mut self, sample_rate: Hertz) -> Self {
+        self.use_plli2s = true;
+        self.plli2s_sample_rate = Some(sample_rate);
+        self
+    }
+
     /// Sets the MCO1 source
     pub fn mco1(mut self, mco1: MCO1) -> Self {
         self.mco1 = mco1;
@@ -558,6 +919,61 @@ impl CFGR {
         self
     }
 
+    /// Sets the supply voltage range, used to calculate the flash wait states.
+    ///
+    /// Value on reset: 2.7 V to 3.6 V
+    pub fn voltage_range(mut self, voltage_range: VoltageRange) -> Self {
+        self.voltage_range = voltage_range;
+        self
+    }
+
+    /// Sets the main regulator voltage scale, instead of the lowest one
+    /// supporting the HCLK frequency. The over-drive is enabled if needed.
+    ///
+    /// A lower scale reduces the consumption.
+    pub fn vos_scale(mut self, vos_scale: VOSscale) -> Self {
+        self.vos_scale = Some(vos_scale);
+        self
+    }
+
+    /// Enables or disables the ART accelerator, the instruction cache of the
+    /// flash memory accessed through the ITCM interface.
+    ///
+    /// Disabling it makes the execution time from the flash memory
+    /// deterministic. Value on reset: enabled
+    pub fn art_accelerator(mut self, enable: bool) -> Self {
+        self.art_accelerator = enable;
+        self
+    }
+
+    /// Enables or disables the flash prefetch, on the ITCM interface.
+    ///
+    /// Value on reset: enabled
+    pub fn prefetch(mut self, enable: bool) -> Self {
+        self.prefetch = enable;
+        self
+    }
+
+    /// Enables the clock security system of the HSE, which switches the
+    /// system clock to the HSI and raises a NMI when the HSE fails, to be
+    /// handled by [`handle_css_interrupt`].
+    ///
+    /// # Panics
+    ///
+    /// `freeze` panics if the HSE isn't configured.
+    pub fn hse_css(mut self) -> Self {
+        self.hse_css = true;
+        self
+    }
+
+    /// Selects the kernel clocks of the peripherals.
+    ///
+    /// Value on reset: `KernelClocks::default()`
+    pub fn kernel_clocks(mut self, kernel_clocks: KernelClocks) -> Self {
+        self.kernel_clocks = kernel_clocks;
+        self
+    }
+
     /// Output clock calculation
     fn calculate_clocks(&self) -> (Clocks, InternalRCCConfig) {
         let mut config = InternalRCCConfig::default();
@@ -572,8 +988,6 @@ impl CFGR {
 
         let mut sysclk = base_clk;
 
-        let mut pll48clk_valid = false;
-
         if self.use_pll {
             sysclk = base_clk as u64 * self.plln as u64
                 / self.pllm as u64
@@ -585,37 +999,6 @@ impl CFGR {
                 };
         }
 
-        // Check if pll48clk is valid
-        if let Some(pll48clk) = self.pll48clk {
-            match pll48clk {
-                PLL48CLK::Pllq => {
-                    pll48clk_valid = {
-                        let pll48clk = base_clk as u64 * self.plln as u64
-                            / self.pllm as u64
-                            / self.pllq as u64;
-                        (48_000_000 - 120_000..=48_000_000 + 120_000).contains(&pll48clk)
-                    }
-                }
-                PLL48CLK::Pllsai => {
-                    pll48clk_valid = {
-                        if self.use_pllsai {
-                            let pll48clk = base_clk as u64 * self.pllsain as u64
-                                / self.pllm as u64
-                                / match self.pllsaip {
-                                    PLLSAIP::Div2 => 2,
-                                    PLLSAIP::Div4 => 4,
-                                    PLLSAIP::Div6 => 6,
-                                    PLLSAIP::Div8 => 8,
-                                };
-                            (48_000_000 - 120_000..=48_000_000 + 120_000).contains(&pll48clk)
-                        } else {
-                            false
-                        }
-                    }
-                }
-            }
-        }
-
         // SYSCLK, must be <= 216 Mhz. By default, HSI/HSE frequency is chosen
         assert!(sysclk <= 216_000_000);
         let sysclk = sysclk as u32;
@@ -722,41 +1105,62 @@ impl CFGR {
         // update pclk2 with the real value
         pclk2 = hclk / ppre2_val;
 
-        // Assumes TIMPRE bit of RCC_DCKCFGR1 is reset (0)
+        // TIMPRE of RCC_DCKCFGR1 is cleared by `freeze`: the timers are
+        // clocked at twice the APB frequency when the APB prescaler isn't 1
         let timclk1 = if ppre1_val == 1 { pclk1 } else { 2 * pclk1 };
         let timclk2 = if ppre2_val == 1 { pclk2 } else { 2 * pclk2 };
 
         // Adjust flash wait states
-        config.flash_waitstates = if sysclk <= 30_000_000 {
-            0b0000
-        } else if sysclk <= 60_000_000 {
-            0b0001
-        } else if sysclk <= 90_000_000 {
-            0b0010
-        } else if sysclk <= 120_000_000 {
-            0b0011
-        } else if sysclk <= 150_000_000 {
-            0b0100
-        } else if sysclk <= 180_000_000 {
-            0b0101
-        } else if sysclk <= 210_000_000 {
-            0b0110
-        } else {
-            0b0111
-        };
+        // Follows RM Table 7. Number of wait states according to CPU clock (HCLK) frequency
+        config.flash_waitstates = flash_waitstates(hclk, self.voltage_range);
         // Adjust power state and overdrive mode
         // Configure follows by RM 4.1.4
         // Values getted from DS Table 16. General operating conditions
-        config.vos_scale = if sysclk <= 144_000_000 {
-            VOSscale::PwrScale3
-        } else if sysclk <= 168_000_000 {
-            VOSscale::PwrScale2
+        let (vos_scale, overdrive) = voltage_scaling(hclk, self.vos_scale, self.voltage_range);
+        config.vos_scale = vos_scale;
+        config.overdrive = overdrive;
+
+        let (plli2sq, plli2sr) = if self.use_plli2s {
+            let plli2sn_freq = base_clk / self.pllm as u64 * self.plli2sn as u64;
+            (
+                Some((plli2sn_freq as u32 / self.plli2sq as u32).Hz()),
+                Some((plli2sn_freq as u32 / self.plli2sr as u32).Hz()),
+            )
         } else {
-            VOSscale::PwrScale1
+            (None, None)
+        };
+
+        let pllsain_freq = base_clk / self.pllm as u64 * self.pllsain as u64;
+        let (pllsaip, pllsaiq) = if self.use_pllsai {
+            (
+                Some((pllsain_freq as u32 / self.pllsaip.divisor()).Hz()),
+                Some((pllsain_freq as u32 / self.pllsaiq as u32).Hz()),
+            )
+        } else {
+            (None, None)
+        };
+        #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+        let pllsair = if self.use_pllsai {
+            Some((pllsain_freq as u32 / self.pllsair as u32).Hz())
+        } else {
+            None
+        };
+
+        // The 48 MHz clock is only reported once selected, CK48MSEL being
+        // otherwise left as it is
+        let pll48clk = match self.pll48clk {
+            Some(PLL48CLK::Pllq) if self.use_pll => {
+                let pllq = base_clk * self.plln as u64 / self.pllm as u64 / self.pllq as u64;
+                Some((pllq as u32).Hz())
+            }
+            Some(PLL48CLK::Pllsai) => pllsaip,
+            _ => None,
+        };
+        // USB specification allow +-0.25%
+        let pll48clk_valid = match pll48clk {
+            Some(freq) => (48_000_000 - 120_000..=48_000_000 + 120_000).contains(&freq.raw()),
+            None => false,
         };
-        // For every frequency higher than 180 need to enable overdrive
-        // Follows by DS Table 16.
-        config.overdrive = sysclk > 180_000_000;
 
         let clocks = Clocks {
             hclk: hclk.Hz(),
@@ -765,10 +1169,19 @@ impl CFGR {
             sysclk: sysclk.Hz(),
             timclk1: timclk1.Hz(),
             timclk2: timclk2.Hz(),
+            pll48clk,
             pll48clk_valid,
             hse: self.hse.map(|hse| hse.freq),
             lse: self.lse.map(|lse| lse.freq),
             lsi: self.lsi,
+            plli2sq,
+            plli2sr,
+            pllsaiq,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsair,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsaidivr: self.pllsaidivr,
+            kernel_clocks: self.kernel_clocks,
         };
 
         (clocks, config)
@@ -852,6 +1265,21 @@ impl CFGR {
         Some((m, n, p, q))
     }
 
+    fn plli2s_configure(&mut self) {
+        if let Some(sample_rate) = self.plli2s_sample_rate {
+            let base_clk = match self.hse.as_ref() {
+                Some(hse) => hse.freq,
+                None => HSI_FREQUENCY,
+            }
+            .raw();
+
+            let (n, r) = plli2s_audio(base_clk / self.pllm as u32, sample_rate.raw())
+                .expect("No PLLI2S configuration for the sample rate");
+            self.plli2sn = n;
+            self.plli2sr = r;
+        }
+    }
+
     fn pll_configure(&mut self) {
         let base_clk = match self.hse.as_ref() {
             Some(hse) => hse.freq,
@@ -883,18 +1311,22 @@ impl CFGR {
             return;
         }
 
-        // We check if (pllm, plln, pllp) allow to obtain the requested Sysclk,
-        // so that we don't have to calculate them
+        // We check if (pllm, plln, pllp, pllq) allow to obtain the requested
+        // Sysclk and 48 MHz clock, so that we don't have to calculate them
+        let vco = base_clk as u64 * self.plln as u64 / self.pllm as u64;
         let p_ok = (sysclk as u64)
-            == (base_clk as u64 * self.plln as u64
-                / self.pllm as u64
+            == vco
                 / match self.pllp {
                     PLLP::Div2 => 2,
                     PLLP::Div4 => 4,
                     PLLP::Div6 => 6,
                     PLLP::Div8 => 8,
-                });
-        if p_ok && q.is_none() {
+                };
+        let q_ok = match q {
+            Some((min, max)) => (min as u64..=max as u64).contains(&(vco / self.pllq as u64)),
+            None => true,
+        };
+        if p_ok && q_ok {
             return;
         }
 
@@ -929,18 +1361,28 @@ impl CFGR {
     /// Configure the "mandatory" clocks (`sysclk`, `hclk`, `pclk1` and `pclk2')
     /// and return them via the `Clocks` struct.
     ///
-    /// The user shouldn't call freeze more than once as the clocks parameters
-    /// cannot be changed after the clocks have started.
+    /// The peripherals configured from the previous `Clocks` are not updated
+    /// if freeze is called again, [`CFGR::reconfigure`] should be used
+    /// instead.
     ///
     /// The implementation makes the following choice: HSI is always chosen over
     /// HSE except when HSE is provided. When HSE is provided, HSE is used
     /// wherever it is possible.
+    ///
+    /// Panics if an USART or an UART is clocked by the LSE, which isn't
+    /// enabled.
     pub fn freeze(mut self) -> Clocks {
         let flash = unsafe { &(*FLASH::ptr()) };
         let rcc = unsafe { &(*RCC::ptr()) };
         let pwr = unsafe { &(*PWR::ptr()) };
 
+        assert!(
+            self.lse.is_some() || !self.kernel_clocks.usart_on_lse(),
+            "The USART kernel clock needs the LSE"
+        );
+
         self.pll_configure();
+        self.plli2s_configure();
 
         let (clocks, config) = self.calculate_clocks();
 
@@ -951,11 +1393,27 @@ impl CFGR {
         while rcc.cr.read().hsirdy().bit_is_clear() {}
         // Switch to HSI
         rcc.cfgr.modify(|_, w| w.sw().hsi());
+        while !rcc.cfgr.read().sws().is_hsi() {}
+
+        // Leave the over-drive mode of a previous configuration, it is entered
+        // again below if needed
+        rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+        if pwr.cr1.read().oden().bit_is_set() {
+            pwr.cr1.modify(|_, w| w.odswen().clear_bit());
+            while pwr.csr1.read().odswrdy().bit_is_set() {}
+            pwr.cr1.modify(|_, w| w.oden().clear_bit());
+        }
 
         // Configure HSE if provided
-        if self.hse.is_some() {
+        if let Some(hse) = &self.hse {
+            // HSEBYP can only be written while the HSE is off
+            let bypass = hse.mode == HSEClockMode::Bypass;
+            if rcc.cr.read().hsebyp().bit() != bypass {
+                rcc.cr.modify(|_, w| w.hseon().off());
+                while rcc.cr.read().hserdy().is_ready() {}
+            }
             // Configure the HSE mode
-            match self.hse.as_ref().unwrap().mode {
+            match hse.mode {
                 HSEClockMode::Bypass => rcc.cr.modify(|_, w| w.hsebyp().bypassed()),
                 HSEClockMode::Oscillator => rcc.cr.modify(|_, w| w.hsebyp().not_bypassed()),
             }
@@ -980,6 +1438,30 @@ impl CFGR {
                 w.pllsrc().bit(self.hse.is_some())
             });
 
+            // The spread spectrum must be configured before the PLL is enabled
+            match self.spread_spectrum {
+                Some(spread_spectrum) => {
+                    let base_clk = match self.hse.as_ref() {
+                        Some(hse) => hse.freq,
+                        None => HSI_FREQUENCY,
+                    };
+                    let vco_input = base_clk.raw() / self.pllm as u32;
+                    let (modper, incstep) =
+                        spread_spectrum_steps(vco_input, self.plln, &spread_spectrum);
+                    rcc.sscgr.write(|w| {
+                        w.modper()
+                            .bits(modper)
+                            .incstep()
+                            .bits(incstep)
+                            .spreadsel()
+                            .bit(spread_spectrum.spread == Spread::Down)
+                            .sscgen()
+                            .set_bit()
+                    });
+                }
+                None => rcc.sscgr.reset(),
+            }
+
             // Enable PWR domain and setup VOSscale and Overdrive options
             rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
 
@@ -995,6 +1477,9 @@ impl CFGR {
             // Wait for PLL to stabilise
             while rcc.cr.read().pllrdy().is_not_ready() {}
 
+            // The voltage scale is applied once the PLL is on
+            while pwr.csr1.read().vosrdy().bit_is_clear() {}
+
             //Over-drive
             if config.overdrive {
                 // Entering Over-drive mode
@@ -1010,10 +1495,18 @@ impl CFGR {
                 //Wait for the ODSWRDY flag in the PWR_CSR1 to be set.
                 while !pwr.csr1.read().odswrdy().bit_is_set() {}
             }
+        } else {
+            // The PLL may be left on by a previous configuration
+            rcc.cr.modify(|_, w| w.pllon().off());
         }
 
         // Configure LSE if provided
         if self.lse.is_some() {
+            // The LSE is in the write protected backup domain
+            rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+            pwr.cr1.modify(|_, w| w.dbp().set_bit());
+            while pwr.cr1.read().dbp().bit_is_clear() {}
+
             // Configure the LSE mode
             match self.lse.as_ref().unwrap().mode {
                 LSEClockMode::Bypass => rcc.bdcr.modify(|_, w| w.lsebyp().bypassed()),
@@ -1034,27 +1527,33 @@ impl CFGR {
                 Some(hse) => hse.freq.raw() as u64 / self.pllm as u64 * self.pllsain as u64,
                 None => 16_000_000 / self.pllm as u64 * self.pllsain as u64,
             };
-            let pllsaip_freq = pllsain_freq
-                / match self.pllsaip {
-                    PLLSAIP::Div2 => 2,
-                    PLLSAIP::Div4 => 4,
-                    PLLSAIP::Div6 => 6,
-                    PLLSAIP::Div8 => 8,
-                };
+            let pllsaip_freq = pllsain_freq / self.pllsaip.divisor() as u64;
             // let pllsaiq_freq = pllsain_freq / self.pllsaiq as u64;
 
-            // The reference manual (RM0410 Rev 4, Page 212), says the following
-            // "Caution: The software has to set these bits correctly to ensure that the VCO output frequency is between 100 and 432 MHz.",
-            // but STM32CubeMX states 192 MHz as the minimum. SSo the stricter requirement was chosen.
-            assert!((192_000_000..=432_000_000).contains(&pllsain_freq));
+            let vco_range = u64::from(pll::VCO_MIN)..=u64::from(pll::VCO_MAX);
+            assert!(vco_range.contains(&pllsain_freq));
             assert!(pllsaip_freq <= 48_000_000);
 
+            // The PLLSAI can only be configured while it is off
+            rcc.cr.modify(|_, w| w.pllsaion().off());
+            while rcc.cr.read().pllsairdy().is_ready() {}
+
             rcc.pllsaicfgr.modify(|_, w| unsafe {
                 w.pllsain().bits(self.pllsain);
                 w.pllsaip().bits(self.pllsaip as u8);
+                #[cfg(not(any(
+                    feature = "svd-f7x2",
+                    feature = "svd-f7x3",
+                    feature = "svd-f730"
+                )))]
+                w.pllsair().bits(self.pllsair);
                 w.pllsaiq().bits(self.pllsaiq)
             });
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            rcc.dckcfgr1
+                .modify(|_, w| w.pllsaidivr().bits(self.pllsaidivr as u8));
             rcc.cr.modify(|_, w| w.pllsaion().on());
+            while rcc.cr.read().pllsairdy().is_not_ready() {}
         }
 
         if let Some(pll48clk) = self.pll48clk {
@@ -1072,9 +1571,14 @@ impl CFGR {
             let plli2sr_freq = plli2sn_freq / self.plli2sr as u64;
             let plli2sq_freq = plli2sn_freq / self.plli2sq as u64;
 
-            assert!((192_000_000..=432_000_000).contains(&plli2sn_freq));
-            assert!(plli2sr_freq <= 216_000_000);
-            assert!(plli2sq_freq <= 216_000_000);
+            let vco_range = u64::from(pll::VCO_MIN)..=u64::from(pll::VCO_MAX);
+            assert!(vco_range.contains(&plli2sn_freq));
+            assert!(plli2sr_freq <= u64::from(pll::OUTPUT_MAX));
+            assert!(plli2sq_freq <= u64::from(pll::OUTPUT_MAX));
+
+            // The PLLI2S can only be configured while it is off
+            rcc.cr.modify(|_, w| w.plli2son().off());
+            while rcc.cr.read().plli2srdy().is_ready() {}
 
             rcc.plli2scfgr.modify(|_, w| unsafe {
                 w.plli2sn().bits(self.plli2sn);
@@ -1082,6 +1586,7 @@ impl CFGR {
                 w.plli2sq().bits(self.plli2sq)
             });
             rcc.cr.modify(|_, w| w.plli2son().on());
+            while rcc.cr.read().plli2srdy().is_not_ready() {}
         }
 
         rcc.cfgr.modify(|_, w| {
@@ -1095,9 +1600,18 @@ impl CFGR {
                 .variant(self.mco2pre.into())
         });
 
-        flash
-            .acr
-            .write(|w| w.latency().bits(config.flash_waitstates));
+        // The ART accelerator must be disabled while it is reset
+        flash.acr.modify(|_, w| w.arten().clear_bit());
+        flash.acr.modify(|_, w| w.artrst().set_bit());
+        flash.acr.modify(|_, w| w.artrst().clear_bit());
+        flash.acr.write(|w| {
+            w.latency()
+                .bits(config.flash_waitstates)
+                .prften()
+                .bit(self.prefetch)
+                .arten()
+                .bit(self.art_accelerator)
+        });
 
         // Configure HCLK, PCLK1, PCLK2
         rcc.cfgr.modify(|_, w| unsafe {
@@ -1108,6 +1622,9 @@ impl CFGR {
                 .hpre()
                 .bits(config.hpre)
         });
+        // The timers are clocked at twice the APB frequency, as computed by
+        // `calculate_clocks`
+        rcc.dckcfgr1.modify(|_, w| w.timpre().clear_bit());
 
         // Select SYSCLK source
         if self.use_pll {
@@ -1125,10 +1642,140 @@ impl CFGR {
         // predivision is applied
         cortex_m::asm::delay(16);
 
+        self.kernel_clocks.apply(rcc);
+
+        if self.hse_css {
+            assert!(self.hse.is_some(), "The CSS needs the HSE");
+            rcc.cr.modify(|_, w| w.csson().on());
+        }
+
+        clocks
+    }
+
+    /// Configures the clocks again after a first `freeze`, then updates
+    /// `peripherals` for the new clocks, for instance to lower the frequencies
+    /// while the application is idle.
+    ///
+    /// The system clock runs from the HSI while the PLL is configured again.
+    /// The peripherals must be idle, as they are clocked by the new
+    /// frequencies before being updated. The HSE, and the PLLSAI and PLLI2S
+    /// not used by the new configuration are kept on.
+    ///
+    /// ```ignore
+    /// let low_power = rcc.cfgr.clone().sysclk(16.MHz());
+    /// let clocks = rcc.cfgr.sysclk(216.MHz()).freeze();
+    /// let mut serial = Serial::new(dp.USART1, pins, &clocks, Config::default());
+    /// // ...
+    /// let clocks = low_power.reconfigure(&mut [&mut serial]);
+    /// ```
+    pub fn reconfigure(self, peripherals: &mut [&mut dyn UpdateClocks]) -> Clocks {
+        let clocks = self.freeze();
+        for peripheral in peripherals.iter_mut() {
+            peripheral.update_clocks(&clocks);
+        }
         clocks
     }
 }
 
+/// Peripheral configured from the clock frequencies
+pub trait UpdateClocks {
+    /// Configures the peripheral again for the new `clocks`.
+    fn update_clocks(&mut self, clocks: &Clocks);
+}
+
+/// Returns the voltage scale, `vos_scale` or the lowest one supporting `hclk`,
+/// and whether the over-drive is needed.
+///
+/// # Panics
+///
+/// Panics if `hclk` is too high for `vos_scale`, or for `voltage_range`.
+fn voltage_scaling(
+    hclk: u32,
+    vos_scale: Option<VOSscale>,
+    voltage_range: VoltageRange,
+) -> (VOSscale, bool) {
+    // The over-drive isn't available below 2.1 V
+    let max_hclk = match voltage_range {
+        VoltageRange::V1_8 => 180_000_000,
+        _ => 216_000_000,
+    };
+    assert!(
+        hclk <= max_hclk,
+        "HCLK {} Hz is too high for {:?}",
+        hclk,
+        voltage_range
+    );
+
+    let vos_scale = vos_scale.unwrap_or(if hclk <= 144_000_000 {
+        VOSscale::PwrScale3
+    } else if hclk <= 168_000_000 {
+        VOSscale::PwrScale2
+    } else {
+        VOSscale::PwrScale1
+    });
+    assert!(
+        hclk <= vos_scale.max_hclk(true),
+        "HCLK {} Hz is too high for {:?}",
+        hclk,
+        vos_scale
+    );
+
+    (vos_scale, hclk > vos_scale.max_hclk(false))
+}
+
+/// Returns the MODPER and INCSTEP values of the spread spectrum modulation,
+/// from the `vco_input` frequency and the PLLN factor.
+///
+/// # Panics
+///
+/// Panics if the modulation frequency or depth is too high, or if the values
+/// don't fit in SSCGR.
+fn spread_spectrum_steps(
+    vco_input: u32,
+    plln: u16,
+    spread_spectrum: &SpreadSpectrum,
+) -> (u16, u16) {
+    let frequency = spread_spectrum.frequency.raw();
+    assert!((1..=10_000).contains(&frequency));
+    assert!(spread_spectrum.depth <= 200);
+
+    // MODPER = round(f_PLL_IN / (4 * f_mod))
+    let modper = (vco_input + 2 * frequency) / (4 * frequency);
+    // INCSTEP = round((2^15 - 1) * md * PLLN / (100 * 5 * MODPER)), md in %
+    let divisor = 100 * 5 * modper as u64 * 100;
+    let incstep =
+        (0x7fff * u64::from(spread_spectrum.depth) * u64::from(plln) + divisor / 2) / divisor;
+
+    assert!(
+        modper <= 0x1fff,
+        "Spread spectrum modulation frequency too low"
+    );
+    assert!(
+        modper as u64 * incstep <= 0x7fff,
+        "Spread spectrum modulation depth too high"
+    );
+
+    (modper as u16, incstep as u16)
+}
+
+/// Returns the number of flash wait states for `hclk` in `voltage_range`.
+fn flash_waitstates(hclk: u32, voltage_range: VoltageRange) -> u8 {
+    (hclk.saturating_sub(1) / voltage_range.frequency_per_wait_state()) as u8
+}
+
+/// Returns the PLLI2SN and PLLI2SR factors giving, from the `vco_input`
+/// frequency, the I2S clock the closest to a multiple of 256 times
+/// `sample_rate` reachable by the I2S prescaler, or `None` if none is within
+/// 1 %.
+fn plli2s_audio(vco_input: u32, sample_rate: u32) -> Option<(u16, u8)> {
+    let mclk = 256 * sample_rate;
+    // The I2S prescaler divides by 2 * I2SDIV + ODD, from 4 to 511
+    pll::search(vco_input, 2..=7, mclk, 10_000, |i2s_clk| {
+        Some((((i2s_clk + mclk / 2) / mclk).clamp(4, 511), ()))
+    })
+    .map(|found| (found.n, found.div))
+}
+
 /// Frozen clock frequencies
 ///
 /// The existence of this value indicates that the clock configuration can no longer be changed
@@ -1140,10 +1787,19 @@ pub struct Clocks {
     sysclk: Hertz,
     timclk1: Hertz,
     timclk2: Hertz,
+    pll48clk: Option<Hertz>,
     pll48clk_valid: bool,
     hse: Option<Hertz>,
     lse: Option<Hertz>,
     lsi: Option<Hertz>,
+    plli2sq: Option<Hertz>,
+    plli2sr: Option<Hertz>,
+    pllsaiq: Option<Hertz>,
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    pllsair: Option<Hertz>,
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    pllsaidivr: PLLSAIDIVR,
+    kernel_clocks: KernelClocks,
 }
 
 impl Clocks {
@@ -1180,10 +1836,15 @@ impl Clocks {
     /// Returns true if the PLL48 clock is within USB
     /// specifications. It is required to use the USB functionality.
     pub fn is_pll48clk_valid(&self) -> bool {
-        // USB specification allow +-0.25%
         self.pll48clk_valid
     }
 
+    /// Returns the frequency of the 48 MHz clock (CK48M) of the USB, SDMMC
+    /// and RNG, if it was selected with `use_pll48clk` and its PLL is enabled.
+    pub fn pll48clk(&self) -> Option<Hertz> {
+        self.pll48clk
+    }
+
     /// Returns the frequency of the `HSE` if `Some`, else `None`.
     pub fn hse(&self) -> Option<Hertz> {
         self.hse
@@ -1198,6 +1859,68 @@ impl Clocks {
     pub fn lsi(&self) -> Option<Hertz> {
         self.lsi
     }
+
+    /// Returns the frequency of the PLLI2S Q output, which can clock the SAI,
+    /// if the PLLI2S is enabled.
+    pub fn plli2sq(&self) -> Option<Hertz> {
+        self.plli2sq
+    }
+
+    /// Returns the frequency of the PLLI2S R output, which clocks the I2S, if
+    /// the PLLI2S is enabled.
+    pub fn plli2sr(&self) -> Option<Hertz> {
+        self.plli2sr
+    }
+
+    /// Returns the frequency of the PLLSAI Q output, which can clock the SAI,
+    /// if the PLLSAI is enabled.
+    pub fn pllsaiq(&self) -> Option<Hertz> {
+        self.pllsaiq
+    }
+
+    /// Returns the frequency of the PLLSAI R output if the PLLSAI is enabled.
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    pub fn pllsair(&self) -> Option<Hertz> {
+        self.pllsair
+    }
+
+    /// Returns the frequency of the LCD-TFT clock, the PLLSAI R output divided
+    /// by PLLSAIDIVR, if the PLLSAI is enabled.
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    pub fn ltdc_clock(&self) -> Option<Hertz> {
+        self.pllsair
+            .map(|pllsair| pllsair / self.pllsaidivr.divisor())
+    }
+
+    /// Returns the frequency of the timers of the bus of `T`, twice the bus
+    /// frequency when the APB prescaler isn't 1.
+    pub fn timer_clock<T: BusTimerClock>(&self) -> Hertz {
+        T::timer_clock(self)
+    }
+
+    /// Returns the kernel clock frequency of the SDMMC1, or `None` if the
+    /// 48 MHz clock is selected but not enabled.
+    pub fn sdmmc1_clock(&self) -> Option<Hertz> {
+        self.kernel_clocks.sdmmc1.frequency(self)
+    }
+
+    /// Returns the kernel clock frequency of the SDMMC2, or `None` if the
+    /// 48 MHz clock is selected but not enabled.
+    #[cfg(not(any(feature = "svd-f745", feature = "svd-f7x6")))]
+    pub fn sdmmc2_clock(&self) -> Option<Hertz> {
+        self.kernel_clocks.sdmmc2.frequency(self)
+    }
+
+    /// Returns the frequency of the I2S clock, or `None` if the PLLI2S is
+    /// selected but not enabled.
+    pub fn i2s_clock(&self) -> Option<Hertz> {
+        self.kernel_clocks.i2s.frequency(self)
+    }
+
+    /// Returns the kernel clocks selected for the peripherals
+    pub fn kernel_clocks(&self) -> &KernelClocks {
+        &self.kernel_clocks
+    }
 }
 
 /// Frequency on bus that peripheral is connected in
@@ -1339,28 +2062,33 @@ pub trait Enable: RccBus {
     unsafe fn disable_unchecked();
 }
 
-/// Enable/disable peripheral in low power mode
+/// Enable/disable peripheral clock in Sleep mode
+///
+/// The clock of an enabled peripheral keeps running in Sleep mode unless it
+/// is disabled here, in the xxxLPENR registers. All of them are enabled on
+/// reset. The setting has no effect on a peripheral disabled by [`Enable`],
+/// and the peripheral is clocked again on wakeup.
 pub trait LPEnable: RccBus {
-    /// Enables peripheral
+    /// Keeps the peripheral clocked in Sleep mode
     fn low_power_enable(bus: &mut Self::Bus);
 
-    /// Disables peripheral
+    /// Stops the peripheral clock in Sleep mode
     fn low_power_disable(bus: &mut Self::Bus);
 
-    /// Check if peripheral enabled
+    /// Check if peripheral clocked in Sleep mode
     fn is_low_power_enabled() -> bool;
 
-    /// Check if peripheral disabled
+    /// Check if peripheral clock stopped in Sleep mode
     fn is_low_power_disabled() -> bool;
 
     /// # Safety
     ///
-    /// Enables peripheral. Takes access to RCC internally
+    /// Keeps the peripheral clocked in Sleep mode. Takes access to RCC internally
     unsafe fn low_power_enable_unchecked();
 
     /// # Safety
     ///
-    /// Disables peripheral. Takes access to RCC internally
+    /// Stops the peripheral clock in Sleep mode. Takes access to RCC internally
     unsafe fn low_power_disable_unchecked();
 }
 
@@ -1379,7 +2107,10 @@ pub trait Reset: RccBus {
 mod tests {
     use fugit::{HertzU32 as Hertz, RateExtU32};
 
-    use super::{FreqRequest, CFGR};
+    use super::{
+        flash_waitstates, plli2s_audio, spread_spectrum_steps, voltage_scaling, FreqRequest,
+        Spread, SpreadSpectrum, VOSscale, VoltageRange, CFGR,
+    };
 
     fn build_request(sysclk: u32, use_pll48clk: bool) -> FreqRequest {
         let p = Some((sysclk - 1, sysclk + 1));
@@ -1448,6 +2179,84 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_voltage_scaling() {
+        let range = VoltageRange::V2_7;
+        assert_eq!(
+            voltage_scaling(216_000_000, None, range),
+            (VOSscale::PwrScale1, true)
+        );
+        assert_eq!(
+            voltage_scaling(180_000_000, None, range),
+            (VOSscale::PwrScale1, false)
+        );
+        assert_eq!(
+            voltage_scaling(168_000_000, None, range),
+            (VOSscale::PwrScale2, false)
+        );
+        assert_eq!(
+            voltage_scaling(180_000_000, Some(VOSscale::PwrScale2), range),
+            (VOSscale::PwrScale2, true)
+        );
+        assert_eq!(
+            voltage_scaling(100_000_000, None, range),
+            (VOSscale::PwrScale3, false)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_voltage_scaling_out_of_spec() {
+        voltage_scaling(168_000_000, Some(VOSscale::PwrScale3), VoltageRange::V2_7);
+    }
+
+    #[test]
+    fn test_flash_waitstates() {
+        assert_eq!(flash_waitstates(16_000_000, VoltageRange::V2_7), 0);
+        assert_eq!(flash_waitstates(30_000_000, VoltageRange::V2_7), 0);
+        assert_eq!(flash_waitstates(30_000_001, VoltageRange::V2_7), 1);
+        assert_eq!(flash_waitstates(216_000_000, VoltageRange::V2_7), 7);
+        assert_eq!(flash_waitstates(168_000_000, VoltageRange::V2_4), 6);
+        assert_eq!(flash_waitstates(180_000_000, VoltageRange::V2_1), 8);
+        assert_eq!(flash_waitstates(180_000_000, VoltageRange::V1_8), 8);
+    }
+
+    #[test]
+    fn test_spread_spectrum_steps() {
+        let spread_spectrum = SpreadSpectrum {
+            frequency: 1.kHz(),
+            depth: 200,
+            spread: Spread::Center,
+        };
+        assert_eq!(
+            spread_spectrum_steps(1_000_000, 240, &spread_spectrum),
+            (250, 126)
+        );
+    }
+
+    fn check_plli2s(vco_input: u32, sample_rate: u32) {
+        let (n, r) = plli2s_audio(vco_input, sample_rate).unwrap();
+        let i2s_clk = u64::from(vco_input) * u64::from(n) / u64::from(r);
+        let mclk = 256 * u64::from(sample_rate);
+        let div = (i2s_clk + mclk / 2) / mclk;
+        let target = div * mclk;
+        let error_ppm = (i2s_clk.max(target) - i2s_clk.min(target)) * 1_000_000 / target;
+
+        assert!((4..=511).contains(&div));
+        assert!(error_ppm < 500, "{} Hz: {} ppm", sample_rate, error_ppm);
+    }
+
+    #[test]
+    fn test_plli2s_audio() {
+        for sample_rate in [8_000, 16_000, 32_000, 44_100, 48_000, 96_000] {
+            check_plli2s(1_000_000, sample_rate);
+            check_plli2s(2_000_000, sample_rate);
+        }
+
+        // Above the reach of the I2S prescaler
+        assert_eq!(plli2s_audio(1_000_000, 1_000_000), None);
+    }
+
     #[test]
     fn test_pll_calc1() {
         check(25_000_000, 48_000_000, false);
@@ -1480,7 +2289,9 @@ mod tests {
 
     #[test]
     fn test_rcc_calc1() {
-        use super::{HSEClock, HSEClockMode, MCO1, MCO2, MCOPRE, PLL48CLK, PLLP, PLLSAIP};
+        use super::{
+            HSEClock, HSEClockMode, VoltageRange, MCO1, MCO2, MCOPRE, PLL48CLK, PLLP, PLLSAIP,
+        };
 
         let cfgr = CFGR {
             hse: None,
@@ -1500,14 +2311,26 @@ mod tests {
             pllsain: 192,
             pllsaip: PLLSAIP::Div2,
             pllsaiq: 2,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsair: 2,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsaidivr: super::PLLSAIDIVR::Div2,
             use_plli2s: false,
             plli2sr: 2,
             plli2sq: 2,
             plli2sn: 192,
+            plli2s_sample_rate: None,
             mco1: MCO1::Hsi,
             mco1pre: MCOPRE::Div1_no_div,
             mco2: MCO2::Sysclk,
             mco2pre: MCOPRE::Div1_no_div,
+            voltage_range: VoltageRange::V2_7,
+            vos_scale: None,
+            art_accelerator: true,
+            prefetch: true,
+            hse_css: false,
+            kernel_clocks: super::KernelClocks::default(),
+            spread_spectrum: None,
         };
 
         let mut cfgr = cfgr
@@ -1526,7 +2349,9 @@ mod tests {
 
     #[test]
     fn test_rcc_calc2() {
-        use super::{HSEClock, HSEClockMode, MCO1, MCO2, MCOPRE, PLL48CLK, PLLP, PLLSAIP};
+        use super::{
+            HSEClock, HSEClockMode, VoltageRange, MCO1, MCO2, MCOPRE, PLL48CLK, PLLP, PLLSAIP,
+        };
 
         let cfgr = CFGR {
             hse: None,
@@ -1546,14 +2371,26 @@ mod tests {
             pllsain: 192,
             pllsaip: PLLSAIP::Div2,
             pllsaiq: 2,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsair: 2,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsaidivr: super::PLLSAIDIVR::Div2,
             use_plli2s: false,
             plli2sr: 2,
             plli2sq: 2,
             plli2sn: 192,
+            plli2s_sample_rate: None,
             mco1: MCO1::Hsi,
             mco1pre: MCOPRE::Div1_no_div,
             mco2: MCO2::Sysclk,
             mco2pre: MCOPRE::Div1_no_div,
+            voltage_range: VoltageRange::V2_7,
+            vos_scale: None,
+            art_accelerator: true,
+            prefetch: true,
+            hse_css: false,
+            kernel_clocks: super::KernelClocks::default(),
+            spread_spectrum: None,
         };
 
         let mut cfgr = cfgr
@@ -1571,7 +2408,9 @@ mod tests {
 
     #[test]
     fn test_rcc_calc3() {
-        use super::{HSEClock, HSEClockMode, MCO1, MCO2, MCOPRE, PLL48CLK, PLLP, PLLSAIP};
+        use super::{
+            HSEClock, HSEClockMode, VoltageRange, MCO1, MCO2, MCOPRE, PLL48CLK, PLLP, PLLSAIP,
+        };
 
         let cfgr = CFGR {
             hse: None,
@@ -1591,14 +2430,26 @@ mod tests {
             pllsain: 192,
             pllsaip: PLLSAIP::Div2,
             pllsaiq: 2,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsair: 2,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsaidivr: super::PLLSAIDIVR::Div2,
             use_plli2s: false,
             plli2sr: 2,
             plli2sq: 2,
             plli2sn: 192,
+            plli2s_sample_rate: None,
             mco1: MCO1::Hsi,
             mco1pre: MCOPRE::Div1_no_div,
             mco2: MCO2::Sysclk,
             mco2pre: MCOPRE::Div1_no_div,
+            voltage_range: VoltageRange::V2_7,
+            vos_scale: None,
+            art_accelerator: true,
+            prefetch: true,
+            hse_css: false,
+            kernel_clocks: super::KernelClocks::default(),
+            spread_spectrum: None,
         };
 
         let mut cfgr = cfgr
@@ -1612,11 +2463,15 @@ mod tests {
         let (clocks, _config) = cfgr.calculate_clocks();
         assert_eq!(clocks.sysclk().raw(), 216_000_000);
         assert!(clocks.is_pll48clk_valid());
+        assert_eq!(clocks.pll48clk(), Some(48.MHz()));
+        assert_eq!(clocks.pclk1().raw(), 54_000_000);
+        assert_eq!(clocks.timclk1().raw(), 108_000_000);
+        assert_eq!(clocks.pllsaiq(), None);
     }
 
     #[test]
     fn test_rcc_default() {
-        use super::{MCO1, MCO2, MCOPRE, PLLP, PLLSAIP};
+        use super::{VoltageRange, MCO1, MCO2, MCOPRE, PLLP, PLLSAIP};
 
         let mut cfgr = CFGR {
             hse: None,
@@ -1636,19 +2491,87 @@ mod tests {
             pllsain: 192,
             pllsaip: PLLSAIP::Div2,
             pllsaiq: 2,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsair: 2,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsaidivr: super::PLLSAIDIVR::Div2,
             use_plli2s: false,
             plli2sr: 2,
             plli2sq: 2,
             plli2sn: 192,
+            plli2s_sample_rate: None,
             mco1: MCO1::Hsi,
             mco1pre: MCOPRE::Div1_no_div,
             mco2: MCO2::Sysclk,
             mco2pre: MCOPRE::Div1_no_div,
+            voltage_range: VoltageRange::V2_7,
+            vos_scale: None,
+            art_accelerator: true,
+            prefetch: true,
+            hse_css: false,
+            kernel_clocks: super::KernelClocks::default(),
+            spread_spectrum: None,
         };
 
         cfgr.pll_configure();
         assert!(!cfgr.use_pll);
         let (clocks, _config) = cfgr.calculate_clocks();
         assert_eq!(clocks.sysclk().raw(), 16_000_000);
+        assert_eq!(clocks.timclk1().raw(), 16_000_000);
+        assert_eq!(clocks.pll48clk(), None);
+        assert!(!clocks.is_pll48clk_valid());
+    }
+
+    #[test]
+    fn test_rcc_pllsai() {
+        use super::{VoltageRange, MCO1, MCO2, MCOPRE, PLL48CLK, PLLP, PLLSAIP};
+
+        let cfgr = CFGR {
+            hse: None,
+            hclk: None,
+            sysclk: None,
+            pclk1: None,
+            pclk2: None,
+            lse: None,
+            lsi: None,
+            use_pll: false,
+            pll48clk: None,
+            pllm: 16,
+            plln: 50,
+            pllp: PLLP::Div2,
+            pllq: 2,
+            use_pllsai: false,
+            pllsain: 192,
+            pllsaip: PLLSAIP::Div4,
+            pllsaiq: 4,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsair: 4,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            pllsaidivr: super::PLLSAIDIVR::Div8,
+            use_plli2s: false,
+            plli2sr: 2,
+            plli2sq: 2,
+            plli2sn: 192,
+            plli2s_sample_rate: None,
+            mco1: MCO1::Hsi,
+            mco1pre: MCOPRE::Div1_no_div,
+            mco2: MCO2::Sysclk,
+            mco2pre: MCOPRE::Div1_no_div,
+            voltage_range: VoltageRange::V2_7,
+            vos_scale: None,
+            art_accelerator: true,
+            prefetch: true,
+            hse_css: false,
+            kernel_clocks: super::KernelClocks::default(),
+            spread_spectrum: None,
+        };
+
+        let cfgr = cfgr.use_pllsai().use_pll48clk(PLL48CLK::Pllsai);
+        let (clocks, _config) = cfgr.calculate_clocks();
+        assert_eq!(clocks.pll48clk(), Some(48.MHz()));
+        assert!(clocks.is_pll48clk_valid());
+        assert_eq!(clocks.pllsaiq(), Some(48.MHz()));
+        #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+        assert_eq!(clocks.ltdc_clock(), Some(6.MHz()));
     }
 }
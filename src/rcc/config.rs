@@ -0,0 +1,212 @@
+//! Clock configuration checked at compile time
+//!
+//! The factors of the main PLL and the bus prescalers are checked by `const`
+//! functions, so an invalid clock tree assigned to a `const` item fails to
+//! build instead of panicking in `freeze`:
+//!
+//! ```ignore
+//! use stm32f7xx_hal::rcc::{BusConfig, HSEClock, HSEClockMode, PllConfig, PLLP};
+//!
+//! const PLL: PllConfig = PllConfig::new(Hertz::MHz(25), 25, 432, PLLP::Div2, 9);
+//! const BUSES: BusConfig = BusConfig::new(PLL.sysclk(), 1, 4, 2);
+//!
+//! let clocks = rcc
+//!     .cfgr
+//!     .hse(HSEClock::new(25.MHz(), HSEClockMode::Bypass))
+//!     .pll(PLL)
+//!     .buses(BUSES)
+//!     .freeze();
+//! ```
+//!
+//! The supply voltage range and the over-drive are only known at runtime,
+//! and still checked by `freeze`.
+
+use super::pll::{VCO_MAX, VCO_MIN};
+use super::PLLP;
+use fugit::HertzU32 as Hertz;
+
+/// Configuration of the main PLL
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PllConfig {
+    pub(super) input: Hertz,
+    pub(super) m: u8,
+    pub(super) n: u16,
+    pub(super) p: PLLP,
+    pub(super) q: u8,
+}
+
+impl PllConfig {
+    /// Checks the PLL factors for the `input` frequency, of the HSE or the
+    /// HSI.
+    ///
+    /// # Panics
+    ///
+    /// Panics, at compile time in a `const` context, if a factor is out of
+    /// range, if the VCO input isn't between 1 and 2 MHz or its output
+    /// between 192 and 432 MHz, if SYSCLK is above 216 MHz or if the Q output
+    /// is above 48 MHz.
+    pub const fn new(input: Hertz, m: u8, n: u16, p: PLLP, q: u8) -> Self {
+        assert!(m >= 2 && m <= 63, "PLLM must be between 2 and 63");
+        assert!(n >= 50 && n <= 432, "PLLN must be between 50 and 432");
+        assert!(q >= 2 && q <= 15, "PLLQ must be between 2 and 15");
+
+        let vco_input = input.raw() / m as u32;
+        assert!(
+            vco_input >= 1_000_000 && vco_input <= 2_000_000,
+            "The VCO input must be between 1 and 2 MHz"
+        );
+        let vco = vco_input as u64 * n as u64;
+        assert!(
+            vco >= VCO_MIN as u64 && vco <= VCO_MAX as u64,
+            "The VCO output must be between 192 and 432 MHz"
+        );
+        assert!(
+            vco / pllp_divisor(p) <= 216_000_000,
+            "SYSCLK must be at most 216 MHz"
+        );
+        // With the tolerance of the USB
+        assert!(
+            vco / q as u64 <= 48_120_000,
+            "The PLL Q output must be at most 48 MHz"
+        );
+
+        Self { input, m, n, p, q }
+    }
+
+    /// Returns the frequency of the VCO output
+    pub const fn vco(&self) -> Hertz {
+        Hertz::from_raw(self.input.raw() / self.m as u32 * self.n as u32)
+    }
+
+    /// Returns the frequency of SYSCLK, from the P output
+    pub const fn sysclk(&self) -> Hertz {
+        Hertz::from_raw((self.vco().raw() as u64 / pllp_divisor(self.p)) as u32)
+    }
+
+    /// Returns the frequency of the Q output, which can clock the 48 MHz
+    /// clock
+    pub const fn pllq(&self) -> Hertz {
+        Hertz::from_raw(self.vco().raw() / self.q as u32)
+    }
+}
+
+/// Prescalers of the AHB and of the APBs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusConfig {
+    hclk: Hertz,
+    pclk1: Hertz,
+    pclk2: Hertz,
+}
+
+impl BusConfig {
+    /// Checks the AHB prescaler `hpre` and the APB prescalers `ppre1` and
+    /// `ppre2` for the `sysclk` frequency.
+    ///
+    /// # Panics
+    ///
+    /// Panics, at compile time in a `const` context, if a prescaler isn't
+    /// available, if SYSCLK is above 216 MHz, or if PCLK1 or PCLK2 is above
+    /// 54 or 108 MHz, 45 or 90 MHz with HCLK up to 180 MHz.
+    pub const fn new(sysclk: Hertz, hpre: u16, ppre1: u8, ppre2: u8) -> Self {
+        assert!(
+            matches!(hpre, 1 | 2 | 4 | 8 | 16 | 64 | 128 | 256 | 512),
+            "HPRE must be 1, 2, 4, 8, 16, 64, 128, 256 or 512"
+        );
+        assert!(
+            matches!(ppre1, 1 | 2 | 4 | 8 | 16),
+            "PPRE1 must be 1, 2, 4, 8 or 16"
+        );
+        assert!(
+            matches!(ppre2, 1 | 2 | 4 | 8 | 16),
+            "PPRE2 must be 1, 2, 4, 8 or 16"
+        );
+
+        let sysclk = sysclk.raw();
+        assert!(sysclk <= 216_000_000, "SYSCLK must be at most 216 MHz");
+
+        let hclk = sysclk / hpre as u32;
+        // The over-drive, raising the limits, is only entered for HCLK above
+        // 180 MHz
+        let (max_pclk1, max_pclk2) = if hclk <= 180_000_000 {
+            (45_000_000, 90_000_000)
+        } else {
+            (54_000_000, 108_000_000)
+        };
+        let pclk1 = hclk / ppre1 as u32;
+        let pclk2 = hclk / ppre2 as u32;
+        assert!(pclk1 <= max_pclk1, "PCLK1 is too high");
+        assert!(pclk2 <= max_pclk2, "PCLK2 is too high");
+
+        Self {
+            hclk: Hertz::from_raw(hclk),
+            pclk1: Hertz::from_raw(pclk1),
+            pclk2: Hertz::from_raw(pclk2),
+        }
+    }
+
+    /// Returns the frequency of the AHB
+    pub const fn hclk(&self) -> Hertz {
+        self.hclk
+    }
+
+    /// Returns the frequency of the APB1
+    pub const fn pclk1(&self) -> Hertz {
+        self.pclk1
+    }
+
+    /// Returns the frequency of the APB2
+    pub const fn pclk2(&self) -> Hertz {
+        self.pclk2
+    }
+}
+
+const fn pllp_divisor(p: PLLP) -> u64 {
+    match p {
+        PLLP::Div2 => 2,
+        PLLP::Div4 => 4,
+        PLLP::Div6 => 6,
+        PLLP::Div8 => 8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BusConfig, PllConfig, PLLP};
+    use fugit::HertzU32 as Hertz;
+
+    const PLL: PllConfig = PllConfig::new(Hertz::MHz(25), 25, 432, PLLP::Div2, 9);
+    const BUSES: BusConfig = BusConfig::new(PLL.sysclk(), 1, 4, 2);
+
+    #[test]
+    fn test_const_config() {
+        assert_eq!(PLL.sysclk(), Hertz::MHz(216));
+        assert_eq!(PLL.pllq(), Hertz::MHz(48));
+        assert_eq!(BUSES.pclk1(), Hertz::MHz(54));
+        assert_eq!(BUSES.pclk2(), Hertz::MHz(108));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_const_config_vco_input() {
+        PllConfig::new(Hertz::MHz(25), 5, 100, PLLP::Div2, 9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_const_config_pclk1() {
+        BusConfig::new(Hertz::MHz(216), 1, 2, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_const_config_vco_output() {
+        PllConfig::new(Hertz::MHz(25), 25, 150, PLLP::Div2, 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_const_config_pclk1_hclk() {
+        // HCLK at 108 MHz runs without the over-drive
+        BusConfig::new(Hertz::MHz(216), 2, 2, 1);
+    }
+}
@@ -0,0 +1,64 @@
+//! Clock security system (CSS)
+//!
+//! Once enabled with [`CFGR::hse_css`](super::CFGR::hse_css), the CSS detects
+//! a failure of the HSE: the HSE is stopped, the system clock switches to the
+//! HSI if it was clocked by the HSE directly or through the PLL, and a
+//! non-maskable interrupt is raised.
+//!
+//! After a failure the prescalers are unchanged, so the bus clocks are
+//! derived from the 16 MHz of the HSI instead of the frequencies returned by
+//! `Clocks`. The devices have no clock security system for the LSE.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::pac::RCC;
+
+/// Address of the function called on a HSE failure, or 0
+static CSS_HANDLER: AtomicUsize = AtomicUsize::new(0);
+static HSE_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Sets the function called by [`handle_css_interrupt`] on a HSE failure,
+/// for instance to reconfigure the peripherals for the HSI clock.
+///
+/// The function runs in the NMI handler: it must not wait for a resource
+/// locked by a critical section, which doesn't mask the NMI.
+pub fn set_css_handler(handler: fn()) {
+    CSS_HANDLER.store(handler as usize, Ordering::Release);
+}
+
+/// Clears the CSS flag and calls the function set by [`set_css_handler`], if
+/// the NMI was raised by a HSE failure.
+///
+/// Returns `true` if the HSE failed. It must be called from the NMI handler,
+/// which is otherwise called again while the CSS flag is set:
+///
+/// ```ignore
+/// #[exception]
+/// fn NonMaskableInt() {
+///     rcc::handle_css_interrupt();
+/// }
+/// ```
+pub fn handle_css_interrupt() -> bool {
+    // NOTE(unsafe) atomic read of the flag, and write to the clear register
+    let rcc = unsafe { &*RCC::ptr() };
+    if rcc.cir.read().cssf().bit_is_clear() {
+        return false;
+    }
+    rcc.cir.modify(|_, w| w.cssc().clear());
+    HSE_FAILED.store(true, Ordering::Release);
+
+    let handler = CSS_HANDLER.load(Ordering::Acquire);
+    if handler != 0 {
+        // NOTE(unsafe) the address was stored from a `fn()` by `set_css_handler`
+        let handler: fn() = unsafe { core::mem::transmute(handler) };
+        handler();
+    }
+
+    true
+}
+
+/// Returns `true` if the HSE failed, the system clock then running from the
+/// HSI.
+pub fn is_hse_failed() -> bool {
+    HSE_FAILED.load(Ordering::Acquire)
+}
@@ -39,8 +39,6 @@ macro_rules! bus_lpenable {
             #[inline(always)]
             fn low_power_enable(bus: &mut Self::Bus) {
                 bus.lpenr().modify(|_, w| w.$lpen().set_bit());
-                // Stall the pipeline to work around erratum 2.1.13 (DM00037591)
-                cortex_m::asm::dsb();
             }
             #[inline(always)]
             fn low_power_disable(bus: &mut Self::Bus) {
@@ -56,11 +54,11 @@ macro_rules! bus_lpenable {
             }
             #[inline(always)]
             unsafe fn low_power_enable_unchecked() {
-                Self::enable(&mut Self::Bus::new());
+                Self::low_power_enable(&mut Self::Bus::new());
             }
             #[inline(always)]
             unsafe fn low_power_disable_unchecked() {
-                Self::disable(&mut Self::Bus::new());
+                Self::low_power_disable(&mut Self::Bus::new());
             }
         }
     };
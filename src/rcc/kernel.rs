@@ -0,0 +1,289 @@
+//! Kernel clocks of the peripherals
+//!
+//! Some peripherals are clocked independently of their bus, by the kernel
+//! clock selected in DCKCFGR2, or in CFGR for the I2S. The selection is set
+//! with [`CFGR::kernel_clocks`](super::CFGR::kernel_clocks), and returned
+//! with the resulting frequencies by [`Clocks`].
+//!
+//! The 48 MHz clock (CK48M) is selected with
+//! [`CFGR::use_pll48clk`](super::CFGR::use_pll48clk), and the SAI kernel
+//! clock by `Sai::new`.
+
+use super::{Clocks, HSI_FREQUENCY};
+use crate::pac::rcc;
+use fugit::HertzU32 as Hertz;
+
+/// Kernel clock of an USART or an UART
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsartClock {
+    /// Clock of the APB of the peripheral
+    Apb,
+    Sysclk,
+    Hsi,
+    Lse,
+}
+
+impl UsartClock {
+    /// Returns the frequency of the kernel clock, `pclk` being the clock of
+    /// the APB of the peripheral, or `None` if the LSE is off.
+    pub fn frequency(self, clocks: &Clocks, pclk: Hertz) -> Option<Hertz> {
+        match self {
+            UsartClock::Apb => Some(pclk),
+            UsartClock::Sysclk => Some(clocks.sysclk()),
+            UsartClock::Hsi => Some(HSI_FREQUENCY),
+            UsartClock::Lse => clocks.lse(),
+        }
+    }
+}
+
+/// Kernel clock of an I2C
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2cClock {
+    /// Clock of the APB1
+    Apb,
+    Sysclk,
+    Hsi,
+}
+
+impl I2cClock {
+    /// Returns the frequency of the kernel clock.
+    pub fn frequency(self, clocks: &Clocks) -> Hertz {
+        match self {
+            I2cClock::Apb => clocks.pclk1(),
+            I2cClock::Sysclk => clocks.sysclk(),
+            I2cClock::Hsi => HSI_FREQUENCY,
+        }
+    }
+}
+
+/// Kernel clock of the LPTIM1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LptimClock {
+    /// Clock of the APB1
+    Apb,
+    Lsi,
+    Hsi,
+    Lse,
+}
+
+impl LptimClock {
+    /// Returns the frequency of the kernel clock, or `None` if the LSI or the
+    /// LSE is off.
+    pub fn frequency(self, clocks: &Clocks) -> Option<Hertz> {
+        match self {
+            LptimClock::Apb => Some(clocks.pclk1()),
+            LptimClock::Lsi => clocks.lsi(),
+            LptimClock::Hsi => Some(HSI_FREQUENCY),
+            LptimClock::Lse => clocks.lse(),
+        }
+    }
+}
+
+/// Kernel clock of the HDMI-CEC
+#[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CecClock {
+    Lse,
+    /// HSI divided by 488
+    Hsi,
+}
+
+#[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+impl CecClock {
+    /// Returns the frequency of the kernel clock, or `None` if the LSE is off.
+    pub fn frequency(self, clocks: &Clocks) -> Option<Hertz> {
+        match self {
+            CecClock::Lse => clocks.lse(),
+            CecClock::Hsi => Some(HSI_FREQUENCY / 488),
+        }
+    }
+}
+
+/// Kernel clock of a SDMMC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SdmmcClock {
+    /// 48 MHz clock, selected with [`CFGR::use_pll48clk`](super::CFGR::use_pll48clk)
+    Pll48,
+    Sysclk,
+}
+
+impl SdmmcClock {
+    /// Returns the frequency of the kernel clock, or `None` if the 48 MHz
+    /// clock is not enabled.
+    pub fn frequency(self, clocks: &Clocks) -> Option<Hertz> {
+        match self {
+            SdmmcClock::Pll48 => clocks.pll48clk(),
+            SdmmcClock::Sysclk => Some(clocks.sysclk()),
+        }
+    }
+}
+
+/// Clock of the I2S
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2sClock {
+    /// R output of the PLLI2S
+    Plli2s,
+    /// External clock of the given frequency on the I2S_CKIN pin
+    External(Hertz),
+}
+
+impl I2sClock {
+    /// Returns the frequency of the kernel clock, or `None` if the PLLI2S is
+    /// off.
+    pub fn frequency(self, clocks: &Clocks) -> Option<Hertz> {
+        match self {
+            I2sClock::Plli2s => clocks.plli2sr(),
+            I2sClock::External(freq) => Some(freq),
+        }
+    }
+}
+
+/// Kernel clocks selection
+///
+/// The default values are the ones on reset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KernelClocks {
+    pub usart1: UsartClock,
+    pub usart2: UsartClock,
+    pub usart3: UsartClock,
+    pub uart4: UsartClock,
+    pub uart5: UsartClock,
+    pub usart6: UsartClock,
+    pub uart7: UsartClock,
+    pub uart8: UsartClock,
+    pub i2c1: I2cClock,
+    pub i2c2: I2cClock,
+    pub i2c3: I2cClock,
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    pub i2c4: I2cClock,
+    pub lptim1: LptimClock,
+    #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+    pub cec: CecClock,
+    pub sdmmc1: SdmmcClock,
+    #[cfg(not(any(feature = "svd-f745", feature = "svd-f7x6")))]
+    pub sdmmc2: SdmmcClock,
+    pub i2s: I2sClock,
+}
+
+impl Default for KernelClocks {
+    fn default() -> Self {
+        Self {
+            usart1: UsartClock::Apb,
+            usart2: UsartClock::Apb,
+            usart3: UsartClock::Apb,
+            uart4: UsartClock::Apb,
+            uart5: UsartClock::Apb,
+            usart6: UsartClock::Apb,
+            uart7: UsartClock::Apb,
+            uart8: UsartClock::Apb,
+            i2c1: I2cClock::Apb,
+            i2c2: I2cClock::Apb,
+            i2c3: I2cClock::Apb,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            i2c4: I2cClock::Apb,
+            lptim1: LptimClock::Apb,
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            cec: CecClock::Lse,
+            sdmmc1: SdmmcClock::Pll48,
+            #[cfg(not(any(feature = "svd-f745", feature = "svd-f7x6")))]
+            sdmmc2: SdmmcClock::Pll48,
+            i2s: I2sClock::Plli2s,
+        }
+    }
+}
+
+impl KernelClocks {
+    fn usarts(&self) -> [UsartClock; 8] {
+        [
+            self.usart1,
+            self.usart2,
+            self.usart3,
+            self.uart4,
+            self.uart5,
+            self.usart6,
+            self.uart7,
+            self.uart8,
+        ]
+    }
+
+    /// Returns `true` if an USART or an UART is clocked by the LSE
+    pub(super) fn usart_on_lse(&self) -> bool {
+        self.usarts().contains(&UsartClock::Lse)
+    }
+
+    /// Returns the mask of the selection bits in DCKCFGR2, and their value.
+    ///
+    /// The CK48MSEL and DSISEL bits are left out.
+    pub(super) fn dckcfgr2_bits(&self) -> (u32, u32) {
+        let mut mask = 0xffff;
+        let mut bits = 0;
+        for (i, usart) in self.usarts().iter().enumerate() {
+            bits |= (*usart as u32) << (2 * i);
+        }
+
+        let i2cs = [
+            (self.i2c1, 16),
+            (self.i2c2, 18),
+            (self.i2c3, 20),
+            #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+            (self.i2c4, 22),
+        ];
+        for (i2c, shift) in i2cs {
+            mask |= 0b11 << shift;
+            bits |= (i2c as u32) << shift;
+        }
+
+        mask |= 0b11 << 24;
+        bits |= (self.lptim1 as u32) << 24;
+
+        #[cfg(not(any(feature = "svd-f7x2", feature = "svd-f7x3", feature = "svd-f730")))]
+        {
+            mask |= 1 << 26;
+            bits |= (self.cec as u32) << 26;
+        }
+
+        mask |= 1 << 28;
+        bits |= (self.sdmmc1 as u32) << 28;
+
+        #[cfg(not(any(feature = "svd-f745", feature = "svd-f7x6")))]
+        {
+            mask |= 1 << 29;
+            bits |= (self.sdmmc2 as u32) << 29;
+        }
+
+        (mask, bits)
+    }
+
+    /// Writes the selection to the RCC registers.
+    pub(super) fn apply(&self, rcc: &rcc::RegisterBlock) {
+        let (mask, bits) = self.dckcfgr2_bits();
+        rcc.dckcfgr2
+            .modify(|r, w| unsafe { w.bits((r.bits() & !mask) | bits) });
+        rcc.cfgr
+            .modify(|_, w| w.i2ssrc().bit(matches!(self.i2s, I2sClock::External(_))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{I2cClock, KernelClocks, LptimClock, SdmmcClock, UsartClock};
+
+    #[test]
+    fn test_dckcfgr2_bits() {
+        let (mask, bits) = KernelClocks::default().dckcfgr2_bits();
+        assert_eq!(bits, 0);
+        // CK48MSEL is kept
+        assert_eq!(mask & 1 << 27, 0);
+
+        let kernel_clocks = KernelClocks {
+            usart1: UsartClock::Hsi,
+            uart8: UsartClock::Lse,
+            i2c2: I2cClock::Sysclk,
+            lptim1: LptimClock::Lse,
+            sdmmc1: SdmmcClock::Sysclk,
+            ..KernelClocks::default()
+        };
+        let (_, bits) = kernel_clocks.dckcfgr2_bits();
+        assert_eq!(bits, 0b10 | 0b11 << 14 | 0b01 << 18 | 0b11 << 24 | 1 << 28);
+    }
+}
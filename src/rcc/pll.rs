@@ -0,0 +1,109 @@
+//! Limits of the PLLSAI and PLLI2S, and search of their factors
+//!
+//! The factors of the PLLI2S for the I2S, and of the PLLSAI or PLLI2S for the
+//! SAI, are searched for an audio clock the closest to a target frequency,
+//! which usually isn't reachable exactly.
+
+use core::ops::RangeInclusive;
+
+/// Range of the VCO output frequency of the PLLs
+///
+/// The reference manual gives 100 MHz as the minimum, but STM32CubeMX states
+/// 192 MHz: the stricter requirement is chosen.
+pub(crate) const VCO_MIN: u32 = 192_000_000;
+pub(crate) const VCO_MAX: u32 = 432_000_000;
+/// Maximum frequency of the Q and R outputs of the PLLs
+pub(crate) const OUTPUT_MAX: u32 = 216_000_000;
+
+/// Factors of a PLL output found by [`search`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Found<T> {
+    /// Multiplication factor
+    pub n: u16,
+    /// Division factor of the output
+    pub div: u8,
+    /// Dividers following the output
+    pub post: T,
+    /// Error of the frequency achieved, in parts per billion
+    pub error_ppb: i64,
+}
+
+/// Returns the N factor, the output divider in `divs` and the following
+/// dividers giving, from the `vco_input` frequency, the frequency the closest
+/// to `target`, or `None` if none is within `tolerance_ppm`.
+///
+/// `post` returns, for an output frequency, the candidates for the following
+/// dividers: their whole division factor and a value identifying them.
+pub(crate) fn search<T, I>(
+    vco_input: u32,
+    divs: RangeInclusive<u8>,
+    target: u32,
+    tolerance_ppm: u32,
+    post: impl Fn(u32) -> I,
+) -> Option<Found<T>>
+where
+    I: IntoIterator<Item = (u32, T)>,
+{
+    let target = i64::from(target);
+    let tolerance_ppb = i64::from(tolerance_ppm) * 1_000;
+    let mut best: Option<Found<T>> = None;
+
+    for n in 50..=432u16 {
+        let vco = vco_input * u32::from(n);
+        if !(VCO_MIN..=VCO_MAX).contains(&vco) {
+            continue;
+        }
+        for div in divs.clone() {
+            let output = vco / u32::from(div);
+            if output > OUTPUT_MAX {
+                continue;
+            }
+            for (post_div, post) in post(output) {
+                let divider = u64::from(div) * u64::from(post_div);
+                // Frequency achieved, in nHz
+                let achieved = (u64::from(vco) * 1_000_000_000 / divider) as i64;
+                let error_ppb = (achieved - target * 1_000_000_000) / target;
+                if error_ppb.abs() > tolerance_ppb {
+                    continue;
+                }
+
+                match &best {
+                    Some(best) if best.error_ppb.abs() <= error_ppb.abs() => {}
+                    _ => {
+                        best = Some(Found {
+                            n,
+                            div,
+                            post,
+                            error_ppb,
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::search;
+
+    #[test]
+    fn test_search() {
+        // 48 MHz exactly from a 1 MHz input, without any divider after the
+        // output
+        let found = search(1_000_000, 2..=7, 48_000_000, 0, |_| Some((1, ()))).unwrap();
+        assert_eq!(found.error_ppb, 0);
+        assert_eq!(
+            u32::from(found.n) * 1_000_000 / u32::from(found.div),
+            48_000_000
+        );
+
+        // Out of reach of the VCO within the tolerance
+        assert_eq!(
+            search(1_000_000, 2..=2, 12_288_000, 1_000, |_| Some((1, ()))),
+            None
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Reset cause
+//!
+//! The reset flags of RCC_CSR are kept until they are cleared, over the
+//! following resets except the power-on reset.
+
+use super::Rcc;
+use crate::pac::RCC;
+
+/// Cause of the last reset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetReason {
+    /// Power-on or power-down reset
+    PowerOn,
+    /// Brownout reset, the supply voltage having dropped below the BOR level
+    Brownout,
+    /// Reset from the NRST pin
+    Pin,
+    /// Software reset, from `SCB::sys_reset`
+    Software,
+    /// Independent watchdog reset
+    IndependentWatchdog,
+    /// Window watchdog reset
+    WindowWatchdog,
+    /// Reset when entering the Stop or Standby mode, with the nRST_STOP or
+    /// nRST_STDBY option byte cleared
+    LowPower,
+    /// No reset flag set, the flags having already been cleared
+    Unknown,
+}
+
+impl ResetReason {
+    /// Returns the cause of the last reset, and clears the reset flags.
+    ///
+    /// The flags of the resets generating an internal reset on the NRST pin
+    /// are set together with the pin reset flag, and the power-on reset also
+    /// sets the brownout reset flag: the most specific cause is returned.
+    pub fn read(_rcc: &mut Rcc) -> Self {
+        // NOTE(unsafe) only the reset flags are accessed, through `Rcc`
+        let csr = unsafe { &(*RCC::ptr()).csr };
+        let flags = csr.read();
+
+        let reason = if flags.lpwrrstf().bit_is_set() {
+            ResetReason::LowPower
+        } else if flags.wwdgrstf().bit_is_set() {
+            ResetReason::WindowWatchdog
+        } else if flags.wdgrstf().bit_is_set() {
+            ResetReason::IndependentWatchdog
+        } else if flags.sftrstf().bit_is_set() {
+            ResetReason::Software
+        } else if flags.porrstf().bit_is_set() {
+            ResetReason::PowerOn
+        } else if flags.borrstf().bit_is_set() {
+            ResetReason::Brownout
+        } else if flags.padrstf().bit_is_set() {
+            ResetReason::Pin
+        } else {
+            ResetReason::Unknown
+        };
+
+        csr.modify(|_, w| w.rmvf().set_bit());
+        reason
+    }
+}
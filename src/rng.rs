@@ -2,13 +2,13 @@ use core::cmp;
 use core::mem;
 
 use crate::pac::{RCC, RNG};
-use crate::rcc::{Enable, Reset};
+use crate::rcc::{Clocks, Enable, Reset};
 use core::num::NonZeroU32;
 use core::ops::Shl;
 use embedded_hal::blocking::rng::Read;
-use rand_core::RngCore;
+use rand_core::{CryptoRng, RngCore};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorKind {
     /// The RNG_CLK was not correctly detected (fRNG_CLK< fHCLK/16).
     /// See CECS in RNG peripheral documentation.
@@ -28,6 +28,7 @@ impl From<ErrorKind> for rand_core::Error {
 
 pub trait RngExt {
     fn init(self) -> Rng;
+    fn constrain(self, clocks: &Clocks) -> Result<Rng, ErrorKind>;
 }
 
 impl RngExt for RNG {
@@ -52,19 +53,31 @@ impl RngExt for RNG {
                 // reset the RNG
                 RNG::reset_unchecked();
             }
-
-            // enable the RNG peripheral
-            self.cr.modify(|_, w| w.rngen().set_bit());
-            // hardware check for clock is used
-            // instead of software calculation, which may be inaccurate.
-            // until data is available we will check for CECS flag, if it is set
-            // means that clock error occured
-            while !self.sr.read().drdy().bit() {
-                assert!(!self.sr.read().cecs().bit());
-            }
         });
 
-        Rng { rb: self }
+        let mut rng = Rng { rb: self };
+        // A clock error is reported by `get_rand` instead
+        let _ = rng.start();
+        rng
+    }
+
+    /// Enable RNG_CLK and the RNG peripheral, checking that the 48 MHz clock
+    /// was configured by `CFGR::use_pll48clk`.
+    /// Returns a ClockError if RNG_CLK is missing or too slow.
+    fn constrain(self, clocks: &Clocks) -> Result<Rng, ErrorKind> {
+        if !clocks.is_pll48clk_valid() {
+            return Err(ErrorKind::ClockError);
+        }
+
+        unsafe {
+            RNG::enable_unchecked();
+            RNG::is_enabled();
+            RNG::reset_unchecked();
+        }
+
+        let mut rng = Rng { rb: self };
+        rng.start()?;
+        Ok(rng)
     }
 }
 
@@ -73,15 +86,49 @@ pub struct Rng {
 }
 
 impl Rng {
+    /// Enables the RNG and waits for the first random word.
+    /// Hardware check for clock is used instead of software calculation,
+    /// which may be inaccurate.
+    fn start(&mut self) -> Result<(), ErrorKind> {
+        self.rb.cr.modify(|_, w| w.rngen().set_bit());
+        // until data is available we will check for CECS flag, if it is set
+        // means that clock error occured
+        while !self.rb.sr.read().drdy().bit() {
+            if self.rb.sr.read().cecs().bit() {
+                return Err(ErrorKind::ClockError);
+            }
+        }
+        Ok(())
+    }
+
+    /// Recovers from a seed error by restarting the RNG, as described in
+    /// the RNG error management section of RM0410. The random words generated
+    /// before the error are discarded.
+    pub fn reseed(&mut self) -> Result<(), ErrorKind> {
+        self.rb.sr.modify(|_, w| w.seis().clear_bit());
+        self.rb.cr.modify(|_, w| w.rngen().clear_bit());
+        self.start()?;
+        if self.rb.sr.read().secs().bit() {
+            return Err(ErrorKind::SeedError);
+        }
+        Ok(())
+    }
+
     /// Returns 32 bits of random data from RNDATA, or error.
     /// May fail if, for example RNG_CLK is misconfigured.
+    ///
+    /// On a seed error the RNG is reseeded before returning `SeedError`, so a
+    /// subsequent call can succeed. A clock error clears itself once RNG_CLK is
+    /// correct again.
     pub fn get_rand(&mut self) -> Result<u32, ErrorKind> {
         loop {
             let status = self.rb.sr.read();
             if status.cecs().bit() {
+                self.rb.sr.modify(|_, w| w.ceis().clear_bit());
                 return Err(ErrorKind::ClockError);
             }
             if status.secs().bit() {
+                self.reseed()?;
                 return Err(ErrorKind::SeedError);
             }
             if status.drdy().bit() {
@@ -104,8 +151,15 @@ impl Read for Rng {
 }
 
 impl RngCore for Rng {
+    /// Panics on a clock error. Seed errors are recovered from.
     fn next_u32(&mut self) -> u32 {
-        self.get_rand().unwrap()
+        loop {
+            match self.get_rand() {
+                Ok(value) => return value,
+                Err(ErrorKind::SeedError) => continue,
+                Err(e) => panic!("RNG error: {:?}", e),
+            }
+        }
     }
 
     fn next_u64(&mut self) -> u64 {
@@ -115,7 +169,10 @@ impl RngCore for Rng {
     }
 
     fn fill_bytes(&mut self, dest: &mut [u8]) {
-        self.try_fill_bytes(dest).unwrap()
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_ne_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
     }
 
     /// Fills buffer with random values, or returns an error
@@ -132,3 +189,7 @@ impl RngCore for Rng {
         Ok(())
     }
 }
+
+/// The RNG is a true random number generator, based on analog noise, and
+/// passes the NIST SP800-22 statistical tests.
+impl CryptoRng for Rng {}
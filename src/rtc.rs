@@ -1,9 +1,9 @@
-//! Interface to the real time clock. See STM32F303 reference manual, section 27.
+//! Interface to the real time clock. See STM32F7 reference manual (RM0410), section 32.
 //! For more details, see
 //! [ST AN4759](https:/www.st.com%2Fresource%2Fen%2Fapplication_note%2Fdm00226326-using-the-hardware-realtime-clock-rtc-and-the-tamper-management-unit-tamp-with-stm32-microcontrollers-stmicroelectronics.pdf&usg=AOvVaw3PzvL2TfYtwS32fw-Uv37h)
 
 use crate::pac::rtc::{dr, tr};
-use crate::pac::{PWR, RCC, RTC};
+use crate::pac::{EXTI, PWR, RCC, RTC};
 use crate::rcc::{Clocks, APB1};
 use core::convert::TryInto;
 use time::{Date, PrimitiveDateTime, Time};
@@ -37,6 +37,270 @@ pub enum RtcClock {
     Hse { divider: u8 },
 }
 
+/// RTC alarms
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alarm {
+    AlarmA,
+    AlarmB,
+}
+
+/// Day matched by an alarm
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlarmDay {
+    /// Day of month [1-31]
+    Date(u8),
+    /// Day of week [1-7], Monday being 1
+    Weekday(u8),
+}
+
+/// Calendar fields an alarm matches on. Fields set to `None` are masked, i.e.
+/// match any value.
+///
+/// For example, an alarm with only `seconds: Some(0)` fires every minute.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AlarmMatch {
+    pub day: Option<AlarmDay>,
+    /// Hours [0-23]
+    pub hours: Option<u8>,
+    /// Minutes [0-59]
+    pub minutes: Option<u8>,
+    /// Seconds [0-59]
+    pub seconds: Option<u8>,
+    /// Sub-seconds value and number of its least significant bits compared
+    /// [0-15] (MASKSS)
+    pub subseconds: Option<(u16, u8)>,
+}
+
+/// Clock of the periodic wakeup timer (WUCKSEL)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WakeupClock {
+    /// RTCCLK / 16, e.g. 488 µs resolution with the 32.768 kHz LSE
+    RtcDiv16 = 0b000,
+    /// RTCCLK / 8
+    RtcDiv8 = 0b001,
+    /// RTCCLK / 4
+    RtcDiv4 = 0b010,
+    /// RTCCLK / 2
+    RtcDiv2 = 0b011,
+    /// ck_spre, usually 1 Hz
+    CkSpre = 0b100,
+    /// ck_spre, with 2^16 added to the reload value for periods up to 36 hours
+    CkSpreExtended = 0b110,
+}
+
+/// Smooth calibration cycle period (CALW8, CALW16)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalibrationCycle {
+    /// 8 seconds, the 2 least significant bits of `calm` are ignored
+    Seconds8,
+    /// 16 seconds, the least significant bit of `calm` is ignored
+    Seconds16,
+    /// 32 seconds
+    Seconds32,
+}
+
+/// Input of the timestamp event (TSINSEL)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimestampPin {
+    PC13 = 0b00,
+    PI8 = 0b01,
+    PC1 = 0b10,
+}
+
+/// Active edge of the timestamp input (TSEDGE)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimestampEdge {
+    Rising,
+    Falling,
+}
+
+/// Calendar captured on a timestamp event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timestamp {
+    /// Captured date and time. The year is not captured by the hardware, the
+    /// current year of the calendar is used.
+    pub datetime: PrimitiveDateTime,
+    /// Captured sub-seconds register, counting down from `prediv_s`
+    pub subseconds: u16,
+    /// Another timestamp event occurred while this one was pending, and was
+    /// not captured
+    pub overflow: bool,
+}
+
+/// RTC tamper inputs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tamper {
+    /// RTC_TAMP1 (PC13)
+    Tamper1,
+    /// RTC_TAMP2 (PI8)
+    Tamper2,
+    /// RTC_TAMP3 (PC13)
+    Tamper3,
+}
+
+impl Tamper {
+    /// TAMPxE bit in TAMPCR, TAMPxTRG is the next one
+    fn enable_bit(self) -> u32 {
+        match self {
+            Tamper::Tamper1 => 0,
+            Tamper::Tamper2 => 3,
+            Tamper::Tamper3 => 5,
+        }
+    }
+
+    /// TAMPxIE bit in TAMPCR, followed by TAMPxNOERASE and TAMPxMF
+    fn interrupt_bit(self) -> u32 {
+        match self {
+            Tamper::Tamper1 => 16,
+            Tamper::Tamper2 => 19,
+            Tamper::Tamper3 => 22,
+        }
+    }
+}
+
+/// Active level or edge of a tamper input (TAMPxTRG)
+///
+/// Edges are only detected with `TamperFilter::Edge`, levels with the other
+/// filter settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TamperTrigger {
+    RisingEdgeOrLowLevel,
+    FallingEdgeOrHighLevel,
+}
+
+/// Tamper input filter (TAMPFLT)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TamperFilter {
+    /// Tamper event on edge, without precharge
+    Edge = 0b00,
+    /// Tamper event after 2 consecutive samples at the active level
+    Samples2 = 0b01,
+    /// Tamper event after 4 consecutive samples at the active level
+    Samples4 = 0b10,
+    /// Tamper event after 8 consecutive samples at the active level
+    Samples8 = 0b11,
+}
+
+/// Tamper input configuration. The filter, sampling and precharge settings are
+/// shared by all tamper inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TamperConfig {
+    pub trigger: TamperTrigger,
+    pub filter: TamperFilter,
+    /// Sampling frequency is RTCCLK / 2^(15 - `sampling`), `sampling` [0-7]
+    pub sampling: u8,
+    /// Precharge duration before sampling is 2^`precharge` RTCCLK cycles,
+    /// `precharge` [0-3]
+    pub precharge: u8,
+    /// Precharge the input with the internal pull-up before sampling
+    pub pull_up: bool,
+    /// Erase the backup registers when the tamper event occurs
+    pub erase_backup: bool,
+}
+
+/// Detected tamper event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TamperDetection {
+    /// The backup registers have been erased by hardware
+    pub backup_erased: bool,
+}
+
+/// RTC interrupt events
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Alarm A matched, EXTI line 17
+    AlarmA,
+    /// Alarm B matched, EXTI line 17
+    AlarmB,
+    /// Periodic wakeup timer expired, EXTI line 22
+    Wakeup,
+    /// Tamper detected, EXTI line 21
+    Tamper(Tamper),
+    /// Timestamp captured, EXTI line 21
+    Timestamp,
+}
+
+impl Event {
+    /// EXTI line of the event
+    fn exti_line(self) -> u8 {
+        match self {
+            Event::AlarmA | Event::AlarmB => 17,
+            Event::Wakeup => 22,
+            Event::Tamper(_) | Event::Timestamp => 21,
+        }
+    }
+}
+
+/// Date and time of the RTC calendar
+///
+/// It is converted from and into `time::PrimitiveDateTime` and, with the
+/// `chrono` feature, `chrono::NaiveDateTime`. A `NaiveDateTime` is converted
+/// with `TryFrom`, as its years may be out of the range of `time`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime(PrimitiveDateTime);
+
+impl From<PrimitiveDateTime> for DateTime {
+    fn from(datetime: PrimitiveDateTime) -> Self {
+        Self(datetime)
+    }
+}
+
+impl From<&PrimitiveDateTime> for DateTime {
+    fn from(datetime: &PrimitiveDateTime) -> Self {
+        Self(*datetime)
+    }
+}
+
+impl From<DateTime> for PrimitiveDateTime {
+    fn from(datetime: DateTime) -> Self {
+        datetime.0
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<chrono::NaiveDateTime> for DateTime {
+    type Error = Error;
+
+    fn try_from(datetime: chrono::NaiveDateTime) -> Result<Self, Error> {
+        use chrono::{Datelike, Timelike};
+
+        let month = (datetime.month() as u8)
+            .try_into()
+            .map_err(|_| Error::InvalidInputData)?;
+        let date = Date::from_calendar_date(datetime.year(), month, datetime.day() as u8)
+            .map_err(|_| Error::InvalidInputData)?;
+        let time = Time::from_hms(
+            datetime.hour() as u8,
+            datetime.minute() as u8,
+            datetime.second() as u8,
+        )
+        .map_err(|_| Error::InvalidInputData)?;
+
+        Ok(Self(PrimitiveDateTime::new(date, time)))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<DateTime> for chrono::NaiveDateTime {
+    fn from(datetime: DateTime) -> Self {
+        let datetime = datetime.0;
+        // The years of `time` are in the range of `chrono`
+        chrono::NaiveDate::from_ymd_opt(
+            datetime.year(),
+            u8::from(datetime.month()).into(),
+            datetime.day().into(),
+        )
+        .and_then(|date| {
+            date.and_hms_opt(
+                datetime.hour().into(),
+                datetime.minute().into(),
+                datetime.second().into(),
+            )
+        })
+        .unwrap()
+    }
+}
+
 pub struct Rtc {
     pub regs: RTC,
 }
@@ -46,6 +310,9 @@ impl Rtc {
     ///     
     /// ** Assumes 1970-01-01 00:00:00 Epoch **
     ///
+    /// The backup domain is reset, which clears the calendar and the backup
+    /// registers, unless the RTC is already running from `clock_source`.
+    ///
     /// See AN4759 (Rev 7) Table 7 for configuration of `prediv_s` and `prediv_a`,
     /// respectively the formula to calculate `ck_spre` on the same page.
     ///
@@ -84,35 +351,54 @@ impl Rtc {
         // As per the sample code, unlock comes first. (Enable PWR and DBP)
         unlock(apb1, pwr);
 
+        // Check if the clock source is enabled.
         match clock_source {
             RtcClock::Lse => {
-                // Check if LSE is enabled.
                 clocks.lse()?;
-                // Force a reset of the backup domain.
-                rcc.bdcr.modify(|_, w| w.bdrst().enabled());
-                rcc.bdcr.modify(|_, w| w.bdrst().disabled());
-                // Set clock source to LSE.
-                rcc.bdcr.modify(|_, w| w.rtcsel().lse());
             }
             RtcClock::Lsi => {
-                // Check if LSI is enabled.
                 clocks.lsi()?;
-                // Force a reset of the backup domain.
-                rcc.bdcr.modify(|_, w| w.bdrst().enabled());
-                rcc.bdcr.modify(|_, w| w.bdrst().disabled());
-                // Set clock source to LSI.
-                rcc.bdcr.modify(|_, w| w.rtcsel().lsi());
             }
-            RtcClock::Hse { divider } => {
-                // Check if HSE is enabled.
+            RtcClock::Hse { .. } => {
                 clocks.hse()?;
+            }
+        }
+
+        // Keep the backup domain, and so the calendar and the backup
+        // registers, if the RTC is already running from the same clock.
+        let bdcr = rcc.bdcr.read();
+        let running = bdcr.rtcen().is_enabled()
+            && match clock_source {
+                RtcClock::Lse => bdcr.rtcsel().is_lse(),
+                RtcClock::Lsi => bdcr.rtcsel().is_lsi(),
+                RtcClock::Hse { .. } => bdcr.rtcsel().is_hse(),
+            };
+
+        if !running {
+            if let RtcClock::Hse { divider } = clock_source {
                 // Set RTCPRE division factor (HES_RTC).
                 rcc.cfgr.modify(|_, w| w.rtcpre().bits(divider));
-                // Force a reset of the backup domain.
-                rcc.bdcr.modify(|_, w| w.bdrst().enabled());
-                rcc.bdcr.modify(|_, w| w.bdrst().disabled());
-                // Set clock source to LSE.
-                rcc.bdcr.modify(|_, w| w.rtcsel().hse());
+            }
+            // Force a reset of the backup domain. This also stops the LSE,
+            // so restore its configuration afterwards.
+            let lse = rcc.bdcr.read();
+            rcc.bdcr.modify(|_, w| w.bdrst().enabled());
+            rcc.bdcr.modify(|_, w| w.bdrst().disabled());
+            if lse.lseon().is_on() {
+                rcc.bdcr.modify(|_, w| {
+                    w.lsebyp()
+                        .bit(lse.lsebyp().bit())
+                        .lsedrv()
+                        .bits(lse.lsedrv().bits())
+                });
+                rcc.bdcr.modify(|_, w| w.lseon().on());
+                while rcc.bdcr.read().lserdy().is_not_ready() {}
+            }
+            // Set clock source.
+            match clock_source {
+                RtcClock::Lse => rcc.bdcr.modify(|_, w| w.rtcsel().lse()),
+                RtcClock::Lsi => rcc.bdcr.modify(|_, w| w.rtcsel().lsi()),
+                RtcClock::Hse { .. } => rcc.bdcr.modify(|_, w| w.rtcsel().hse()),
             }
         }
         // Start the actual RTC.
@@ -158,6 +444,21 @@ impl Rtc {
         self.regs.wpr.write(|w| unsafe { w.bits(0xFF) });
     }
 
+    /// Disables write protection for registers that can be modified outside
+    /// of the initialization mode
+    fn unprotect<F>(&mut self, mut closure: F)
+    where
+        F: FnMut(&mut RTC),
+    {
+        // Disable write protection
+        self.regs.wpr.write(|w| unsafe { w.bits(0xCA) });
+        self.regs.wpr.write(|w| unsafe { w.bits(0x53) });
+        // Invoke closure
+        closure(&mut self.regs);
+        // Enable write protection
+        self.regs.wpr.write(|w| unsafe { w.bits(0xFF) });
+    }
+
     /// Set the time using time::Time.
     pub fn set_time(&mut self, time: &Time) -> Result<(), Error> {
         let (ht, hu) = bcd2_encode(time.hour().into())?;
@@ -270,9 +571,11 @@ impl Rtc {
         let (yt, yu) = bcd2_encode((date.year() - 1970) as u32)?;
         let (mt, mu) = bcd2_encode(u8::from(date.month()).into())?;
         let (dt, du) = bcd2_encode(date.day().into())?;
+        let wdu = date.weekday().number_from_monday();
 
         self.modify(|regs| {
             regs.dr.write(|w| {
+                unsafe { w.wdu().bits(wdu) };
                 w.dt().bits(dt);
                 w.du().bits(du);
                 w.mt().bit(mt > 0);
@@ -285,11 +588,13 @@ impl Rtc {
         Ok(())
     }
 
-    /// Set the date and time.
+    /// Set the date and time, from a `time::PrimitiveDateTime` or a [`DateTime`]
+    /// converted from a `chrono::NaiveDateTime`.
     ///
     /// The year cannot be less than 1970, since the Unix epoch is assumed (1970-01-01 00:00:00).
     /// Also, the year cannot be greater than 2069 since the RTC range is 0 - 99.
-    pub fn set_datetime(&mut self, date: &PrimitiveDateTime) -> Result<(), Error> {
+    pub fn set_datetime(&mut self, datetime: impl Into<DateTime>) -> Result<(), Error> {
+        let date = datetime.into().0;
         if !(1970..=2069).contains(&date.year()) {
             return Err(Error::InvalidInputData);
         }
@@ -297,6 +602,7 @@ impl Rtc {
         let (yt, yu) = bcd2_encode((date.year() - 1970) as u32)?;
         let (mt, mu) = bcd2_encode(u8::from(date.month()).into())?;
         let (dt, du) = bcd2_encode(date.day().into())?;
+        let wdu = date.weekday().number_from_monday();
 
         let (ht, hu) = bcd2_encode(date.hour().into())?;
         let (mnt, mnu) = bcd2_encode(date.minute().into())?;
@@ -304,6 +610,7 @@ impl Rtc {
 
         self.modify(|regs| {
             regs.dr.write(|w| {
+                unsafe { w.wdu().bits(wdu) };
                 w.dt().bits(dt);
                 w.du().bits(du);
                 w.mt().bit(mt > 0);
@@ -325,6 +632,381 @@ impl Rtc {
         Ok(())
     }
 
+    /// Get the day of week [1-7], Monday being 1.
+    pub fn get_weekday(&mut self) -> u8 {
+        while self.regs.isr.read().rsf().bit_is_clear() {}
+        let weekday = self.regs.dr.read().wdu().bits();
+        self.regs.isr.modify(|_, w| w.rsf().clear_bit());
+        weekday
+    }
+
+    /// Adds one hour to the calendar, e.g. when daylight saving time starts.
+    ///
+    /// The calendar keeps running, there is no need to enter the
+    /// initialization mode.
+    pub fn add_hour(&mut self) {
+        self.unprotect(|regs| regs.cr.modify(|_, w| w.add1h().set_bit()));
+    }
+
+    /// Subtracts one hour from the calendar, e.g. when daylight saving time
+    /// ends. Has no effect if the current hour is 0.
+    pub fn sub_hour(&mut self) {
+        self.unprotect(|regs| regs.cr.modify(|_, w| w.sub1h().set_bit()));
+    }
+
+    /// Stores whether daylight saving time is in effect (BKP bit), so that
+    /// it is remembered across resets whether the hour has been changed.
+    pub fn set_daylight_saving(&mut self, dst: bool) {
+        self.unprotect(|regs| regs.cr.modify(|_, w| w.bkp().bit(dst)));
+    }
+
+    /// Returns the value stored with `set_daylight_saving`
+    pub fn daylight_saving(&self) -> bool {
+        self.regs.cr.read().bkp().bit_is_set()
+    }
+
+    /// Configures and enables `alarm`.
+    ///
+    /// The alarm flag is set when the calendar matches all the fields set in
+    /// `matching`; use `listen` to get an interrupt, which can also wake up the
+    /// device from Stop and Standby modes.
+    pub fn set_alarm(&mut self, alarm: Alarm, matching: &AlarmMatch) -> Result<(), Error> {
+        let (alrmr, alrmssr) = encode_alarm(matching)?;
+
+        self.unprotect(|regs| match alarm {
+            Alarm::AlarmA => {
+                regs.cr.modify(|_, w| w.alrae().clear_bit());
+                while regs.isr.read().alrawf().bit_is_clear() {}
+                regs.alrmar.write(|w| unsafe { w.bits(alrmr) });
+                regs.alrmassr.write(|w| unsafe { w.bits(alrmssr) });
+                regs.cr.modify(|_, w| w.alrae().set_bit());
+            }
+            Alarm::AlarmB => {
+                regs.cr.modify(|_, w| w.alrbe().clear_bit());
+                while regs.isr.read().alrbwf().bit_is_clear() {}
+                regs.alrmbr.write(|w| unsafe { w.bits(alrmr) });
+                regs.alrmbssr.write(|w| unsafe { w.bits(alrmssr) });
+                regs.cr.modify(|_, w| w.alrbe().set_bit());
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Disables `alarm`
+    pub fn disable_alarm(&mut self, alarm: Alarm) {
+        self.unprotect(|regs| match alarm {
+            Alarm::AlarmA => regs.cr.modify(|_, w| w.alrae().clear_bit()),
+            Alarm::AlarmB => regs.cr.modify(|_, w| w.alrbe().clear_bit()),
+        });
+    }
+
+    /// Returns `true` if `alarm` matched since its flag was last cleared
+    pub fn check_alarm_flag(&self, alarm: Alarm) -> bool {
+        let isr = self.regs.isr.read();
+        match alarm {
+            Alarm::AlarmA => isr.alraf().bit_is_set(),
+            Alarm::AlarmB => isr.alrbf().bit_is_set(),
+        }
+    }
+
+    /// Clears the flag of `alarm` and the pending bit of EXTI line 17
+    pub fn clear_alarm_flag(&mut self, alarm: Alarm) {
+        match alarm {
+            Alarm::AlarmA => self.clear_interrupt(Event::AlarmA),
+            Alarm::AlarmB => self.clear_interrupt(Event::AlarmB),
+        }
+    }
+
+    /// Starts the periodic wakeup timer.
+    ///
+    /// The `Wakeup` event occurs every `reload + 1` cycles of `clock`, also in
+    /// Stop and Standby modes; use `listen` to get an interrupt.
+    pub fn enable_wakeup(&mut self, clock: WakeupClock, reload: u16) {
+        self.unprotect(|regs| {
+            regs.cr.modify(|_, w| w.wute().clear_bit());
+            while regs.isr.read().wutwf().bit_is_clear() {}
+            regs.wutr.write(|w| w.wut().bits(reload));
+            regs.cr
+                .modify(|r, w| unsafe { w.bits((r.bits() & !0b111) | clock as u32) });
+            regs.cr.modify(|_, w| w.wute().set_bit());
+        });
+    }
+
+    /// Stops the periodic wakeup timer
+    pub fn disable_wakeup(&mut self) {
+        self.unprotect(|regs| regs.cr.modify(|_, w| w.wute().clear_bit()));
+    }
+
+    /// Configures the smooth digital calibration.
+    ///
+    /// `calm` [0-511] RTCCLK pulses are masked, and 512 pulses are added if
+    /// `calp` is set, during each 32 second cycle. The calibrated frequency is
+    /// `f_rtcclk * (1 + (calp * 512 - calm) / (2^20 + calm - calp * 512))`.
+    pub fn set_smooth_calibration(
+        &mut self,
+        calp: bool,
+        calm: u16,
+        cycle: CalibrationCycle,
+    ) -> Result<(), Error> {
+        if calm > 511 {
+            return Err(Error::InvalidInputData);
+        }
+
+        self.unprotect(|regs| {
+            // A previous calibration must be taken into account first
+            while regs.isr.read().recalpf().bit_is_set() {}
+            regs.calr.write(|w| {
+                let w = w.calp().bit(calp).calm().bits(calm);
+                match cycle {
+                    CalibrationCycle::Seconds8 => w.calw8().set_bit(),
+                    CalibrationCycle::Seconds16 => w.calw16().set_bit(),
+                    CalibrationCycle::Seconds32 => w,
+                }
+            })
+        });
+
+        Ok(())
+    }
+
+    /// Compensates a measured RTC clock error of `ppm` parts per million,
+    /// positive when the clock runs fast, with a 32 second calibration cycle.
+    ///
+    /// The resolution is about 0.954 ppm, and errors from -488.3 to +487.3 ppm
+    /// can be compensated.
+    pub fn calibrate_ppm(&mut self, ppm: f32) -> Result<(), Error> {
+        let (calp, calm) = smooth_calibration(ppm).ok_or(Error::InvalidInputData)?;
+        self.set_smooth_calibration(calp, calm, CalibrationCycle::Seconds32)
+    }
+
+    /// Enables the timestamp function: the calendar is captured when `edge`
+    /// occurs on `pin`. Use `listen` to get an interrupt.
+    pub fn enable_timestamp(&mut self, pin: TimestampPin, edge: TimestampEdge) {
+        self.unprotect(|regs| {
+            // TSEDGE must be changed while TSE is cleared
+            regs.cr.modify(|_, w| w.tse().clear_bit());
+            // TSINSEL
+            regs.or
+                .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << 1)) | ((pin as u32) << 1)) });
+            regs.cr.modify(|_, w| match edge {
+                TimestampEdge::Rising => w.tsedge().clear_bit(),
+                TimestampEdge::Falling => w.tsedge().set_bit(),
+            });
+            regs.cr.modify(|_, w| w.tse().set_bit());
+        });
+    }
+
+    /// Disables the timestamp function
+    pub fn disable_timestamp(&mut self) {
+        self.unprotect(|regs| regs.cr.modify(|_, w| w.tse().clear_bit()));
+    }
+
+    /// Returns the captured calendar if a timestamp event occurred, and clears
+    /// the timestamp flags
+    pub fn read_timestamp(&mut self) -> Option<Timestamp> {
+        let isr = self.regs.isr.read();
+        if isr.tsf().bit_is_clear() {
+            return None;
+        }
+
+        let tstr = self.regs.tstr.read();
+        let tsdr = self.regs.tsdr.read();
+        let subseconds = self.regs.tsssr.read().ss().bits();
+        let overflow = isr.tsovf().bit_is_set();
+        // TSOVF must be cleared after TSF
+        self.regs.isr.modify(|_, w| w.tsf().clear_bit());
+        self.regs.isr.modify(|_, w| w.tsovf().clear_bit());
+
+        let year = decode_year(&self.regs.dr.read());
+        let month = bcd2_decode(tsdr.mt().bit() as u8, tsdr.mu().bits()) as u8;
+        let day = bcd2_decode(tsdr.dt().bits(), tsdr.du().bits()) as u8;
+        let hours = bcd2_decode(tstr.ht().bits(), tstr.hu().bits()) as u8;
+        let minutes = bcd2_decode(tstr.mnt().bits(), tstr.mnu().bits()) as u8;
+        let seconds = bcd2_decode(tstr.st().bits(), tstr.su().bits()) as u8;
+
+        Some(Timestamp {
+            datetime: PrimitiveDateTime::new(
+                Date::from_calendar_date(year.into(), month.try_into().ok()?, day).ok()?,
+                Time::from_hms(hours, minutes, seconds).ok()?,
+            ),
+            subseconds,
+            overflow,
+        })
+    }
+
+    /// Reads backup register `index` [0-31]
+    pub fn read_backup_register(&self, index: usize) -> Result<u32, Error> {
+        self.regs
+            .bkpr
+            .get(index)
+            .map(|r| r.read().bits())
+            .ok_or(Error::InvalidInputData)
+    }
+
+    /// Writes backup register `index` [0-31].
+    ///
+    /// The backup registers keep their content across resets and in Standby
+    /// mode, and with VBAT as long as the backup domain is powered. They are
+    /// erased on a tamper event (unless disabled) and on a backup domain
+    /// reset.
+    pub fn write_backup_register(&mut self, index: usize, value: u32) -> Result<(), Error> {
+        let reg = self.regs.bkpr.get(index).ok_or(Error::InvalidInputData)?;
+        // Backup domain write protection may have been enabled again since
+        // the RTC was initialized
+        // NOTE(unsafe) atomic read-modify-write of the DBP bit only
+        let pwr = unsafe { &(*PWR::ptr()) };
+        if pwr.cr1.read().dbp().bit_is_clear() {
+            pwr.cr1.modify(|_, w| w.dbp().set_bit());
+        }
+        reg.write(|w| unsafe { w.bits(value) });
+
+        Ok(())
+    }
+
+    /// Enables the detection on `tamper`.
+    ///
+    /// The settings of `config` shared by all tamper inputs are overwritten.
+    /// Use `listen` to get an interrupt, which can also wake up the device
+    /// from Stop and Standby modes.
+    pub fn enable_tamper(&mut self, tamper: Tamper, config: &TamperConfig) -> Result<(), Error> {
+        if config.sampling > 7 || config.precharge > 3 {
+            return Err(Error::InvalidInputData);
+        }
+
+        let e = tamper.enable_bit();
+        let ie = tamper.interrupt_bit();
+        let trigger = match config.trigger {
+            TamperTrigger::RisingEdgeOrLowLevel => 0,
+            TamperTrigger::FallingEdgeOrHighLevel => 1,
+        };
+        self.unprotect(|regs| {
+            // The input must be disabled while its trigger is changed
+            regs.tampcr
+                .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << e)) });
+            regs.tampcr.modify(|r, w| unsafe {
+                let bits = r.bits()
+                    // TAMPFREQ, TAMPFLT, TAMPPRCH, TAMPPUDIS
+                    & !(0xff << 8)
+                    & !(1 << (e + 1))
+                    & !(1 << (ie + 1));
+                w.bits(
+                    bits | ((config.sampling as u32) << 8)
+                        | ((config.filter as u32) << 11)
+                        | ((config.precharge as u32) << 13)
+                        | ((!config.pull_up as u32) << 15)
+                        | (trigger << (e + 1))
+                        | ((!config.erase_backup as u32) << (ie + 1)),
+                )
+            });
+            regs.tampcr
+                .modify(|r, w| unsafe { w.bits(r.bits() | (1 << e)) });
+        });
+
+        Ok(())
+    }
+
+    /// Disables the detection on `tamper`
+    pub fn disable_tamper(&mut self, tamper: Tamper) {
+        let e = tamper.enable_bit();
+        self.unprotect(|regs| {
+            regs.tampcr
+                .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << e)) })
+        });
+    }
+
+    /// Returns the detection if a tamper event occurred on `tamper` since
+    /// the flag was last cleared with `clear_interrupt`
+    pub fn check_tamper(&self, tamper: Tamper) -> Option<TamperDetection> {
+        let isr = self.regs.isr.read();
+        let detected = match tamper {
+            Tamper::Tamper1 => isr.tamp1f().bit_is_set(),
+            Tamper::Tamper2 => isr.tamp2f().bit_is_set(),
+            Tamper::Tamper3 => isr.tamp3f().bit_is_set(),
+        };
+        let noerase = self.regs.tampcr.read().bits() & (1 << (tamper.interrupt_bit() + 1));
+        if detected {
+            Some(TamperDetection {
+                backup_erased: noerase == 0,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Starts listening for an interrupt event, routed through its EXTI line
+    /// (rising edge)
+    pub fn listen(&mut self, exti: &mut EXTI, event: Event) {
+        let line = event.exti_line();
+        exti.rtsr
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << line)) });
+        exti.imr
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << line)) });
+
+        self.set_interrupt(event, true);
+    }
+
+    /// Stops listening for an interrupt event. The EXTI line stays enabled,
+    /// as it may be shared with other events.
+    pub fn unlisten(&mut self, event: Event) {
+        self.set_interrupt(event, false);
+    }
+
+    fn set_interrupt(&mut self, event: Event, enable: bool) {
+        self.unprotect(|regs| match event {
+            Event::AlarmA => regs.cr.modify(|_, w| w.alraie().bit(enable)),
+            Event::AlarmB => regs.cr.modify(|_, w| w.alrbie().bit(enable)),
+            Event::Wakeup => regs.cr.modify(|_, w| w.wutie().bit(enable)),
+            Event::Timestamp => regs.cr.modify(|_, w| w.tsie().bit(enable)),
+            Event::Tamper(tamper) => {
+                let ie = 1 << tamper.interrupt_bit();
+                regs.tampcr.modify(|r, w| unsafe {
+                    if enable {
+                        w.bits(r.bits() | ie)
+                    } else {
+                        w.bits(r.bits() & !ie)
+                    }
+                })
+            }
+        });
+    }
+
+    /// Returns `true` if `event` occurred
+    pub fn is_pending(&self, event: Event) -> bool {
+        match event {
+            Event::AlarmA => self.check_alarm_flag(Alarm::AlarmA),
+            Event::AlarmB => self.check_alarm_flag(Alarm::AlarmB),
+            Event::Wakeup => self.regs.isr.read().wutf().bit_is_set(),
+            Event::Tamper(tamper) => self.check_tamper(tamper).is_some(),
+            Event::Timestamp => self.regs.isr.read().tsf().bit_is_set(),
+        }
+    }
+
+    /// Clears the flag of `event` and the pending bit of its EXTI line
+    pub fn clear_interrupt(&mut self, event: Event) {
+        self.regs.isr.modify(|_, w| match event {
+            Event::AlarmA => w.alraf().clear_bit(),
+            Event::AlarmB => w.alrbf().clear_bit(),
+            Event::Wakeup => w.wutf().clear_bit(),
+            Event::Tamper(Tamper::Tamper1) => w.tamp1f().clear_bit(),
+            Event::Tamper(Tamper::Tamper2) => w.tamp2f().clear_bit(),
+            Event::Tamper(Tamper::Tamper3) => w.tamp3f().clear_bit(),
+            Event::Timestamp => w.tsf().clear_bit(),
+        });
+        if event == Event::Timestamp {
+            // TSOVF must be cleared after TSF
+            self.regs.isr.modify(|_, w| w.tsovf().clear_bit());
+        }
+        // NOTE(unsafe) atomic write to a write-1-to-clear register
+        let exti = unsafe { &(*EXTI::ptr()) };
+        exti.pr.write(|w| unsafe { w.bits(1 << event.exti_line()) });
+    }
+
+    /// Get the date and time, to be converted into `time::PrimitiveDateTime` or
+    /// `chrono::NaiveDateTime`
+    pub fn datetime(&mut self) -> DateTime {
+        DateTime(self.get_datetime())
+    }
+
     pub fn get_datetime(&mut self) -> PrimitiveDateTime {
         // Wait for Registers synchronization flag,  to ensure consistency between the RTC_SSR, RTC_TR and RTC_DR shadow registers.
         while self.regs.isr.read().rsf().bit_is_clear() {}
@@ -374,6 +1056,62 @@ fn bcd2_encode(word: u32) -> Result<(u8, u8), Error> {
     Ok((l, r))
 }
 
+/// Encodes an alarm into the `ALRMxR` and `ALRMxSSR` register values
+fn encode_alarm(matching: &AlarmMatch) -> Result<(u32, u32), Error> {
+    // A masked field is ignored by the comparison
+    fn field(value: Option<u8>, max: u8, mask: u32, shift: u32) -> Result<u32, Error> {
+        match value {
+            None => Ok(mask),
+            Some(v) if v > max => Err(Error::InvalidInputData),
+            Some(v) => {
+                let (t, u) = bcd2_encode(v.into())?;
+                Ok(((t as u32) << (shift + 4)) | ((u as u32) << shift))
+            }
+        }
+    }
+
+    let day = match matching.day {
+        None => 1 << 31,
+        Some(AlarmDay::Date(date)) if (1..=31).contains(&date) => field(Some(date), 31, 0, 24)?,
+        // WDSEL
+        Some(AlarmDay::Weekday(weekday)) if (1..=7).contains(&weekday) => {
+            (1 << 30) | ((weekday as u32) << 24)
+        }
+        Some(_) => return Err(Error::InvalidInputData),
+    };
+    let alrmr = day
+        | field(matching.hours, 23, 1 << 23, 16)?
+        | field(matching.minutes, 59, 1 << 15, 8)?
+        | field(matching.seconds, 59, 1 << 7, 0)?;
+
+    let alrmssr = match matching.subseconds {
+        None => 0,
+        Some((ss, maskss)) if maskss <= 15 && ss < 0x8000 => ((maskss as u32) << 24) | ss as u32,
+        Some(_) => return Err(Error::InvalidInputData),
+    };
+
+    Ok((alrmr, alrmssr))
+}
+
+/// Converts a clock error in ppm, positive when the clock runs fast, into the
+/// (CALP, CALM) values compensating it over a 32 second cycle (2^20 pulses of
+/// a 32.768 kHz clock)
+fn smooth_calibration(ppm: f32) -> Option<(bool, u16)> {
+    // This dependency is not used when `cargo test`ing. More info:
+    // https://docs.rs/micromath/1.1.1/micromath/index.html#unused-import-warnings-when-linking-std
+    #[cfg(not(test))]
+    use micromath::F32Ext as _;
+
+    let pulses = (ppm * (1 << 20) as f32 / 1_000_000.0).round() as i32;
+    match pulses {
+        // Mask pulses to slow the clock down
+        0..=511 => Some((false, pulses as u16)),
+        // Add 512 pulses and mask the excess to speed the clock up
+        -512..=-1 => Some((true, (512 + pulses) as u16)),
+        _ => None,
+    }
+}
+
 fn bcd2_decode(fst: u8, snd: u8) -> u32 {
     (fst * 10 + snd).into()
 }
@@ -424,3 +1162,20 @@ fn decode_year(dr: &dr::R) -> u16 {
     let year = bcd2_decode(dr.yt().bits(), dr.yu().bits()) + 1970; // 1970-01-01 is the epoch begin.
     year as u16
 }
+
+#[cfg(test)]
+mod tests {
+    use super::smooth_calibration;
+
+    #[test]
+    fn test_smooth_calibration() {
+        assert_eq!(smooth_calibration(0.0), Some((false, 0)));
+        // 1 pulse every 2^20 is ~0.954 ppm
+        assert_eq!(smooth_calibration(10.0), Some((false, 10)));
+        assert_eq!(smooth_calibration(-10.0), Some((true, 502)));
+        assert_eq!(smooth_calibration(487.0), Some((false, 511)));
+        assert_eq!(smooth_calibration(-488.0), Some((true, 0)));
+        assert_eq!(smooth_calibration(500.0), None);
+        assert_eq!(smooth_calibration(-500.0), None);
+    }
+}
@@ -0,0 +1,801 @@
+//! Serial audio interface (SAI)
+//!
+//! Each SAI peripheral has two independent sub-blocks, A and B, each
+//! configured as a master or slave, transmitter or receiver, with its own
+//! frame and FIFO. [`Sai::split`] returns the two [`SubBlock`]s, configured
+//! with a [`Config`] for the I2S, MSB-justified or LSB-justified protocols
+//! with two slots, the left and right channels, or for the TDM protocol with
+//! up to 16 slots, of which only some may be transferred.
+//!
+//! The masters generate the bit clock, the frame synchronization and the
+//! master clock (MCLK) from the SAI kernel clock, selected with
+//! [`ClockSource`]: the Q output of the PLLSAI or the PLLI2S, which must
+//! have been enabled with [`crate::rcc::CFGR::use_pllsai`] or
+//! [`crate::rcc::CFGR::use_plli2s`], or the I2S_CKIN pin.
+//!
+//! For full-duplex, a slave sub-block can be synchronous with the other
+//! sub-block of the same SAI, sharing its bit clock and frame
+//! synchronization, so the samples sent and received stay aligned. Only the
+//! SD pin of the synchronous sub-block is used.
+//!
+//! [`Sai::split`] takes the SCK, FS, SD and MCLK pins of each sub-block, in
+//! the alternate function of that sub-block, or [`NoPin`] for the MCLK pin
+//! when the master clock isn't output, for the SCK and FS pins of a
+//! synchronous sub-block, and for all the pins of an unused sub-block.
+//!
+//! See chapter 35 in the STM32F76xxx Reference Manual (RM0410).
+
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use fugit::{HertzU32 as Hertz, RateExtU32};
+
+use crate::gpio::{self, Alternate, NoPin};
+use crate::pac::{sai1, RCC, SAI1, SAI2};
+use crate::rcc::{Clocks, Enable, RccBus, Reset, HSI_FREQUENCY};
+
+mod circular;
+mod clock;
+
+pub use circular::{CircularTransfer, Half};
+pub use clock::{AudioClock, AudioPll};
+
+/// Source of the SAI kernel clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockSource {
+    /// Q output of the PLLSAI, divided by 1 to 32 (PLLSAIDIVQ). The divider
+    /// is shared by SAI1 and SAI2.
+    Pllsai(u8),
+    /// Q output of the PLLI2S, divided by 1 to 32 (PLLI2SDIVQ). The divider
+    /// is shared by SAI1 and SAI2.
+    Plli2s(u8),
+    /// I2S_CKIN pin, of the given frequency
+    External(Hertz),
+}
+
+/// Role of a sub-block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    MasterTransmitter,
+    MasterReceiver,
+    SlaveTransmitter,
+    SlaveReceiver,
+}
+
+impl Mode {
+    pub fn is_master(self) -> bool {
+        matches!(self, Mode::MasterTransmitter | Mode::MasterReceiver)
+    }
+
+    pub fn is_transmitter(self) -> bool {
+        matches!(self, Mode::MasterTransmitter | Mode::SlaveTransmitter)
+    }
+}
+
+/// Audio protocol, with two slots per frame
+///
+/// * `I2s`: the frame synchronization is low for the left channel, one bit
+///   before the data, which is MSB first
+/// * `MsbJustified`: the frame synchronization is high for the left
+///   channel, on the first bit of the data, which is at the start of the slot
+/// * `LsbJustified`: like `MsbJustified`, but the data is at the end of the
+///   slot
+/// * `Tdm`: the frame synchronization is a pulse of one bit, one bit before
+///   the data of the first slot, which is MSB first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    I2s,
+    MsbJustified,
+    LsbJustified,
+    Tdm,
+}
+
+/// Size of the audio samples
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataSize {
+    Bits8,
+    Bits10,
+    Bits16,
+    Bits20,
+    Bits24,
+    Bits32,
+}
+
+impl DataSize {
+    pub fn bits(self) -> u8 {
+        match self {
+            DataSize::Bits8 => 8,
+            DataSize::Bits10 => 10,
+            DataSize::Bits16 => 16,
+            DataSize::Bits20 => 20,
+            DataSize::Bits24 => 24,
+            DataSize::Bits32 => 32,
+        }
+    }
+}
+
+/// Size of the slots, at least the size of the data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotSize {
+    /// The size of the data
+    DataSize,
+    Bits16,
+    Bits32,
+}
+
+/// Companding of the 8-bit samples, as in the G.711 telephony codecs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Companding {
+    None,
+    MuLaw,
+    ALaw,
+}
+
+/// Configuration of a sub-block
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub mode: Mode,
+    pub protocol: Protocol,
+    pub data_size: DataSize,
+    pub slot_size: SlotSize,
+    /// Number of slots per frame, 2 to 16, of up to 256 bits in total
+    pub slots: u8,
+    /// Slots transferred, bit n for slot n. The FIFO holds the samples of the
+    /// enabled slots only, in turn.
+    pub slot_enable: u16,
+    /// The same sample is sent in both slots, or only the first slot is
+    /// received, with 2 slots
+    pub mono: bool,
+    /// The 8-bit samples are compressed by a transmitter, and expanded by a
+    /// receiver, to and from 14-bit (µ-law) or 13-bit (A-law) samples, left
+    /// aligned in 16 bits
+    pub companding: Companding,
+    /// Frame rate of a master, ignored for a slave
+    pub sample_rate: Hertz,
+    /// A master generates the master clock at 256 times the sample rate,
+    /// output on the MCLK pin while the sub-block is enabled, instead of
+    /// dividing the kernel clock directly to the bit clock
+    pub master_clock: bool,
+    /// A slave is clocked by the other sub-block of the SAI, configured with
+    /// the same frame, instead of its own SCK and FS pins
+    pub synchronous: bool,
+}
+
+impl Config {
+    /// 16-bit stereo I2S at `sample_rate`, with the master clock
+    pub fn i2s(mode: Mode, sample_rate: Hertz) -> Self {
+        Config {
+            mode,
+            protocol: Protocol::I2s,
+            data_size: DataSize::Bits16,
+            slot_size: SlotSize::DataSize,
+            slots: 2,
+            slot_enable: 0b11,
+            mono: false,
+            companding: Companding::None,
+            sample_rate,
+            master_clock: true,
+            synchronous: false,
+        }
+    }
+
+    /// 16-bit TDM at `sample_rate`, with `slots` slots of 16 bits, all of
+    /// them enabled, and with the master clock
+    pub fn tdm(mode: Mode, sample_rate: Hertz, slots: u8) -> Self {
+        Config {
+            protocol: Protocol::Tdm,
+            slot_size: SlotSize::Bits16,
+            slots,
+            slot_enable: ((1u32 << slots) - 1) as u16,
+            ..Config::i2s(mode, sample_rate)
+        }
+    }
+
+    fn slot_bits(&self) -> u8 {
+        match self.slot_size {
+            SlotSize::DataSize => self.data_size.bits(),
+            SlotSize::Bits16 => 16,
+            SlotSize::Bits32 => 32,
+        }
+    }
+}
+
+/// SAI error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The FIFO was empty when a sample was sent, or full when a sample was
+    /// received
+    OverrunUnderrun,
+    /// The frame synchronization of a slave came earlier than expected
+    AnticipatedFrameSync,
+    /// The frame synchronization of a slave came later than expected
+    LateFrameSync,
+    /// The frame length of a master is not compatible with the master clock
+    WrongClockConfiguration,
+    /// The sample rate of a master is too low to be divided from the kernel
+    /// clock
+    SampleRate,
+    /// The DMA stream reported a transfer error
+    DmaTransfer,
+}
+
+/// SAI peripheral
+pub trait Instance: Deref<Target = sai1::RegisterBlock> + Enable + Reset {
+    #[doc(hidden)]
+    fn ptr() -> *const sai1::RegisterBlock;
+    #[doc(hidden)]
+    fn select_clock(rcc: &crate::pac::rcc::RegisterBlock, source: ClockSource);
+}
+
+macro_rules! instance {
+    ($($SAIX:ident: $saixsel:ident,)+) => {
+        $(
+            impl Instance for $SAIX {
+                fn ptr() -> *const sai1::RegisterBlock {
+                    $SAIX::ptr()
+                }
+
+                fn select_clock(rcc: &crate::pac::rcc::RegisterBlock, source: ClockSource) {
+                    rcc.dckcfgr1.modify(|_, w| match source {
+                        ClockSource::Pllsai(_) => w.$saixsel().pllsai(),
+                        ClockSource::Plli2s(_) => w.$saixsel().plli2s(),
+                        ClockSource::External(_) => w.$saixsel().afif(),
+                    });
+                }
+            }
+        )+
+    };
+}
+
+instance! {
+    SAI1: sai1sel,
+    SAI2: sai2sel,
+}
+
+/// Sub-block A or B of an SAI
+pub trait Block {
+    #[doc(hidden)]
+    fn ch(sai: &sai1::RegisterBlock) -> &sai1::CH;
+}
+
+pub struct BlockA;
+pub struct BlockB;
+
+impl Block for BlockA {
+    fn ch(sai: &sai1::RegisterBlock) -> &sai1::CH {
+        &sai.cha
+    }
+}
+
+impl Block for BlockB {
+    fn ch(sai: &sai1::RegisterBlock) -> &sai1::CH {
+        &sai.chb
+    }
+}
+
+/// Pins of a sub-block: SCK, FS, SD and MCLK
+pub trait Pins<I, B> {}
+
+impl<I, B, SCK, FS, SD, MCLK> Pins<I, B> for (SCK, FS, SD, MCLK)
+where
+    SCK: Sck<I, B>,
+    FS: Fs<I, B>,
+    SD: Sd<I, B>,
+    MCLK: Mclk<I, B>,
+{
+}
+
+/// Implemented for all pins that can function as the SCK pin of a sub-block
+///
+/// Users of this crate should not implement this trait.
+pub trait Sck<I, B> {}
+
+/// Implemented for all pins that can function as the FS pin of a sub-block
+///
+/// Users of this crate should not implement this trait.
+pub trait Fs<I, B> {}
+
+/// Implemented for all pins that can function as the SD pin of a sub-block
+///
+/// Users of this crate should not implement this trait.
+pub trait Sd<I, B> {}
+
+/// Implemented for all pins that can function as the MCLK pin of a
+/// sub-block
+///
+/// Users of this crate should not implement this trait.
+pub trait Mclk<I, B> {}
+
+impl<I, B> Sck<I, B> for NoPin {}
+impl<I, B> Fs<I, B> for NoPin {}
+impl<I, B> Sd<I, B> for NoPin {}
+impl<I, B> Mclk<I, B> for NoPin {}
+
+macro_rules! pins {
+    (
+        $(
+            $SAI:ty, $Block:ty {
+                SCK: [$($sck:ty,)*],
+                FS: [$($fs:ty,)*],
+                SD: [$($sd:ty,)*],
+                MCLK: [$($mclk:ty,)*],
+            }
+        )+
+    ) => {
+        $(
+            $(
+                impl Sck<$SAI, $Block> for $sck {}
+            )*
+            $(
+                impl Fs<$SAI, $Block> for $fs {}
+            )*
+            $(
+                impl Sd<$SAI, $Block> for $sd {}
+            )*
+            $(
+                impl Mclk<$SAI, $Block> for $mclk {}
+            )*
+        )+
+    };
+}
+
+pins! {
+    SAI1, BlockA {
+        SCK: [
+            gpio::PE5<Alternate<6>>,
+        ],
+        FS: [
+            gpio::PE4<Alternate<6>>,
+        ],
+        SD: [
+            gpio::PB2<Alternate<6>>,
+            gpio::PC1<Alternate<6>>,
+            gpio::PD6<Alternate<6>>,
+            gpio::PE6<Alternate<6>>,
+        ],
+        MCLK: [
+            gpio::PE2<Alternate<6>>,
+            gpio::PG7<Alternate<6>>,
+        ],
+    }
+    SAI1, BlockB {
+        SCK: [
+            gpio::PF8<Alternate<6>>,
+        ],
+        FS: [
+            gpio::PF9<Alternate<6>>,
+        ],
+        SD: [
+            gpio::PE3<Alternate<6>>,
+            gpio::PF6<Alternate<6>>,
+        ],
+        MCLK: [
+            gpio::PF7<Alternate<6>>,
+        ],
+    }
+    SAI2, BlockA {
+        SCK: [
+            gpio::PD13<Alternate<10>>,
+            gpio::PI5<Alternate<10>>,
+        ],
+        FS: [
+            gpio::PD12<Alternate<10>>,
+            gpio::PI7<Alternate<10>>,
+        ],
+        SD: [
+            gpio::PD11<Alternate<10>>,
+            gpio::PI6<Alternate<10>>,
+        ],
+        MCLK: [
+            gpio::PE0<Alternate<10>>,
+            gpio::PI4<Alternate<10>>,
+        ],
+    }
+    SAI2, BlockB {
+        SCK: [
+            gpio::PA2<Alternate<8>>,
+            gpio::PE12<Alternate<10>>,
+            gpio::PH2<Alternate<10>>,
+        ],
+        FS: [
+            gpio::PA12<Alternate<8>>,
+            gpio::PC0<Alternate<8>>,
+            gpio::PE13<Alternate<10>>,
+            gpio::PG9<Alternate<10>>,
+        ],
+        SD: [
+            gpio::PE11<Alternate<10>>,
+            gpio::PF11<Alternate<10>>,
+            gpio::PG10<Alternate<10>>,
+        ],
+        MCLK: [
+            gpio::PE14<Alternate<10>>,
+            gpio::PH3<Alternate<10>>,
+        ],
+    }
+}
+
+/// Entry point to the SAI API
+pub struct Sai<I> {
+    _sai: I,
+    kernel_clock: Hertz,
+}
+
+impl<I: Instance> Sai<I> {
+    /// Enables the SAI, clocked by `source`
+    pub fn new(sai: I, source: ClockSource, clocks: &Clocks, apb: &mut <I as RccBus>::Bus) -> Self {
+        I::enable(apb);
+        I::reset(apb);
+
+        // NOTE(unsafe) only the clock selection of this SAI and the shared
+        // Q dividers, set from `source`, are written
+        let rcc = unsafe { &*RCC::ptr() };
+        match source {
+            ClockSource::Pllsai(div) => {
+                assert!((1..=32).contains(&div));
+                rcc.dckcfgr1.modify(|_, w| w.pllsaidivq().bits(div - 1));
+            }
+            ClockSource::Plli2s(div) => {
+                assert!((1..=32).contains(&div));
+                rcc.dckcfgr1.modify(|_, w| w.plli2sdivq().bits(div - 1));
+            }
+            ClockSource::External(_) => {}
+        }
+        I::select_clock(rcc, source);
+
+        Sai {
+            _sai: sai,
+            kernel_clock: kernel_clock(source, clocks),
+        }
+    }
+
+    /// Returns the frequency of the kernel clock
+    pub fn kernel_clock(&self) -> Hertz {
+        self.kernel_clock
+    }
+
+    /// Splits the SAI into its two sub-blocks, connected to `pins_a` and
+    /// `pins_b`
+    pub fn split<PA, PB>(
+        self,
+        _pins_a: PA,
+        _pins_b: PB,
+    ) -> (SubBlock<I, BlockA>, SubBlock<I, BlockB>)
+    where
+        PA: Pins<I, BlockA>,
+        PB: Pins<I, BlockB>,
+    {
+        (
+            SubBlock::new(self.kernel_clock),
+            SubBlock::new(self.kernel_clock),
+        )
+    }
+}
+
+/// Returns the frequency of the SAI kernel clock from `source`, computed from
+/// the PLL configuration
+fn kernel_clock(source: ClockSource, clocks: &Clocks) -> Hertz {
+    // NOTE(unsafe) read only
+    let rcc = unsafe { &*RCC::ptr() };
+    let vco_input = vco_input(clocks);
+
+    let freq = match source {
+        ClockSource::Pllsai(div) => {
+            assert!(rcc.cr.read().pllsairdy().bit_is_set(), "The PLLSAI is off");
+            let cfgr = rcc.pllsaicfgr.read();
+            vco_input * u32::from(cfgr.pllsain().bits())
+                / u32::from(cfgr.pllsaiq().bits())
+                / u32::from(div)
+        }
+        ClockSource::Plli2s(div) => {
+            assert!(rcc.cr.read().plli2srdy().bit_is_set(), "The PLLI2S is off");
+            let cfgr = rcc.plli2scfgr.read();
+            vco_input * u32::from(cfgr.plli2sn().bits())
+                / u32::from(cfgr.plli2sq().bits())
+                / u32::from(div)
+        }
+        ClockSource::External(freq) => return freq,
+    };
+    freq.Hz()
+}
+
+/// Returns the input frequency of the PLLs, after the PLLM divider
+fn vco_input(clocks: &Clocks) -> u32 {
+    // NOTE(unsafe) read only
+    let pllcfgr = unsafe { &*RCC::ptr() }.pllcfgr.read();
+    let input = if pllcfgr.pllsrc().bit_is_set() {
+        clocks.hse().expect("The PLLs are clocked by the HSE").raw()
+    } else {
+        HSI_FREQUENCY.raw()
+    };
+    input / u32::from(pllcfgr.pllm().bits())
+}
+
+/// Returns the MCKDIV value dividing `kernel_clock` the closest to `target`,
+/// with the 2 factor of the divider, or `None` if the division is above the
+/// range of MCKDIV
+fn master_divider(kernel_clock: u32, target: u32) -> Option<u8> {
+    // The divider is MCKDIV * 2, or 1 when MCKDIV is 0
+    let div = (kernel_clock + target / 2) / target;
+    if div <= 1 {
+        Some(0)
+    } else if div <= 31 {
+        Some(((div + 1) / 2) as u8)
+    } else {
+        None
+    }
+}
+
+/// Sub-block of an SAI, transmitting or receiving audio samples
+pub struct SubBlock<I, B> {
+    kernel_clock: Hertz,
+    sample_rate: Option<Hertz>,
+    master_clock: Option<Hertz>,
+    _sai: PhantomData<(I, B)>,
+}
+
+impl<I: Instance, B: Block> SubBlock<I, B> {
+    fn new(kernel_clock: Hertz) -> Self {
+        SubBlock {
+            kernel_clock,
+            sample_rate: None,
+            master_clock: None,
+            _sai: PhantomData,
+        }
+    }
+
+    pub(crate) fn ch(&self) -> &sai1::CH {
+        // NOTE(unsafe) the registers of this sub-block are owned by `self`
+        B::ch(unsafe { &*I::ptr() })
+    }
+
+    /// Configures the sub-block, which is disabled
+    ///
+    /// The sample rate of a master is the closest reachable from the kernel
+    /// clock, returned by [`SubBlock::sample_rate`]. `Error::SampleRate` is
+    /// returned if it is too low for the master clock divider.
+    pub fn configure(&mut self, config: &Config) -> Result<(), Error> {
+        self.disable();
+
+        let data_bits = config.data_size.bits();
+        let slot_bits = config.slot_bits();
+        assert!(slot_bits >= data_bits);
+        assert!((2..=16).contains(&config.slots));
+        assert!(config.slot_enable != 0 && u32::from(config.slot_enable) >> config.slots == 0);
+        assert!(config.companding == Companding::None || data_bits == 8);
+        let frame_bits = u32::from(slot_bits) * u32::from(config.slots);
+        assert!(frame_bits <= 256);
+        assert!(!config.synchronous || !config.mode.is_master());
+
+        // The bit clock, divided from the master clock at 256 times the
+        // sample rate, or directly from the kernel clock
+        let mckdiv = if config.mode.is_master() {
+            let per_frame = if config.master_clock { 256 } else { frame_bits };
+            let target = config.sample_rate.raw() * per_frame;
+            let mckdiv =
+                master_divider(self.kernel_clock.raw(), target).ok_or(Error::SampleRate)?;
+            let div = if mckdiv == 0 {
+                1
+            } else {
+                u32::from(mckdiv) * 2
+            };
+            self.sample_rate = Some((self.kernel_clock.raw() / div / per_frame).Hz());
+            self.master_clock = if config.master_clock {
+                Some((self.kernel_clock.raw() / div).Hz())
+            } else {
+                None
+            };
+            mckdiv
+        } else {
+            self.sample_rate = None;
+            self.master_clock = None;
+            0
+        };
+
+        let ch = self.ch();
+        ch.cr1.write(|w| {
+            let w = match config.mode {
+                Mode::MasterTransmitter => w.mode().master_tx(),
+                Mode::MasterReceiver => w.mode().master_rx(),
+                Mode::SlaveTransmitter => w.mode().slave_tx(),
+                Mode::SlaveReceiver => w.mode().slave_rx(),
+            };
+            let w = match config.data_size {
+                DataSize::Bits8 => w.ds().bit8(),
+                DataSize::Bits10 => w.ds().bit10(),
+                DataSize::Bits16 => w.ds().bit16(),
+                DataSize::Bits20 => w.ds().bit20(),
+                DataSize::Bits24 => w.ds().bit24(),
+                DataSize::Bits32 => w.ds().bit32(),
+            };
+            // The transmitters change the data on the falling edge of the bit
+            // clock, and the receivers sample it on the rising edge
+            let w = if config.mode.is_transmitter() {
+                w.ckstr().clear_bit()
+            } else {
+                w.ckstr().set_bit()
+            };
+            unsafe { w.mckdiv().bits(mckdiv) }
+                .nodiv()
+                .bit(!config.master_clock)
+                .mono()
+                .bit(config.mono)
+                .prtcfg()
+                .free()
+                .lsbfirst()
+                .msb_first()
+                .syncen()
+                .variant(if config.synchronous {
+                    sai1::ch::cr1::SYNCEN_A::INTERNAL
+                } else {
+                    sai1::ch::cr1::SYNCEN_A::ASYNCHRONOUS
+                })
+                .outdriv()
+                .on_start()
+        });
+        ch.cr2.write(|w| {
+            let w = match config.companding {
+                Companding::None => w.comp().no_companding(),
+                Companding::MuLaw => w.comp().mu_law(),
+                Companding::ALaw => w.comp().alaw(),
+            };
+            w.cpl().twos_complement().fflush().set_bit()
+        });
+
+        ch.frcr.write(|w| {
+            let w = unsafe { w.frl().bits((frame_bits - 1) as u8) };
+            match config.protocol {
+                // The frame synchronization identifies the channel
+                Protocol::I2s | Protocol::MsbJustified | Protocol::LsbJustified => {
+                    let w = unsafe { w.fsall().bits((frame_bits / 2 - 1) as u8) }
+                        .fsdef()
+                        .set_bit();
+                    if config.protocol == Protocol::I2s {
+                        w.fspol().falling_edge().fsoff().before_first()
+                    } else {
+                        w.fspol().rising_edge().fsoff().on_first()
+                    }
+                }
+                // The frame synchronization only marks the start of the frame
+                Protocol::Tdm => unsafe { w.fsall().bits(0) }
+                    .fsdef()
+                    .clear_bit()
+                    .fspol()
+                    .rising_edge()
+                    .fsoff()
+                    .before_first(),
+            }
+        });
+
+        let first_bit_offset = match config.protocol {
+            Protocol::LsbJustified => slot_bits - data_bits,
+            _ => 0,
+        };
+        ch.slotr.write(|w| {
+            let w = match config.slot_size {
+                SlotSize::DataSize => w.slotsz().data_size(),
+                SlotSize::Bits16 => w.slotsz().bit16(),
+                SlotSize::Bits32 => w.slotsz().bit32(),
+            };
+            unsafe {
+                w.sloten()
+                    .bits(config.slot_enable)
+                    .nbslot()
+                    .bits(config.slots - 1)
+                    .fboff()
+                    .bits(first_bit_offset)
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Returns the sample rate of a master, or `None` for a slave
+    pub fn sample_rate(&self) -> Option<Hertz> {
+        self.sample_rate
+    }
+
+    /// Returns the frequency of the master clock output by a master, or
+    /// `None` without master clock
+    pub fn master_clock(&self) -> Option<Hertz> {
+        self.master_clock
+    }
+
+    /// Enables the sub-block, which starts transferring, once the FIFO of a
+    /// transmitter is filled
+    pub fn enable(&mut self) {
+        self.ch().cr1.modify(|_, w| w.saien().enabled());
+    }
+
+    /// Enables this synchronous sub-block, then the `master` sub-block it is
+    /// clocked by, so both start on the same frame
+    pub fn enable_synchronized<M: Block>(&mut self, master: &mut SubBlock<I, M>) {
+        self.enable();
+        master.enable();
+    }
+
+    /// Disables the sub-block at the end of the current frame, and waits for
+    /// it
+    pub fn disable(&mut self) {
+        let ch = self.ch();
+        ch.cr1.modify(|_, w| w.saien().disabled());
+        while ch.cr1.read().saien().bit_is_set() {}
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ch().cr1.read().saien().bit_is_set()
+    }
+
+    /// Empties the FIFO
+    pub fn flush(&mut self) {
+        self.ch().cr2.modify(|_, w| w.fflush().set_bit());
+    }
+
+    /// Returns and clears the error flags
+    fn check_errors(&mut self) -> Result<(), Error> {
+        let ch = self.ch();
+        let sr = ch.sr.read();
+        let error = if sr.ovrudr().bit_is_set() {
+            Error::OverrunUnderrun
+        } else if sr.afsdet().bit_is_set() {
+            Error::AnticipatedFrameSync
+        } else if sr.lfsdet().bit_is_set() {
+            Error::LateFrameSync
+        } else if sr.wckcfg().bit_is_set() {
+            Error::WrongClockConfiguration
+        } else {
+            return Ok(());
+        };
+        ch.clrfr.write(|w| {
+            w.covrudr()
+                .set_bit()
+                .cafsdet()
+                .set_bit()
+                .clfsdet()
+                .set_bit()
+                .cwckcfg()
+                .set_bit()
+        });
+        Err(error)
+    }
+
+    /// Writes a sample to the FIFO of a transmitter, the slots being sent
+    /// in turn
+    pub fn write(&mut self, sample: u32) -> nb::Result<(), Error> {
+        self.check_errors()?;
+        let ch = self.ch();
+        if ch.sr.read().flvl().is_full() {
+            return Err(nb::Error::WouldBlock);
+        }
+        ch.dr.write(|w| unsafe { w.bits(sample) });
+        Ok(())
+    }
+
+    /// Reads a sample from the FIFO of a receiver, the slots being received
+    /// in turn
+    pub fn read(&mut self) -> nb::Result<u32, Error> {
+        self.check_errors()?;
+        let ch = self.ch();
+        if ch.sr.read().flvl().is_empty() {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(ch.dr.read().bits())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_master_divider() {
+        // 49.152 MHz, 48 kHz with the master clock
+        assert_eq!(master_divider(49_152_000, 48_000 * 256), Some(2));
+        // 12.288 MHz, 48 kHz with the master clock
+        assert_eq!(master_divider(12_288_000, 48_000 * 256), Some(0));
+        // 45.1584 MHz, 44.1 kHz with a 64-bit frame
+        assert_eq!(master_divider(45_158_400, 44_100 * 64), Some(8));
+        // 45.1584 MHz, 44.1 kHz with a 32-bit frame, beyond MCKDIV
+        assert_eq!(master_divider(45_158_400, 44_100 * 32), None);
+    }
+}
@@ -0,0 +1,238 @@
+//! Continuous audio streaming with a circular DMA transfer
+//!
+//! The DMA goes over the buffer again and again: while it transfers one
+//! half, the other half is refilled for the playback, or processed for the
+//! capture. [`CircularTransfer::poll`] hands each half over as soon as the
+//! DMA is done with it, from the half transfer and transfer complete flags,
+//! which can also raise the DMA stream interrupt.
+//!
+//! ```ignore
+//! let pins = (
+//!     gpioe.pe12.into_alternate(),
+//!     gpioe.pe13.into_alternate(),
+//!     gpioe.pe11.into_alternate(),
+//!     gpioe.pe14.into_alternate(),
+//! );
+//! let (_, mut tx) = Sai::new(dp.SAI2, ClockSource::Plli2s(1), &clocks, &mut rcc.apb2)
+//!     .split((NoPin, NoPin, NoPin, NoPin), pins);
+//! tx.configure(&Config::i2s(Mode::MasterTransmitter, 48.kHz()))?;
+//! let mut transfer = tx.write_circular(buffer, &dma, dma_streams.stream7);
+//! transfer.listen(&dma);
+//! let transfer = transfer.start(&dma);
+//!
+//! // In the DMA2_STREAM7 interrupt handler
+//! transfer.poll(&dma, |_half, samples| fill(samples))?;
+//! ```
+
+use core::ops::DerefMut;
+use core::pin::Pin;
+
+use as_slice::AsMutSlice;
+
+use super::{Block, Error, Instance, SubBlock};
+use crate::{
+    dma::{self, SupportedWordSize},
+    state,
+};
+
+/// Half of the buffer of a circular transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Half {
+    First,
+    Second,
+}
+
+/// Circular DMA transfer of a sub-block
+pub struct CircularTransfer<I, B, BUF, Word, State>
+where
+    SubBlock<I, B>: dma::Target,
+{
+    transfer: dma::Transfer<SubBlock<I, B>, BUF, State>,
+    ptr: *mut Word,
+    len: usize,
+}
+
+type Handle<I, B> = dma::Handle<<SubBlock<I, B> as dma::Target>::Instance, state::Enabled>;
+
+impl<I, B> SubBlock<I, B>
+where
+    I: Instance,
+    B: Block,
+    SubBlock<I, B>: dma::Target,
+{
+    /// Plays `buffer` continuously, the sub-block being a configured
+    /// transmitter
+    ///
+    /// The buffer holds the samples of the slots in turn, and its length must
+    /// be even.
+    pub fn write_circular<BUF, Word>(
+        self,
+        buffer: Pin<BUF>,
+        handle: &Handle<I, B>,
+        stream: <Self as dma::Target>::Stream,
+    ) -> CircularTransfer<I, B, BUF, Word, dma::Ready>
+    where
+        BUF: DerefMut + 'static,
+        BUF::Target: AsMutSlice<Element = Word>,
+        Word: SupportedWordSize,
+    {
+        self.circular(buffer, handle, stream, dma::Direction::MemoryToPeripheral)
+    }
+
+    /// Captures to `buffer` continuously, the sub-block being a configured
+    /// receiver
+    ///
+    /// The buffer receives the samples of the slots in turn, and its length
+    /// must be even.
+    pub fn read_circular<BUF, Word>(
+        self,
+        buffer: Pin<BUF>,
+        handle: &Handle<I, B>,
+        stream: <Self as dma::Target>::Stream,
+    ) -> CircularTransfer<I, B, BUF, Word, dma::Ready>
+    where
+        BUF: DerefMut + 'static,
+        BUF::Target: AsMutSlice<Element = Word>,
+        Word: SupportedWordSize,
+    {
+        self.circular(buffer, handle, stream, dma::Direction::PeripheralToMemory)
+    }
+
+    fn circular<BUF, Word>(
+        self,
+        mut buffer: Pin<BUF>,
+        handle: &Handle<I, B>,
+        stream: <Self as dma::Target>::Stream,
+        direction: dma::Direction,
+    ) -> CircularTransfer<I, B, BUF, Word, dma::Ready>
+    where
+        BUF: DerefMut + 'static,
+        BUF::Target: AsMutSlice<Element = Word>,
+        Word: SupportedWordSize,
+    {
+        // The slice is only accessed through `ptr` and `len` from now on
+        let slice = unsafe { buffer.as_mut().get_unchecked_mut() }.as_mut_slice();
+        let (ptr, len) = (slice.as_mut_ptr(), slice.len());
+        assert!(len > 0 && len % 2 == 0);
+
+        let ch = self.ch();
+        ch.cr1.modify(|_, w| w.dmaen().enabled());
+        let address = &ch.dr as *const _ as u32;
+
+        // Safe, because the buffer is owned by the transfer, and only
+        // accessed by the DMA or by `poll`, one half at a time
+        let mut transfer =
+            unsafe { dma::Transfer::new(handle, stream, buffer, self, address, direction) };
+        transfer.enable_circular(handle);
+
+        CircularTransfer { transfer, ptr, len }
+    }
+}
+
+impl<I, B, BUF, Word> CircularTransfer<I, B, BUF, Word, dma::Ready>
+where
+    I: Instance,
+    B: Block,
+    SubBlock<I, B>: dma::Target,
+    BUF: 'static,
+{
+    /// Enables the half transfer, transfer complete and transfer error
+    /// interrupts of the DMA stream
+    pub fn listen(&mut self, handle: &Handle<I, B>) {
+        self.transfer.enable_interrupts(
+            handle,
+            dma::Interrupts {
+                transfer_complete: true,
+                half_transfer: true,
+                transfer_error: true,
+                ..dma::Interrupts::default()
+            },
+        );
+    }
+
+    /// Starts the DMA transfer, then the sub-block
+    ///
+    /// Of synchronized sub-blocks, the synchronous one must be started
+    /// first, so both start on the same frame.
+    pub fn start(self, handle: &Handle<I, B>) -> CircularTransfer<I, B, BUF, Word, dma::Started> {
+        let transfer = self.transfer.start(handle);
+        // NOTE(unsafe) the sub-block is owned by the transfer
+        B::ch(unsafe { &*I::ptr() })
+            .cr1
+            .modify(|_, w| w.saien().enabled());
+
+        CircularTransfer {
+            transfer,
+            ptr: self.ptr,
+            len: self.len,
+        }
+    }
+}
+
+impl<I, B, BUF, Word> CircularTransfer<I, B, BUF, Word, dma::Started>
+where
+    I: Instance,
+    B: Block,
+    SubBlock<I, B>: dma::Target,
+{
+    /// Calls `f` with the half of the buffer the DMA is done with, if any:
+    /// to be refilled for the playback, or processed for the capture
+    ///
+    /// `f` must return before the DMA reaches this half again, in half of the
+    /// buffer duration. An underrun of the transmitter, or an overrun of the
+    /// receiver, is reported as [`Error::OverrunUnderrun`], and the stream
+    /// goes on.
+    pub fn poll<F>(&mut self, handle: &Handle<I, B>, f: F) -> Result<Option<Half>, Error>
+    where
+        F: FnOnce(Half, &mut [Word]),
+    {
+        self.transfer
+            .check_errors(handle)
+            .map_err(|_| Error::DmaTransfer)?;
+
+        // NOTE(unsafe) read and clear of the flags of the sub-block owned by
+        // the transfer
+        let ch = B::ch(unsafe { &*I::ptr() });
+        if ch.sr.read().ovrudr().bit_is_set() {
+            ch.clrfr.write(|w| w.covrudr().set_bit());
+            return Err(Error::OverrunUnderrun);
+        }
+
+        let half = if self.transfer.take_half_transfer(handle) {
+            Half::First
+        } else if self.transfer.take_transfer_complete(handle) {
+            Half::Second
+        } else {
+            return Ok(None);
+        };
+
+        let len = self.len / 2;
+        let offset = match half {
+            Half::First => 0,
+            Half::Second => len,
+        };
+        // Safe, because the DMA is transferring the other half
+        let samples = unsafe { core::slice::from_raw_parts_mut(self.ptr.add(offset), len) };
+        f(half, samples);
+        Ok(Some(half))
+    }
+
+    /// Stops the sub-block and the DMA transfer, and returns the resources
+    #[allow(clippy::type_complexity)]
+    pub fn stop(
+        self,
+        handle: &Handle<I, B>,
+    ) -> Result<
+        dma::TransferResources<SubBlock<I, B>, BUF>,
+        (dma::TransferResources<SubBlock<I, B>, BUF>, dma::Error),
+    > {
+        // NOTE(unsafe) the sub-block is owned by the transfer
+        let ch = B::ch(unsafe { &*I::ptr() });
+        ch.cr1
+            .modify(|_, w| w.saien().disabled().dmaen().disabled());
+        while ch.cr1.read().saien().bit_is_set() {}
+
+        self.transfer.cancel(handle);
+        self.transfer.wait(handle)
+    }
+}
@@ -0,0 +1,177 @@
+//! Audio PLL configuration for the standard sample rates
+//!
+//! The 44.1 kHz and 48 kHz families of sample rates are not reachable
+//! exactly from the usual PLL inputs. [`AudioClock::configure`] searches the
+//! N and Q factors of the PLLSAI or the PLLI2S, the DIVQ divider of the SAI
+//! kernel clock and the MCKDIV divider of the sub-blocks, for the master
+//! clock the closest to the sample rate times the MCLK ratio.
+//!
+//! ```ignore
+//! let audio = AudioClock::configure(AudioPll::Plli2s, 48.kHz(), 256, &clocks);
+//! let pins = (
+//!     gpioe.pe12.into_alternate(),
+//!     gpioe.pe13.into_alternate(),
+//!     gpioe.pe11.into_alternate(),
+//!     gpioe.pe14.into_alternate(),
+//! );
+//! let (_, mut tx) = Sai::new(dp.SAI2, audio.source, &clocks, &mut rcc.apb2)
+//!     .split((NoPin, NoPin, NoPin, NoPin), pins);
+//! tx.configure(&Config::i2s(Mode::MasterTransmitter, 48.kHz()))?;
+//! ```
+
+use fugit::{HertzU32 as Hertz, RateExtU32};
+
+use super::{master_divider, vco_input, ClockSource};
+use crate::pac::RCC;
+use crate::rcc::{pll, Clocks};
+
+/// PLL feeding the SAI kernel clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioPll {
+    Pllsai,
+    Plli2s,
+}
+
+/// Clocks of the audio PLL, configured for a sample rate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioClock {
+    /// Kernel clock source, to pass to [`super::Sai::new`]
+    pub source: ClockSource,
+    pub kernel_clock: Hertz,
+    /// Master clock, divided from the kernel clock by the sub-blocks
+    pub master_clock: Hertz,
+    /// Sample rate achieved, the master clock divided by the MCLK ratio
+    pub sample_rate: Hertz,
+    /// Error of the sample rate, in parts per million
+    pub error_ppm: i32,
+}
+
+/// Factors of the PLL and dividers for a master clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Dividers {
+    n: u16,
+    q: u8,
+    div_q: u8,
+    mckdiv: u8,
+    error_ppm: i32,
+}
+
+impl AudioClock {
+    /// Configures `pll` for a master clock at `mclk_ratio` times
+    /// `sample_rate`, and returns the clocks achieved
+    ///
+    /// The SAI master clock is at 256 times the sample rate: `mclk_ratio`
+    /// must be 256 for a sub-block configured with `master_clock`, or the
+    /// number of bits of the frame without.
+    ///
+    /// The PLL is stopped, reconfigured and restarted, which also changes its
+    /// other outputs: the LTDC and 48 MHz clocks for the PLLSAI, the SPDIFRX
+    /// clock for the PLLI2S. The DIVQ divider is shared by SAI1 and SAI2.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no configuration reaches the master clock within 1%.
+    pub fn configure(
+        pll: AudioPll,
+        sample_rate: Hertz,
+        mclk_ratio: u32,
+        clocks: &Clocks,
+    ) -> AudioClock {
+        let vco_input = vco_input(clocks);
+        let target = sample_rate.raw() * mclk_ratio;
+        let dividers = search(vco_input, target).expect("No PLL configuration for the sample rate");
+        let Dividers {
+            n,
+            q,
+            div_q,
+            mckdiv,
+            ..
+        } = dividers;
+
+        // NOTE(unsafe) only the PLL reconfigured for the SAI and the shared
+        // DIVQ divider are written
+        let rcc = unsafe { &*RCC::ptr() };
+        let source = match pll {
+            AudioPll::Pllsai => {
+                rcc.cr.modify(|_, w| w.pllsaion().off());
+                while rcc.cr.read().pllsairdy().bit_is_set() {}
+                rcc.pllsaicfgr
+                    .modify(|_, w| unsafe { w.pllsain().bits(n).pllsaiq().bits(q) });
+                rcc.cr.modify(|_, w| w.pllsaion().on());
+                while rcc.cr.read().pllsairdy().bit_is_clear() {}
+                rcc.dckcfgr1.modify(|_, w| w.pllsaidivq().bits(div_q - 1));
+                ClockSource::Pllsai(div_q)
+            }
+            AudioPll::Plli2s => {
+                rcc.cr.modify(|_, w| w.plli2son().off());
+                while rcc.cr.read().plli2srdy().bit_is_set() {}
+                rcc.plli2scfgr
+                    .modify(|_, w| unsafe { w.plli2sn().bits(n).plli2sq().bits(q) });
+                rcc.cr.modify(|_, w| w.plli2son().on());
+                while rcc.cr.read().plli2srdy().bit_is_clear() {}
+                rcc.dckcfgr1.modify(|_, w| w.plli2sdivq().bits(div_q - 1));
+                ClockSource::Plli2s(div_q)
+            }
+        };
+
+        let kernel_clock = vco_input * u32::from(n) / u32::from(q) / u32::from(div_q);
+        let master_clock = kernel_clock / mckdiv_factor(mckdiv);
+        AudioClock {
+            source,
+            kernel_clock: kernel_clock.Hz(),
+            master_clock: master_clock.Hz(),
+            sample_rate: (master_clock / mclk_ratio).Hz(),
+            error_ppm: dividers.error_ppm,
+        }
+    }
+}
+
+/// Returns the division of the master clock for MCKDIV
+fn mckdiv_factor(mckdiv: u8) -> u32 {
+    if mckdiv == 0 {
+        1
+    } else {
+        u32::from(mckdiv) * 2
+    }
+}
+
+/// Returns the dividers of the master clock the closest to `target`, from a
+/// PLL input of `vco_input`, within 1%
+fn search(vco_input: u32, target: u32) -> Option<Dividers> {
+    let found = pll::search(vco_input, 2..=15, target, 10_000, |q_output| {
+        (1..=32u8).filter_map(move |div_q| {
+            let mckdiv = master_divider(q_output / u32::from(div_q), target)?;
+            Some((u32::from(div_q) * mckdiv_factor(mckdiv), (div_q, mckdiv)))
+        })
+    })?;
+    let (div_q, mckdiv) = found.post;
+    Some(Dividers {
+        n: found.n,
+        q: found.div,
+        div_q,
+        mckdiv,
+        error_ppm: (found.error_ppb / 1_000) as i32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        // 48 kHz and 44.1 kHz, MCLK at 256 times, from a 1 MHz PLL input
+        for &(target, max_error) in &[(12_288_000, 200), (11_289_600, 20)] {
+            let dividers = search(1_000_000, target).unwrap();
+            assert!(dividers.error_ppm.abs() <= max_error);
+
+            let kernel_clock = 1_000_000 * u32::from(dividers.n)
+                / u32::from(dividers.q)
+                / u32::from(dividers.div_q);
+            assert_eq!(master_divider(kernel_clock, target), Some(dividers.mckdiv));
+        }
+
+        // 96 kHz from a 2 MHz PLL input
+        assert!(search(2_000_000, 24_576_000).unwrap().error_ppm.abs() <= 200);
+    }
+}
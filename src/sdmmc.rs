@@ -0,0 +1,950 @@
+//! SD card, eMMC and SDIO driver for the SDMMC1 interface
+//!
+//! The card is initialized at 400 kHz with [`Sdmmc::init_card`], then read and
+//! written by 512-byte blocks at up to 25 MHz, or 50 MHz in high speed mode. The
+//! data goes through the FIFO, polled by the CPU. The command and data lines
+//! need pull-ups, either external or the internal ones of the GPIOs.
+//!
+//! Once identified, the card is switched to the bus width of the [`Pins`]
+//! given: 1 bit with the clock, command and D0 pins only, 4 bits with D0 to
+//! D3, or 8 bits with D0 to D7 for the eMMC devices (the SD cards then use 4
+//! bits). The card is then switched to the high speed mode when it supports
+//! it. The eMMC devices, which don't answer the SD initialization
+//! commands, are detected by [`Sdmmc::init_card`].
+//!
+//! The SDIO cards, such as the WiFi and Bluetooth modules, are initialized
+//! with [`Sdmmc::init_sdio`] instead. Their registers are accessed with
+//! CMD52 and CMD53 through [`Sdmmc::io_read_byte`], [`Sdmmc::io_read`] and
+//! their write counterparts, and the interrupts of their functions are
+//! enabled with [`Sdmmc::listen_io_interrupt`].
+//!
+//! For the removable cards, the card detect and write protect switches of the
+//! [`Slot`] are polled by [`Sdmmc::update_slot`], which forgets the card once
+//! removed and initializes the new one once inserted. A transfer interrupted
+//! by the removal fails, and the next ones return [`Error::NoCard`].
+//!
+//! The SDMMC kernel clock is selected in
+//! [`KernelClocks::sdmmc1`](crate::rcc::KernelClocks::sdmmc1): by default the
+//! 48 MHz PLL48CLK, which must be enabled with
+//! [`CFGR::use_pll48clk`](crate::rcc::CFGR::use_pll48clk).
+//!
+//! With the `sdmmc-fatfs` feature, [`SdmmcBlockDevice`] implements the
+//! `BlockDevice` trait of `embedded-sdmmc`, to mount FAT filesystems.
+//!
+//! See section 35 of RM0410.
+
+use crate::gpio::{
+    gpiob::{PB8, PB9},
+    gpioc::{PC10, PC11, PC12, PC6, PC7, PC8, PC9},
+    gpiod::PD2,
+    Alternate,
+};
+use crate::pac::SDMMC1;
+use crate::rcc::{Clocks, Enable, Reset, UpdateClocks, APB2};
+
+/// Size of the blocks read and written
+pub const BLOCK_SIZE: usize = 512;
+
+/// Highest SDMMC_CK frequency of the identification mode
+const INIT_CLOCK: u32 = 400_000;
+/// Highest SDMMC_CK frequency of the default speed mode
+const DEFAULT_SPEED_CLOCK: u32 = 25_000_000;
+/// Highest SDMMC_CK frequency of the high speed mode
+const HIGH_SPEED_CLOCK: u32 = 50_000_000;
+
+/// Error bits of the R1 card status
+const R1_ERRORS: u32 = 0xfdff_e008;
+const R1_READY_FOR_DATA: u32 = 1 << 8;
+/// Transfer state, in the CURRENT_STATE field of the R1 card status
+const R1_STATE_TRANSFER: u32 = 4;
+/// Card powered up, in the OCR
+const OCR_BUSY: u32 = 1 << 31;
+/// High capacity card, in the OCR
+const OCR_CCS: u32 = 1 << 30;
+/// 3.2-3.4 V supported, in the OCR
+const OCR_VOLTAGE: u32 = 0x0030_0000;
+/// Voltage supplied (2.7-3.6 V) and check pattern of CMD8
+const CMD8_ARG: u32 = 0x1aa;
+/// Switch command class (CMD6) support, in the CCC field of the CSD
+const CSD_CCC_SWITCH: u32 = 84 + 10;
+/// CMD6 argument selecting the high speed function of the group 1, the
+/// other groups unchanged
+const SWITCH_HIGH_SPEED: u32 = 0x80ff_fff1;
+/// Sector addressing and 2.7-3.6 V, in the eMMC OCR
+const OCR_EMMC: u32 = 0x40ff_8000;
+/// Sector addressing, in the eMMC OCR access mode
+const OCR_SECTOR_MODE: u32 = 0b10 << 29;
+/// Relative address assigned to the eMMC device
+const EMMC_RCA: u16 = 1;
+/// Error of a CMD6 switch, in the R1 card status of an eMMC
+const R1_SWITCH_ERROR: u32 = 1 << 7;
+/// Fields of the eMMC extended CSD register
+const EXT_CSD_BUS_WIDTH: u8 = 183;
+const EXT_CSD_HS_TIMING: u8 = 185;
+const EXT_CSD_CARD_TYPE: usize = 196;
+const EXT_CSD_SEC_COUNT: usize = 212;
+/// 52 MHz high speed support, in the eMMC CARD_TYPE field
+const CARD_TYPE_HS_52: u8 = 1 << 1;
+/// Attempts of ACMD41 while the card powers up
+const POWER_UP_ATTEMPTS: u32 = 10_000;
+
+/// Static flags of the SDMMC_STA register
+const STA_CCRCFAIL: u32 = 1 << 0;
+const STA_DCRCFAIL: u32 = 1 << 1;
+const STA_CTIMEOUT: u32 = 1 << 2;
+const STA_DTIMEOUT: u32 = 1 << 3;
+const STA_TXUNDERR: u32 = 1 << 4;
+const STA_RXOVERR: u32 = 1 << 5;
+const STA_CMDREND: u32 = 1 << 6;
+const STA_CMDSENT: u32 = 1 << 7;
+const STA_DATAEND: u32 = 1 << 8;
+const STA_DBCKEND: u32 = 1 << 10;
+const STA_TXFIFOHE: u32 = 1 << 14;
+const STA_RXFIFOHF: u32 = 1 << 15;
+const STA_RXDAVL: u32 = 1 << 21;
+const STA_SDIOIT: u32 = 1 << 22;
+const STA_STATIC: u32 = STA_CCRCFAIL
+    | STA_DCRCFAIL
+    | STA_CTIMEOUT
+    | STA_DTIMEOUT
+    | STA_TXUNDERR
+    | STA_RXOVERR
+    | STA_CMDREND
+    | STA_CMDSENT
+    | STA_DATAEND
+    | STA_DBCKEND
+    | STA_SDIOIT;
+
+mod sdio;
+mod slot;
+
+pub use slot::{Slot, SlotEvent, WriteProtect};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// SDMMC1 clock
+pub trait Ck: sealed::Sealed {}
+/// SDMMC1 command
+pub trait Cmd: sealed::Sealed {}
+/// SDMMC1 data 0
+pub trait D0: sealed::Sealed {}
+pub trait D1: sealed::Sealed {}
+pub trait D2: sealed::Sealed {}
+pub trait D3: sealed::Sealed {}
+pub trait D4: sealed::Sealed {}
+pub trait D5: sealed::Sealed {}
+pub trait D6: sealed::Sealed {}
+pub trait D7: sealed::Sealed {}
+
+macro_rules! pins {
+    ($($PIN:ident: [$($Signal:ident),+],)+) => {
+        $(
+            impl sealed::Sealed for $PIN<Alternate<12>> {}
+            $(
+                impl $Signal for $PIN<Alternate<12>> {}
+            )+
+        )+
+    };
+}
+
+pins! {
+    PB8: [D4],
+    PB9: [D5],
+    PC6: [D6],
+    PC7: [D7],
+    PC8: [D0],
+    PC9: [D1],
+    PC10: [D2],
+    PC11: [D3],
+    PC12: [Ck],
+    PD2: [Cmd],
+}
+
+/// Data bus width
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusWidth {
+    One = 0b00,
+    Four = 0b01,
+    Eight = 0b10,
+}
+
+/// Pins of the card bus: the clock, command and 1, 4 or 8 data pins
+pub trait Pins: sealed::Sealed {
+    #[doc(hidden)]
+    const BUS_WIDTH: BusWidth;
+}
+
+impl<CK: Ck, CMD: Cmd, DAT0: D0> sealed::Sealed for (CK, CMD, DAT0) {}
+impl<CK: Ck, CMD: Cmd, DAT0: D0> Pins for (CK, CMD, DAT0) {
+    const BUS_WIDTH: BusWidth = BusWidth::One;
+}
+
+impl<CK, CMD, DAT0, DAT1, DAT2, DAT3> sealed::Sealed for (CK, CMD, DAT0, DAT1, DAT2, DAT3)
+where
+    CK: Ck,
+    CMD: Cmd,
+    DAT0: D0,
+    DAT1: D1,
+    DAT2: D2,
+    DAT3: D3,
+{
+}
+
+impl<CK, CMD, DAT0, DAT1, DAT2, DAT3> Pins for (CK, CMD, DAT0, DAT1, DAT2, DAT3)
+where
+    CK: Ck,
+    CMD: Cmd,
+    DAT0: D0,
+    DAT1: D1,
+    DAT2: D2,
+    DAT3: D3,
+{
+    const BUS_WIDTH: BusWidth = BusWidth::Four;
+}
+
+impl<CK, CMD, DAT0, DAT1, DAT2, DAT3, DAT4, DAT5, DAT6, DAT7> sealed::Sealed
+    for (CK, CMD, DAT0, DAT1, DAT2, DAT3, DAT4, DAT5, DAT6, DAT7)
+where
+    CK: Ck,
+    CMD: Cmd,
+    DAT0: D0,
+    DAT1: D1,
+    DAT2: D2,
+    DAT3: D3,
+    DAT4: D4,
+    DAT5: D5,
+    DAT6: D6,
+    DAT7: D7,
+{
+}
+
+impl<CK, CMD, DAT0, DAT1, DAT2, DAT3, DAT4, DAT5, DAT6, DAT7> Pins
+    for (CK, CMD, DAT0, DAT1, DAT2, DAT3, DAT4, DAT5, DAT6, DAT7)
+where
+    CK: Ck,
+    CMD: Cmd,
+    DAT0: D0,
+    DAT1: D1,
+    DAT2: D2,
+    DAT3: D3,
+    DAT4: D4,
+    DAT5: D5,
+    DAT6: D6,
+    DAT7: D7,
+{
+    const BUS_WIDTH: BusWidth = BusWidth::Eight;
+}
+
+/// SDMMC errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// No card was initialized
+    NoCard,
+    /// The card did not answer the command
+    Timeout,
+    /// The command response CRC is invalid
+    CommandCrc,
+    /// The data block CRC is invalid
+    DataCrc,
+    /// The data transfer timed out
+    DataTimeout,
+    /// The receive FIFO overflowed
+    RxOverrun,
+    /// The transmit FIFO underflowed
+    TxUnderrun,
+    /// The card does not support the supplied voltage
+    UnsupportedCard,
+    /// The block is beyond the capacity of the card
+    OutOfRange,
+    /// The write protect switch of the slot is set
+    WriteProtected,
+    /// The card reported an error, in its R1 card status
+    CardStatus(u32),
+}
+
+/// Response expected for a command
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Response {
+    None,
+    /// 48-bit response
+    Short,
+    /// 48-bit response without a valid CRC (R3)
+    ShortNoCrc,
+    /// 136-bit response
+    Long,
+}
+
+/// Kind of card
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CardType {
+    Sd,
+    Emmc,
+    /// SDIO card, without memory
+    Sdio,
+}
+
+/// Initialized card
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Card {
+    pub card_type: CardType,
+    /// Relative card address
+    pub rca: u16,
+    /// SDHC, SDXC or eMMC larger than 2 GB, addressed by blocks instead of
+    /// bytes
+    pub high_capacity: bool,
+    /// Card identification register, most significant word first
+    pub cid: [u32; 4],
+    /// Card specific data register, most significant word first
+    pub csd: [u32; 4],
+    pub bus_width: BusWidth,
+    /// Bus clocked at up to 50 MHz
+    pub high_speed: bool,
+    /// Number of I/O functions of an SDIO card, 0 for the memory cards
+    pub io_functions: u8,
+    blocks: u32,
+}
+
+impl Card {
+    /// Returns the number of 512-byte blocks of the card
+    pub fn block_count(&self) -> u32 {
+        self.blocks
+    }
+
+    /// Returns the capacity of the card in bytes
+    pub fn size(&self) -> u64 {
+        u64::from(self.block_count()) * BLOCK_SIZE as u64
+    }
+}
+
+/// SD card interface
+pub struct Sdmmc<PINS> {
+    sdmmc: SDMMC1,
+    pins: PINS,
+    /// Kernel clock frequency, in Hz
+    kernel_clock: u32,
+    /// SDMMC_CK frequency, in Hz
+    bus_clock: u32,
+    card: Option<Card>,
+    write_protected: bool,
+}
+
+impl<PINS: Pins> Sdmmc<PINS> {
+    /// Enables the SDMMC1 clock and powers up the bus. The card is not
+    /// initialized.
+    ///
+    /// Panics if the kernel clock is PLL48CLK and it is not running at
+    /// 48 MHz, or if the kernel clock is too fast for the identification
+    /// mode, above 102.8 MHz.
+    pub fn new(sdmmc: SDMMC1, pins: PINS, apb2: &mut APB2, clocks: &Clocks) -> Self {
+        let kernel_clock = kernel_clock(clocks);
+
+        SDMMC1::enable(apb2);
+        SDMMC1::reset(apb2);
+
+        let mut sdmmc = Self {
+            sdmmc,
+            pins,
+            kernel_clock,
+            bus_clock: 0,
+            card: None,
+            write_protected: false,
+        };
+        sdmmc.set_bus_clock(INIT_CLOCK);
+        sdmmc
+            .sdmmc
+            .power
+            .write(|w| unsafe { w.pwrctrl().bits(0b11) });
+        sdmmc.sdmmc.clkcr.modify(|_, w| w.clken().set_bit());
+        sdmmc
+    }
+
+    /// Identifies and selects the card, then switches it to the widest bus
+    /// and the fastest clock supported. Must be called again after the card
+    /// is replaced.
+    pub fn init_card(&mut self) -> Result<(), Error> {
+        self.eject();
+        self.set_bus_clock(INIT_CLOCK);
+        self.set_bus_width(BusWidth::One);
+
+        // The card needs 74 clock cycles after power up, about 1 ms at 216 MHz
+        cortex_m::asm::delay(216_000);
+
+        self.cmd(0, 0, Response::None)?;
+
+        // Only the version 2.00 cards answer CMD8
+        let v2 = match self.cmd(8, CMD8_ARG, Response::Short) {
+            Ok(r7) if r7 & 0xfff == CMD8_ARG => true,
+            Ok(_) => return Err(Error::UnsupportedCard),
+            Err(Error::Timeout) => false,
+            Err(e) => return Err(e),
+        };
+
+        let hcs = if v2 { OCR_CCS } else { 0 };
+        let mut attempts = 0;
+        let ocr = loop {
+            match self.app_cmd(0) {
+                // The eMMC devices don't answer CMD55
+                Err(Error::Timeout) if !v2 && attempts == 0 => return self.init_emmc(),
+                result => result?,
+            }
+            let ocr = self.cmd(41, hcs | OCR_VOLTAGE, Response::ShortNoCrc)?;
+            if ocr & OCR_VOLTAGE == 0 {
+                return Err(Error::UnsupportedCard);
+            }
+            if ocr & OCR_BUSY != 0 {
+                break ocr;
+            }
+            attempts += 1;
+            if attempts == POWER_UP_ATTEMPTS {
+                return Err(Error::Timeout);
+            }
+        };
+
+        self.cmd(2, 0, Response::Long)?;
+        let cid = self.long_response();
+        let rca = (self.cmd(3, 0, Response::Short)? >> 16) as u16;
+        self.cmd(9, u32::from(rca) << 16, Response::Long)?;
+        let csd = self.long_response();
+        self.cmd_r1(7, u32::from(rca) << 16)?;
+
+        let high_capacity = ocr & OCR_CCS != 0;
+        if !high_capacity {
+            self.cmd_r1(16, BLOCK_SIZE as u32)?;
+        }
+
+        self.set_bus_clock(DEFAULT_SPEED_CLOCK);
+        self.card = Some(Card {
+            card_type: CardType::Sd,
+            rca,
+            high_capacity,
+            cid,
+            csd,
+            bus_width: BusWidth::One,
+            high_speed: false,
+            io_functions: 0,
+            blocks: csd_block_count(&csd),
+        });
+
+        if PINS::BUS_WIDTH != BusWidth::One {
+            // Disconnect the card pull-up on D3 (ACMD42), then switch to the
+            // 4-bit bus (ACMD6)
+            self.app_cmd(rca)?;
+            self.cmd_r1(42, 0)?;
+            self.app_cmd(rca)?;
+            self.cmd_r1(6, 0b10)?;
+            self.set_bus_width(BusWidth::Four);
+        }
+
+        // CMD6 is only supported by the cards of the switch command class
+        if register_bits(&csd, CSD_CCC_SWITCH, CSD_CCC_SWITCH) != 0 {
+            let mut status = [0; 64];
+            self.read_data(6, SWITCH_HIGH_SPEED, &mut status)?;
+            // Function selected in the group 1, in the bits 379:376
+            if status[16] & 0xf == 1 {
+                self.set_high_speed();
+            }
+        }
+        Ok(())
+    }
+
+    /// Identifies and selects an eMMC device, then switches to the bus width
+    /// of the pins and to high speed
+    fn init_emmc(&mut self) -> Result<(), Error> {
+        self.cmd(0, 0, Response::None)?;
+
+        let mut attempts = 0;
+        let ocr = loop {
+            let ocr = self.cmd(1, OCR_EMMC, Response::ShortNoCrc)?;
+            if ocr & OCR_BUSY != 0 {
+                break ocr;
+            }
+            attempts += 1;
+            if attempts == POWER_UP_ATTEMPTS {
+                return Err(Error::Timeout);
+            }
+        };
+
+        // The host assigns the relative address of the eMMC
+        let rca = EMMC_RCA;
+        self.cmd(2, 0, Response::Long)?;
+        let cid = self.long_response();
+        self.cmd_r1(3, u32::from(rca) << 16)?;
+        self.cmd(9, u32::from(rca) << 16, Response::Long)?;
+        let csd = self.long_response();
+        self.cmd_r1(7, u32::from(rca) << 16)?;
+
+        let high_capacity = ocr & OCR_SECTOR_MODE != 0;
+        if !high_capacity {
+            self.cmd_r1(16, BLOCK_SIZE as u32)?;
+        }
+        self.set_bus_clock(DEFAULT_SPEED_CLOCK);
+
+        // CMD8 reads the extended CSD on an eMMC
+        let mut ext_csd = [0; BLOCK_SIZE];
+        self.read_data(8, 0, &mut ext_csd)?;
+        let blocks = if high_capacity {
+            let sec_count = &ext_csd[EXT_CSD_SEC_COUNT..EXT_CSD_SEC_COUNT + 4];
+            u32::from_le_bytes([sec_count[0], sec_count[1], sec_count[2], sec_count[3]])
+        } else {
+            csd_v1_block_count(&csd)
+        };
+        self.card = Some(Card {
+            card_type: CardType::Emmc,
+            rca,
+            high_capacity,
+            cid,
+            csd,
+            bus_width: BusWidth::One,
+            high_speed: false,
+            io_functions: 0,
+            blocks,
+        });
+
+        let width = PINS::BUS_WIDTH;
+        if width != BusWidth::One {
+            // BUS_WIDTH: 1 for 4 bits, 2 for 8 bits
+            self.emmc_switch(EXT_CSD_BUS_WIDTH, width as u8)?;
+            self.set_bus_width(width);
+        }
+        if ext_csd[EXT_CSD_CARD_TYPE] & CARD_TYPE_HS_52 != 0 {
+            self.emmc_switch(EXT_CSD_HS_TIMING, 1)?;
+            self.set_high_speed();
+        }
+        Ok(())
+    }
+
+    /// Returns the card initialized by [`Sdmmc::init_card`]
+    pub fn card(&self) -> Result<&Card, Error> {
+        self.card.as_ref().ok_or(Error::NoCard)
+    }
+
+    /// Forgets the card, after it is removed, and stops the transfer in
+    /// progress. The next accesses fail with [`Error::NoCard`] until a card
+    /// is initialized again.
+    pub fn eject(&mut self) {
+        self.card = None;
+        self.sdmmc.dctrl.reset();
+        self.clear_flags();
+    }
+
+    /// Reads the block `block` of the card
+    pub fn read_block(&mut self, block: u32, buffer: &mut [u8; BLOCK_SIZE]) -> Result<(), Error> {
+        let address = self.block_address(block)?;
+        self.read_data(17, address, buffer)
+    }
+
+    /// Sends the command `index`, and reads the block it returns, of the
+    /// size of `buffer`: a power of two, up to 512 bytes
+    fn read_data(&mut self, index: u8, arg: u32, buffer: &mut [u8]) -> Result<(), Error> {
+        // The data path is started before the command, to be ready for the
+        // block following the response
+        self.start_data(true, buffer.len());
+        self.cmd_r1(index, arg)?;
+        self.receive(buffer)
+    }
+
+    /// Reads the block of data started by [`Sdmmc::start_data`] from the FIFO
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        let mut words = buffer.chunks_exact_mut(4);
+        loop {
+            let sta = self.sdmmc.sta.read().bits();
+            Self::check_data_errors(sta)?;
+            if sta & STA_RXFIFOHF != 0 {
+                // Half of the FIFO, 8 words, can be read
+                for chunk in words.by_ref().take(8) {
+                    let word = self.sdmmc.fifo.read().bits();
+                    chunk.copy_from_slice(&word.to_le_bytes());
+                }
+            } else if sta & STA_DATAEND != 0 {
+                break;
+            }
+        }
+        // Words left in the FIFO at the end of the block
+        while self.sdmmc.sta.read().bits() & STA_RXDAVL != 0 {
+            let word = self.sdmmc.fifo.read().bits();
+            if let Some(chunk) = words.next() {
+                chunk.copy_from_slice(&word.to_le_bytes());
+            }
+        }
+        self.clear_flags();
+        Ok(())
+    }
+
+    /// Writes the block `block` of the card, and waits until it is programmed
+    pub fn write_block(&mut self, block: u32, buffer: &[u8; BLOCK_SIZE]) -> Result<(), Error> {
+        let address = self.block_address(block)?;
+        if self.write_protected {
+            return Err(Error::WriteProtected);
+        }
+
+        self.start_data(false, BLOCK_SIZE);
+        self.cmd_r1(24, address)?;
+        self.transmit(buffer)?;
+
+        self.wait_ready().map(|_| ())
+    }
+
+    /// Writes the block of data started by [`Sdmmc::start_data`] to the FIFO
+    fn transmit(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        let mut words = buffer.chunks_exact(4);
+        loop {
+            let sta = self.sdmmc.sta.read().bits();
+            Self::check_data_errors(sta)?;
+            if sta & STA_DATAEND != 0 {
+                break;
+            }
+            if sta & STA_TXFIFOHE != 0 {
+                // Half of the FIFO, 8 words, can be written
+                for chunk in words.by_ref().take(8) {
+                    let word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    self.sdmmc.fifo.write(|w| unsafe { w.bits(word) });
+                }
+            }
+        }
+        self.clear_flags();
+        Ok(())
+    }
+
+    /// Turns off the bus, and returns the peripheral and the pins
+    pub fn free(self) -> (SDMMC1, PINS) {
+        self.sdmmc.clkcr.modify(|_, w| w.clken().clear_bit());
+        self.sdmmc
+            .power
+            .write(|w| unsafe { w.pwrctrl().bits(0b00) });
+        (self.sdmmc, self.pins)
+    }
+
+    /// Waits until the card is back in the transfer state, ready for data,
+    /// and returns its status
+    fn wait_ready(&mut self) -> Result<u32, Error> {
+        let rca = self.card()?.rca;
+        loop {
+            let status = self.cmd_r1(13, u32::from(rca) << 16)?;
+            if status & R1_READY_FOR_DATA != 0 && (status >> 9) & 0xf == R1_STATE_TRANSFER {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Writes the byte `index` of the eMMC extended CSD with CMD6, and waits
+    /// for the switch to complete
+    fn emmc_switch(&mut self, index: u8, value: u8) -> Result<(), Error> {
+        // Write byte access
+        let arg = (0b11 << 24) | (u32::from(index) << 16) | (u32::from(value) << 8);
+        self.cmd_r1(6, arg)?;
+        let status = self.wait_ready()?;
+        if status & R1_SWITCH_ERROR != 0 {
+            return Err(Error::CardStatus(status));
+        }
+        Ok(())
+    }
+
+    /// Returns the command argument addressing `block`
+    fn block_address(&self, block: u32) -> Result<u32, Error> {
+        let card = self.card()?;
+        if block >= card.block_count() {
+            return Err(Error::OutOfRange);
+        }
+        Ok(if card.high_capacity {
+            block
+        } else {
+            block * BLOCK_SIZE as u32
+        })
+    }
+
+    /// Clocks the bus at the highest frequency up to `max`
+    fn set_bus_clock(&mut self, max: u32) {
+        match clock_divider(self.kernel_clock, max) {
+            Some(clkdiv) => {
+                self.bus_clock = self.kernel_clock / (u32::from(clkdiv) + 2);
+                self.sdmmc
+                    .clkcr
+                    .modify(|_, w| unsafe { w.bypass().clear_bit().clkdiv().bits(clkdiv) });
+            }
+            None => {
+                self.bus_clock = self.kernel_clock;
+                self.sdmmc.clkcr.modify(|_, w| w.bypass().set_bit());
+            }
+        }
+        self.set_data_timeout();
+    }
+
+    /// Clocks the bus for the high speed mode
+    fn set_high_speed(&mut self) {
+        self.set_bus_clock(HIGH_SPEED_CLOCK);
+        if let Some(card) = &mut self.card {
+            card.high_speed = true;
+        }
+    }
+
+    fn set_data_timeout(&mut self) {
+        // 250 ms
+        self.sdmmc
+            .dtimer
+            .write(|w| unsafe { w.bits(self.bus_clock / 4) });
+    }
+
+    fn set_bus_width(&mut self, width: BusWidth) {
+        self.sdmmc
+            .clkcr
+            .modify(|_, w| unsafe { w.widbus().bits(width as u8) });
+        if let Some(card) = &mut self.card {
+            card.bus_width = width;
+        }
+    }
+
+    /// Configures and enables the data path state machine for one block of
+    /// `length` bytes
+    fn start_data(&mut self, card_to_host: bool, length: usize) {
+        let sdio = matches!(self.card, Some(card) if card.card_type == CardType::Sdio);
+        self.clear_flags();
+        self.sdmmc.dlen.write(|w| unsafe { w.bits(length as u32) });
+        self.sdmmc.dctrl.write(|w| unsafe {
+            w.dblocksize()
+                .bits(length.trailing_zeros() as u8)
+                .dtdir()
+                .bit(card_to_host)
+                .sdioen()
+                .bit(sdio)
+                .dten()
+                .set_bit()
+        });
+    }
+
+    fn check_data_errors(sta: u32) -> Result<(), Error> {
+        if sta & STA_DCRCFAIL != 0 {
+            Err(Error::DataCrc)
+        } else if sta & STA_DTIMEOUT != 0 {
+            Err(Error::DataTimeout)
+        } else if sta & STA_RXOVERR != 0 {
+            Err(Error::RxOverrun)
+        } else if sta & STA_TXUNDERR != 0 {
+            Err(Error::TxUnderrun)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn clear_flags(&mut self) {
+        self.sdmmc.icr.write(|w| unsafe { w.bits(STA_STATIC) });
+    }
+
+    /// Sends the command `index` and returns the 32 bits of a short response
+    fn cmd(&mut self, index: u8, arg: u32, response: Response) -> Result<u32, Error> {
+        self.clear_flags();
+        let waitresp = match response {
+            Response::None => 0b00,
+            Response::Short | Response::ShortNoCrc => 0b01,
+            Response::Long => 0b11,
+        };
+        self.sdmmc.arg.write(|w| unsafe { w.bits(arg) });
+        self.sdmmc.cmd.write(|w| unsafe {
+            w.cmdindex()
+                .bits(index)
+                .waitresp()
+                .bits(waitresp)
+                .cpsmen()
+                .set_bit()
+        });
+
+        let result = loop {
+            let sta = self.sdmmc.sta.read().bits();
+            if response == Response::None {
+                if sta & STA_CMDSENT != 0 {
+                    break Ok(());
+                }
+            } else if sta & STA_CTIMEOUT != 0 {
+                break Err(Error::Timeout);
+            } else if sta & STA_CCRCFAIL != 0 {
+                // The R3 response has no CRC
+                if response == Response::ShortNoCrc {
+                    break Ok(());
+                }
+                break Err(Error::CommandCrc);
+            } else if sta & STA_CMDREND != 0 {
+                break Ok(());
+            }
+        };
+        self.clear_flags();
+        result.map(|_| self.sdmmc.resp1.read().bits())
+    }
+
+    /// Sends a command with an R1 response, and checks the card status
+    fn cmd_r1(&mut self, index: u8, arg: u32) -> Result<u32, Error> {
+        let status = self.cmd(index, arg, Response::Short)?;
+        if status & R1_ERRORS != 0 {
+            return Err(Error::CardStatus(status));
+        }
+        Ok(status)
+    }
+
+    /// Sends CMD55, announcing an application specific command
+    fn app_cmd(&mut self, rca: u16) -> Result<(), Error> {
+        self.cmd_r1(55, u32::from(rca) << 16).map(|_| ())
+    }
+
+    fn long_response(&self) -> [u32; 4] {
+        [
+            self.sdmmc.resp1.read().bits(),
+            self.sdmmc.resp2.read().bits(),
+            self.sdmmc.resp3.read().bits(),
+            self.sdmmc.resp4.read().bits(),
+        ]
+    }
+}
+
+/// Returns the bits `msb` to `lsb` of a 128-bit register, given most
+/// significant word first
+fn register_bits(register: &[u32; 4], msb: u32, lsb: u32) -> u32 {
+    let mut value = 0;
+    for bit in (lsb..=msb).rev() {
+        let word = register[3 - (bit / 32) as usize];
+        value = (value << 1) | ((word >> (bit % 32)) & 1);
+    }
+    value
+}
+
+impl<PINS: Pins> UpdateClocks for Sdmmc<PINS> {
+    /// Programs the clock divider again for the new kernel clock, keeping the
+    /// speed mode of the card.
+    ///
+    /// Panics in the same cases as [`Sdmmc::new`].
+    fn update_clocks(&mut self, clocks: &Clocks) {
+        self.kernel_clock = kernel_clock(clocks);
+        let max = match &self.card {
+            None => INIT_CLOCK,
+            Some(card) if card.high_speed => HIGH_SPEED_CLOCK,
+            Some(_) => DEFAULT_SPEED_CLOCK,
+        };
+        self.set_bus_clock(max);
+    }
+}
+
+/// Returns the frequency of the kernel clock selected in `clocks`, in Hz
+fn kernel_clock(clocks: &Clocks) -> u32 {
+    clocks
+        .sdmmc1_clock()
+        .expect("PLL48CLK is not enabled")
+        .raw()
+}
+
+/// Returns the CLKDIV value giving the highest SDMMC_CK frequency up to `max`,
+/// SDMMC_CK being the kernel clock divided by `CLKDIV + 2`, or `None` if the
+/// divider can be bypassed.
+///
+/// Panics if the divider is too small.
+fn clock_divider(kernel_clock: u32, max: u32) -> Option<u8> {
+    if kernel_clock <= max {
+        return None;
+    }
+    let div = (kernel_clock + max - 1) / max;
+    Some(u8::try_from(div - 2).expect("The SDMMC kernel clock is too fast"))
+}
+
+/// Returns the number of 512-byte blocks from the CSD of an SD card
+fn csd_block_count(csd: &[u32; 4]) -> u32 {
+    match register_bits(csd, 127, 126) {
+        // CSD version 1.0, standard capacity
+        0 => csd_v1_block_count(csd),
+        // CSD version 2.0, high and extended capacity
+        _ => (register_bits(csd, 69, 48) + 1) * 1024,
+    }
+}
+
+/// Returns the number of 512-byte blocks from the CSD of a standard
+/// capacity SD card, or of an eMMC of 2 GB at most
+fn csd_v1_block_count(csd: &[u32; 4]) -> u32 {
+    let c_size = register_bits(csd, 73, 62);
+    let c_size_mult = register_bits(csd, 49, 47);
+    let read_bl_len = register_bits(csd, 83, 80);
+    ((c_size + 1) << (c_size_mult + 2 + read_bl_len)) / BLOCK_SIZE as u32
+}
+
+#[cfg(feature = "sdmmc-fatfs")]
+pub use block_device::SdmmcBlockDevice;
+
+#[cfg(feature = "sdmmc-fatfs")]
+mod block_device {
+    use core::cell::RefCell;
+
+    use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+
+    use super::{Error, Pins, Sdmmc};
+
+    /// `embedded-sdmmc` block device over an initialized card
+    pub struct SdmmcBlockDevice<PINS> {
+        sdmmc: RefCell<Sdmmc<PINS>>,
+    }
+
+    impl<PINS: Pins> SdmmcBlockDevice<PINS> {
+        pub fn new(sdmmc: Sdmmc<PINS>) -> Self {
+            Self {
+                sdmmc: RefCell::new(sdmmc),
+            }
+        }
+
+        /// Returns the SDMMC driver, to reinitialize the card
+        pub fn sdmmc(&mut self) -> &mut Sdmmc<PINS> {
+            self.sdmmc.get_mut()
+        }
+
+        pub fn free(self) -> Sdmmc<PINS> {
+            self.sdmmc.into_inner()
+        }
+    }
+
+    impl<PINS: Pins> BlockDevice for SdmmcBlockDevice<PINS> {
+        type Error = Error;
+
+        fn read(
+            &self,
+            blocks: &mut [Block],
+            start_block_idx: BlockIdx,
+            _reason: &str,
+        ) -> Result<(), Error> {
+            let mut sdmmc = self.sdmmc.borrow_mut();
+            for (block, idx) in blocks.iter_mut().zip(start_block_idx.0..) {
+                sdmmc.read_block(idx, &mut block.contents)?;
+            }
+            Ok(())
+        }
+
+        fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Error> {
+            let mut sdmmc = self.sdmmc.borrow_mut();
+            for (block, idx) in blocks.iter().zip(start_block_idx.0..) {
+                sdmmc.write_block(idx, &block.contents)?;
+            }
+            Ok(())
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, Error> {
+            Ok(BlockCount(self.sdmmc.borrow().card()?.block_count()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_divider() {
+        assert_eq!(clock_divider(48_000_000, INIT_CLOCK), Some(118));
+        assert_eq!(clock_divider(48_000_000, DEFAULT_SPEED_CLOCK), Some(0));
+        assert_eq!(clock_divider(48_000_000, HIGH_SPEED_CLOCK), None);
+        assert_eq!(clock_divider(100_000_000, DEFAULT_SPEED_CLOCK), Some(2));
+        assert_eq!(clock_divider(100_000_000, HIGH_SPEED_CLOCK), Some(0));
+    }
+
+    #[test]
+    fn test_csd_block_count() {
+        // 2 GB SDSC card
+        let csd_v1 = [0x002e_0032, 0x5b5a_83a9, 0xffff_ff80, 0x1680_0000];
+        assert_eq!(csd_block_count(&csd_v1), 3_842_048);
+        // 8 GB SDHC card
+        let csd_v2 = [0x400e_0032, 0x5b59_0000, 0x3b37_7f80, 0x0a40_4000];
+        assert_eq!(csd_block_count(&csd_v2), 15_523_840);
+    }
+}
@@ -0,0 +1,282 @@
+use super::{
+    BusWidth, Card, CardType, Error, Pins, Response, Sdmmc, DEFAULT_SPEED_CLOCK, INIT_CLOCK,
+    OCR_BUSY, OCR_VOLTAGE, POWER_UP_ATTEMPTS,
+};
+
+/// Card common control registers, in the function 0
+const CCCR_IO_ENABLE: u32 = 0x02;
+const CCCR_IO_READY: u32 = 0x03;
+const CCCR_INT_ENABLE: u32 = 0x04;
+const CCCR_INT_PENDING: u32 = 0x05;
+const CCCR_BUS_INTERFACE: u32 = 0x07;
+const CCCR_CARD_CAPABILITY: u32 = 0x08;
+const CCCR_CIS_POINTER: u32 = 0x09;
+const CCCR_BUS_SPEED: u32 = 0x13;
+
+/// 4-bit bus, in the bus interface control register
+const BUS_INTERFACE_4BIT: u8 = 0b10;
+/// Card pull-up on D3 disconnected, in the bus interface control register
+const BUS_INTERFACE_CD_DISABLE: u8 = 1 << 7;
+/// Low speed card, in the card capability register
+const CAPABILITY_LSC: u8 = 1 << 6;
+/// 4-bit bus support of a low speed card, in the card capability register
+const CAPABILITY_4BLS: u8 = 1 << 7;
+/// Master interrupt enable, in the interrupt enable register
+const INT_ENABLE_MASTER: u8 = 1 << 0;
+/// High speed support and enable, in the bus speed select register
+const BUS_SPEED_SHS: u8 = 1 << 0;
+const BUS_SPEED_EHS: u8 = 1 << 1;
+
+/// Error flags of the R5 response: COM_CRC_ERROR, ILLEGAL_COMMAND, ERROR,
+/// FUNCTION_NUMBER and OUT_OF_RANGE
+const R5_ERRORS: u32 = 0xcb00;
+/// Size of the function address space
+const ADDRESS_SPACE: u32 = 0x2_0000;
+
+/// Manufacturer identification tuple of the CIS
+const CISTPL_MANFID: u8 = 0x20;
+const CISTPL_END: u8 = 0xff;
+/// Tuples walked through before giving up
+const CIS_MAX_TUPLES: usize = 64;
+
+impl<PINS: Pins> Sdmmc<PINS> {
+    /// Identifies and selects an SDIO card, then switches it to the widest
+    /// bus and the fastest clock supported. Must be called instead of
+    /// [`Sdmmc::init_card`] for the I/O cards, and again after the card is
+    /// replaced.
+    pub fn init_sdio(&mut self) -> Result<(), Error> {
+        self.eject();
+        self.set_bus_clock(INIT_CLOCK);
+        self.set_bus_width(BusWidth::One);
+
+        // The card needs 74 clock cycles after power up, about 1 ms at 216 MHz
+        cortex_m::asm::delay(216_000);
+
+        // The first CMD5 reads the supported voltages, the next ones wait for
+        // the card to power up
+        let r4 = self.cmd(5, 0, Response::ShortNoCrc)?;
+        if r4 & OCR_VOLTAGE == 0 {
+            return Err(Error::UnsupportedCard);
+        }
+        let io_functions = ((r4 >> 28) & 0b111) as u8;
+        let mut attempts = 0;
+        while self.cmd(5, OCR_VOLTAGE, Response::ShortNoCrc)? & OCR_BUSY == 0 {
+            attempts += 1;
+            if attempts == POWER_UP_ATTEMPTS {
+                return Err(Error::Timeout);
+            }
+        }
+
+        let rca = (self.cmd(3, 0, Response::Short)? >> 16) as u16;
+        self.cmd_r1(7, u32::from(rca) << 16)?;
+
+        self.set_bus_clock(DEFAULT_SPEED_CLOCK);
+        self.card = Some(Card {
+            card_type: CardType::Sdio,
+            rca,
+            high_capacity: false,
+            cid: [0; 4],
+            csd: [0; 4],
+            bus_width: BusWidth::One,
+            high_speed: false,
+            io_functions,
+            blocks: 0,
+        });
+
+        let capability = self.io_read_byte(0, CCCR_CARD_CAPABILITY)?;
+        let four_bits = capability & CAPABILITY_LSC == 0 || capability & CAPABILITY_4BLS != 0;
+        if PINS::BUS_WIDTH != BusWidth::One && four_bits {
+            let interface = self.io_read_byte(0, CCCR_BUS_INTERFACE)?;
+            let interface = (interface & !0b11) | BUS_INTERFACE_4BIT | BUS_INTERFACE_CD_DISABLE;
+            self.io_write_byte(0, CCCR_BUS_INTERFACE, interface)?;
+            self.set_bus_width(BusWidth::Four);
+        }
+
+        let bus_speed = self.io_read_byte(0, CCCR_BUS_SPEED)?;
+        if bus_speed & BUS_SPEED_SHS != 0 {
+            self.io_write_byte(0, CCCR_BUS_SPEED, bus_speed | BUS_SPEED_EHS)?;
+            self.set_high_speed();
+        }
+        Ok(())
+    }
+
+    /// Reads the register at `address` of the I/O function `function`, 0
+    /// being the common registers, with CMD52
+    pub fn io_read_byte(&mut self, function: u8, address: u32) -> Result<u8, Error> {
+        self.cmd52(false, function, address, 0)
+    }
+
+    /// Writes the register at `address` of the I/O function `function` with
+    /// CMD52, and returns the value read back
+    pub fn io_write_byte(&mut self, function: u8, address: u32, value: u8) -> Result<u8, Error> {
+        self.cmd52(true, function, address, value)
+    }
+
+    /// Reads `buffer` from the I/O function `function` with CMD53 in byte
+    /// mode, starting at `address`, which is incremented after each byte if
+    /// `increment` is `true`, or stays on a FIFO register otherwise.
+    ///
+    /// Panics if the length of `buffer` is not a power of two from 4 to 512.
+    pub fn io_read(
+        &mut self,
+        function: u8,
+        address: u32,
+        increment: bool,
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        let arg = self.cmd53_arg(false, function, address, increment, buffer.len())?;
+        self.start_data(true, buffer.len());
+        self.cmd_r5(53, arg)?;
+        self.receive(buffer)
+    }
+
+    /// Writes `buffer` to the I/O function `function` with CMD53 in byte
+    /// mode, as [`Sdmmc::io_read`]
+    ///
+    /// Panics if the length of `buffer` is not a power of two from 4 to 512.
+    pub fn io_write(
+        &mut self,
+        function: u8,
+        address: u32,
+        increment: bool,
+        buffer: &[u8],
+    ) -> Result<(), Error> {
+        let arg = self.cmd53_arg(true, function, address, increment, buffer.len())?;
+        self.start_data(false, buffer.len());
+        self.cmd_r5(53, arg)?;
+        self.transmit(buffer)
+    }
+
+    /// Enables the I/O function `function`, and waits until it is ready
+    pub fn enable_io_function(&mut self, function: u8) -> Result<(), Error> {
+        let enable = self.io_read_byte(0, CCCR_IO_ENABLE)?;
+        self.io_write_byte(0, CCCR_IO_ENABLE, enable | (1 << function))?;
+        while self.io_read_byte(0, CCCR_IO_READY)? & (1 << function) == 0 {}
+        Ok(())
+    }
+
+    pub fn disable_io_function(&mut self, function: u8) -> Result<(), Error> {
+        let enable = self.io_read_byte(0, CCCR_IO_ENABLE)?;
+        self.io_write_byte(0, CCCR_IO_ENABLE, enable & !(1 << function))?;
+        Ok(())
+    }
+
+    /// Returns the standard interface code of the I/O function `function`,
+    /// from its function basic registers: 0x01 for a UART, 0x02 and 0x03 for
+    /// Bluetooth, 0x07 for WLAN...
+    pub fn io_function_interface(&mut self, function: u8) -> Result<u8, Error> {
+        let fbr = 0x100 * u32::from(function);
+        match self.io_read_byte(0, fbr)? & 0xf {
+            // Extended code, in the next register
+            0xf => self.io_read_byte(0, fbr + 1),
+            code => Ok(code),
+        }
+    }
+
+    /// Returns the manufacturer and card identifiers, from the common CIS,
+    /// or `None` if the CIS has no manufacturer identification tuple
+    pub fn io_manufacturer_id(&mut self) -> Result<Option<(u16, u16)>, Error> {
+        let mut address = 0;
+        for i in 0..3 {
+            let byte = self.io_read_byte(0, CCCR_CIS_POINTER + i)?;
+            address |= u32::from(byte) << (8 * i);
+        }
+
+        for _ in 0..CIS_MAX_TUPLES {
+            let code = self.io_read_byte(0, address)?;
+            if code == CISTPL_END {
+                break;
+            }
+            let link = self.io_read_byte(0, address + 1)?;
+            if code == CISTPL_MANFID {
+                let mut id = [0; 4];
+                for (i, byte) in id.iter_mut().enumerate() {
+                    *byte = self.io_read_byte(0, address + 2 + i as u32)?;
+                }
+                return Ok(Some((
+                    u16::from_le_bytes([id[0], id[1]]),
+                    u16::from_le_bytes([id[2], id[3]]),
+                )));
+            }
+            address += 2 + u32::from(link);
+        }
+        Ok(None)
+    }
+
+    /// Enables the interrupts of the I/O function `function`, signalled by
+    /// the card on D1 and raising the SDMMC1 interrupt
+    pub fn listen_io_interrupt(&mut self, function: u8) -> Result<(), Error> {
+        let enable = self.io_read_byte(0, CCCR_INT_ENABLE)?;
+        self.io_write_byte(
+            0,
+            CCCR_INT_ENABLE,
+            enable | INT_ENABLE_MASTER | (1 << function),
+        )?;
+        // The card interrupts are only detected in the SD I/O mode
+        self.sdmmc.dctrl.modify(|_, w| w.sdioen().set_bit());
+        self.sdmmc.mask.modify(|_, w| w.sdioitie().set_bit());
+        Ok(())
+    }
+
+    /// Disables the interrupts of the I/O function `function`, and the
+    /// SDMMC1 interrupt once no function is left
+    pub fn unlisten_io_interrupt(&mut self, function: u8) -> Result<(), Error> {
+        let enable = self.io_read_byte(0, CCCR_INT_ENABLE)? & !(1 << function);
+        if enable & !INT_ENABLE_MASTER == 0 {
+            self.io_write_byte(0, CCCR_INT_ENABLE, 0)?;
+            self.sdmmc.mask.modify(|_, w| w.sdioitie().clear_bit());
+        } else {
+            self.io_write_byte(0, CCCR_INT_ENABLE, enable)?;
+        }
+        Ok(())
+    }
+
+    /// Clears the SDIO interrupt flag, and returns the I/O functions with a
+    /// pending interrupt, bit `n` being set for the function `n`
+    pub fn io_pending_interrupts(&mut self) -> Result<u8, Error> {
+        self.sdmmc.icr.write(|w| w.sdioitc().set_bit());
+        self.io_read_byte(0, CCCR_INT_PENDING)
+    }
+
+    fn cmd52(&mut self, write: bool, function: u8, address: u32, data: u8) -> Result<u8, Error> {
+        self.card()?;
+        assert!(function < 8 && address < ADDRESS_SPACE);
+
+        // Read after write on the writes
+        let arg = (u32::from(write) << 31)
+            | (u32::from(function) << 28)
+            | (u32::from(write) << 27)
+            | (address << 9)
+            | u32::from(data);
+        self.cmd_r5(52, arg).map(|r5| r5 as u8)
+    }
+
+    fn cmd53_arg(
+        &self,
+        write: bool,
+        function: u8,
+        address: u32,
+        increment: bool,
+        length: usize,
+    ) -> Result<u32, Error> {
+        self.card()?;
+        assert!(function < 8 && address < ADDRESS_SPACE);
+        assert!(length.is_power_of_two() && (4..=512).contains(&length));
+
+        // Byte mode, a count of 0 meaning 512 bytes
+        Ok((u32::from(write) << 31)
+            | (u32::from(function) << 28)
+            | (u32::from(increment) << 26)
+            | (address << 9)
+            | (length as u32 & 0x1ff))
+    }
+
+    /// Sends an I/O command, and checks the flags of its R5 response
+    fn cmd_r5(&mut self, index: u8, arg: u32) -> Result<u32, Error> {
+        let r5 = self.cmd(index, arg, Response::Short)?;
+        if r5 & R5_ERRORS != 0 {
+            return Err(Error::CardStatus(r5));
+        }
+        Ok(r5)
+    }
+}
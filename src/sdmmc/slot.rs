@@ -0,0 +1,129 @@
+use core::convert::Infallible;
+
+use embedded_hal::digital::v2::InputPin;
+
+use super::{Error, Pins, Sdmmc};
+use crate::gpio::NoPin;
+
+/// Insertion or removal of the card
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotEvent {
+    Inserted,
+    Removed,
+}
+
+/// Write protect switch of a card slot, or [`NoPin`] for the slots without
+/// one, such as the microSD slots
+pub trait WriteProtect {
+    /// Returns `true` if the card is write protected
+    fn is_write_protected(&self) -> bool;
+}
+
+impl WriteProtect for NoPin {
+    fn is_write_protected(&self) -> bool {
+        false
+    }
+}
+
+impl<P: InputPin<Error = Infallible>> WriteProtect for P {
+    /// The switch is open, and the pin pulled up, when the card is protected
+    fn is_write_protected(&self) -> bool {
+        self.is_high().unwrap()
+    }
+}
+
+/// Card detect and write protect switches of a card slot
+///
+/// The card detect pin is low when a card is inserted, and needs a pull-up.
+/// [`Slot::poll`] is meant to be called periodically, or from the EXTI
+/// interrupt of the card detect pin, the state of the switch being only
+/// trusted once read identical twice in a row.
+pub struct Slot<CD, WP = NoPin> {
+    detect: CD,
+    write_protect: WP,
+    present: bool,
+    /// Last state read, not yet confirmed
+    pending: Option<bool>,
+}
+
+impl<CD, WP> Slot<CD, WP>
+where
+    CD: InputPin<Error = Infallible>,
+    WP: WriteProtect,
+{
+    /// The slot starts empty: the first calls to [`Slot::poll`] report a
+    /// card already inserted
+    pub fn new(detect: CD, write_protect: WP) -> Self {
+        Self {
+            detect,
+            write_protect,
+            present: false,
+            pending: None,
+        }
+    }
+
+    /// Returns `true` if a card is in the slot, without debouncing
+    pub fn is_present(&self) -> bool {
+        self.detect.is_low().unwrap()
+    }
+
+    /// Returns `true` if the card in the slot is write protected
+    pub fn is_write_protected(&self) -> bool {
+        self.write_protect.is_write_protected()
+    }
+
+    /// Returns the insertion or removal of the card, once the new state of
+    /// the card detect switch is confirmed by a second call
+    pub fn poll(&mut self) -> Option<SlotEvent> {
+        let present = self.is_present();
+        if present == self.present {
+            self.pending = None;
+            return None;
+        }
+        if self.pending != Some(present) {
+            self.pending = Some(present);
+            return None;
+        }
+
+        self.pending = None;
+        self.present = present;
+        Some(if present {
+            SlotEvent::Inserted
+        } else {
+            SlotEvent::Removed
+        })
+    }
+
+    pub fn free(self) -> (CD, WP) {
+        (self.detect, self.write_protect)
+    }
+}
+
+impl<PINS: Pins> Sdmmc<PINS> {
+    /// Polls the card slot, forgets the card once removed, and initializes
+    /// the new card once inserted, with [`Sdmmc::init_card`]. Returns the
+    /// event of the slot.
+    ///
+    /// The writes to a write protected card fail with
+    /// [`Error::WriteProtected`].
+    pub fn update_slot<CD, WP>(
+        &mut self,
+        slot: &mut Slot<CD, WP>,
+    ) -> Result<Option<SlotEvent>, Error>
+    where
+        CD: InputPin<Error = Infallible>,
+        WP: WriteProtect,
+    {
+        let event = slot.poll();
+        match event {
+            Some(SlotEvent::Inserted) => {
+                self.eject();
+                self.write_protected = slot.is_write_protected();
+                self.init_card()?;
+            }
+            Some(SlotEvent::Removed) => self.eject(),
+            None => {}
+        }
+        Ok(event)
+    }
+}
@@ -19,8 +19,9 @@ use crate::pac::{RCC, UART4, UART5, UART7, USART1, USART2, USART3, USART6};
 
 use crate::gpio::{self, Alternate};
 
-use crate::rcc::Clocks;
+use crate::rcc::{Clocks, UpdateClocks, UsartClock};
 use crate::{BitsPerSecond, U32Ext};
+use fugit::HertzU32 as Hertz;
 
 /// Serial error
 #[derive(Debug)]
@@ -116,6 +117,8 @@ impl PinRx<UART7> for gpio::PF6<Alternate<8>> {}
 pub struct Serial<USART, PINS> {
     usart: USART,
     pins: PINS,
+    baud_rate: BitsPerSecond,
+    sysclock: bool,
 }
 
 impl<USART, PINS> Serial<USART, PINS>
@@ -130,33 +133,19 @@ where
         // TODO: The unsafe calls below should be replaced with accessing
         //       the correct registers directly.
 
-        USART::select_sysclock(rcc, config.sysclock);
+        let clk = Self::select_kernel_clock(rcc, clocks, config.sysclock);
         unsafe {
             USART::enable_unchecked();
         }
 
-        let clk = if config.sysclock {
-            clocks.sysclk()
-        } else {
-            USART::clock(clocks)
+        let over8 = match config.oversampling {
+            Oversampling::By8 => true,
+            Oversampling::By16 => false,
         };
+        usart.cr1.modify(|_, w| w.over8().bit(over8));
 
         // Calculate correct baudrate divisor on the fly
-        let brr = match config.oversampling {
-            Oversampling::By8 => {
-                usart.cr1.modify(|_, w| w.over8().set_bit());
-
-                let usart_div = 2 * clk / config.baud_rate;
-
-                0xfff0 & usart_div | 0x0007 & ((usart_div & 0x000f) >> 1)
-            }
-            Oversampling::By16 => {
-                usart.cr1.modify(|_, w| w.over8().clear_bit());
-
-                clk / config.baud_rate
-            }
-        };
-
+        let brr = baud_rate_divisor(clk, config.baud_rate, over8);
         usart.brr.write(|w| unsafe { w.bits(brr) });
 
         // Set character match and reset other registers to disable advanced USART features
@@ -171,7 +160,30 @@ where
         // Enable DMA
         usart.cr3.write(|w| w.dmat().enabled().dmar().enabled());
 
-        Serial { usart, pins }
+        Serial {
+            usart,
+            pins,
+            baud_rate: config.baud_rate,
+            sysclock: config.sysclock,
+        }
+    }
+
+    /// Selects the kernel clock of the peripheral, SYSCLK if `sysclock`, and
+    /// returns its frequency
+    fn select_kernel_clock(
+        rcc: &pac::rcc::RegisterBlock,
+        clocks: &Clocks,
+        sysclock: bool,
+    ) -> Hertz {
+        let source = if sysclock {
+            UsartClock::Sysclk
+        } else {
+            USART::clock_source(clocks)
+        };
+        USART::select_clock(rcc, source);
+
+        // The LSE of an USART kernel clock is checked by `CFGR::freeze`
+        source.frequency(clocks, USART::clock(clocks)).unwrap()
     }
 
     /// Starts listening for an interrupt event
@@ -243,6 +255,38 @@ where
     }
 }
 
+impl<USART, PINS> UpdateClocks for Serial<USART, PINS>
+where
+    PINS: Pins<USART>,
+    USART: Instance,
+{
+    /// Programs the baud rate divisor again for the new kernel clock
+    fn update_clocks(&mut self, clocks: &Clocks) {
+        // NOTE(unsafe) only the clock selection of this USART is written
+        let rcc = unsafe { &(*RCC::ptr()) };
+        let clk = Self::select_kernel_clock(rcc, clocks, self.sysclock);
+        let over8 = self.usart.cr1.read().over8().bit_is_set();
+
+        // BRR can only be written while the USART is disabled
+        let enabled = self.usart.cr1.read().ue().is_enabled();
+        self.usart.cr1.modify(|_, w| w.ue().disabled());
+        let brr = baud_rate_divisor(clk, self.baud_rate, over8);
+        self.usart.brr.write(|w| unsafe { w.bits(brr) });
+        self.usart.cr1.modify(|_, w| w.ue().bit(enabled));
+    }
+}
+
+/// Returns the BRR value dividing the kernel clock `clk` down to `baud_rate`
+fn baud_rate_divisor(clk: Hertz, baud_rate: BitsPerSecond, over8: bool) -> u32 {
+    if over8 {
+        let usart_div = 2 * clk / baud_rate;
+
+        0xfff0 & usart_div | 0x0007 & ((usart_div & 0x000f) >> 1)
+    } else {
+        clk / baud_rate
+    }
+}
+
 impl<USART, PINS> serial::Read<u8> for Serial<USART, PINS>
 where
     USART: Instance,
@@ -535,6 +579,8 @@ pub struct Config {
     pub baud_rate: BitsPerSecond,
     pub oversampling: Oversampling,
     pub character_match: Option<u8>,
+    /// Clocks the peripheral with SYSCLK, instead of its kernel clock
+    /// selected in `Clocks`
     pub sysclock: bool,
 }
 
@@ -570,12 +616,14 @@ pub enum Event {
 /// Implemented by all USART instances
 pub trait Instance: Deref<Target = pac::usart1::RegisterBlock> + Enable + Reset + BusClock {
     fn ptr() -> *const pac::usart1::RegisterBlock;
-    fn select_sysclock(rcc: &pac::rcc::RegisterBlock, sys: bool);
+    fn select_clock(rcc: &pac::rcc::RegisterBlock, source: UsartClock);
+    /// Returns the kernel clock selected for the peripheral in `clocks`
+    fn clock_source(clocks: &Clocks) -> UsartClock;
 }
 
 macro_rules! impl_instance {
     ($(
-        $USARTX:ident: ($usartXsel:ident),
+        $USARTX:ident: ($usartXsel:ident, $usartX:ident),
     )+) => {
         $(
             impl Instance for $USARTX {
@@ -583,8 +631,12 @@ macro_rules! impl_instance {
                     $USARTX::ptr()
                 }
 
-                fn select_sysclock(rcc: &pac::rcc::RegisterBlock, sys: bool) {
-                    rcc.dckcfgr2.modify(|_, w| w.$usartXsel().bits(sys as _));
+                fn select_clock(rcc: &pac::rcc::RegisterBlock, source: UsartClock) {
+                    rcc.dckcfgr2.modify(|_, w| w.$usartXsel().bits(source as _));
+                }
+
+                fn clock_source(clocks: &Clocks) -> UsartClock {
+                    clocks.kernel_clocks().$usartX
                 }
             }
         )+
@@ -593,13 +645,13 @@ macro_rules! impl_instance {
 
 #[cfg(any(feature = "device-selected",))]
 impl_instance! {
-    USART1: (usart1sel),
-    USART2: (usart2sel),
-    USART3: (usart3sel),
-    UART4:  (uart4sel),
-    UART5:  (uart5sel),
-    USART6: (usart6sel),
-    UART7:  (uart7sel),
+    USART1: (usart1sel, usart1),
+    USART2: (usart2sel, usart2),
+    USART3: (usart3sel, usart3),
+    UART4:  (uart4sel, uart4),
+    UART5:  (uart5sel, uart5),
+    USART6: (usart6sel, usart6),
+    UART7:  (uart7sel, uart7),
 }
 
 impl<USART> fmt::Write for Tx<USART>
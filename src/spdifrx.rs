@@ -0,0 +1,599 @@
+//! SPDIF receiver (SPDIFRX)
+//!
+//! The receiver decodes the S/PDIF (IEC 60958) stream of one of its four
+//! inputs. It first recovers the symbol clock from the transitions of the
+//! stream, which [`SpdifRx::start`] waits for, then delivers the samples of
+//! the two channels in turn, with their status bits, and gathers the channel
+//! status and user data bits of each frame in a control word.
+//!
+//! The samples can be read one at a time, or with the DMA through
+//! [`SpdifRx::read_all`]. The kernel clock is the P output of the PLLI2S,
+//! which must have been enabled with [`crate::rcc::CFGR::use_plli2s`], and
+//! be high enough for the sample rate of the stream, see the reference
+//! manual.
+//!
+//! The SPDIFRX_IN pins must have been configured in alternate function 7 or
+//! 8, depending on the pin.
+//!
+//! ```ignore
+//! let mut spdifrx = SpdifRx::new(dp.SPDIFRX, Config::default(), &clocks, &mut rcc.apb1);
+//! block!(spdifrx.start())?;
+//! let rate = spdifrx.sample_rate();
+//! let sample = Sample(block!(spdifrx.read())?);
+//! ```
+//!
+//! See chapter 36 in the STM32F76xxx Reference Manual (RM0410).
+
+use core::ops::DerefMut;
+use core::pin::Pin;
+
+use as_slice::AsMutSlice;
+use fugit::{HertzU32 as Hertz, RateExtU32};
+
+use crate::pac::{RCC, SPDIFRX};
+use crate::rcc::{Clocks, Enable, Reset};
+use crate::{dma, state};
+
+/// Frequency of the HSI, feeding the PLLs when the HSE is not used
+const HSI: u32 = 16_000_000;
+
+/// Input of the receiver, from the SPDIFRX_IN0 to SPDIFRX_IN3 pins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    In0,
+    In1,
+    In2,
+    In3,
+}
+
+/// Layout of the samples read from the receiver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    /// The 24-bit sample in bits 0 to 23, then the status bits, decoded by
+    /// [`Sample`]
+    RightAligned,
+    /// The 24-bit sample in bits 8 to 31, the status bits in bits 0 to 5
+    LeftAligned,
+    /// The 16 most significant bits of the samples of both channels in one
+    /// word, channel A in the upper half, without the status bits
+    Packed16,
+}
+
+/// Number of attempts of the symbol clock recovery before a timeout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Retries {
+    None,
+    Three,
+    Fifteen,
+    SixtyThree,
+}
+
+/// Channel of the frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    A,
+    B,
+}
+
+/// Configuration of the receiver
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub input: Input,
+    pub data_format: DataFormat,
+    /// The samples of both channels are delivered, A then B, instead of
+    /// channel A only
+    pub stereo: bool,
+    /// The parity error, validity, user, channel status and preamble type
+    /// bits are delivered with the samples, instead of zeros
+    pub status_bits: bool,
+    /// Channel of the channel status bits gathered in the control words
+    pub channel_status: Channel,
+    pub retries: Retries,
+    /// The symbol clock recovery waits for some activity on the input,
+    /// instead of timing out
+    pub wait_for_activity: bool,
+}
+
+impl Default for Config {
+    /// Stereo, right aligned samples with their status bits from the IN0
+    /// input, waiting for activity
+    fn default() -> Self {
+        Config {
+            input: Input::In0,
+            data_format: DataFormat::RightAligned,
+            stereo: true,
+            status_bits: true,
+            channel_status: Channel::A,
+            retries: Retries::SixtyThree,
+            wait_for_activity: true,
+        }
+    }
+}
+
+/// SPDIFRX error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A sample or a control word was received with a parity error
+    Parity,
+    /// A sample was received before the previous one was read
+    Overrun,
+    /// The symbol clock recovery failed
+    Synchronization,
+    /// The number of transitions or the preambles of the stream are wrong,
+    /// once synchronized
+    Framing,
+    /// The symbol clock recovery timed out, after the configured retries
+    Timeout,
+}
+
+/// SPDIFRX interrupt
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A sample can be read
+    DataReady,
+    /// A control word can be read
+    ControlReady,
+    /// A sample or a control word has a parity error
+    Parity,
+    /// A sample was lost
+    Overrun,
+    /// A block started, with the B preamble
+    StartOfBlock,
+    /// The symbol clock was recovered
+    Synchronized,
+    /// A synchronization, framing or timeout error happened
+    Error,
+}
+
+/// Sample in the [`DataFormat::RightAligned`] format, with its status bits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sample(pub u32);
+
+/// Preamble of a sub-frame, telling its channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preamble {
+    /// Channel A, at the start of a block
+    B,
+    /// Channel A
+    M,
+    /// Channel B
+    W,
+}
+
+impl Sample {
+    /// Returns the 24-bit sample, from the MSB of the 20-bit or 24-bit audio
+    /// word
+    pub fn data(&self) -> u32 {
+        self.0 & 0x00ff_ffff
+    }
+
+    /// Returns the sample as a signed 24-bit value, sign-extended
+    pub fn signed(&self) -> i32 {
+        ((self.0 << 8) as i32) >> 8
+    }
+
+    pub fn parity_error(&self) -> bool {
+        self.0 & (1 << 24) != 0
+    }
+
+    /// Returns the validity bit, set when the sample is not suitable for a
+    /// conversion to analog
+    pub fn validity(&self) -> bool {
+        self.0 & (1 << 25) != 0
+    }
+
+    pub fn user_bit(&self) -> bool {
+        self.0 & (1 << 26) != 0
+    }
+
+    pub fn channel_status_bit(&self) -> bool {
+        self.0 & (1 << 27) != 0
+    }
+
+    /// Returns the preamble of the sample, if the status bits are delivered
+    pub fn preamble(&self) -> Option<Preamble> {
+        match (self.0 >> 28) & 0b11 {
+            0b01 => Some(Preamble::B),
+            0b10 => Some(Preamble::M),
+            0b11 => Some(Preamble::W),
+            _ => None,
+        }
+    }
+}
+
+/// Channel status and user data bits of consecutive frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ControlWord {
+    /// User data bits, of both channels
+    pub user: u16,
+    /// Channel status bits of the configured channel
+    pub channel_status: u8,
+    /// The bits are the first of a block
+    pub start_of_block: bool,
+}
+
+/// Channel status block of 192 bits, gathered from the control words
+///
+/// The first bytes tell the format of the stream: consumer or professional,
+/// linear PCM or not, sample rate and word length. See IEC 60958-3.
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelStatus {
+    bytes: [u8; 24],
+    len: usize,
+}
+
+impl ChannelStatus {
+    pub const fn new() -> Self {
+        ChannelStatus {
+            bytes: [0; 24],
+            len: 0,
+        }
+    }
+
+    /// Adds the bits of `word`, and returns whether the block is complete
+    ///
+    /// The words before the start of the first block are ignored. The block
+    /// starts over with the next start of block.
+    pub fn push(&mut self, word: ControlWord) -> bool {
+        if word.start_of_block {
+            self.len = 0;
+        } else if self.len == 0 || self.len == self.bytes.len() {
+            return false;
+        }
+
+        self.bytes[self.len] = word.channel_status;
+        self.len += 1;
+        self.is_complete()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.len == self.bytes.len()
+    }
+
+    /// Returns the bytes of the block, in the order of reception
+    pub fn bytes(&self) -> &[u8; 24] {
+        &self.bytes
+    }
+}
+
+impl Default for ChannelStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// SPDIF receiver
+pub struct SpdifRx {
+    spdifrx: SPDIFRX,
+    kernel_clock: Hertz,
+}
+
+impl SpdifRx {
+    /// Enables and configures the receiver, which stays idle until
+    /// [`SpdifRx::start`]
+    pub fn new(
+        spdifrx: SPDIFRX,
+        config: Config,
+        clocks: &Clocks,
+        apb: &mut <SPDIFRX as crate::rcc::RccBus>::Bus,
+    ) -> Self {
+        SPDIFRX::enable(apb);
+        SPDIFRX::reset(apb);
+
+        let drfmt = match config.data_format {
+            DataFormat::RightAligned => 0b00,
+            DataFormat::LeftAligned => 0b01,
+            DataFormat::Packed16 => 0b10,
+        };
+        let nbtr = match config.retries {
+            Retries::None => 0b00,
+            Retries::Three => 0b01,
+            Retries::Fifteen => 0b10,
+            Retries::SixtyThree => 0b11,
+        };
+        let mask = !config.status_bits;
+        spdifrx.cr.write(|w| unsafe {
+            w.insel()
+                .bits(config.input as u8)
+                .drfmt()
+                .bits(drfmt)
+                .nbtr()
+                .bits(nbtr)
+                .rxsteo()
+                .bit(config.stereo)
+                .pmsk()
+                .bit(mask)
+                .vmsk()
+                .bit(mask)
+                .cumsk()
+                .bit(mask)
+                .ptmsk()
+                .bit(mask)
+                .chsel()
+                .bit(config.channel_status == Channel::B)
+                .wfa()
+                .bit(config.wait_for_activity)
+        });
+
+        SpdifRx {
+            spdifrx,
+            kernel_clock: kernel_clock(clocks),
+        }
+    }
+
+    /// Returns the frequency of the kernel clock
+    pub fn kernel_clock(&self) -> Hertz {
+        self.kernel_clock
+    }
+
+    /// Recovers the symbol clock, then starts the reception
+    ///
+    /// Returns `WouldBlock` until the receiver is synchronized on the
+    /// stream. After an error, the receiver is idle, and this starts over.
+    pub fn start(&mut self) -> nb::Result<(), Error> {
+        match self.spdifrx.cr.read().spdifen().bits() {
+            0b00 => {
+                self.spdifrx.ifcr.write(|w| w.syncdcf().set_bit());
+                self.spdifrx
+                    .cr
+                    .modify(|_, w| unsafe { w.spdifen().bits(0b01) });
+                Err(nb::Error::WouldBlock)
+            }
+            0b11 => Ok(()),
+            _ => {
+                if let Err(error) = self.check_sync_errors() {
+                    self.stop();
+                    return Err(nb::Error::Other(error));
+                }
+                if self.spdifrx.sr.read().syncd().bit_is_clear() {
+                    return Err(nb::Error::WouldBlock);
+                }
+                self.spdifrx.ifcr.write(|w| w.syncdcf().set_bit());
+                self.spdifrx
+                    .cr
+                    .modify(|_, w| unsafe { w.spdifen().bits(0b11) });
+                Ok(())
+            }
+        }
+    }
+
+    /// Stops the reception, the receiver going back to idle
+    pub fn stop(&mut self) {
+        self.spdifrx
+            .cr
+            .modify(|_, w| unsafe { w.spdifen().bits(0b00) });
+    }
+
+    /// Returns whether the receiver is synchronized and receiving
+    pub fn is_receiving(&self) -> bool {
+        self.spdifrx.cr.read().spdifen().bits() == 0b11
+    }
+
+    /// Returns the sample rate of the stream, estimated from the duration of
+    /// 5 symbols measured by the symbol clock recovery
+    ///
+    /// The stream must have been synchronized.
+    pub fn sample_rate(&self) -> Hertz {
+        let width5 = u32::from(self.spdifrx.sr.read().width5().bits());
+        sample_rate(self.kernel_clock.raw(), width5).Hz()
+    }
+
+    /// Reads a sample, in the configured format
+    pub fn read(&mut self) -> nb::Result<u32, Error> {
+        self.check_errors().map_err(nb::Error::Other)?;
+        if self.spdifrx.sr.read().rxne().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(self.spdifrx.dr.read().bits())
+    }
+
+    /// Reads the channel status and user data bits of the next frames
+    pub fn read_control(&mut self) -> nb::Result<ControlWord, Error> {
+        self.check_errors().map_err(nb::Error::Other)?;
+        if self.spdifrx.sr.read().csrne().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+        let csr = self.spdifrx.csr.read();
+        Ok(ControlWord {
+            user: csr.usr().bits(),
+            channel_status: csr.cs().bits(),
+            start_of_block: csr.sob().bit_is_set(),
+        })
+    }
+
+    /// Reads the control words until a complete channel status block
+    pub fn read_channel_status(&mut self, status: &mut ChannelStatus) -> nb::Result<(), Error> {
+        loop {
+            if status.push(self.read_control()?) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns the errors of the reception, clearing the parity and overrun
+    /// errors
+    fn check_errors(&mut self) -> Result<(), Error> {
+        let sr = self.spdifrx.sr.read();
+        if sr.perr().bit_is_set() {
+            self.spdifrx.ifcr.write(|w| w.perrcf().set_bit());
+            Err(Error::Parity)
+        } else if sr.ovr().bit_is_set() {
+            self.spdifrx.ifcr.write(|w| w.ovrcf().set_bit());
+            Err(Error::Overrun)
+        } else {
+            self.check_sync_errors()
+        }
+    }
+
+    /// Returns the errors stopping the reception, cleared by going idle
+    fn check_sync_errors(&self) -> Result<(), Error> {
+        let sr = self.spdifrx.sr.read();
+        if sr.serr().bit_is_set() {
+            Err(Error::Synchronization)
+        } else if sr.ferr().bit_is_set() {
+            Err(Error::Framing)
+        } else if sr.terr().bit_is_set() {
+            Err(Error::Timeout)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Starts listening for an interrupt event
+    pub fn listen(&mut self, event: Event) {
+        self.spdifrx.imr.modify(|_, w| match event {
+            Event::DataReady => w.rxneie().set_bit(),
+            Event::ControlReady => w.csrneie().set_bit(),
+            Event::Parity => w.perrie().set_bit(),
+            Event::Overrun => w.ovrie().set_bit(),
+            Event::StartOfBlock => w.sblkie().set_bit(),
+            Event::Synchronized => w.syncdie().set_bit(),
+            Event::Error => w.ifeie().set_bit(),
+        });
+    }
+
+    /// Stops listening for an interrupt event
+    pub fn unlisten(&mut self, event: Event) {
+        self.spdifrx.imr.modify(|_, w| match event {
+            Event::DataReady => w.rxneie().clear_bit(),
+            Event::ControlReady => w.csrneie().clear_bit(),
+            Event::Parity => w.perrie().clear_bit(),
+            Event::Overrun => w.ovrie().clear_bit(),
+            Event::StartOfBlock => w.sblkie().clear_bit(),
+            Event::Synchronized => w.syncdie().clear_bit(),
+            Event::Error => w.ifeie().clear_bit(),
+        });
+    }
+
+    /// Returns whether the flag of an interrupt event is set
+    pub fn is_pending(&self, event: Event) -> bool {
+        let sr = self.spdifrx.sr.read();
+        match event {
+            Event::DataReady => sr.rxne().bit_is_set(),
+            Event::ControlReady => sr.csrne().bit_is_set(),
+            Event::Parity => sr.perr().bit_is_set(),
+            Event::Overrun => sr.ovr().bit_is_set(),
+            Event::StartOfBlock => sr.sbd().bit_is_set(),
+            Event::Synchronized => sr.syncd().bit_is_set(),
+            Event::Error => {
+                sr.serr().bit_is_set() || sr.ferr().bit_is_set() || sr.terr().bit_is_set()
+            }
+        }
+    }
+
+    /// Clears the flag of an interrupt event
+    ///
+    /// The data and control flags are cleared by reading the sample or the
+    /// control word, the error flags by stopping the receiver.
+    pub fn clear_interrupt(&mut self, event: Event) {
+        self.spdifrx.ifcr.write(|w| match event {
+            Event::Parity => w.perrcf().set_bit(),
+            Event::Overrun => w.ovrcf().set_bit(),
+            Event::StartOfBlock => w.sbdcf().set_bit(),
+            Event::Synchronized => w.syncdcf().set_bit(),
+            Event::DataReady | Event::ControlReady | Event::Error => w,
+        });
+    }
+
+    /// Reads samples with the DMA until `buffer` is full
+    ///
+    /// The receiver must have been started. The DMA request is enabled right
+    /// away, so the transfer must be started before the next sample to avoid
+    /// an overrun. DMA supports transfers up to 65535 samples. If `buffer`
+    /// is longer, this method will panic.
+    pub fn read_all<B>(
+        self,
+        buffer: Pin<B>,
+        dma: &dma::Handle<<Self as dma::Target>::Instance, state::Enabled>,
+        stream: <Self as dma::Target>::Stream,
+    ) -> dma::Transfer<Self, B, dma::Ready>
+    where
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u32>,
+    {
+        self.spdifrx.cr.modify(|_, w| w.rxdmaen().set_bit());
+        let address = &self.spdifrx.dr as *const _ as _;
+
+        // Safe, because the trait bounds on this method guarantee that `buffer`
+        // can be written to safely.
+        unsafe {
+            dma::Transfer::new(
+                dma,
+                stream,
+                buffer,
+                self,
+                address,
+                dma::Direction::PeripheralToMemory,
+            )
+        }
+    }
+
+    /// Disables the DMA request, after a transfer
+    pub fn disable_dma(&mut self) {
+        self.spdifrx.cr.modify(|_, w| w.rxdmaen().clear_bit());
+    }
+
+    /// Stops the receiver, and returns the peripheral
+    pub fn free(mut self) -> SPDIFRX {
+        self.stop();
+        self.spdifrx
+    }
+}
+
+/// Returns the frequency of the P output of the PLLI2S, computed from the PLL
+/// configuration
+fn kernel_clock(clocks: &Clocks) -> Hertz {
+    // NOTE(unsafe) read only
+    let rcc = unsafe { &*RCC::ptr() };
+    assert!(rcc.cr.read().plli2srdy().bit_is_set(), "The PLLI2S is off");
+    let pllcfgr = rcc.pllcfgr.read();
+    let input = if pllcfgr.pllsrc().bit_is_set() {
+        clocks.hse().expect("The PLLs are clocked by the HSE").raw()
+    } else {
+        HSI
+    };
+
+    let cfgr = rcc.plli2scfgr.read();
+    let p = 2 * (u32::from(cfgr.plli2sp().bits()) + 1);
+    (input / u32::from(pllcfgr.pllm().bits()) * u32::from(cfgr.plli2sn().bits()) / p).Hz()
+}
+
+/// Returns the sample rate of a stream of which 5 symbols last `width5`
+/// periods of `kernel_clock`, with 64 symbols per frame
+fn sample_rate(kernel_clock: u32, width5: u32) -> u32 {
+    if width5 == 0 {
+        return 0;
+    }
+    (5 * u64::from(kernel_clock) / (64 * u64::from(width5))) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_rate() {
+        // 48 kHz with a kernel clock of 96 MHz
+        assert_eq!(sample_rate(96_000_000, 156), 48_076);
+        assert_eq!(sample_rate(96_000_000, 0), 0);
+    }
+
+    #[test]
+    fn test_channel_status() {
+        let word = |channel_status, start_of_block| ControlWord {
+            user: 0,
+            channel_status,
+            start_of_block,
+        };
+
+        let mut status = ChannelStatus::new();
+        assert!(!status.push(word(0xff, false)));
+        for i in 0..23 {
+            assert!(!status.push(word(i, i == 0)));
+        }
+        assert!(status.push(word(23, false)));
+        assert_eq!(status.bytes()[23], 23);
+        assert!(!status.push(word(0xff, false)));
+        assert_eq!(status.bytes()[0], 0);
+    }
+}
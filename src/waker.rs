@@ -0,0 +1,30 @@
+//! Waker storage shared between the async drivers and their interrupt handlers
+
+use core::cell::RefCell;
+use core::task::Waker;
+
+use cortex_m::interrupt::{self, Mutex};
+
+pub(crate) struct WakerCell(Mutex<RefCell<Option<Waker>>>);
+
+impl WakerCell {
+    pub(crate) const fn new() -> Self {
+        Self(Mutex::new(RefCell::new(None)))
+    }
+
+    pub(crate) fn register(&self, waker: &Waker) {
+        interrupt::free(|cs| {
+            let mut slot = self.0.borrow(cs).borrow_mut();
+            match slot.as_ref() {
+                Some(w) if w.will_wake(waker) => {}
+                _ => *slot = Some(waker.clone()),
+            }
+        });
+    }
+
+    pub(crate) fn wake(&self) {
+        if let Some(waker) = interrupt::free(|cs| self.0.borrow(cs).borrow_mut().take()) {
+            waker.wake();
+        }
+    }
+}
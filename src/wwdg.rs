@@ -0,0 +1,178 @@
+//! Window watchdog (WWDG)
+//!
+//! The window watchdog is clocked from PCLK1 and resets the device if it is
+//! not fed before its counter expires, or if it is fed too early, before the
+//! refresh window opens. The early wakeup interrupt (EWI) fires one counter
+//! tick before the reset, which leaves the application time to save state.
+//!
+//! See section 28 of RM0410.
+
+use crate::pac::WWDG;
+use crate::rcc::{Clocks, Enable, Reset, APB1};
+
+use embedded_hal::watchdog::{Watchdog, WatchdogEnable};
+use fugit::{HertzU32 as Hertz, MicrosDurationU32};
+
+/// Counter value below which the watchdog resets the device
+const COUNTER_MIN: u8 = 0x40;
+const COUNTER_MAX: u8 = 0x7f;
+
+/// WWDG counter clock prescaler (WDGTB), applied after the fixed /4096 divider
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Prescaler {
+    Div1 = 0b00,
+    Div2 = 0b01,
+    Div4 = 0b10,
+    Div8 = 0b11,
+}
+
+/// Window watchdog
+pub struct WindowWatchdog {
+    wwdg: WWDG,
+    pclk1: Hertz,
+    /// Counter reload value, as written on each feed
+    counter: u8,
+}
+
+impl WindowWatchdog {
+    /// Enables the WWDG clock. The watchdog is not started.
+    pub fn new(wwdg: WWDG, apb1: &mut APB1, clocks: &Clocks) -> Self {
+        WWDG::enable(apb1);
+        WWDG::reset(apb1);
+
+        Self {
+            wwdg,
+            pclk1: clocks.pclk1(),
+            counter: COUNTER_MAX,
+        }
+    }
+
+    /// Starts the watchdog with the raw configuration.
+    ///
+    /// `counter` (0x40..=0x7f) is reloaded on each feed; the device is reset
+    /// when it decrements below 0x40. Feeding is only allowed while the
+    /// counter is lower or equal to `window`, feeding earlier resets the
+    /// device. Once started, the watchdog can only be stopped by a reset.
+    pub fn start_raw(&mut self, prescaler: Prescaler, window: u8, counter: u8) {
+        assert!((COUNTER_MIN..=COUNTER_MAX).contains(&counter));
+        assert!(window <= COUNTER_MAX);
+
+        self.counter = counter;
+        self.wwdg
+            .cfr
+            .modify(|_, w| w.wdgtb().bits(prescaler as u8).w().bits(window));
+        self.wwdg.cr.write(|w| w.t().bits(counter).wdga().set_bit());
+    }
+
+    /// Starts the watchdog so it resets the device if it is not fed within
+    /// `timeout`, and also if it is fed sooner than `min` after the previous
+    /// feed.
+    ///
+    /// Panics if `timeout` is out of range for the current PCLK1 frequency.
+    pub fn start_windowed(&mut self, min: MicrosDurationU32, timeout: MicrosDurationU32) {
+        let (prescaler, ticks) =
+            timeout_ticks(self.pclk1.raw(), timeout.ticks()).expect("WWDG timeout out of range");
+        let counter = COUNTER_MIN - 1 + ticks;
+
+        // Number of ticks to wait before the window opens, rounded up
+        let tick_us = tick_us(self.pclk1.raw(), prescaler);
+        let closed = ((min.ticks() as u64 + tick_us - 1) / tick_us).min(ticks as u64 - 1) as u8;
+
+        self.start_raw(prescaler, counter - closed, counter);
+    }
+
+    /// Feeds the watchdog, reloading its counter
+    pub fn feed(&mut self) {
+        self.wwdg.cr.write(|w| w.t().bits(self.counter));
+    }
+
+    /// Returns the current counter value
+    pub fn counter(&self) -> u8 {
+        self.wwdg.cr.read().t().bits()
+    }
+
+    /// Enables the early wakeup interrupt, raised when the counter reaches
+    /// 0x40, one tick before the reset.
+    ///
+    /// The WWDG interrupt must also be unmasked in the NVIC. Once enabled, the
+    /// interrupt can only be disabled by a reset.
+    pub fn listen(&mut self) {
+        self.wwdg.cfr.modify(|_, w| w.ewi().set_bit());
+    }
+
+    /// Returns `true` if the early wakeup interrupt is pending
+    pub fn is_pending(&self) -> bool {
+        self.wwdg.sr.read().ewif().bit_is_set()
+    }
+
+    /// Clears the early wakeup interrupt flag
+    pub fn clear_interrupt(&mut self) {
+        self.wwdg.sr.write(|w| w.ewif().clear_bit());
+    }
+
+    /// Releases the WWDG peripheral
+    pub fn free(self) -> WWDG {
+        self.wwdg
+    }
+}
+
+/// Duration of one counter tick, in microseconds
+fn tick_us(pclk1: u32, prescaler: Prescaler) -> u64 {
+    (4096u64 << prescaler as u8) * 1_000_000 / pclk1 as u64
+}
+
+/// Finds the smallest prescaler for which `timeout_us` fits in the counter.
+/// Returns it with the number of ticks (1..=64) until the reset.
+fn timeout_ticks(pclk1: u32, timeout_us: u32) -> Option<(Prescaler, u8)> {
+    [
+        Prescaler::Div1,
+        Prescaler::Div2,
+        Prescaler::Div4,
+        Prescaler::Div8,
+    ]
+    .iter()
+    .find_map(|&prescaler| {
+        let ticks = timeout_us as u64 / tick_us(pclk1, prescaler);
+        match ticks {
+            1..=64 => Some((prescaler, ticks as u8)),
+            _ => None,
+        }
+    })
+}
+
+impl WatchdogEnable for WindowWatchdog {
+    type Time = MicrosDurationU32;
+
+    /// Starts the watchdog with an open window: it can be fed at any time
+    /// before `period` elapses
+    fn start<T: Into<Self::Time>>(&mut self, period: T) {
+        self.start_windowed(MicrosDurationU32::from_ticks(0), period.into());
+    }
+}
+
+impl Watchdog for WindowWatchdog {
+    fn feed(&mut self) {
+        self.feed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout_ticks() {
+        // 54 MHz PCLK1: 75 us per tick without prescaler
+        assert_eq!(timeout_ticks(54_000_000, 75), Some((Prescaler::Div1, 1)));
+        assert_eq!(
+            timeout_ticks(54_000_000, 4_800),
+            Some((Prescaler::Div1, 64))
+        );
+        assert_eq!(
+            timeout_ticks(54_000_000, 10_000),
+            Some((Prescaler::Div4, 33))
+        );
+        assert_eq!(timeout_ticks(54_000_000, 50), None);
+        assert_eq!(timeout_ticks(54_000_000, 40_000), None);
+    }
+}